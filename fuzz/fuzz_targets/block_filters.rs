@@ -0,0 +1,11 @@
+#![no_main]
+
+use ckb_light_client::fuzzing::Harness;
+use ckb_light_client_fuzz::{BlockFilters, Input};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: Input<BlockFilters>| {
+    let mut harness = Harness::new();
+    let data = input.data(&harness);
+    harness.filter_message(input.sender(), &data);
+});
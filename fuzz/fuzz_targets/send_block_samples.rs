@@ -0,0 +1,11 @@
+#![no_main]
+
+use ckb_light_client::fuzzing::Harness;
+use ckb_light_client_fuzz::{Input, SendBlockSamples};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: Input<SendBlockSamples>| {
+    let mut harness = Harness::new();
+    let data = input.data(&harness);
+    harness.light_client_message(input.sender(), &data);
+});
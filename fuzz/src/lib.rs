@@ -0,0 +1,477 @@
+//! Inputs of the fuzz targets.
+//!
+//! Most arbitrary bytes are rejected by the molecule verification at once, so the targets also
+//! accept messages which are built from arbitrary fields. The headers in a built message are
+//! linked to the previous one, or to the tip header of the proved peer in the harness, unless
+//! the fuzzer chooses an arbitrary parent hash.
+
+use arbitrary::Arbitrary;
+use ckb_light_client::fuzzing::{Harness, Sender};
+use ckb_types::{bytes::Bytes, packed, prelude::*};
+
+/// The input of a fuzz target which feeds the message `T` into the harness.
+#[derive(Arbitrary, Debug)]
+pub struct Input<T> {
+    from_proved_peer: bool,
+    message: Message<T>,
+}
+
+#[derive(Arbitrary, Debug)]
+enum Message<T> {
+    /// The bytes of a whole protocol message, which could be any message of the protocol.
+    Bytes(Vec<u8>),
+    /// The bytes of the message content, which are wrapped into the protocol message as is.
+    Content(Vec<u8>),
+    /// A well-formed message.
+    Structured(T),
+}
+
+/// A message whose fields are arbitrary.
+pub trait Content {
+    type Entity: Entity;
+
+    /// Builds the message content, the harness could be prepared to wait for it.
+    fn build(&self, harness: &Harness) -> Self::Entity;
+
+    /// Wraps the content into the protocol message.
+    fn wrap(content: Self::Entity) -> Bytes;
+}
+
+impl<T: Content> Input<T> {
+    pub fn sender(&self) -> Sender {
+        if self.from_proved_peer {
+            Sender::Proved
+        } else {
+            Sender::Connected
+        }
+    }
+
+    /// Returns the bytes which are received from the peer.
+    pub fn data(&self, harness: &Harness) -> Bytes {
+        match &self.message {
+            Message::Bytes(data) => Bytes::from(data.clone()),
+            Message::Content(data) => T::wrap(<T::Entity as Entity>::new_unchecked(Bytes::from(
+                data.clone(),
+            ))),
+            Message::Structured(content) => T::wrap(content.build(harness)),
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+enum Parent {
+    Previous,
+    Hash([u8; 32]),
+}
+
+#[derive(Arbitrary, Debug)]
+enum Number {
+    /// Skips some blocks after the previous header.
+    Next(u8),
+    Any(u64),
+}
+
+#[derive(Arbitrary, Debug)]
+struct Header {
+    version: u32,
+    compact_target: u32,
+    timestamp: u64,
+    number: Number,
+    // same as the previous header if it's none
+    epoch: Option<u64>,
+    parent: Parent,
+    transactions_root: [u8; 32],
+    proposals_hash: [u8; 32],
+    extra_hash: [u8; 32],
+    dao: [u8; 32],
+    nonce: u128,
+}
+
+impl Header {
+    fn build(&self, previous: &packed::Header) -> packed::Header {
+        let previous_number: u64 = previous.raw().number().unpack();
+        let number = match self.number {
+            Number::Next(skipped) => previous_number.saturating_add(1 + u64::from(skipped)),
+            Number::Any(number) => number,
+        };
+        let epoch = self
+            .epoch
+            .map(|epoch| epoch.pack())
+            .unwrap_or_else(|| previous.raw().epoch());
+        let parent_hash = match self.parent {
+            Parent::Previous => previous.calc_header_hash(),
+            Parent::Hash(hash) => hash.pack(),
+        };
+        let raw = packed::RawHeader::new_builder()
+            .version(self.version.pack())
+            .compact_target(self.compact_target.pack())
+            .timestamp(self.timestamp.pack())
+            .number(number.pack())
+            .epoch(epoch)
+            .parent_hash(parent_hash)
+            .transactions_root(self.transactions_root.pack())
+            .proposals_hash(self.proposals_hash.pack())
+            .extra_hash(self.extra_hash.pack())
+            .dao(self.dao.pack())
+            .build();
+        packed::Header::new_builder()
+            .raw(raw)
+            .nonce(self.nonce.pack())
+            .build()
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+struct VerifiableHeader {
+    header: Header,
+    uncles_hash: [u8; 32],
+    extension: Option<Vec<u8>>,
+}
+
+impl VerifiableHeader {
+    fn build(&self, previous: &packed::Header) -> packed::VerifiableHeader {
+        let extension = self
+            .extension
+            .as_ref()
+            .map(|extension| Bytes::from(extension.clone()).pack());
+        packed::VerifiableHeader::new_builder()
+            .header(self.header.build(previous))
+            .uncles_hash(self.uncles_hash.pack())
+            .extension(packed::BytesOpt::new_builder().set(extension).build())
+            .build()
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+struct VerifiableHeaderWithChainRoot {
+    header: VerifiableHeader,
+    chain_root: HeaderDigest,
+}
+
+#[derive(Arbitrary, Debug)]
+struct HeaderDigest {
+    children_hash: [u8; 32],
+    total_difficulty: [u8; 32],
+    start_number: u64,
+    end_number: u64,
+    start_epoch: u64,
+    end_epoch: u64,
+    start_timestamp: u64,
+    end_timestamp: u64,
+    start_compact_target: u32,
+    end_compact_target: u32,
+}
+
+impl HeaderDigest {
+    fn build(&self) -> packed::HeaderDigest {
+        packed::HeaderDigest::new_builder()
+            .children_hash(self.children_hash.pack())
+            .total_difficulty(uint256(&self.total_difficulty))
+            .start_number(self.start_number.pack())
+            .end_number(self.end_number.pack())
+            .start_epoch(self.start_epoch.pack())
+            .end_epoch(self.end_epoch.pack())
+            .start_timestamp(self.start_timestamp.pack())
+            .end_timestamp(self.end_timestamp.pack())
+            .start_compact_target(self.start_compact_target.pack())
+            .end_compact_target(self.end_compact_target.pack())
+            .build()
+    }
+}
+
+fn uint256(bytes: &[u8; 32]) -> packed::Uint256 {
+    packed::Uint256::from_slice(bytes).expect("Uint256 is 32 bytes")
+}
+
+fn proof(items: &[HeaderDigest]) -> packed::HeaderDigestVec {
+    packed::HeaderDigestVec::new_builder()
+        .set(items.iter().map(HeaderDigest::build).collect())
+        .build()
+}
+
+// Builds the headers one by one, returns the last header too.
+fn build_headers(
+    headers: &[VerifiableHeaderWithChainRoot],
+    previous: packed::Header,
+) -> (packed::VerifiableHeaderWithChainRootVec, packed::Header) {
+    let mut previous = previous;
+    let headers = headers
+        .iter()
+        .map(|item| {
+            let header = item.header.build(&previous);
+            previous = header.header();
+            packed::VerifiableHeaderWithChainRoot::new_builder()
+                .header(header.header())
+                .uncles_hash(header.uncles_hash())
+                .extension(header.extension())
+                .chain_root(item.chain_root.build())
+                .build()
+        })
+        .collect();
+    let headers = packed::VerifiableHeaderWithChainRootVec::new_builder()
+        .set(headers)
+        .build();
+    (headers, previous)
+}
+
+fn light_client_message<T: Into<packed::LightClientMessageUnion>>(content: T) -> Bytes {
+    packed::LightClientMessage::new_builder()
+        .set(content)
+        .build()
+        .as_bytes()
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct SendLastState {
+    tip_header: VerifiableHeader,
+    total_difficulty: [u8; 32],
+}
+
+impl Content for SendLastState {
+    type Entity = packed::SendLastState;
+
+    fn build(&self, harness: &Harness) -> Self::Entity {
+        packed::SendLastState::new_builder()
+            .tip_header(self.tip_header.build(&harness.tip_header().header()))
+            .total_difficulty(uint256(&self.total_difficulty))
+            .build()
+    }
+
+    fn wrap(content: Self::Entity) -> Bytes {
+        light_client_message(content)
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+struct GetBlockSamples {
+    start_number: u64,
+    last_n_blocks: u64,
+    difficulty_boundary: [u8; 32],
+    difficulties: Vec<[u8; 32]>,
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct SendBlockSamples {
+    // the request which the proved peer is waiting for, the default one if it's none
+    request: Option<GetBlockSamples>,
+    root: HeaderDigest,
+    proof: Vec<HeaderDigest>,
+    reorg_last_n_headers: Vec<VerifiableHeaderWithChainRoot>,
+    sampled_headers: Vec<VerifiableHeaderWithChainRoot>,
+    last_n_headers: Vec<VerifiableHeaderWithChainRoot>,
+}
+
+impl Content for SendBlockSamples {
+    type Entity = packed::SendBlockSamples;
+
+    fn build(&self, harness: &Harness) -> Self::Entity {
+        let tip_header = harness.tip_header().header();
+        if let Some(request) = &self.request {
+            let tip_hash = tip_header.calc_header_hash();
+            let difficulties = request.difficulties.iter().map(uint256).collect();
+            let request = packed::GetBlockSamples::new_builder()
+                .last_hash(tip_hash.clone())
+                .start_hash(tip_hash)
+                .start_number(request.start_number.pack())
+                .last_n_blocks(request.last_n_blocks.pack())
+                .difficulty_boundary(uint256(&request.difficulty_boundary))
+                .difficulties(packed::Uint256Vec::new_builder().set(difficulties).build())
+                .build();
+            harness.expect_block_samples(request);
+        }
+        let (reorg_last_n_headers, _) =
+            build_headers(&self.reorg_last_n_headers, tip_header.clone());
+        let (sampled_headers, last_sampled_header) =
+            build_headers(&self.sampled_headers, tip_header);
+        let (last_n_headers, _) = build_headers(&self.last_n_headers, last_sampled_header);
+        packed::SendBlockSamples::new_builder()
+            .root(self.root.build())
+            .proof(proof(&self.proof))
+            .reorg_last_n_headers(reorg_last_n_headers)
+            .sampled_headers(sampled_headers)
+            .last_n_headers(last_n_headers)
+            .build()
+    }
+
+    fn wrap(content: Self::Entity) -> Bytes {
+        light_client_message(content)
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct SendBlockProof {
+    // whether the proved peer is waiting for the proof, and whether the tip block is requested
+    requested: Option<bool>,
+    root: HeaderDigest,
+    proof: Vec<HeaderDigest>,
+    tip_header: VerifiableHeader,
+    headers: Vec<Header>,
+}
+
+impl Content for SendBlockProof {
+    type Entity = packed::SendBlockProof;
+
+    fn build(&self, harness: &Harness) -> Self::Entity {
+        let seeded_tip_header = harness.tip_header().header();
+        let headers: Vec<_> = self
+            .headers
+            .iter()
+            .map(|header| header.build(&seeded_tip_header))
+            .collect();
+        let content = packed::SendBlockProof::new_builder()
+            .root(self.root.build())
+            .proof(proof(&self.proof))
+            .tip_header(self.tip_header.build(&seeded_tip_header))
+            .headers(packed::HeaderVec::new_builder().set(headers).build())
+            .build();
+        if let Some(fetch_tip) = self.requested {
+            harness.expect_block_proof(&content, fetch_tip);
+        }
+        content
+    }
+
+    fn wrap(content: Self::Entity) -> Bytes {
+        light_client_message(content)
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+enum Filter {
+    /// A filter which matches the filtered script.
+    Matched,
+    Any(Vec<u8>),
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct BlockFilters {
+    // continuous with the filtered blocks if it's none
+    start_number: Option<u64>,
+    block_hashes: Vec<[u8; 32]>,
+    filters: Vec<Filter>,
+}
+
+impl Content for BlockFilters {
+    type Entity = packed::BlockFilters;
+
+    fn build(&self, harness: &Harness) -> Self::Entity {
+        let start_number = self
+            .start_number
+            .unwrap_or_else(|| harness.filtered_block_number() + 1);
+        let block_hashes: Vec<packed::Byte32> =
+            self.block_hashes.iter().map(|hash| hash.pack()).collect();
+        let filters: Vec<packed::Bytes> = self
+            .filters
+            .iter()
+            .map(|filter| match filter {
+                Filter::Matched => harness.matched_filter().pack(),
+                Filter::Any(data) => Bytes::from(data.clone()).pack(),
+            })
+            .collect();
+        packed::BlockFilters::new_builder()
+            .start_number(start_number.pack())
+            .block_hashes(block_hashes.pack())
+            .filters(filters.pack())
+            .build()
+    }
+
+    fn wrap(content: Self::Entity) -> Bytes {
+        packed::BlockFilterMessage::new_builder()
+            .set(content)
+            .build()
+            .as_bytes()
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+struct CellOutput {
+    capacity: u64,
+    lock_args: Vec<u8>,
+    // the filtered script if it's none
+    lock_code_hash: Option<[u8; 32]>,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Transaction {
+    inputs: Vec<([u8; 32], u32)>,
+    outputs: Vec<CellOutput>,
+    witnesses: Vec<Vec<u8>>,
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct SendBlock {
+    header: Header,
+    transactions: Vec<Transaction>,
+}
+
+impl Content for SendBlock {
+    type Entity = packed::SendBlock;
+
+    fn build(&self, harness: &Harness) -> Self::Entity {
+        let script = harness.script();
+        let transactions: Vec<packed::Transaction> = self
+            .transactions
+            .iter()
+            .map(|tx| {
+                let inputs: Vec<packed::CellInput> = tx
+                    .inputs
+                    .iter()
+                    .map(|(tx_hash, index)| {
+                        let out_point = packed::OutPoint::new_builder()
+                            .tx_hash(tx_hash.pack())
+                            .index(index.pack())
+                            .build();
+                        packed::CellInput::new_builder()
+                            .previous_output(out_point)
+                            .build()
+                    })
+                    .collect();
+                let outputs: Vec<packed::CellOutput> = tx
+                    .outputs
+                    .iter()
+                    .map(|output| {
+                        let lock = script
+                            .clone()
+                            .as_builder()
+                            .args(Bytes::from(output.lock_args.clone()).pack());
+                        let lock = match output.lock_code_hash {
+                            Some(code_hash) => lock.code_hash(code_hash.pack()),
+                            None => lock,
+                        };
+                        packed::CellOutput::new_builder()
+                            .capacity(output.capacity.pack())
+                            .lock(lock.build())
+                            .build()
+                    })
+                    .collect();
+                let outputs_data: Vec<packed::Bytes> =
+                    outputs.iter().map(|_| Bytes::new().pack()).collect();
+                let witnesses: Vec<packed::Bytes> = tx
+                    .witnesses
+                    .iter()
+                    .map(|witness| Bytes::from(witness.clone()).pack())
+                    .collect();
+                let raw = packed::RawTransaction::new_builder()
+                    .inputs(inputs.pack())
+                    .outputs(outputs.pack())
+                    .outputs_data(outputs_data.pack())
+                    .build();
+                packed::Transaction::new_builder()
+                    .raw(raw)
+                    .witnesses(witnesses.pack())
+                    .build()
+            })
+            .collect();
+        let block = packed::Block::new_builder()
+            .header(self.header.build(&harness.tip_header().header()))
+            .transactions(transactions.pack())
+            .build();
+        packed::SendBlock::new_builder().block(block).build()
+    }
+
+    fn wrap(content: Self::Entity) -> Bytes {
+        packed::SyncMessage::new_builder()
+            .set(content)
+            .build()
+            .as_bytes()
+    }
+}
@@ -0,0 +1,197 @@
+//! CKB addresses, see [RFC 21](https://github.com/nervosnetwork/rfcs/blob/master/rfcs/0021-ckb-address-format/0021-ckb-address-format.md).
+//!
+//! Addresses are always encoded in the full format, all formats of RFC 21 are decoded, including
+//! the deprecated short and full formats.
+
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+use bech32::{FromBase32, ToBase32, Variant};
+use ckb_types::{core::ScriptHashType, h256, packed, prelude::*, H256};
+
+const FORMAT_FULL: u8 = 0x00;
+const FORMAT_SHORT: u8 = 0x01;
+const FORMAT_FULL_DATA: u8 = 0x02;
+const FORMAT_FULL_TYPE: u8 = 0x04;
+
+pub(crate) const SIGHASH_TYPE_HASH: H256 =
+    h256!("0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8");
+const MULTISIG_TYPE_HASH: H256 =
+    h256!("0x5c5069eb0857efc65e1bca0c07df34c31663b3622fd3876c876320fc9634e2a8");
+const ACP_MAINNET_TYPE_HASH: H256 =
+    h256!("0xd369597ff47f29fbc0d47d2e3775370d1250b85140c670e4718af712983a2354");
+const ACP_TESTNET_TYPE_HASH: H256 =
+    h256!("0x3419a1c09eb2567f6552ee7a8ecffd64155cffe0f1796e6e61ec088d740c1356");
+
+/// The network of an address, which decides the human-readable prefix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetworkType {
+    Mainnet,
+    /// The testnet and all dev chains.
+    Testnet,
+}
+
+impl NetworkType {
+    /// Returns the network of the chain, by the name in its chain spec.
+    pub fn from_chain_name(name: &str) -> Self {
+        if name == "ckb" {
+            Self::Mainnet
+        } else {
+            Self::Testnet
+        }
+    }
+
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Self::Mainnet => "ckb",
+            Self::Testnet => "ckt",
+        }
+    }
+
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        match prefix {
+            "ckb" => Some(Self::Mainnet),
+            "ckt" => Some(Self::Testnet),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Address {
+    network: NetworkType,
+    script: packed::Script,
+}
+
+impl Address {
+    pub fn new(network: NetworkType, script: packed::Script) -> Self {
+        Self { network, script }
+    }
+
+    pub fn network(&self) -> NetworkType {
+        self.network
+    }
+
+    pub fn script(&self) -> &packed::Script {
+        &self.script
+    }
+
+    /// Encodes the address in the deprecated short format, only the secp256k1 blake160 sighash,
+    /// multisig and anyone-can-pay locks could be encoded.
+    pub fn to_short_string(&self) -> Option<String> {
+        if ScriptHashType::try_from(self.script.hash_type()).ok() != Some(ScriptHashType::Type) {
+            return None;
+        }
+        let code_hash: H256 = self.script.code_hash().unpack();
+        let args = self.script.args().raw_data();
+        let index = short_code_hash_index(self.network, &code_hash, args.len())?;
+        let mut payload = vec![FORMAT_SHORT, index];
+        payload.extend_from_slice(&args);
+        Some(encode(self.network, &payload, Variant::Bech32))
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut payload = vec![FORMAT_FULL];
+        payload.extend_from_slice(self.script.code_hash().as_slice());
+        payload.extend_from_slice(self.script.hash_type().as_slice());
+        payload.extend_from_slice(&self.script.args().raw_data());
+        write!(f, "{}", encode(self.network, &payload, Variant::Bech32m))
+    }
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (prefix, data, variant) =
+            bech32::decode(s).map_err(|err| format!("invalid bech32 string: {}", err))?;
+        let network = NetworkType::from_prefix(&prefix)
+            .ok_or_else(|| format!("unknown address prefix {}", prefix))?;
+        let payload =
+            Vec::<u8>::from_base32(&data).map_err(|err| format!("invalid bech32 data: {}", err))?;
+        let (format, payload) = payload
+            .split_first()
+            .ok_or_else(|| "empty address payload".to_owned())?;
+        let expected_variant = if *format == FORMAT_FULL {
+            Variant::Bech32m
+        } else {
+            Variant::Bech32
+        };
+        if variant != expected_variant {
+            return Err(format!(
+                "address format {:#04x} should be encoded with {:?}",
+                format, expected_variant
+            ));
+        }
+        let script = match *format {
+            FORMAT_FULL => {
+                if payload.len() < 33 {
+                    return Err("the full address payload is too short".to_owned());
+                }
+                let hash_type = ScriptHashType::try_from(packed::Byte::new(payload[32]))
+                    .map_err(|_| format!("invalid hash type {}", payload[32]))?;
+                build_script(&payload[..32], hash_type, &payload[33..])
+            }
+            FORMAT_SHORT => {
+                if payload.is_empty() {
+                    return Err("the short address payload is too short".to_owned());
+                }
+                let args = &payload[1..];
+                let code_hash =
+                    short_code_hash(network, payload[0], args.len()).ok_or_else(|| {
+                        format!("invalid short address code hash index {}", payload[0])
+                    })?;
+                build_script(code_hash.as_bytes(), ScriptHashType::Type, args)
+            }
+            FORMAT_FULL_DATA | FORMAT_FULL_TYPE => {
+                if payload.len() < 32 {
+                    return Err("the full address payload is too short".to_owned());
+                }
+                let hash_type = if *format == FORMAT_FULL_DATA {
+                    ScriptHashType::Data
+                } else {
+                    ScriptHashType::Type
+                };
+                build_script(&payload[..32], hash_type, &payload[32..])
+            }
+            _ => return Err(format!("unknown address format {:#04x}", format)),
+        };
+        Ok(Self { network, script })
+    }
+}
+
+fn encode(network: NetworkType, payload: &[u8], variant: Variant) -> String {
+    bech32::encode(network.prefix(), payload.to_base32(), variant)
+        .expect("the address prefix is valid")
+}
+
+fn build_script(code_hash: &[u8], hash_type: ScriptHashType, args: &[u8]) -> packed::Script {
+    packed::Script::new_builder()
+        .code_hash(packed::Byte32::from_slice(code_hash).expect("32 bytes code hash"))
+        .hash_type(hash_type.into())
+        .args(args.pack())
+        .build()
+}
+
+// the code hash of the short format and the allowed length of its args
+fn short_code_hash(network: NetworkType, index: u8, args_len: usize) -> Option<H256> {
+    match index {
+        0x00 if args_len == 20 => Some(SIGHASH_TYPE_HASH),
+        0x01 if args_len == 20 => Some(MULTISIG_TYPE_HASH),
+        // the args of anyone-can-pay could have the minimal amounts after the blake160
+        0x02 if (20..=22).contains(&args_len) => match network {
+            NetworkType::Mainnet => Some(ACP_MAINNET_TYPE_HASH),
+            NetworkType::Testnet => Some(ACP_TESTNET_TYPE_HASH),
+        },
+        _ => None,
+    }
+}
+
+fn short_code_hash_index(network: NetworkType, code_hash: &H256, args_len: usize) -> Option<u8> {
+    (0x00..=0x02).find(|index| {
+        short_code_hash(network, *index, args_len)
+            .map(|expected| &expected == code_hash)
+            .unwrap_or(false)
+    })
+}
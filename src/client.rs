@@ -0,0 +1,435 @@
+//! The embeddable light client.
+//!
+//! ```ignore
+//! let chain_spec = ckb_light_client::load_chain_spec("testnet")?;
+//! let client = LightClientBuilder::new(chain_spec, "data/store", network_config)
+//!     .start(&handle)?;
+//! client.set_scripts(scripts)?;
+//! let tip = client.get_tip_header()?;
+//! client.shutdown();
+//! ```
+
+use std::{
+    path::PathBuf,
+    sync::{mpsc::SyncSender, Arc, RwLock},
+};
+
+use ckb_app_config::NetworkConfig;
+use ckb_async_runtime::Handle;
+use ckb_chain_spec::{consensus::Consensus, ChainSpec};
+use ckb_jsonrpc_types::{Capacity, HeaderView, JsonBytes, Script, Transaction, Uint128, Uint32};
+use ckb_network::{
+    CKBProtocol, CKBProtocolHandler, DefaultExitHandler, ExitHandler, NetworkController,
+    NetworkService, NetworkState, SupportProtocols,
+};
+use ckb_resource::Resource;
+use ckb_types::{core::FeeRate, H256};
+use jsonrpc_http_server::Server;
+
+use crate::{
+    address::NetworkType,
+    error::{Error, Result},
+    metrics::{Metrics, MetricsService},
+    protocols::{
+        FilterProtocol, LightClientProtocol, Peers, PendingTxs, RelayProtocol, SyncProtocol,
+    },
+    service::{
+        BlockFilterRpc, BlockFilterRpcImpl, Cell, ChainRpc, ChainRpcImpl, DaoCell, DaoRpc,
+        DaoRpcImpl, Order, Pagination, ScriptStatus, SearchKey, SendTransactionResult, Service,
+        SystemScriptInfo, TransactionRpc, TransactionRpcImpl, TransactionStatus,
+        TransactionWithHeader, TransferTransaction, Tx, WatchWalletStatus,
+    },
+    storage::{
+        KeyValueStore, Pruner, RetentionPolicy, RocksdbOptions, Storage, StorageWithLastHeaders,
+    },
+    system_scripts::SystemScripts,
+};
+
+// Same as the default `min_fee_rate` of the CKB full node.
+const DEFAULT_MIN_FEE_RATE: u64 = 1000;
+const PENDING_TXS_LIMIT: usize = 64;
+
+/// Loads the chain spec by name ("mainnet" or "testnet") or from a file path.
+pub fn load_chain_spec(chain: &str) -> Result<ChainSpec> {
+    let resource = match chain {
+        "mainnet" => Resource::bundled("specs/mainnet.toml".to_string()),
+        "testnet" => Resource::bundled("specs/testnet.toml".to_string()),
+        path => Resource::file_system(path.into()),
+    };
+    ChainSpec::load_from(&resource)
+        .map_err(|err| Error::config(format!("failed to load chain spec since {}", err)))
+}
+
+/// Builds and starts a [`LightClient`].
+pub struct LightClientBuilder {
+    chain_spec: ChainSpec,
+    store_path: PathBuf,
+    network_config: NetworkConfig,
+    min_fee_rate: FeeRate,
+    store_options: RocksdbOptions,
+    retention_policy: RetentionPolicy,
+    store: Option<Arc<dyn KeyValueStore>>,
+}
+
+impl LightClientBuilder {
+    pub fn new<P: Into<PathBuf>>(
+        chain_spec: ChainSpec,
+        store_path: P,
+        network_config: NetworkConfig,
+    ) -> Self {
+        Self {
+            chain_spec,
+            store_path: store_path.into(),
+            network_config,
+            min_fee_rate: FeeRate::from_u64(DEFAULT_MIN_FEE_RATE),
+            store_options: RocksdbOptions::default(),
+            retention_policy: RetentionPolicy::default(),
+            store: None,
+        }
+    }
+
+    /// Uses the store instead of opening a RocksDB at the store path, e.g. a
+    /// [`MemoryStore`](crate::MemoryStore) for ephemeral nodes.
+    pub fn store<S: KeyValueStore + 'static>(mut self, store: S) -> Self {
+        self.store = Some(Arc::new(store));
+        self
+    }
+
+    /// The tuning options of the RocksDB opened at the store path.
+    pub fn store_options(mut self, store_options: RocksdbOptions) -> Self {
+        self.store_options = store_options;
+        self
+    }
+
+    /// Prunes the transaction history in the background by the policy, keeps all by default.
+    pub fn retention_policy(mut self, retention_policy: RetentionPolicy) -> Self {
+        self.retention_policy = retention_policy;
+        self
+    }
+
+    /// The minimal fee rate (shannons per KB) of the transactions which are sent to peers.
+    pub fn min_fee_rate(mut self, min_fee_rate: FeeRate) -> Self {
+        self.min_fee_rate = min_fee_rate;
+        self
+    }
+
+    /// Opens the storage and starts the network protocols on the provided runtime.
+    pub fn start(self, handle: &Handle) -> Result<LightClient> {
+        crate::utils::fs::need_directory(&self.network_config.path)?;
+
+        let storage = match self.store {
+            Some(store) => Storage::with_shared_store(store)?,
+            None => Storage::open(&self.store_path, &self.store_options)?,
+        };
+        let consensus = self
+            .chain_spec
+            .build_consensus()
+            .map_err(|err| Error::config(format!("failed to build consensus since {}", err)))?;
+        storage.init_genesis_block(consensus.genesis_block().data());
+        let system_scripts = Arc::new(SystemScripts::new(&storage.get_genesis_block(), &consensus));
+        let pruner = match self.retention_policy {
+            RetentionPolicy::KeepAll => None,
+            policy => Some(Pruner::new(storage.clone(), policy).start()?),
+        };
+
+        let pending_txs = Arc::new(RwLock::new(PendingTxs::new(PENDING_TXS_LIMIT)));
+        let network_state = NetworkState::from_config(self.network_config)
+            .map(Arc::new)
+            .map_err(|err| {
+                let errmsg = format!("failed to initialize network state since {}", err);
+                Error::runtime(errmsg)
+            })?;
+        let required_protocol_ids = vec![
+            SupportProtocols::Sync.protocol_id(),
+            SupportProtocols::LightClient.protocol_id(),
+            SupportProtocols::Filter.protocol_id(),
+        ];
+
+        let last_headers = Arc::new(RwLock::new(Vec::new()));
+        let peers = Arc::new(Peers::new(Arc::clone(&last_headers)));
+        let metrics = Metrics::new();
+        let sync_protocol =
+            SyncProtocol::new(storage.clone(), Arc::clone(&pending_txs), metrics.clone());
+        let relay_protocol =
+            RelayProtocol::new(pending_txs.clone(), Arc::clone(&peers), metrics.clone());
+        let light_client: Box<dyn CKBProtocolHandler> = Box::new(LightClientProtocol::new(
+            storage.clone(),
+            Arc::clone(&peers),
+            consensus.clone(),
+            metrics.clone(),
+        ));
+        let filter_protocol =
+            FilterProtocol::new(storage.clone(), Arc::clone(&peers), metrics.clone());
+
+        let protocols = vec![
+            CKBProtocol::new_with_support_protocol(
+                SupportProtocols::Sync,
+                Box::new(sync_protocol),
+                Arc::clone(&network_state),
+            ),
+            CKBProtocol::new_with_support_protocol(
+                SupportProtocols::RelayV2,
+                Box::new(relay_protocol),
+                Arc::clone(&network_state),
+            ),
+            CKBProtocol::new_with_support_protocol(
+                SupportProtocols::LightClient,
+                light_client,
+                Arc::clone(&network_state),
+            ),
+            CKBProtocol::new_with_support_protocol(
+                SupportProtocols::Filter,
+                Box::new(filter_protocol),
+                Arc::clone(&network_state),
+            ),
+        ];
+
+        let exit_handler = DefaultExitHandler::default();
+        let network_controller = NetworkService::new(
+            Arc::clone(&network_state),
+            protocols,
+            required_protocol_ids,
+            consensus.identify_name(),
+            clap::crate_version!().to_owned(),
+            exit_handler.clone(),
+        )
+        .start(handle)
+        .map_err(|err| {
+            let errmsg = format!("failed to start network since {}", err);
+            Error::runtime(errmsg)
+        })?;
+
+        let swl = StorageWithLastHeaders::new(storage.clone(), Arc::clone(&last_headers));
+        Ok(LightClient {
+            block_filter_rpc: BlockFilterRpcImpl {
+                storage: storage.clone(),
+                pending_txs: Arc::clone(&pending_txs),
+                network: NetworkType::from_chain_name(&consensus.id),
+            },
+            chain_rpc: ChainRpcImpl {
+                swl: swl.clone(),
+                consensus: consensus.clone(),
+                system_scripts: Arc::clone(&system_scripts),
+            },
+            dao_rpc: DaoRpcImpl {
+                swl: swl.clone(),
+                peers: Arc::clone(&peers),
+                consensus: consensus.clone(),
+            },
+            transaction_rpc: TransactionRpcImpl {
+                pending_txs: Arc::clone(&pending_txs),
+                swl,
+                consensus: consensus.clone(),
+                system_scripts,
+                min_fee_rate: self.min_fee_rate,
+            },
+            storage,
+            store_path: self.store_path,
+            last_headers,
+            peers,
+            pending_txs,
+            consensus,
+            min_fee_rate: self.min_fee_rate,
+            metrics,
+            handle: handle.clone(),
+            network_controller,
+            exit_handler,
+            pruner,
+        })
+    }
+}
+
+/// The handle of a started light client.
+pub struct LightClient {
+    block_filter_rpc: BlockFilterRpcImpl,
+    chain_rpc: ChainRpcImpl,
+    dao_rpc: DaoRpcImpl,
+    transaction_rpc: TransactionRpcImpl,
+
+    storage: Storage,
+    store_path: PathBuf,
+    last_headers: Arc<RwLock<Vec<ckb_types::core::HeaderView>>>,
+    peers: Arc<Peers>,
+    pending_txs: Arc<RwLock<PendingTxs>>,
+    consensus: Consensus,
+    min_fee_rate: FeeRate,
+    metrics: Metrics,
+    // the runtime which the network and the metrics service run on
+    handle: Handle,
+    network_controller: NetworkController,
+    exit_handler: DefaultExitHandler,
+    // the pruner thread stops when it's dropped
+    pruner: Option<SyncSender<()>>,
+}
+
+impl LightClient {
+    /// Sets the scripts which should be filtered, the old ones are replaced.
+    pub fn set_scripts(&self, scripts: Vec<ScriptStatus>) -> Result<()> {
+        self.block_filter_rpc
+            .set_scripts(scripts)
+            .map_err(rpc_error)
+    }
+
+    pub fn get_scripts(&self) -> Result<Vec<ScriptStatus>> {
+        self.block_filter_rpc.get_scripts().map_err(rpc_error)
+    }
+
+    pub fn get_cells(
+        &self,
+        search_key: SearchKey,
+        order: Order,
+        limit: u32,
+        after: Option<JsonBytes>,
+    ) -> Result<Pagination<Cell>> {
+        self.block_filter_rpc
+            .get_cells(search_key, order, Uint32::from(limit), after)
+            .map_err(rpc_error)
+    }
+
+    pub fn get_cells_capacity(&self, search_key: SearchKey) -> Result<Capacity> {
+        self.block_filter_rpc
+            .get_cells_capacity(search_key)
+            .map_err(rpc_error)
+    }
+
+    pub fn get_udt_balance(
+        &self,
+        search_key: SearchKey,
+        udt_type_script: Script,
+    ) -> Result<Uint128> {
+        self.block_filter_rpc
+            .get_udt_balance(search_key, udt_type_script)
+            .map_err(rpc_error)
+    }
+
+    pub fn get_transactions(
+        &self,
+        search_key: SearchKey,
+        order: Order,
+        limit: u32,
+        after: Option<JsonBytes>,
+    ) -> Result<Pagination<Tx>> {
+        self.block_filter_rpc
+            .get_transactions(search_key, order, Uint32::from(limit), after)
+            .map_err(rpc_error)
+    }
+
+    /// Registers a watch-only wallet by the extended public key, its lock scripts are derived
+    /// and filtered from the block number.
+    pub fn add_watch_wallet(
+        &self,
+        xpub: String,
+        path: String,
+        gap_limit: u32,
+        block_number: u64,
+    ) -> Result<()> {
+        self.block_filter_rpc
+            .add_watch_wallet(xpub, path, gap_limit.into(), block_number.into())
+            .map_err(rpc_error)
+    }
+
+    pub fn get_watch_wallets(&self) -> Result<Vec<WatchWalletStatus>> {
+        self.block_filter_rpc.get_watch_wallets().map_err(rpc_error)
+    }
+
+    pub fn remove_watch_wallet(&self, xpub: String, path: String) -> Result<()> {
+        self.block_filter_rpc
+            .remove_watch_wallet(xpub, path)
+            .map_err(rpc_error)
+    }
+
+    pub fn send_transaction(&self, tx: Transaction) -> Result<SendTransactionResult> {
+        self.transaction_rpc.send_transaction(tx).map_err(rpc_error)
+    }
+
+    /// Builds an unsigned transaction which transfers the capacity from the secp256k1 blake160
+    /// sighash lock scripts, see [`TransferTransaction`] for how to sign it.
+    pub fn build_transfer(
+        &self,
+        from_scripts: Vec<Script>,
+        to: Script,
+        capacity: u64,
+        fee_rate: u64,
+    ) -> Result<TransferTransaction> {
+        self.transaction_rpc
+            .build_transfer(from_scripts, to, capacity.into(), fee_rate.into())
+            .map_err(rpc_error)
+    }
+
+    pub fn get_transaction(&self, tx_hash: H256) -> Result<Option<TransactionWithHeader>> {
+        self.chain_rpc.get_transaction(tx_hash).map_err(rpc_error)
+    }
+
+    pub fn get_transaction_status(&self, tx_hash: H256) -> Result<TransactionStatus> {
+        self.transaction_rpc
+            .get_transaction_status(tx_hash)
+            .map_err(rpc_error)
+    }
+
+    /// Returns the NervosDAO cells of the lock script, see [`DaoCell`] for the cells whose
+    /// required headers are still being fetched.
+    pub fn get_dao_cells(&self, lock_script: Script) -> Result<Vec<DaoCell>> {
+        self.dao_rpc.get_dao_cells(lock_script).map_err(rpc_error)
+    }
+
+    /// Returns the system scripts deployed in the genesis block and their cell deps.
+    pub fn get_system_scripts(&self) -> Result<Vec<SystemScriptInfo>> {
+        self.chain_rpc.get_system_scripts().map_err(rpc_error)
+    }
+
+    /// Returns the proved tip header.
+    pub fn get_tip_header(&self) -> Result<HeaderView> {
+        self.chain_rpc.get_tip_header().map_err(rpc_error)
+    }
+
+    /// Blocks the current thread until [`shutdown`](Self::shutdown) is called or the network
+    /// service exits.
+    pub fn wait_for_exit(&self) {
+        self.exit_handler.wait_for_exit();
+    }
+
+    /// Stops the light client, the storage is closed when all its users are dropped.
+    pub fn shutdown(self) {
+        self.exit_handler.notify_exit();
+        drop(self.pruner);
+        drop(self.network_controller);
+    }
+
+    pub(crate) fn exit_handler(&self) -> DefaultExitHandler {
+        self.exit_handler.clone()
+    }
+
+    pub(crate) fn start_rpc_service(
+        &self,
+        listen_address: &str,
+        indexer_compatible: bool,
+    ) -> Server {
+        Service::new(listen_address)
+            .indexer_compatible(indexer_compatible)
+            .start(
+                self.network_controller.clone(),
+                self.storage.clone(),
+                Arc::clone(&self.last_headers),
+                Arc::clone(&self.peers),
+                Arc::clone(&self.pending_txs),
+                self.consensus.clone(),
+                self.min_fee_rate,
+            )
+    }
+
+    pub(crate) fn start_metrics_service(&self, listen_address: &str) -> Result<()> {
+        MetricsService::new(
+            self.metrics.clone(),
+            self.storage.clone(),
+            self.store_path.clone(),
+            Arc::clone(&self.peers),
+            Arc::clone(&self.pending_txs),
+        )
+        .start(listen_address, &self.handle)
+        .map(|_| ())
+    }
+}
+
+fn rpc_error(err: jsonrpc_core::Error) -> Error {
+    Error::runtime(err.message)
+}
@@ -1,4 +1,6 @@
-use std::{convert::TryFrom, fmt::Display, fs::OpenOptions, io::Read as _, str::FromStr};
+use std::{
+    convert::TryFrom, fmt::Display, fs::OpenOptions, io::Read as _, path::PathBuf, str::FromStr,
+};
 
 use crate::{
     error::{Error, Result},
@@ -7,12 +9,30 @@ use crate::{
 
 pub(crate) enum AppConfig {
     Run(RunConfig),
+    Export(ExportConfig),
+    Import(ImportConfig),
+    DbCheck(DbCheckConfig),
 }
 
 pub(crate) struct RunConfig {
     pub(crate) run_env: RunEnv,
 }
 
+pub(crate) struct ExportConfig {
+    pub(crate) run_env: RunEnv,
+    pub(crate) target: PathBuf,
+}
+
+pub(crate) struct ImportConfig {
+    pub(crate) run_env: RunEnv,
+    pub(crate) source: PathBuf,
+}
+
+pub(crate) struct DbCheckConfig {
+    pub(crate) run_env: RunEnv,
+    pub(crate) repair: bool,
+}
+
 impl AppConfig {
     pub(crate) fn load() -> Result<Self> {
         let yaml = clap::load_yaml!("cli.yaml");
@@ -28,6 +48,9 @@ impl AppConfig {
         log::info!("Executing ...");
         match self {
             Self::Run(cfg) => cfg.execute(),
+            Self::Export(cfg) => cfg.execute(),
+            Self::Import(cfg) => cfg.execute(),
+            Self::DbCheck(cfg) => cfg.execute(),
         }
     }
 }
@@ -37,6 +60,15 @@ impl<'a> TryFrom<&'a clap::ArgMatches<'a>> for AppConfig {
     fn try_from(matches: &'a clap::ArgMatches) -> Result<Self> {
         match matches.subcommand() {
             ("run", Some(submatches)) => RunConfig::try_from(submatches).map(AppConfig::Run),
+            ("export", Some(submatches)) => {
+                ExportConfig::try_from(submatches).map(AppConfig::Export)
+            }
+            ("import", Some(submatches)) => {
+                ImportConfig::try_from(submatches).map(AppConfig::Import)
+            }
+            ("db-check", Some(submatches)) => {
+                DbCheckConfig::try_from(submatches).map(AppConfig::DbCheck)
+            }
             (subcmd, _) => Err(Error::config(format!("subcommand {}", subcmd))),
         }
     }
@@ -50,6 +82,39 @@ impl<'a> TryFrom<&'a clap::ArgMatches<'a>> for RunConfig {
     }
 }
 
+impl<'a> TryFrom<&'a clap::ArgMatches<'a>> for ExportConfig {
+    type Error = Error;
+    fn try_from(matches: &'a clap::ArgMatches) -> Result<Self> {
+        let run_env = parse_from_file::<RunEnv>(matches, "config-file")?;
+        let target = matches
+            .value_of("target")
+            .map(PathBuf::from)
+            .ok_or_else(|| Error::argument_should_exist("target"))?;
+        Ok(Self { run_env, target })
+    }
+}
+
+impl<'a> TryFrom<&'a clap::ArgMatches<'a>> for ImportConfig {
+    type Error = Error;
+    fn try_from(matches: &'a clap::ArgMatches) -> Result<Self> {
+        let run_env = parse_from_file::<RunEnv>(matches, "config-file")?;
+        let source = matches
+            .value_of("source")
+            .map(PathBuf::from)
+            .ok_or_else(|| Error::argument_should_exist("source"))?;
+        Ok(Self { run_env, source })
+    }
+}
+
+impl<'a> TryFrom<&'a clap::ArgMatches<'a>> for DbCheckConfig {
+    type Error = Error;
+    fn try_from(matches: &'a clap::ArgMatches) -> Result<Self> {
+        let run_env = parse_from_file::<RunEnv>(matches, "config-file")?;
+        let repair = matches.is_present("repair");
+        Ok(Self { run_env, repair })
+    }
+}
+
 fn parse_from_file<T: FromStr>(matches: &clap::ArgMatches, name: &str) -> Result<T>
 where
     <T as FromStr>::Err: Display,
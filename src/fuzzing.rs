@@ -0,0 +1,335 @@
+//! Entry points for the fuzz targets in `fuzz/`.
+//!
+//! The harness feeds untrusted peer messages into the message processors, with a storage and
+//! peers which are seeded as if one of the peers had been proved at the genesis block, so a
+//! message could reach the deep checks instead of being ignored at the beginning.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use ckb_chain_spec::consensus::ConsensusBuilder;
+use ckb_network::{
+    async_trait, bytes::Bytes, Behaviour, CKBProtocolContext, Error, Peer, PeerIndex, ProtocolId,
+    SupportProtocols, TargetSession,
+};
+use ckb_types::{
+    core::{BlockNumber, ScriptHashType},
+    packed,
+    prelude::*,
+    utilities::{compact_to_difficulty, merkle_mountain_range::VerifiableHeader},
+    H256,
+};
+
+use golomb_coded_set::{GCSFilterWriter, SipHasher24Builder, M, P};
+
+use crate::metrics::Metrics;
+use crate::protocols::{
+    FilterProtocol, LastState, LightClientProtocol, Peers, PendingTxs, ProveRequest, ProveState,
+    Status, StatusCode, SyncProtocol,
+};
+use crate::storage::{MemoryStore, Storage};
+
+/// A protocol context which drops all outgoing messages.
+struct FuzzContext {
+    protocol: SupportProtocols,
+}
+
+#[async_trait]
+impl CKBProtocolContext for FuzzContext {
+    async fn set_notify(&self, _interval: Duration, _token: u64) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn remove_notify(&self, _token: u64) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn async_quick_send_message(
+        &self,
+        _proto_id: ProtocolId,
+        _peer_index: PeerIndex,
+        _data: Bytes,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn async_quick_send_message_to(
+        &self,
+        _peer_index: PeerIndex,
+        _data: Bytes,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn async_quick_filter_broadcast(
+        &self,
+        _target: TargetSession,
+        _data: Bytes,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn async_future_task(
+        &self,
+        _task: Pin<Box<dyn Future<Output = ()> + 'static + Send>>,
+        _blocking: bool,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn async_send_message(
+        &self,
+        _proto_id: ProtocolId,
+        _peer_index: PeerIndex,
+        _data: Bytes,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn async_send_message_to(
+        &self,
+        _peer_index: PeerIndex,
+        _data: Bytes,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+    fn quick_send_message(
+        &self,
+        _proto_id: ProtocolId,
+        _peer_index: PeerIndex,
+        _data: Bytes,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+    fn quick_send_message_to(&self, _peer_index: PeerIndex, _data: Bytes) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn async_filter_broadcast(
+        &self,
+        _target: TargetSession,
+        _data: Bytes,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn async_disconnect(&self, _peer_index: PeerIndex, _message: &str) -> Result<(), Error> {
+        Ok(())
+    }
+    fn quick_filter_broadcast(&self, _target: TargetSession, _data: Bytes) -> Result<(), Error> {
+        Ok(())
+    }
+    fn future_task(
+        &self,
+        _task: Pin<Box<dyn Future<Output = ()> + 'static + Send>>,
+        _blocking: bool,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+    fn send_message(
+        &self,
+        _proto_id: ProtocolId,
+        _peer_index: PeerIndex,
+        _data: Bytes,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+    fn send_message_to(&self, _peer_index: PeerIndex, _data: Bytes) -> Result<(), Error> {
+        Ok(())
+    }
+    fn filter_broadcast(&self, _target: TargetSession, _data: Bytes) -> Result<(), Error> {
+        Ok(())
+    }
+    fn disconnect(&self, _peer_index: PeerIndex, _message: &str) -> Result<(), Error> {
+        Ok(())
+    }
+    fn get_peer(&self, _peer_index: PeerIndex) -> Option<Peer> {
+        None
+    }
+    fn with_peer_mut(&self, _peer_index: PeerIndex, _f: Box<dyn FnOnce(&mut Peer)>) {}
+    fn connected_peers(&self) -> Vec<PeerIndex> {
+        Vec::new()
+    }
+    fn report_peer(&self, _peer_index: PeerIndex, _behaviour: Behaviour) {}
+    fn ban_peer(&self, _peer_index: PeerIndex, _duration: Duration, _reason: String) {}
+    fn protocol_id(&self) -> ProtocolId {
+        self.protocol.protocol_id()
+    }
+}
+
+/// The peers which send the messages.
+#[derive(Clone, Copy, Debug)]
+pub enum Sender {
+    /// A peer which is just connected.
+    Connected,
+    /// A peer which is proved at the genesis block, and is waiting for block samples and proofs.
+    Proved,
+}
+
+/// The message processors of all protocols which receive untrusted data from a peer.
+///
+/// Each method processes a whole protocol message as it is received from the network, and
+/// panics if the result is neither OK nor a ban of the peer.
+pub struct Harness {
+    connected_peer: PeerIndex,
+    proved_peer: PeerIndex,
+    peers: Arc<Peers>,
+    light_client: LightClientProtocol,
+    filter: FilterProtocol,
+    sync: SyncProtocol,
+    script: packed::Script,
+    tip_header: packed::VerifiableHeader,
+    last_state: LastState,
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Harness {
+    pub fn new() -> Self {
+        let consensus = ConsensusBuilder::default().build();
+        let genesis = consensus.genesis_block();
+        let storage = Storage::with_store(MemoryStore::new()).unwrap();
+        storage.init_genesis_block(genesis.data());
+        let script = packed::Script::new_builder()
+            .code_hash(H256::default().pack())
+            .hash_type(ScriptHashType::Type.into())
+            .build();
+        storage.update_filter_scripts(vec![(script.clone(), 0)].into_iter().collect());
+
+        let connected_peer = PeerIndex::new(1);
+        let proved_peer = PeerIndex::new(2);
+        let tip_header = packed::VerifiableHeader::new_builder()
+            .header(genesis.header().data())
+            .build();
+        let last_state = LastState::new(
+            VerifiableHeader::new(genesis.header(), Default::default(), None),
+            compact_to_difficulty(genesis.compact_target()),
+        );
+        let request = ProveRequest::new(last_state.clone(), Default::default());
+        let prove_state =
+            ProveState::new_from_request(request.clone(), Vec::new(), vec![genesis.header()]);
+        let peers = Arc::new(Peers::default());
+        peers.add_peer(connected_peer);
+        peers.add_peer(proved_peer);
+        peers.update_last_state(proved_peer, last_state.clone());
+        peers.commit_prove_state(proved_peer, prove_state);
+        peers.submit_prove_request(proved_peer, request);
+
+        Self {
+            connected_peer,
+            proved_peer,
+            light_client: LightClientProtocol::new(
+                storage.clone(),
+                Arc::clone(&peers),
+                consensus,
+                Metrics::new(),
+            ),
+            filter: FilterProtocol::new(storage.clone(), Arc::clone(&peers), Metrics::new()),
+            sync: SyncProtocol::new(
+                storage,
+                Arc::new(RwLock::new(PendingTxs::new(64))),
+                Metrics::new(),
+            ),
+            peers,
+            script,
+            tip_header,
+            last_state,
+        }
+    }
+
+    /// The tip header which the proved peer is proved at.
+    pub fn tip_header(&self) -> packed::VerifiableHeader {
+        self.tip_header.clone()
+    }
+
+    /// The script which is filtered.
+    pub fn script(&self) -> packed::Script {
+        self.script.clone()
+    }
+
+    /// The number of the last filtered block, the next block filters should start after it.
+    pub fn filtered_block_number(&self) -> BlockNumber {
+        self.filter.pending_peer.min_filtered_block_number()
+    }
+
+    /// Returns a block filter which matches the filtered script.
+    pub fn matched_filter(&self) -> Bytes {
+        let mut writer = std::io::Cursor::new(Vec::new());
+        let mut filter = GCSFilterWriter::new(&mut writer, SipHasher24Builder::new(0, 0), M, P);
+        filter.add_element(self.script.calc_script_hash().as_slice());
+        filter
+            .finish()
+            .expect("flush to memory writer should be OK");
+        Bytes::from(writer.into_inner())
+    }
+
+    /// Waits for the block samples of the request, which are proved by the seeded tip header.
+    pub fn expect_block_samples(&self, request: packed::GetBlockSamples) {
+        let request = ProveRequest::new(self.last_state.clone(), request);
+        self.peers.submit_prove_request(self.proved_peer, request);
+    }
+
+    /// Waits for the block proof, so the message passes the check of the request.
+    pub fn expect_block_proof(&self, message: &packed::SendBlockProof, fetch_tip: bool) {
+        let block_hashes: Vec<packed::Byte32> = message
+            .headers()
+            .into_iter()
+            .map(|header| header.calc_header_hash())
+            .collect();
+        let request = packed::GetBlockProof::new_builder()
+            .block_hashes(block_hashes.pack())
+            .tip_hash(message.tip_header().header().calc_header_hash())
+            .build();
+        self.peers
+            .insert_block_proof_request(self.proved_peer, request, fetch_tip);
+    }
+
+    pub fn light_client_message(&mut self, sender: Sender, data: &[u8]) {
+        let peer = self.peer(sender);
+        let nc = FuzzContext {
+            protocol: SupportProtocols::LightClient,
+        };
+        let status = match packed::LightClientMessageReader::from_slice(data) {
+            Ok(message) => self.light_client.try_process(&nc, peer, message.to_enum()),
+            Err(_) => StatusCode::MalformedProtocolMessage.into(),
+        };
+        check(status);
+    }
+
+    pub fn filter_message(&mut self, sender: Sender, data: &[u8]) {
+        let peer = self.peer(sender);
+        let nc = Arc::new(FuzzContext {
+            protocol: SupportProtocols::Filter,
+        });
+        let status = match packed::BlockFilterMessageReader::from_slice(data) {
+            Ok(message) => self.filter.try_process(nc, peer, message.to_enum()),
+            Err(_) => StatusCode::MalformedProtocolMessage.into(),
+        };
+        check(status);
+    }
+
+    pub fn sync_message(&mut self, sender: Sender, data: &[u8]) {
+        let peer = self.peer(sender);
+        let nc = FuzzContext {
+            protocol: SupportProtocols::Sync,
+        };
+        let status = match packed::SyncMessageReader::from_compatible_slice(data) {
+            Ok(message) => self.sync.try_process(&nc, peer, message.to_enum()),
+            Err(_) => StatusCode::MalformedProtocolMessage.into(),
+        };
+        check(status);
+    }
+
+    fn peer(&self, sender: Sender) -> PeerIndex {
+        match sender {
+            Sender::Connected => self.connected_peer,
+            Sender::Proved => self.proved_peer,
+        }
+    }
+}
+
+// Untrusted data should never cause a local error.
+fn check(status: Status) {
+    assert!(
+        status.is_ok() || status.should_ban().is_some(),
+        "unexpected result {}",
+        status
+    );
+}
@@ -0,0 +1,50 @@
+//! A CKB light client based on FlyClient.
+//!
+//! Besides the `ckb-light-client` binary, the light client could be embedded into other
+//! services through [`LightClientBuilder`].
+
+#[cfg(test)]
+#[macro_use]
+mod tests;
+
+mod address;
+mod client;
+mod config;
+mod error;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+mod metrics;
+mod protocols;
+mod service;
+mod storage;
+mod subcmds;
+mod system_scripts;
+mod types;
+mod utils;
+mod verify;
+mod wallet;
+
+pub use ckb_app_config::NetworkConfig;
+pub use ckb_async_runtime::Handle;
+pub use ckb_chain_spec::ChainSpec;
+
+pub use address::{Address, NetworkType};
+pub use client::{load_chain_spec, LightClient, LightClientBuilder};
+pub use error::{Error, Result};
+pub use service::{
+    BlockchainInfo, Cell, CellType, DaoCell, DaoPhase, Order, Pagination, ScriptStatus, ScriptType,
+    SearchKey, SearchKeyFilter, SendTransactionResult, SigningMessage, SystemScriptInfo,
+    TransactionStatus, TransactionWithHeader, TransferTransaction, Tx, TxStatus, TxWithCell,
+    TxWithCells, WatchWalletStatus,
+};
+pub use storage::{
+    BatchOperation, CompressionType, Direction, KVIter, KVPair, KeyValueSnapshot, KeyValueStore,
+    MemoryStore, PruneReport, RetentionPolicy, RocksdbOptions, RocksdbStore, WriteBatch,
+};
+
+/// Loads the config from the command line arguments and executes the subcommand.
+#[doc(hidden)]
+pub fn run_app() -> Result<()> {
+    config::AppConfig::load()?.execute()
+}
@@ -1,25 +1,9 @@
-#[cfg(test)]
-#[macro_use]
-mod tests;
-
-mod config;
-mod error;
-mod protocols;
-mod service;
-mod storage;
-mod subcmds;
-mod types;
-mod utils;
-mod verify;
-
-use config::AppConfig;
-
 fn main() -> anyhow::Result<()> {
     env_logger::init();
 
     log::info!("Starting ...");
 
-    AppConfig::load()?.execute()?;
+    ckb_light_client::run_app()?;
 
     log::info!("Done.");
 
@@ -0,0 +1,321 @@
+//! Metrics of the light client, exported in the Prometheus text format.
+//!
+//! Counters and histograms are updated where the events happen, gauges which
+//! could be read from the states directly are refreshed when the metrics are
+//! scraped.
+
+use std::{
+    convert::Infallible,
+    fs,
+    net::{SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use ckb_async_runtime::Handle;
+use ckb_types::{prelude::*, U256};
+use jsonrpc_http_server::hyper::{
+    header, server::conn::Http, service::service_fn, Body, Method, Request, Response, StatusCode,
+};
+use jsonrpc_server_utils::tokio;
+use prometheus::{
+    Encoder as _, Gauge, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::{
+    error::{Error, Result},
+    protocols::{Peers, PendingTxs, Status},
+    storage::Storage,
+};
+
+/// The metrics of one light client, the protocols update them and `MetricsService` exports
+/// them, the clones share the same registry.
+#[derive(Clone)]
+pub(crate) struct Metrics {
+    registry: Registry,
+
+    pub(crate) proved_tip_number: IntGauge,
+    pub(crate) filtered_block_number: IntGaugeVec,
+    pub(crate) filter_matched_blocks: IntCounter,
+    pub(crate) filter_false_positive_blocks: IntCounter,
+    pub(crate) filter_false_positive_rate: Gauge,
+    pub(crate) protocol_status: IntCounterVec,
+    pub(crate) peer_bans: IntCounterVec,
+    pub(crate) get_block_proof_duration: Histogram,
+    pub(crate) connected_peers: IntGauge,
+    pub(crate) proved_peers: IntGauge,
+    pub(crate) pending_txs: IntGauge,
+    pub(crate) db_size: IntGauge,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Self {
+        let registry = Registry::new_custom(Some("ckb_light_client".to_owned()), None)
+            .expect("create registry should be ok");
+
+        let proved_tip_number = IntGauge::new(
+            "proved_tip_number",
+            "The block number of the proved tip header.",
+        )
+        .expect("create metric should be ok");
+        let filtered_block_number = IntGaugeVec::new(
+            Opts::new(
+                "filtered_block_number",
+                "The block number which the script has been filtered to.",
+            ),
+            &["script_hash"],
+        )
+        .expect("create metric should be ok");
+        let filter_matched_blocks = IntCounter::new(
+            "filter_matched_blocks_total",
+            "The count of the blocks which are matched by the block filters.",
+        )
+        .expect("create metric should be ok");
+        let filter_false_positive_blocks = IntCounter::new(
+            "filter_false_positive_blocks_total",
+            "The count of the downloaded blocks which contain no cells of the filter scripts.",
+        )
+        .expect("create metric should be ok");
+        let filter_false_positive_rate = Gauge::new(
+            "filter_false_positive_rate",
+            "The rate of the false positive blocks in the matched blocks.",
+        )
+        .expect("create metric should be ok");
+        let protocol_status = IntCounterVec::new(
+            Opts::new(
+                "protocol_status_total",
+                "The count of the statuses of the processed protocol messages.",
+            ),
+            &["protocol", "code"],
+        )
+        .expect("create metric should be ok");
+        let peer_bans = IntCounterVec::new(
+            Opts::new("peer_bans_total", "The count of the banned peers."),
+            &["protocol"],
+        )
+        .expect("create metric should be ok");
+        let get_block_proof_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "get_block_proof_duration_seconds",
+                "The duration between sending a GetBlockProof request and receiving its proof.",
+            )
+            .buckets(vec![0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0]),
+        )
+        .expect("create metric should be ok");
+        let connected_peers = IntGauge::new(
+            "connected_peers",
+            "The count of the connected light client peers.",
+        )
+        .expect("create metric should be ok");
+        let proved_peers = IntGauge::new(
+            "proved_peers",
+            "The count of the peers whose last states are proved.",
+        )
+        .expect("create metric should be ok");
+        let pending_txs = IntGauge::new(
+            "pending_txs",
+            "The count of the transactions which are waiting to be relayed.",
+        )
+        .expect("create metric should be ok");
+        let db_size = IntGauge::new("db_size_bytes", "The total size of the storage files.")
+            .expect("create metric should be ok");
+
+        for collector in [
+            Box::new(proved_tip_number.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(filtered_block_number.clone()),
+            Box::new(filter_matched_blocks.clone()),
+            Box::new(filter_false_positive_blocks.clone()),
+            Box::new(filter_false_positive_rate.clone()),
+            Box::new(protocol_status.clone()),
+            Box::new(peer_bans.clone()),
+            Box::new(get_block_proof_duration.clone()),
+            Box::new(connected_peers.clone()),
+            Box::new(proved_peers.clone()),
+            Box::new(pending_txs.clone()),
+            Box::new(db_size.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("register metric should be ok");
+        }
+
+        Self {
+            registry,
+            proved_tip_number,
+            filtered_block_number,
+            filter_matched_blocks,
+            filter_false_positive_blocks,
+            filter_false_positive_rate,
+            protocol_status,
+            peer_bans,
+            get_block_proof_duration,
+            connected_peers,
+            proved_peers,
+            pending_txs,
+            db_size,
+        }
+    }
+
+    pub(crate) fn observe_status(&self, protocol: &str, status: &Status) {
+        self.protocol_status
+            .with_label_values(&[protocol, &(status.code() as u16).to_string()])
+            .inc();
+    }
+
+    pub(crate) fn observe_ban(&self, protocol: &str) {
+        self.peer_bans.with_label_values(&[protocol]).inc();
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("encode metrics should be ok");
+        buffer
+    }
+}
+
+/// Serves the metrics on a HTTP endpoint which is separate from the JSON-RPC one.
+pub(crate) struct MetricsService {
+    metrics: Metrics,
+    storage: Storage,
+    store_path: PathBuf,
+    peers: Arc<Peers>,
+    pending_txs: Arc<RwLock<PendingTxs>>,
+}
+
+impl MetricsService {
+    pub(crate) fn new(
+        metrics: Metrics,
+        storage: Storage,
+        store_path: PathBuf,
+        peers: Arc<Peers>,
+        pending_txs: Arc<RwLock<PendingTxs>>,
+    ) -> Self {
+        Self {
+            metrics,
+            storage,
+            store_path,
+            peers,
+            pending_txs,
+        }
+    }
+
+    /// Returns the bound address, which has the actual port when the port 0 is listened on.
+    ///
+    /// The connections are served concurrently on the runtime, a slow one doesn't block the
+    /// others.
+    pub(crate) fn start(self, listen_address: &str, handle: &Handle) -> Result<SocketAddr> {
+        let bind_error = |err| {
+            let errmsg = format!(
+                "failed to bind metrics service to {} since {}",
+                listen_address, err
+            );
+            Error::runtime(errmsg)
+        };
+        let listener = TcpListener::bind(listen_address).map_err(bind_error)?;
+        let local_address = listener.local_addr().map_err(bind_error)?;
+        listener.set_nonblocking(true).map_err(bind_error)?;
+        log::info!("Metrics service listening on {}", local_address);
+        let service = Arc::new(self);
+        handle.spawn(async move {
+            let listener = match tokio::net::TcpListener::from_std(listener) {
+                Ok(listener) => listener,
+                Err(err) => {
+                    log::error!("metrics service failed to listen since {}", err);
+                    return;
+                }
+            };
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _address)) => stream,
+                    Err(err) => {
+                        log::warn!("metrics service failed to accept since {}", err);
+                        continue;
+                    }
+                };
+                let service = Arc::clone(&service);
+                tokio::spawn(async move {
+                    let http_service = service_fn(move |request| {
+                        let response = service.respond(&request);
+                        async move { Ok::<_, Infallible>(response) }
+                    });
+                    if let Err(err) = Http::new().serve_connection(stream, http_service).await {
+                        log::debug!("metrics service failed to respond since {}", err);
+                    }
+                });
+            }
+        });
+        Ok(local_address)
+    }
+
+    fn respond(&self, request: &Request<Body>) -> Response<Body> {
+        if request.method() == Method::GET && request.uri().path() == "/metrics" {
+            self.refresh();
+            Response::builder()
+                .header(header::CONTENT_TYPE, TextEncoder::new().format_type())
+                .body(Body::from(self.metrics.encode()))
+                .expect("build response should be ok")
+        } else {
+            Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .expect("build response should be ok")
+        }
+    }
+
+    fn refresh(&self) {
+        let metrics = &self.metrics;
+
+        let (_, tip_header): (U256, _) = self.storage.get_last_state();
+        let tip_number: u64 = tip_header.raw().number().unpack();
+        metrics.proved_tip_number.set(tip_number as i64);
+
+        metrics.filtered_block_number.reset();
+        for (script, block_number) in self.storage.get_filter_scripts() {
+            metrics
+                .filtered_block_number
+                .with_label_values(&[&format!("{:#x}", script.calc_script_hash())])
+                .set(block_number as i64);
+        }
+
+        let matched = metrics.filter_matched_blocks.get();
+        if matched > 0 {
+            let false_positive = metrics.filter_false_positive_blocks.get();
+            metrics
+                .filter_false_positive_rate
+                .set(false_positive as f64 / matched as f64);
+        }
+
+        metrics
+            .connected_peers
+            .set(self.peers.get_peers_index().len() as i64);
+        metrics
+            .proved_peers
+            .set(self.peers.get_peers_which_are_proved().len() as i64);
+        metrics.pending_txs.set(
+            self.pending_txs
+                .read()
+                .expect("read access should be OK")
+                .len() as i64,
+        );
+        metrics.db_size.set(dir_size(&self.store_path) as i64);
+    }
+}
+
+// the total size of all files in the directory, errors are ignored
+fn dir_size(path: &Path) -> u64 {
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| match entry.metadata() {
+                    Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+                    Ok(metadata) => metadata.len(),
+                    Err(_) => 0,
+                })
+                .sum()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,32 @@
+use once_cell::sync::Lazy;
+use std::sync::Arc;
+use std::time::Instant;
+
+static START: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// The clock of the request timers of the protocols, in milliseconds.
+///
+/// It's monotonic by default, so the timers are not stalled when the system time steps
+/// backwards, the tests replace it with a virtual one.
+#[derive(Clone)]
+pub(crate) struct Clock(Arc<dyn Fn() -> u64 + Send + Sync>);
+
+impl Clock {
+    pub(crate) fn new<F>(now: F) -> Self
+    where
+        F: Fn() -> u64 + Send + Sync + 'static,
+    {
+        Self(Arc::new(now))
+    }
+
+    pub(crate) fn now(&self) -> u64 {
+        (self.0)()
+    }
+}
+
+impl Default for Clock {
+    // all default clocks share the same start, so their timestamps are comparable
+    fn default() -> Self {
+        Self::new(|| START.elapsed().as_millis() as u64)
+    }
+}
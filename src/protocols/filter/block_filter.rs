@@ -1,5 +1,6 @@
 use super::{components, BAD_MESSAGE_BAN_TIME};
-use crate::protocols::{Peers, Status, StatusCode};
+use crate::metrics::Metrics;
+use crate::protocols::{Clock, Peers, Status, StatusCode};
 use crate::storage::Storage;
 use ckb_network::{async_trait, bytes::Bytes, CKBProtocolContext, CKBProtocolHandler, PeerIndex};
 use ckb_types::{core::BlockNumber, packed, prelude::*};
@@ -7,7 +8,6 @@ use golomb_coded_set::{GCSFilterReader, SipHasher24Builder, M, P};
 use log::{debug, error, info, trace, warn};
 use std::io::Cursor;
 use std::sync::RwLock;
-use std::time::Instant;
 use std::{sync::Arc, time::Duration};
 
 pub(crate) const GET_BLOCK_FILTERS_TOKEN: u64 = 0;
@@ -15,7 +15,10 @@ const GET_BLOCK_FILTERS_TIMEOUT: Duration = Duration::from_secs(15);
 
 pub struct PendingGetBlockFiltersPeer {
     pub(crate) storage: Storage,
-    pub(crate) last_ask_time: Arc<RwLock<Option<Instant>>>,
+    // the time of the last GetBlockFilters request
+    pub(crate) last_ask_time: Arc<RwLock<Option<u64>>>,
+    pub(crate) clock: Clock,
+    pub(crate) metrics: Metrics,
 }
 
 impl PendingGetBlockFiltersPeer {
@@ -45,6 +48,7 @@ impl PendingGetBlockFiltersPeer {
                         .get(index)
                         .expect("checked index");
                     info!("check_filters_data matched, block_hash: {:#x}", block_hash);
+                    self.metrics.filter_matched_blocks.inc();
                     Some(block_hash)
                 } else {
                     trace!(
@@ -59,9 +63,15 @@ impl PendingGetBlockFiltersPeer {
 
     pub fn should_ask(&self) -> bool {
         !self.storage.get_filter_scripts().is_empty()
-            && (self.last_ask_time.read().unwrap().is_none()
-                || self.last_ask_time.read().unwrap().unwrap().elapsed()
-                    > GET_BLOCK_FILTERS_TIMEOUT)
+            && self
+                .last_ask_time
+                .read()
+                .unwrap()
+                .map(|last_ask_time| {
+                    self.clock.now().saturating_sub(last_ask_time)
+                        > GET_BLOCK_FILTERS_TIMEOUT.as_millis() as u64
+                })
+                .unwrap_or(true)
     }
 
     pub fn min_filtered_block_number(&self) -> BlockNumber {
@@ -75,7 +85,10 @@ impl PendingGetBlockFiltersPeer {
 
     pub fn update_block_number(&self, block_number: BlockNumber) {
         self.storage.update_block_number(block_number);
-        self.last_ask_time.write().unwrap().replace(Instant::now());
+        self.last_ask_time
+            .write()
+            .unwrap()
+            .replace(self.clock.now());
     }
 }
 
@@ -85,19 +98,27 @@ pub struct FilterProtocol {
 }
 
 impl FilterProtocol {
-    pub fn new(storage: Storage, peers: Arc<Peers>) -> Self {
+    pub fn new(storage: Storage, peers: Arc<Peers>, metrics: Metrics) -> Self {
         Self {
             pending_peer: PendingGetBlockFiltersPeer {
                 storage,
                 last_ask_time: Arc::new(RwLock::new(None)),
+                clock: Clock::default(),
+                metrics,
             },
             peers,
         }
     }
+
+    #[cfg(test)]
+    pub(crate) fn with_clock(mut self, clock: Clock) -> Self {
+        self.pending_peer.clock = clock;
+        self
+    }
 }
 
 impl FilterProtocol {
-    fn try_process(
+    pub(crate) fn try_process(
         &self,
         nc: Arc<dyn CKBProtocolContext + Sync>,
         peer: PeerIndex,
@@ -152,12 +173,14 @@ impl CKBProtocolHandler for FilterProtocol {
                     BAD_MESSAGE_BAN_TIME,
                     String::from("send us a malformed message"),
                 );
+                self.pending_peer.metrics.observe_ban("filter");
                 return;
             }
         };
 
         let item_name = msg.item_name();
         let status = self.try_process(Arc::clone(&nc), peer, msg);
+        self.pending_peer.metrics.observe_status("filter", &status);
         trace!(
             "FilterProtocol.received peer={}, message={}",
             peer,
@@ -169,6 +192,7 @@ impl CKBProtocolHandler for FilterProtocol {
                 item_name, peer, ban_time, status
             );
             nc.ban_peer(peer, ban_time, status.to_string());
+            self.pending_peer.metrics.observe_ban("filter");
         } else if status.should_warn() {
             warn!("process {} from {}, result is {}", item_name, peer, status);
         } else if !status.is_ok() {
@@ -1,6 +1,7 @@
 use ckb_constant::sync::INIT_BLOCKS_IN_TRANSIT_PER_PEER;
 use ckb_network::{CKBProtocolContext, PeerIndex, SupportProtocols};
 use ckb_types::{packed, prelude::*, utilities::merkle_mountain_range::VerifiableHeader};
+use faketime::unix_time_as_millis;
 use log::error;
 
 use super::{
@@ -64,6 +65,12 @@ impl<'a> SendBlockProofProcess<'a> {
             );
             return StatusCode::PeerIsNotOnProcess.into();
         }
+        let (requested_at, fetch_tip) = request.expect("checked Some");
+        let elapsed = unix_time_as_millis().saturating_sub(requested_at);
+        self.protocol
+            .metrics
+            .get_block_proof_duration
+            .observe(elapsed as f64 / 1000.0);
 
         // Check PoW
         if let Err(status) = self
@@ -84,10 +91,18 @@ impl<'a> SendBlockProofProcess<'a> {
             return status;
         }
 
+        // Store the headers which are fetched for RPC, no need to get their blocks
+        let (fetched_headers, headers): (Vec<_>, Vec<_>) = headers
+            .into_iter()
+            .partition(|header| self.protocol.peers().remove_fetching_header(&header.hash()));
+        for header in fetched_headers {
+            self.protocol.storage.add_fetched_header(&header.data());
+        }
+
         // Send get blocks
         let block_hashes: Vec<packed::Byte32> = headers
             .iter()
-            .chain(if request.expect("checked Some").1 {
+            .chain(if fetch_tip {
                 Some(tip_header.header())
             } else {
                 None
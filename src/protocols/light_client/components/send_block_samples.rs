@@ -60,6 +60,11 @@ impl<'a> SendBlockSamplesProcess<'a> {
 
         let mmr_activated_epoch = self.protocol.mmr_activated_epoch();
 
+        // The last header is always in last-N headers.
+        if self.message.last_n_headers().is_empty() {
+            return StatusCode::MalformedProtocolMessage.with_context("no last-N headers");
+        }
+
         // Check if the response is match the request.
         if let Err(status) = check_if_response_is_matched(
             mmr_activated_epoch,
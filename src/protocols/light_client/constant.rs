@@ -4,3 +4,5 @@ pub const REFRESH_PEERS_TOKEN: u64 = 0;
 pub const REFRESH_PEERS_DURATION: Duration = Duration::from_secs(60);
 pub const CHECK_GET_BLOCK_PROOFS_TOKEN: u64 = 1;
 pub const CHECK_GET_BLOCK_PROOFS_DURATION: Duration = Duration::from_secs(10);
+// the max count of block hashes in a GetBlockProof message, same as the full node
+pub const GET_BLOCK_PROOF_LIMIT: usize = 1000;
@@ -6,7 +6,9 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use ckb_chain_spec::consensus::Consensus;
-use ckb_network::{async_trait, bytes::Bytes, CKBProtocolContext, CKBProtocolHandler, PeerIndex};
+use ckb_network::{
+    async_trait, bytes::Bytes, CKBProtocolContext, CKBProtocolHandler, PeerIndex, SupportProtocols,
+};
 use ckb_types::{
     core::{BlockNumber, EpochNumber, HeaderView},
     packed,
@@ -34,6 +36,7 @@ use super::{
     BAD_MESSAGE_BAN_TIME,
 };
 
+use crate::metrics::Metrics;
 use crate::protocols::LAST_N_BLOCKS;
 use crate::storage::Storage;
 
@@ -41,6 +44,7 @@ pub struct LightClientProtocol {
     storage: Storage,
     peers: Arc<Peers>,
     consensus: Consensus,
+    metrics: Metrics,
 }
 
 #[async_trait]
@@ -95,12 +99,14 @@ impl CKBProtocolHandler for LightClientProtocol {
                     BAD_MESSAGE_BAN_TIME,
                     String::from("send us a malformed message"),
                 );
+                self.metrics.observe_ban("light_client");
                 return;
             }
         };
 
         let item_name = msg.item_name();
         let status = self.try_process(nc.as_ref(), peer, msg);
+        self.metrics.observe_status("light_client", &status);
         trace!("LightClient.received peer={}, message={}", peer, item_name);
         if let Some(ban_time) = status.should_ban() {
             error!(
@@ -108,6 +114,7 @@ impl CKBProtocolHandler for LightClientProtocol {
                 item_name, peer, ban_time, status
             );
             nc.ban_peer(peer, ban_time, status.to_string());
+            self.metrics.observe_ban("light_client");
         } else if status.should_warn() {
             warn!("process {} from {}, result is {}", item_name, peer, status);
         } else if !status.is_ok() {
@@ -122,6 +129,7 @@ impl CKBProtocolHandler for LightClientProtocol {
             }
             constant::CHECK_GET_BLOCK_PROOFS_TOKEN => {
                 self.check_get_block_proof_requests(nc.as_ref());
+                self.fetch_headers(nc.as_ref());
             }
             _ => unreachable!(),
         }
@@ -129,7 +137,7 @@ impl CKBProtocolHandler for LightClientProtocol {
 }
 
 impl LightClientProtocol {
-    fn try_process(
+    pub(crate) fn try_process(
         &mut self,
         nc: &dyn CKBProtocolContext,
         peer: PeerIndex,
@@ -217,31 +225,12 @@ impl LightClientProtocol {
         if new_prove_state.get_total_difficulty() > &old_total_difficulty {
             if let Some(state) = self.peers().get_state(&peer) {
                 if let Some(old_prove_state) = state.get_prove_state() {
-                    let reorg_last_headers = new_prove_state.get_reorg_last_headers();
-                    if !reorg_last_headers.is_empty() {
-                        let last_headers: HashMap<_, _> = old_prove_state
-                            .get_last_headers()
-                            .iter()
-                            .map(|header| (header.number(), header.hash()))
-                            .collect();
-                        let fork_number =
-                            reorg_last_headers.iter().rev().find_map(|reorg_header| {
-                                let number = reorg_header.number();
-                                last_headers
-                                    .get(&number)
-                                    .map(|hash| {
-                                        if reorg_header.hash().eq(hash) {
-                                            None
-                                        } else {
-                                            Some(number)
-                                        }
-                                    })
-                                    .unwrap_or(Some(number))
-                            });
-                        if let Some(to_number) = fork_number {
-                            trace!("rollback to block#{}", to_number);
-                            self.storage.rollback_to_block(to_number);
-                        }
+                    if let Some(to_number) = reorg_rollback_number(
+                        old_prove_state.get_last_headers(),
+                        new_prove_state.get_reorg_last_headers(),
+                    ) {
+                        trace!("rollback since block#{}", to_number);
+                        self.storage.rollback_to_block(to_number);
                     }
                 }
             }
@@ -255,12 +244,42 @@ impl LightClientProtocol {
     }
 }
 
+/// Returns the first block number of the filtered blocks which are rolled back when the chain is
+/// reorganized, which is the block after the last common block of the old last headers and the
+/// reorg last headers, or the first reorg header if there is no common block in them.
+pub(crate) fn reorg_rollback_number(
+    last_headers: &[HeaderView],
+    reorg_last_headers: &[HeaderView],
+) -> Option<BlockNumber> {
+    let last_headers: HashMap<_, _> = last_headers
+        .iter()
+        .map(|header| (header.number(), header.hash()))
+        .collect();
+    reorg_last_headers
+        .iter()
+        .rev()
+        .find(|reorg_header| {
+            last_headers
+                .get(&reorg_header.number())
+                .map(|hash| reorg_header.hash().eq(hash))
+                .unwrap_or(false)
+        })
+        .map(|common_header| common_header.number() + 1)
+        .or_else(|| reorg_last_headers.first().map(|header| header.number()))
+}
+
 impl LightClientProtocol {
-    pub(crate) fn new(storage: Storage, peers: Arc<Peers>, consensus: Consensus) -> Self {
+    pub(crate) fn new(
+        storage: Storage,
+        peers: Arc<Peers>,
+        consensus: Consensus,
+        metrics: Metrics,
+    ) -> Self {
         Self {
             storage,
             peers,
             consensus,
+            metrics,
         }
     }
 
@@ -310,6 +329,46 @@ impl LightClientProtocol {
         }
     }
 
+    // requests the headers required by RPC with proofs from a proved peer
+    fn fetch_headers(&self, nc: &dyn CKBProtocolContext) {
+        let block_hashes = self.peers().get_headers_to_fetch();
+        if block_hashes.is_empty() {
+            return;
+        }
+        let (peer, prove_state) = match self.peers().get_peers_which_are_proved().pop() {
+            Some(item) => item,
+            None => return,
+        };
+        for hashes in block_hashes.chunks(constant::GET_BLOCK_PROOF_LIMIT) {
+            let can_insert = self
+                .peers()
+                .get_state(&peer)
+                .map(|state| state.can_insert_block_proof_request())
+                .unwrap_or(false);
+            if !can_insert {
+                break;
+            }
+            let content = packed::GetBlockProof::new_builder()
+                .block_hashes(hashes.to_vec().pack())
+                .tip_hash(prove_state.get_last_header().header().hash())
+                .build();
+            let message = packed::LightClientMessage::new_builder()
+                .set(content.clone())
+                .build();
+            if let Err(err) = nc.send_message(
+                SupportProtocols::LightClient.protocol_id(),
+                peer,
+                message.as_bytes(),
+            ) {
+                error!("nc.send_message LightClientMessage, error: {:?}", err);
+                break;
+            }
+            self.peers()
+                .insert_block_proof_request(peer, content, false);
+            self.peers().mark_headers_fetching(hashes);
+        }
+    }
+
     fn refresh_all_peers(&mut self, nc: &dyn CKBProtocolContext) {
         let now = faketime::unix_time_as_millis();
         let before = now - constant::REFRESH_PEERS_DURATION.as_millis() as u64;
@@ -1,4 +1,6 @@
-use crate::protocols::{GET_BLOCK_PROOF_TIMEOUT, MAX_BLOCK_RPOOF_REQUESTS};
+use crate::protocols::{
+    FETCHING_HEADER_EXPIRY, GET_BLOCK_PROOF_TIMEOUT, MAX_BLOCK_RPOOF_REQUESTS, MAX_FETCHING_HEADERS,
+};
 use ckb_network::PeerIndex;
 use ckb_types::{
     bytes::Bytes, core::HeaderView, packed, prelude::*,
@@ -16,6 +18,16 @@ pub struct Peers {
     inner: DashMap<PeerIndex, Peer>,
     // verified last N block headers
     last_headers: Arc<RwLock<Vec<HeaderView>>>,
+    // the headers which are not in any filtered block but required by RPC, e.g. the deposit
+    // headers of NervosDAO withdrawals
+    fetching_headers: DashMap<packed::Byte32, FetchingHeader>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct FetchingHeader {
+    added_at: u64,
+    // 0 means not requested from a peer yet
+    requested_at: u64,
 }
 
 #[derive(Default, Clone)]
@@ -200,6 +212,7 @@ impl Peers {
         Self {
             inner: Default::default(),
             last_headers,
+            fetching_headers: Default::default(),
         }
     }
 
@@ -315,4 +328,57 @@ impl Peers {
             })
             .collect()
     }
+
+    /// Fetches the headers with proofs in the background, the headers which are not fetched in
+    /// `FETCHING_HEADER_EXPIRY` are given up, e.g. the ones not in the chain.
+    pub(crate) fn fetch_headers(&self, block_hashes: Vec<packed::Byte32>) {
+        let now = unix_time_as_millis();
+        self.remove_expired_fetching_headers(now);
+        for block_hash in block_hashes {
+            if self.fetching_headers.len() >= MAX_FETCHING_HEADERS {
+                log::debug!("too many headers are being fetched, skip the rest");
+                break;
+            }
+            self.fetching_headers
+                .entry(block_hash)
+                .or_insert(FetchingHeader {
+                    added_at: now,
+                    requested_at: 0,
+                });
+        }
+    }
+
+    pub(crate) fn is_fetching_header(&self, block_hash: &packed::Byte32) -> bool {
+        self.fetching_headers.contains_key(block_hash)
+    }
+
+    // the headers which are not requested yet or whose requests are timeout
+    pub(crate) fn get_headers_to_fetch(&self) -> Vec<packed::Byte32> {
+        let now = unix_time_as_millis();
+        self.remove_expired_fetching_headers(now);
+        self.fetching_headers
+            .iter()
+            .filter(|item| now.saturating_sub(item.value().requested_at) > GET_BLOCK_PROOF_TIMEOUT)
+            .map(|item| item.key().clone())
+            .collect()
+    }
+
+    pub(crate) fn mark_headers_fetching(&self, block_hashes: &[packed::Byte32]) {
+        let now = unix_time_as_millis();
+        for block_hash in block_hashes {
+            if let Some(mut header) = self.fetching_headers.get_mut(block_hash) {
+                header.requested_at = now;
+            }
+        }
+    }
+
+    fn remove_expired_fetching_headers(&self, now: u64) {
+        self.fetching_headers
+            .retain(|_, header| now.saturating_sub(header.added_at) <= FETCHING_HEADER_EXPIRY);
+    }
+
+    // returns true if the header is fetched for RPC
+    pub(crate) fn remove_fetching_header(&self, block_hash: &packed::Byte32) -> bool {
+        self.fetching_headers.remove(block_hash).is_some()
+    }
 }
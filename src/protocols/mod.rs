@@ -2,6 +2,7 @@ use std::time::Duration;
 
 use ckb_types::core::BlockNumber;
 
+mod clock;
 mod filter;
 mod light_client;
 mod relayer;
@@ -11,11 +12,16 @@ mod synchronizer;
 #[cfg(test)]
 pub(crate) use filter::GET_BLOCK_FILTERS_TOKEN;
 #[cfg(test)]
-pub(crate) use light_client::{LastState, PeerState, ProveRequest, ProveState};
+pub(crate) use light_client::constant::{REFRESH_PEERS_DURATION, REFRESH_PEERS_TOKEN};
+#[cfg(test)]
+pub(crate) use light_client::PeerState;
+#[cfg(any(test, feature = "fuzzing"))]
+pub(crate) use light_client::{LastState, ProveRequest, ProveState};
 
+pub(crate) use clock::Clock;
 pub(crate) use filter::FilterProtocol;
-pub(crate) use light_client::{LightClientProtocol, Peers};
-pub(crate) use relayer::{PendingTxs, RelayProtocol};
+pub(crate) use light_client::{reorg_rollback_number, LightClientProtocol, Peers};
+pub(crate) use relayer::{PendingTxs, RelayProtocol, RelayStatus};
 pub(crate) use status::{Status, StatusCode};
 pub(crate) use synchronizer::SyncProtocol;
 
@@ -24,5 +30,8 @@ pub const BAD_MESSAGE_BAN_TIME: Duration = Duration::from_secs(5 * 60);
 pub const MAX_BLOCK_RPOOF_REQUESTS: usize = 64;
 // if have GetBlockProof request last more than 60 seconds, ban the peer
 pub const GET_BLOCK_PROOF_TIMEOUT: u64 = 60 * 1000;
+// the headers which are fetched for RPC, they are given up after 30 minutes
+pub const MAX_FETCHING_HEADERS: usize = 1000;
+pub const FETCHING_HEADER_EXPIRY: u64 = 30 * 60 * 1000;
 
 pub const LAST_N_BLOCKS: BlockNumber = 100;
@@ -9,54 +9,212 @@ use linked_hash_map::LinkedHashMap;
 use log::{debug, trace, warn};
 use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, RwLock};
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
-use crate::protocols::{Peers, BAD_MESSAGE_BAN_TIME};
+use crate::metrics::Metrics;
+use crate::protocols::{Clock, Peers, BAD_MESSAGE_BAN_TIME};
 
 const CHECK_PENDING_TXS_TOKEN: u64 = 0;
+// the first retry interval of the transactions which are not accepted by any peer
+const RELAY_RETRY_BASE_INTERVAL: Duration = Duration::from_secs(30);
+const MAX_RELAY_RETRIES: u32 = 5;
+// the accepted transactions which are not committed in time are dropped, it's the default expiry
+// of the tx-pool of the full nodes
+const ACCEPTED_TX_EXPIRY: Duration = Duration::from_secs(12 * 60 * 60);
 
 pub(crate) struct RelayProtocol {
     connected_peers: Arc<Peers>,
     // Record the peers which have opened the relay protocol, value is used to close the protocol in the inactive period
-    opened_peers: HashMap<PeerIndex, Option<Instant>>,
+    opened_peers: HashMap<PeerIndex, Option<u64>>,
     // Pending transactions which are waiting for relay
     pending_txs: Arc<RwLock<PendingTxs>>,
+    clock: Clock,
+    metrics: Metrics,
 }
 
 // a simple struct to store the pending transactions in memory with size limit
 pub(crate) struct PendingTxs {
-    txs: LinkedHashMap<packed::Byte32, (packed::Transaction, Cycle, HashSet<PeerId>)>,
-    updated_at: Instant,
+    txs: LinkedHashMap<packed::Byte32, PendingTx>,
+    // the transactions which are not accepted by any peer after all retries
+    rejected: LinkedHashMap<packed::Byte32, ()>,
+    updated_at: u64,
     limit: usize,
+    // the relay protocol shares the clock of the pending transactions
+    clock: Clock,
+}
+
+struct PendingTx {
+    tx: packed::Transaction,
+    cycles: Cycle,
+    // the peers which the transaction hash is announced to
+    announced_peers: HashSet<PeerId>,
+    // the peers which fetched the transaction by `GetRelayTransactions`
+    sent_peers: HashSet<PeerId>,
+    // the peers which announced the transaction hash to us, full nodes only announce
+    // the transactions which are accepted by their tx-pool
+    accepted_peers: HashSet<PeerId>,
+    retries: u32,
+    pushed_at: u64,
+    retry_at: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RelayStatus {
+    /// The transaction is not fetched by any peer yet.
+    Pending,
+    /// The transaction is fetched by some peers, but no peer has accepted it yet.
+    Relayed,
+    /// The transaction is accepted by the tx-pool of some peers.
+    Accepted,
+    /// The transaction is not accepted by any peer after all retries and is dropped.
+    Rejected,
+}
+
+pub(crate) struct RelayState {
+    pub(crate) status: RelayStatus,
+    pub(crate) sent_peers: Vec<PeerId>,
+    pub(crate) accepted_peers: Vec<PeerId>,
+    pub(crate) retries: u32,
+}
+
+impl PendingTx {
+    fn new(tx: packed::Transaction, cycles: Cycle, now: u64) -> Self {
+        Self {
+            tx,
+            cycles,
+            announced_peers: HashSet::new(),
+            sent_peers: HashSet::new(),
+            accepted_peers: HashSet::new(),
+            retries: 0,
+            pushed_at: now,
+            retry_at: now + RELAY_RETRY_BASE_INTERVAL.as_millis() as u64,
+        }
+    }
+
+    fn status(&self) -> RelayStatus {
+        if !self.accepted_peers.is_empty() {
+            RelayStatus::Accepted
+        } else if !self.sent_peers.is_empty() {
+            RelayStatus::Relayed
+        } else {
+            RelayStatus::Pending
+        }
+    }
 }
 
 impl PendingTxs {
     pub fn new(limit: usize) -> Self {
+        let clock = Clock::default();
         Self {
             txs: LinkedHashMap::new(),
-            updated_at: Instant::now(),
+            rejected: LinkedHashMap::new(),
+            updated_at: clock.now(),
             limit,
+            clock,
         }
     }
 
+    #[cfg(test)]
+    pub(crate) fn with_clock(mut self, clock: Clock) -> Self {
+        self.updated_at = clock.now();
+        self.clock = clock;
+        self
+    }
+
     pub fn push(&mut self, tx: TransactionView, cycles: Cycle) {
+        let now = self.clock.now();
+        self.rejected.remove(&tx.hash());
         self.txs
-            .insert(tx.hash(), (tx.data(), cycles, HashSet::new()));
+            .insert(tx.hash(), PendingTx::new(tx.data(), cycles, now));
         if self.txs.len() > self.limit {
             self.txs.pop_front();
         }
-        self.updated_at = Instant::now();
+        self.updated_at = now;
+    }
+
+    pub fn len(&self) -> usize {
+        self.txs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty()
+    }
+
+    fn get(&self, hash: packed::Byte32) -> Option<(packed::Transaction, Cycle)> {
+        self.txs
+            .get(&hash)
+            .map(|pending_tx| (pending_tx.tx.clone(), pending_tx.cycles))
+    }
+
+    // remove the transaction when it's committed, the cells consumed by it will be released
+    pub fn remove(&mut self, hash: &packed::Byte32) -> Option<packed::Transaction> {
+        self.txs.remove(hash).map(|pending_tx| pending_tx.tx)
+    }
+
+    // returns the out points which are consumed by pending transactions
+    #[allow(clippy::mutable_key_type)]
+    pub fn spent_out_points(&self) -> HashSet<packed::OutPoint> {
+        self.txs
+            .values()
+            .flat_map(|pending_tx| {
+                pending_tx
+                    .tx
+                    .raw()
+                    .inputs()
+                    .into_iter()
+                    .map(|input| input.previous_output())
+            })
+            .collect()
+    }
+
+    // returns the out points, outputs and outputs data which are created by pending transactions
+    pub fn outputs(&self) -> Vec<(packed::OutPoint, packed::CellOutput, packed::Bytes)> {
+        self.txs
+            .iter()
+            .flat_map(|(tx_hash, pending_tx)| {
+                pending_tx
+                    .tx
+                    .raw()
+                    .outputs()
+                    .into_iter()
+                    .zip(pending_tx.tx.raw().outputs_data().into_iter())
+                    .enumerate()
+                    .map(move |(index, (output, output_data))| {
+                        (
+                            packed::OutPoint::new(tx_hash.clone(), index as u32),
+                            output,
+                            output_data,
+                        )
+                    })
+            })
+            .collect()
     }
 
-    fn get(&self, hash: packed::Byte32) -> Option<(packed::Transaction, Cycle, HashSet<PeerId>)> {
-        self.txs.get(&hash).cloned()
+    pub fn get_relay_state(&self, hash: &packed::Byte32) -> Option<RelayState> {
+        if let Some(pending_tx) = self.txs.get(hash) {
+            Some(RelayState {
+                status: pending_tx.status(),
+                sent_peers: pending_tx.sent_peers.iter().cloned().collect(),
+                accepted_peers: pending_tx.accepted_peers.iter().cloned().collect(),
+                retries: pending_tx.retries,
+            })
+        } else if self.rejected.contains_key(hash) {
+            Some(RelayState {
+                status: RelayStatus::Rejected,
+                sent_peers: Vec::new(),
+                accepted_peers: Vec::new(),
+                retries: MAX_RELAY_RETRIES,
+            })
+        } else {
+            None
+        }
     }
 
     fn fetch_transaction_hashes_for_broadcast(&mut self, peer_id: PeerId) -> Vec<packed::Byte32> {
         self.txs
             .iter_mut()
-            .filter_map(|(hash, (_, _, peers))| {
-                if peers.insert(peer_id.clone()) {
+            .filter_map(|(hash, pending_tx)| {
+                if pending_tx.announced_peers.insert(peer_id.clone()) {
                     Some(hash.clone())
                 } else {
                     None
@@ -65,17 +223,109 @@ impl PendingTxs {
             .collect()
     }
 
+    // record that the transaction is fetched by the peer
+    pub fn mark_as_sent(&mut self, hash: &packed::Byte32, peer_id: PeerId) {
+        if let Some(pending_tx) = self.txs.get_mut(hash) {
+            pending_tx.sent_peers.insert(peer_id);
+        }
+    }
+
+    // record that the transaction hash is announced by the peer, returns false if it's not a
+    // pending transaction
+    pub fn mark_as_accepted(&mut self, hash: &packed::Byte32, peer_id: PeerId) -> bool {
+        if let Some(pending_tx) = self.txs.get_mut(hash) {
+            pending_tx.accepted_peers.insert(peer_id);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Retry the transactions which are not accepted by any peer before the deadline with
+    // exponential backoff, the peers which fetched the transaction but didn't accept it are
+    // not asked again. The transactions which exceed the max retries are dropped.
+    //
+    // Returns the number of the retried transactions.
+    pub fn retry_unaccepted_txs(&mut self, now: u64) -> usize {
+        let mut retried = 0;
+        let mut rejected = Vec::new();
+        for (hash, pending_tx) in self.txs.iter_mut() {
+            if !pending_tx.accepted_peers.is_empty() || pending_tx.retry_at > now {
+                continue;
+            }
+            if pending_tx.retries >= MAX_RELAY_RETRIES {
+                rejected.push(hash.clone());
+                continue;
+            }
+            pending_tx.retries += 1;
+            pending_tx.retry_at =
+                now + (RELAY_RETRY_BASE_INTERVAL * 2u32.pow(pending_tx.retries)).as_millis() as u64;
+            let sent_peers = &pending_tx.sent_peers;
+            pending_tx
+                .announced_peers
+                .retain(|peer_id| sent_peers.contains(peer_id));
+            retried += 1;
+        }
+        for hash in rejected {
+            debug!(
+                "transaction {:#x} is not accepted by any peer after {} retries, drop it",
+                hash, MAX_RELAY_RETRIES
+            );
+            self.txs.remove(&hash);
+            self.rejected.insert(hash, ());
+            if self.rejected.len() > self.limit {
+                self.rejected.pop_front();
+            }
+        }
+        if retried > 0 {
+            self.updated_at = now;
+        }
+        retried
+    }
+
+    // Drop the accepted transactions which are not committed before the expiry, they are
+    // probably evicted from the tx-pool of the peers, the cells consumed by them are released.
+    //
+    // Returns the number of the dropped transactions.
+    pub fn expire_accepted_txs(&mut self, now: u64) -> usize {
+        let expired: Vec<_> = self
+            .txs
+            .iter()
+            .filter(|(_hash, pending_tx)| {
+                !pending_tx.accepted_peers.is_empty()
+                    && now.saturating_sub(pending_tx.pushed_at)
+                        > ACCEPTED_TX_EXPIRY.as_millis() as u64
+            })
+            .map(|(hash, _pending_tx)| hash.clone())
+            .collect();
+        for hash in &expired {
+            debug!(
+                "transaction {:#x} is not committed in {:?} after it's accepted, drop it",
+                hash, ACCEPTED_TX_EXPIRY
+            );
+            self.txs.remove(hash);
+        }
+        expired.len()
+    }
+
     fn is_not_empty_and_updated_at(&self, seconds: u64) -> bool {
-        !self.txs.is_empty() && self.updated_at.elapsed() < Duration::from_secs(seconds)
+        !self.txs.is_empty() && self.clock.now().saturating_sub(self.updated_at) < seconds * 1000
     }
 }
 
 impl RelayProtocol {
-    pub fn new(pending_txs: Arc<RwLock<PendingTxs>>, connected_peers: Arc<Peers>) -> Self {
+    pub fn new(
+        pending_txs: Arc<RwLock<PendingTxs>>,
+        connected_peers: Arc<Peers>,
+        metrics: Metrics,
+    ) -> Self {
+        let clock = pending_txs.read().unwrap().clock.clone();
         Self {
             opened_peers: HashMap::new(),
             pending_txs,
             connected_peers,
+            clock,
+            metrics,
         }
     }
 }
@@ -126,7 +376,7 @@ impl CKBProtocolHandler for RelayProtocol {
                         peer, err
                     );
                 }
-                self.opened_peers.insert(peer, Some(Instant::now()));
+                self.opened_peers.insert(peer, Some(self.clock.now()));
             } else {
                 self.opened_peers.insert(peer, None);
             }
@@ -158,6 +408,7 @@ impl CKBProtocolHandler for RelayProtocol {
                     BAD_MESSAGE_BAN_TIME,
                     String::from("send us a malformed message"),
                 );
+                self.metrics.observe_ban("relay");
                 return;
             }
         };
@@ -167,39 +418,80 @@ impl CKBProtocolHandler for RelayProtocol {
             peer,
             message.item_name()
         );
-        if let packed::RelayMessageUnionReader::GetRelayTransactions(reader) = message {
-            let pending_txs = self.pending_txs.read().expect("read access should be OK");
-            let relay_txs: Vec<_> = reader
-                .tx_hashes()
-                .iter()
-                .filter_map(|tx_hash| {
-                    pending_txs.get(tx_hash.to_entity()).map(|(tx, cycles, _)| {
-                        packed::RelayTransaction::new_builder()
-                            .transaction(tx)
-                            .cycles(cycles.pack())
-                            .build()
+        let peer_id = nc
+            .get_peer(peer)
+            .and_then(|p| extract_peer_id(&p.connected_addr));
+        match message {
+            packed::RelayMessageUnionReader::GetRelayTransactions(reader) => {
+                let mut pending_txs = self.pending_txs.write().expect("write access should be OK");
+                let relay_txs: Vec<_> = reader
+                    .tx_hashes()
+                    .iter()
+                    .filter_map(|tx_hash| {
+                        let tx_hash = tx_hash.to_entity();
+                        pending_txs.get(tx_hash.clone()).map(|(tx, cycles)| {
+                            if let Some(peer_id) = peer_id.as_ref() {
+                                pending_txs.mark_as_sent(&tx_hash, peer_id.clone());
+                            }
+                            packed::RelayTransaction::new_builder()
+                                .transaction(tx)
+                                .cycles(cycles.pack())
+                                .build()
+                        })
                     })
-                })
-                .collect();
-
-            let content = packed::RelayTransactions::new_builder()
-                .transactions(relay_txs.pack())
-                .build();
-            let msg = packed::RelayMessage::new_builder().set(content).build();
-            if let Err(err) = nc.send_message_to(peer, msg.as_bytes()) {
-                warn!(
-                    "RelayProtocol failed to send RelayTransactions message to peer={} since {:?}",
-                    peer, err
-                );
+                    .collect();
+
+                let content = packed::RelayTransactions::new_builder()
+                    .transactions(relay_txs.pack())
+                    .build();
+                let msg = packed::RelayMessage::new_builder().set(content).build();
+                if let Err(err) = nc.send_message_to(peer, msg.as_bytes()) {
+                    warn!(
+                        "RelayProtocol failed to send RelayTransactions message to peer={} since {:?}",
+                        peer, err
+                    );
+                }
+            }
+            packed::RelayMessageUnionReader::RelayTransactionHashes(reader) => {
+                // full nodes announce the transactions which are accepted by their tx-pool,
+                // it's the only feedback of the relayed transactions since there is no reject message
+                if let Some(peer_id) = peer_id {
+                    let mut pending_txs =
+                        self.pending_txs.write().expect("write access should be OK");
+                    for tx_hash in reader.tx_hashes().iter() {
+                        let tx_hash = tx_hash.to_entity();
+                        if pending_txs.mark_as_accepted(&tx_hash, peer_id.clone()) {
+                            debug!(
+                                "RelayProtocol transaction {:#x} is accepted by peer={}",
+                                tx_hash, peer
+                            );
+                        }
+                    }
+                }
+            }
+            _ => {
+                // ignore other messages
             }
-        } else {
-            // ignore other messages
         }
     }
 
     async fn notify(&mut self, nc: Arc<dyn CKBProtocolContext + Sync>, token: u64) {
         match token {
             CHECK_PENDING_TXS_TOKEN => {
+                let now = self.clock.now();
+                self.pending_txs.write().unwrap().expire_accepted_txs(now);
+                // the transactions which are not accepted in time will be announced to the peers
+                // which haven't fetched them, so open the protocol to all connected peers
+                let retried = self.pending_txs.write().unwrap().retry_unaccepted_txs(now);
+                if retried > 0 {
+                    debug!("RelayProtocol.notify retry {} transactions", retried);
+                    let peers = self
+                        .connected_peers
+                        .get_peers_index()
+                        .into_iter()
+                        .filter(|peer| !self.opened_peers.contains_key(peer));
+                    open_relay_protocol(nc.as_ref(), peers);
+                }
                 // we check pending txs every 2 seconds, if the timestamp of the pending txs is updated in the last minute
                 // and connected relay protocol peers is empty, we try to open the protocol and broadcast the pending txs
                 if self
@@ -209,20 +501,10 @@ impl CKBProtocolHandler for RelayProtocol {
                     .is_not_empty_and_updated_at(60)
                     && self.opened_peers.is_empty()
                 {
-                    let p2p_control = nc.p2p_control().expect("p2p_control should be exist");
-                    for peer in self.connected_peers.get_peers_index() {
-                        if let Err(err) =
-                            p2p_control.open_protocol(peer, SupportProtocols::RelayV2.protocol_id())
-                        {
-                            warn!(
-                                "RelayProtocol failed to open protocol to peer={} since {:?}",
-                                peer, err
-                            );
-                        }
-                    }
+                    open_relay_protocol(nc.as_ref(), self.connected_peers.get_peers_index());
                 } else {
                     let mut pending_txs = self.pending_txs.write().unwrap();
-                    for (&peer, instant) in self.opened_peers.iter_mut() {
+                    for (&peer, opened_at) in self.opened_peers.iter_mut() {
                         if let Some(peer_id) = nc
                             .get_peer(peer)
                             .and_then(|p| extract_peer_id(&p.connected_addr))
@@ -246,19 +528,21 @@ impl CKBProtocolHandler for RelayProtocol {
                                         peer, err
                                     );
                                 }
-                                instant.replace(Instant::now());
-                            } else if instant
-                                .map(|i| i.elapsed() > Duration::from_secs(60))
+                                opened_at.replace(self.clock.now());
+                            } else if opened_at
+                                .map(|t| self.clock.now().saturating_sub(t) > 60 * 1000)
                                 .unwrap_or(true)
                             {
                                 debug!(
                                     "RelayProtocol.notify peer={} is inactive, close the protocol",
                                     peer
                                 );
-                                let _ = nc
-                                    .p2p_control()
-                                    .expect("p2p_control should be exist")
-                                    .close_protocol(peer, SupportProtocols::RelayV2.protocol_id());
+                                if let Some(p2p_control) = nc.p2p_control() {
+                                    let _ = p2p_control.close_protocol(
+                                        peer,
+                                        SupportProtocols::RelayV2.protocol_id(),
+                                    );
+                                }
                             }
                         }
                     }
@@ -270,3 +554,21 @@ impl CKBProtocolHandler for RelayProtocol {
         }
     }
 }
+
+// The p2p control is not available when the protocol is not running in a real network service.
+fn open_relay_protocol<I: IntoIterator<Item = PeerIndex>>(nc: &dyn CKBProtocolContext, peers: I) {
+    let p2p_control = if let Some(p2p_control) = nc.p2p_control() {
+        p2p_control
+    } else {
+        warn!("RelayProtocol failed to open protocol since the p2p control is unavailable");
+        return;
+    };
+    for peer in peers {
+        if let Err(err) = p2p_control.open_protocol(peer, SupportProtocols::RelayV2.protocol_id()) {
+            warn!(
+                "RelayProtocol failed to open protocol to peer={} since {:?}",
+                peer, err
+            );
+        }
+    }
+}
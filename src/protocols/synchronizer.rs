@@ -1,18 +1,55 @@
 use ckb_network::{async_trait, bytes::Bytes, CKBProtocolContext, CKBProtocolHandler, PeerIndex};
 use ckb_types::{packed, prelude::*};
-use log::{info, trace, warn};
-use std::sync::Arc;
+use log::{debug, error, info, trace, warn};
+use std::sync::{Arc, RwLock};
 
-use super::BAD_MESSAGE_BAN_TIME;
-use crate::storage::Storage;
+use super::{PendingTxs, Status, StatusCode, BAD_MESSAGE_BAN_TIME};
+use crate::{metrics::Metrics, storage::Storage};
 
 pub(crate) struct SyncProtocol {
     storage: Storage,
+    // the committed transactions are removed from the pending ones
+    pending_txs: Arc<RwLock<PendingTxs>>,
+    metrics: Metrics,
 }
 
 impl SyncProtocol {
-    pub fn new(storage: Storage) -> Self {
-        Self { storage }
+    pub fn new(storage: Storage, pending_txs: Arc<RwLock<PendingTxs>>, metrics: Metrics) -> Self {
+        Self {
+            storage,
+            pending_txs,
+            metrics,
+        }
+    }
+
+    pub(crate) fn try_process(
+        &self,
+        nc: &dyn CKBProtocolContext,
+        peer: PeerIndex,
+        message: packed::SyncMessageUnionReader<'_>,
+    ) -> Status {
+        match message {
+            packed::SyncMessageUnionReader::SendBlock(reader) => {
+                let block = reader.to_entity().block();
+                if !self.storage.filter_block(block.clone()) {
+                    self.metrics.filter_false_positive_blocks.inc();
+                }
+                let mut pending_txs = self.pending_txs.write().expect("write access should be OK");
+                for tx in block.transactions().into_iter() {
+                    pending_txs.remove(&tx.calc_tx_hash());
+                }
+                Status::ok()
+            }
+            _ => {
+                let content = packed::InIBD::new_builder().build();
+                let msg = packed::SyncMessage::new_builder().set(content).build();
+                if let Err(err) = nc.send_message_to(peer, msg.as_bytes()) {
+                    let error_message = format!("nc.send_message InIBD, error: {:?}", err);
+                    return StatusCode::Network.with_context(error_message);
+                }
+                Status::ok()
+            }
+        }
     }
 }
 
@@ -51,29 +88,26 @@ impl CKBProtocolHandler for SyncProtocol {
                     BAD_MESSAGE_BAN_TIME,
                     String::from("send us a malformed message"),
                 );
+                self.metrics.observe_ban("sync");
                 return;
             }
         };
 
-        trace!(
-            "SyncProtocol.received peer={}, message={}",
-            peer,
-            message.item_name()
-        );
-        match message {
-            packed::SyncMessageUnionReader::SendBlock(reader) => {
-                self.storage.filter_block(reader.to_entity().block());
-            }
-            _ => {
-                let content = packed::InIBD::new_builder().build();
-                let msg = packed::SyncMessage::new_builder().set(content).build();
-                if let Err(err) = nc.send_message_to(peer, msg.as_bytes()) {
-                    warn!(
-                        "SyncProtocol.received failed to send InIBD message to peer={} since {:?}",
-                        peer, err
-                    );
-                }
-            }
+        let item_name = message.item_name();
+        let status = self.try_process(nc.as_ref(), peer, message);
+        self.metrics.observe_status("sync", &status);
+        trace!("SyncProtocol.received peer={}, message={}", peer, item_name);
+        if let Some(ban_time) = status.should_ban() {
+            error!(
+                "process {} from {}, ban {:?} since result is {}",
+                item_name, peer, ban_time, status
+            );
+            nc.ban_peer(peer, ban_time, status.to_string());
+            self.metrics.observe_ban("sync");
+        } else if status.should_warn() {
+            warn!("process {} from {}, result is {}", item_name, peer, status);
+        } else if !status.is_ok() {
+            debug!("process {} from {}, result is {}", item_name, peer, status);
         }
     }
 }
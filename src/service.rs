@@ -1,30 +1,42 @@
 use ckb_chain_spec::consensus::Consensus;
+use ckb_hash::new_blake2b;
 use ckb_jsonrpc_types::{
-    BlockNumber, Capacity, CellOutput, HeaderView, JsonBytes, NodeAddress, OutPoint,
-    RemoteNodeProtocol, Script, Transaction, TransactionView, Uint32, Uint64,
+    BannedAddr, BlockNumber, Capacity, CellDep, CellOutput, EpochNumberWithFraction, EpochView,
+    HeaderView, JsonBytes, LocalNode, LocalNodeProtocol, NodeAddress, OutPoint, RemoteNodeProtocol,
+    Script, ScriptHashType, Timestamp, Transaction, TransactionView, Uint128, Uint32, Uint64,
+};
+use ckb_network::{
+    extract_peer_id,
+    multiaddr::{Multiaddr, Protocol},
+    NetworkController, PeerId,
 };
-use ckb_network::{extract_peer_id, NetworkController};
 use ckb_traits::HeaderProvider;
-use ckb_types::{core, packed, prelude::*, H256};
-use jsonrpc_core::{Error, IoHandler, Result};
+use ckb_types::{bytes::Bytes, core, packed, prelude::*, H256, U256};
+use faketime::unix_time_as_millis;
+use jsonrpc_core::{Error, ErrorCode, IoHandler, Result};
 use jsonrpc_derive::rpc;
 use jsonrpc_http_server::{Server, ServerBuilder};
 use jsonrpc_server_utils::cors::AccessControlAllowOrigin;
 use jsonrpc_server_utils::hosts::DomainsValidation;
-use rocksdb::{
-    ops::{Get, Iterate},
-    Direction, IteratorMode,
-};
 use serde::{Deserialize, Serialize};
 use std::{
+    borrow::Cow,
+    collections::HashSet,
     net::ToSocketAddrs,
+    str::FromStr,
     sync::{Arc, RwLock},
 };
 
 use crate::{
-    protocols::{Peers, PendingTxs},
-    storage::{self, extract_raw_data, Key, KeyPrefix, Storage, StorageWithLastHeaders},
+    address::{Address, NetworkType},
+    protocols::{Peers, PendingTxs, RelayStatus},
+    storage::{
+        extract_raw_data, CellValue, Direction, Key, KeyPrefix, Snapshot, Storage,
+        StorageWithLastHeaders,
+    },
+    system_scripts::{SystemScript, SystemScripts},
     verify::verify_tx,
+    wallet::{DerivationPath, ExtendedPubKey, WatchWallet},
 };
 
 #[rpc(server)]
@@ -56,12 +68,53 @@ pub trait BlockFilterRpc {
 
     #[rpc(name = "get_cells_capacity")]
     fn get_cells_capacity(&self, search_key: SearchKey) -> Result<Capacity>;
+
+    /// Returns the total amount of the sUDT / xUDT cells of the udt type script, which are
+    /// owned by the lock script of the search key.
+    #[rpc(name = "get_udt_balance")]
+    fn get_udt_balance(&self, search_key: SearchKey, udt_type_script: Script) -> Result<Uint128>;
+
+    /// Registers a watch-only wallet, the sighash lock scripts derived at `<path>/<index>` from
+    /// the extended public key are filtered from the block number, and more are derived when the
+    /// last `gap_limit` ones are used.
+    #[rpc(name = "add_watch_wallet")]
+    fn add_watch_wallet(
+        &self,
+        xpub: String,
+        path: String,
+        gap_limit: Uint32,
+        block_number: BlockNumber,
+    ) -> Result<()>;
+
+    #[rpc(name = "get_watch_wallets")]
+    fn get_watch_wallets(&self) -> Result<Vec<WatchWalletStatus>>;
+
+    /// Stops filtering the scripts of the wallet, the indexed cells and transactions are kept.
+    #[rpc(name = "remove_watch_wallet")]
+    fn remove_watch_wallet(&self, xpub: String, path: String) -> Result<()>;
 }
 
 #[rpc(server)]
 pub trait TransactionRpc {
     #[rpc(name = "send_transaction")]
-    fn send_transaction(&self, tx: Transaction) -> Result<H256>;
+    fn send_transaction(&self, tx: Transaction) -> Result<SendTransactionResult>;
+
+    #[rpc(name = "get_transaction_status")]
+    fn get_transaction_status(&self, tx_hash: H256) -> Result<TransactionStatus>;
+
+    /// Builds an unsigned transaction which transfers the capacity from the secp256k1 blake160
+    /// sighash lock scripts to the lock script, the change is returned to the first one.
+    ///
+    /// Only the live cells without type script and data which are indexed by the filter
+    /// scripts are collected, the fee rate is in shannons per KB.
+    #[rpc(name = "build_transfer")]
+    fn build_transfer(
+        &self,
+        from_scripts: Vec<Script>,
+        to: Script,
+        capacity: Capacity,
+        fee_rate: Uint64,
+    ) -> Result<TransferTransaction>;
 }
 
 #[rpc(server)]
@@ -72,22 +125,115 @@ pub trait ChainRpc {
     #[rpc(name = "get_header")]
     fn get_header(&self, block_hash: H256) -> Result<Option<HeaderView>>;
 
+    #[rpc(name = "get_header_by_number")]
+    fn get_header_by_number(&self, block_number: BlockNumber) -> Result<Option<HeaderView>>;
+
+    #[rpc(name = "get_blockchain_info")]
+    fn get_blockchain_info(&self) -> Result<BlockchainInfo>;
+
+    #[rpc(name = "get_current_epoch")]
+    fn get_current_epoch(&self) -> Result<EpochView>;
+
     #[rpc(name = "get_transaction")]
     fn get_transaction(&self, tx_hash: H256) -> Result<Option<TransactionWithHeader>>;
+
+    /// Returns the system scripts deployed in the genesis block and their cell deps.
+    #[rpc(name = "get_system_scripts")]
+    fn get_system_scripts(&self) -> Result<Vec<SystemScriptInfo>>;
+}
+
+#[rpc(server)]
+pub trait DaoRpc {
+    /// Returns the NervosDAO cells of the lock script, the missing headers which are required
+    /// to withdraw them are fetched in the background, try again later for these cells.
+    #[rpc(name = "get_dao_cells")]
+    fn get_dao_cells(&self, lock_script: Script) -> Result<Vec<DaoCell>>;
 }
 
 #[rpc(server)]
 pub trait NetRpc {
+    #[rpc(name = "local_node_info")]
+    fn local_node_info(&self) -> Result<LocalNode>;
+
     #[rpc(name = "get_peers")]
     fn get_peers(&self) -> Result<Vec<RemoteNode>>;
+
+    #[rpc(name = "add_node")]
+    fn add_node(&self, peer_id: String, address: String) -> Result<()>;
+
+    #[rpc(name = "remove_node")]
+    fn remove_node(&self, peer_id: String) -> Result<()>;
+
+    /// curl http://localhost:9000/ -X POST -H "Content-Type: application/json" -d '{"jsonrpc": "2.0", "method":"set_ban", "params": ["192.168.0.2", "insert", "0x1ac89236180", true, "set_ban example"], "id": 1}'
+    #[rpc(name = "set_ban")]
+    fn set_ban(
+        &self,
+        address: String,
+        command: String,
+        ban_time: Option<Timestamp>,
+        absolute: Option<bool>,
+        reason: Option<String>,
+    ) -> Result<()>;
+
+    #[rpc(name = "get_banned_addresses")]
+    fn get_banned_addresses(&self) -> Result<Vec<BannedAddr>>;
+
+    #[rpc(name = "clear_banned_addresses")]
+    fn clear_banned_addresses(&self) -> Result<()>;
+}
+
+/// The same methods as ckb-indexer, which replace the ones of `BlockFilterRpc` when the
+/// indexer compatible mode is enabled.
+#[rpc(server)]
+pub trait IndexerRpc {
+    /// Returns the block which all filter scripts are filtered to, null when there is no filter
+    /// script or its header is not stored.
+    #[rpc(name = "get_indexer_tip")]
+    fn get_indexer_tip(&self) -> Result<Option<IndexerTip>>;
+
+    #[rpc(name = "get_cells")]
+    fn get_cells(
+        &self,
+        search_key: SearchKey,
+        order: Order,
+        limit: Uint32,
+        after: Option<JsonBytes>,
+    ) -> Result<Pagination<IndexerCell>>;
+
+    #[rpc(name = "get_transactions")]
+    fn get_transactions(
+        &self,
+        search_key: SearchKey,
+        order: Order,
+        limit: Uint32,
+        after: Option<JsonBytes>,
+    ) -> Result<Pagination<IndexerTx>>;
+
+    #[rpc(name = "get_cells_capacity")]
+    fn get_cells_capacity(&self, search_key: SearchKey) -> Result<Option<IndexerCellsCapacity>>;
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct ScriptStatus {
+    /// Could be omitted when `address` is set.
+    #[serde(default)]
     pub script: Script,
+    /// The CKB address of the script, it replaces `script` when it's set, and it's always
+    /// returned by `get_scripts`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
     pub block_number: BlockNumber,
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct WatchWalletStatus {
+    pub xpub: String,
+    pub path: String,
+    pub gap_limit: Uint32,
+    /// How many lock scripts are derived and filtered.
+    pub derived_count: Uint32,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct RemoteNode {
     /// The remote node version.
@@ -113,6 +259,10 @@ pub struct RemoteNode {
 }
 #[derive(Deserialize, Serialize)]
 pub struct PeerSyncState {
+    /// The tip header announced by the remote peer.
+    ///
+    /// It's not proved yet.
+    pub announced_tip_header: Option<HeaderView>,
     /// Requested best known header of remote peer.
     ///
     /// This is the best known header yet to be proved.
@@ -123,38 +273,75 @@ pub struct PeerSyncState {
 
 #[derive(Deserialize)]
 pub struct SearchKey {
-    pub(crate) script: Script,
-    pub(crate) script_type: ScriptType,
-    pub(crate) filter: Option<SearchKeyFilter>,
-    pub(crate) group_by_transaction: Option<bool>,
+    /// Could be omitted when `address` is set.
+    #[serde(default)]
+    pub script: Script,
+    /// The CKB address of the script, it replaces `script` when it's set.
+    pub address: Option<String>,
+    pub script_type: ScriptType,
+    pub filter: Option<SearchKeyFilter>,
+    pub group_by_transaction: Option<bool>,
+    /// Only used by `get_cells` and `get_cells_capacity`, the cells created by pending
+    /// transactions are included when it's true. The cells consumed by pending transactions
+    /// are always excluded, until the transactions are committed or dropped.
+    pub include_pending: Option<bool>,
+    /// Only used by `get_cells` and `get_transactions`, the count of the items of all pages
+    /// is returned in `total_count` when it's true.
+    pub with_total_count: Option<bool>,
+    /// Only used by `get_cells`, the `output_data` of the cells is omitted when it's false,
+    /// defaults to true.
+    pub with_data: Option<bool>,
 }
 
 impl Default for SearchKey {
     fn default() -> Self {
         Self {
             script: Script::default(),
+            address: None,
             script_type: ScriptType::Lock,
             filter: None,
             group_by_transaction: None,
+            include_pending: None,
+            with_total_count: None,
+            with_data: None,
         }
     }
 }
 
+/// The filters of the cells, the ranges are [inclusive, exclusive].
+///
+/// `get_transactions` applies the cell filters to the output cells and the cells consumed by the
+/// inputs, the inputs whose consumed cells are pruned never match them.
 #[derive(Deserialize, Default)]
 pub struct SearchKeyFilter {
-    pub(crate) script: Option<Script>,
-    pub(crate) output_data_len_range: Option<[Uint64; 2]>,
-    pub(crate) output_capacity_range: Option<[Uint64; 2]>,
-    pub(crate) block_range: Option<[BlockNumber; 2]>,
+    /// The prefix of the other script of the cell, i.e. the type script when
+    /// `search_key.script_type` is lock.
+    pub script: Option<Script>,
+    /// The range of the length of the other script's code hash, hash type and args, it's 0 for
+    /// the cells without a type script.
+    pub script_len_range: Option<[Uint64; 2]>,
+    pub output_data: Option<JsonBytes>,
+    /// How `output_data` is matched, defaults to prefix.
+    pub output_data_filter_mode: Option<SearchMode>,
+    pub output_data_len_range: Option<[Uint64; 2]>,
+    pub output_capacity_range: Option<[Uint64; 2]>,
+    pub block_range: Option<[BlockNumber; 2]>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, Copy)]
 #[serde(rename_all = "snake_case")]
 pub enum ScriptType {
     Lock,
     Type,
 }
 
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchMode {
+    Prefix,
+    Exact,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Order {
@@ -164,11 +351,16 @@ pub enum Order {
 
 #[derive(Serialize)]
 pub struct Cell {
-    output: CellOutput,
-    output_data: JsonBytes,
-    pub(crate) out_point: OutPoint,
-    block_number: BlockNumber,
-    tx_index: Uint32,
+    pub output: CellOutput,
+    /// Null when `search_key.with_data` is false.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_data: Option<JsonBytes>,
+    pub out_point: OutPoint,
+    /// Null means the cell is created by a pending transaction.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<BlockNumber>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_index: Option<Uint32>,
 }
 
 #[derive(Serialize)]
@@ -190,19 +382,19 @@ impl Tx {
 
 #[derive(Serialize)]
 pub struct TxWithCell {
-    transaction: TransactionView,
-    block_number: BlockNumber,
-    tx_index: Uint32,
-    io_index: Uint32,
-    io_type: CellType,
+    pub transaction: TransactionView,
+    pub block_number: BlockNumber,
+    pub tx_index: Uint32,
+    pub io_index: Uint32,
+    pub io_type: CellType,
 }
 
 #[derive(Serialize)]
 pub struct TxWithCells {
-    transaction: TransactionView,
-    block_number: BlockNumber,
-    tx_index: Uint32,
-    cells: Vec<(CellType, Uint32)>,
+    pub transaction: TransactionView,
+    pub block_number: BlockNumber,
+    pub tx_index: Uint32,
+    pub cells: Vec<(CellType, Uint32)>,
 }
 
 #[derive(Serialize, Clone)]
@@ -214,28 +406,192 @@ pub enum CellType {
 
 #[derive(Serialize)]
 pub struct Pagination<T> {
-    pub(crate) objects: Vec<T>,
-    pub(crate) last_cursor: JsonBytes,
+    pub objects: Vec<T>,
+    pub last_cursor: JsonBytes,
+    /// The count of the items of all pages, only returned when
+    /// `search_key.with_total_count` is true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_count: Option<Uint64>,
+}
+
+#[derive(Serialize)]
+pub struct SendTransactionResult {
+    pub tx_hash: H256,
+    /// The transaction fee in shannons.
+    pub fee: Capacity,
+    /// The fee rate in shannons per KB.
+    pub fee_rate: Uint64,
+}
+
+/// An unsigned transfer transaction, each message is signed by the private key of its lock group
+/// and the 65 bytes signature is put into the lock field of the witness at `witness_index`.
+#[derive(Deserialize, Serialize)]
+pub struct TransferTransaction {
+    /// The lock field of the first witness of each lock group is filled with 65 zero bytes, it
+    /// should be replaced with the signature.
+    pub transaction: Transaction,
+    /// The transaction fee in shannons.
+    pub fee: Capacity,
+    /// The messages to be signed, one for each lock group.
+    pub signing_messages: Vec<SigningMessage>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SigningMessage {
+    pub lock: Script,
+    /// The index of the witness which holds the signature, it's the first input of the group.
+    pub witness_index: Uint32,
+    /// The message of the secp256k1 recoverable signature.
+    pub message: H256,
+}
+
+#[derive(Serialize)]
+pub struct TransactionStatus {
+    pub status: TxStatus,
+    /// The node ids of the peers which fetched the transaction.
+    pub sent_to: Vec<String>,
+    /// The node ids of the peers which accepted the transaction into their tx-pool.
+    pub accepted_by: Vec<String>,
+    /// How many times the transaction is re-announced since no peer accepted it.
+    pub retries: Uint32,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TxStatus {
+    /// Not fetched by any peer yet.
+    Pending,
+    /// Fetched by some peers, but not accepted by any peer yet.
+    Relayed,
+    /// Accepted by the tx-pool of some peers.
+    Accepted,
+    /// Not accepted by any peer after all retries, the transaction is dropped.
+    Rejected,
+    /// Found in a filtered block.
+    Committed,
+    /// Never sent by this node or already evicted.
+    Unknown,
+}
+
+#[derive(Serialize)]
+pub struct BlockchainInfo {
+    /// The chain name, e.g. "ckb", "ckb_testnet" or "ckb_dev".
+    pub chain: String,
+    pub genesis_hash: H256,
+    /// The proved tip header.
+    pub tip_header: HeaderView,
+    /// The total difficulty of the proved tip.
+    pub total_difficulty: U256,
+    /// The epoch of the proved tip.
+    pub epoch: EpochNumberWithFraction,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DaoPhase {
+    /// Deposited, the next step is to withdraw it as a withdrawing cell.
+    Deposited,
+    /// Withdrawing, the next step is to unlock it after the lock period.
+    Withdrawing,
+}
+
+#[derive(Serialize)]
+pub struct DaoCell {
+    pub cell: Cell,
+    pub phase: DaoPhase,
+    /// The headers which should be in the `header_deps` of the transaction spending this cell,
+    /// null when some of them are being fetched.
+    pub header_deps: Option<Vec<H256>>,
+    /// The maximum withdrawable capacity, counted to the proved tip for the deposited cells,
+    /// null when the required headers are being fetched.
+    pub maximum_withdraw: Option<Capacity>,
+}
+
+#[derive(Serialize)]
+pub struct SystemScriptInfo {
+    /// `secp256k1_blake160_sighash_all`, `secp256k1_blake160_multisig_all` or `dao`.
+    pub name: String,
+    pub code_hash: H256,
+    pub hash_type: ScriptHashType,
+    pub cell_dep: CellDep,
 }
 
 #[derive(Serialize)]
 pub struct TransactionWithHeader {
-    pub(crate) transaction: TransactionView,
-    pub(crate) header: HeaderView,
+    pub transaction: TransactionView,
+    pub header: HeaderView,
+}
+
+#[derive(Serialize)]
+pub struct IndexerTip {
+    pub block_hash: H256,
+    pub block_number: BlockNumber,
+}
+
+#[derive(Serialize)]
+pub struct IndexerCell {
+    pub output: CellOutput,
+    pub output_data: Option<JsonBytes>,
+    pub out_point: OutPoint,
+    pub block_number: BlockNumber,
+    pub tx_index: Uint32,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum IndexerTx {
+    Ungrouped(IndexerTxWithCell),
+    Grouped(IndexerTxWithCells),
+}
+
+#[derive(Serialize)]
+pub struct IndexerTxWithCell {
+    pub tx_hash: H256,
+    pub block_number: BlockNumber,
+    pub tx_index: Uint32,
+    pub io_index: Uint32,
+    pub io_type: CellType,
+}
+
+#[derive(Serialize)]
+pub struct IndexerTxWithCells {
+    pub tx_hash: H256,
+    pub block_number: BlockNumber,
+    pub tx_index: Uint32,
+    pub cells: Vec<(CellType, Uint32)>,
+}
+
+#[derive(Serialize)]
+pub struct IndexerCellsCapacity {
+    pub capacity: Capacity,
+    pub block_hash: H256,
+    pub block_number: BlockNumber,
 }
 
 pub struct BlockFilterRpcImpl {
     pub(crate) storage: Storage,
+    pub(crate) pending_txs: Arc<RwLock<PendingTxs>>,
+    pub(crate) network: NetworkType,
 }
 
 pub struct TransactionRpcImpl {
-    pending_txs: Arc<RwLock<PendingTxs>>,
-    swl: StorageWithLastHeaders,
-    consensus: Consensus,
+    pub(crate) pending_txs: Arc<RwLock<PendingTxs>>,
+    pub(crate) swl: StorageWithLastHeaders,
+    pub(crate) consensus: Consensus,
+    pub(crate) system_scripts: Arc<SystemScripts>,
+    pub(crate) min_fee_rate: core::FeeRate,
 }
 
 pub struct ChainRpcImpl {
     pub(crate) swl: StorageWithLastHeaders,
+    pub(crate) consensus: Consensus,
+    pub(crate) system_scripts: Arc<SystemScripts>,
+}
+
+pub struct DaoRpcImpl {
+    pub(crate) swl: StorageWithLastHeaders,
+    pub(crate) peers: Arc<Peers>,
+    pub(crate) consensus: Consensus,
 }
 
 pub struct NetRpcImpl {
@@ -243,18 +599,148 @@ pub struct NetRpcImpl {
     peers: Arc<Peers>,
 }
 
+pub struct IndexerRpcImpl {
+    pub(crate) block_filter_rpc: BlockFilterRpcImpl,
+    pub(crate) swl: StorageWithLastHeaders,
+}
+
+#[allow(clippy::mutable_key_type)]
+impl BlockFilterRpcImpl {
+    // the script is replaced by the address when it's set, the address should be of the
+    // configured chain
+    fn resolve_script(&self, script: Script, address: Option<&str>, name: &str) -> Result<Script> {
+        match address {
+            Some(address) => {
+                let address = Address::from_str(address).map_err(|err| {
+                    Error::invalid_params(format!("invalid {}.address: {}", name, err))
+                })?;
+                if address.network() != self.network {
+                    return Err(Error::invalid_params(format!(
+                        "{}.address should start with {}",
+                        name,
+                        self.network.prefix()
+                    )));
+                }
+                Ok(address.script().clone().into())
+            }
+            None if script == Script::default() => Err(Error::invalid_params(format!(
+                "{}.script or {}.address should be set",
+                name, name
+            ))),
+            None => Ok(script),
+        }
+    }
+
+    fn parse_watch_wallet(xpub: &str, path: &str, gap_limit: u32) -> Result<WatchWallet> {
+        let xpub = ExtendedPubKey::from_str(xpub)
+            .map_err(|err| Error::invalid_params(format!("invalid xpub: {}", err)))?;
+        let path = DerivationPath::from_str(path)
+            .map_err(|err| Error::invalid_params(format!("invalid path: {}", err)))?;
+        Ok(WatchWallet {
+            xpub,
+            path,
+            gap_limit,
+        })
+    }
+
+    fn resolve_search_key(&self, search_key: SearchKey) -> Result<SearchKey> {
+        let script = self.resolve_script(
+            search_key.script,
+            search_key.address.as_deref(),
+            "search_key",
+        )?;
+        Ok(SearchKey {
+            script,
+            address: None,
+            ..search_key
+        })
+    }
+
+    // the cells consumed by pending transactions are never returned, whether the pending cells
+    // are included or not
+    fn get_pending_spent_out_points(&self) -> HashSet<packed::OutPoint> {
+        self.pending_txs
+            .read()
+            .expect("pending_txs lock is poisoned")
+            .spent_out_points()
+    }
+
+    // collect the cells which are created by pending transactions and match the search key,
+    // the cells of transactions which are already filtered in a block are skipped, the cells are
+    // sorted by the out point to be paginated stably
+    fn get_pending_cells(&self, search_key: &SearchKey) -> Result<Vec<Cell>> {
+        if !search_key.include_pending.unwrap_or_default() {
+            return Ok(Vec::new());
+        }
+        let filter_options = build_filter_options(search_key)?;
+        // pending cells are not in any block
+        if filter_options.block_range.is_some() {
+            return Ok(Vec::new());
+        }
+        let script: packed::Script = search_key.script.clone().into();
+        let script_prefix = extract_raw_data(&script);
+
+        let pending_txs = self
+            .pending_txs
+            .read()
+            .expect("pending_txs lock is poisoned");
+        let spent_out_points = pending_txs.spent_out_points();
+        let mut cells = Vec::new();
+        for (out_point, output, output_data) in pending_txs.outputs() {
+            let script = match search_key.script_type {
+                ScriptType::Lock => Some(output.lock()),
+                ScriptType::Type => output.type_().to_opt(),
+            };
+            if !script
+                .map(|script| extract_raw_data(&script).starts_with(&script_prefix))
+                .unwrap_or(false)
+            {
+                continue;
+            }
+            let raw_data = output_data.raw_data();
+            if !filter_options.match_cell(&output, raw_data.len(), Some(&raw_data)) {
+                continue;
+            }
+            if spent_out_points.contains(&out_point)
+                || self
+                    .storage
+                    .get(Key::TxHash(&out_point.tx_hash()).into_vec())
+                    .map_err(storage_error)?
+                    .is_some()
+            {
+                continue;
+            }
+            cells.push((out_point, output, output_data));
+        }
+        cells.sort_by_key(|(out_point, _output, _output_data)| pending_cell_key(out_point));
+        Ok(cells
+            .into_iter()
+            .map(|(out_point, output, output_data)| Cell {
+                output: output.into(),
+                output_data: Some(output_data.into()),
+                out_point: out_point.into(),
+                block_number: None,
+                tx_index: None,
+            })
+            .collect())
+    }
+}
+
 #[allow(clippy::mutable_key_type)]
 impl BlockFilterRpc for BlockFilterRpcImpl {
     fn set_scripts(&self, scripts: Vec<ScriptStatus>) -> Result<()> {
         let scripts = scripts
             .into_iter()
-            .map(|script_status| {
-                (
-                    script_status.script.into(),
-                    script_status.block_number.into(),
-                )
+            .enumerate()
+            .map(|(i, script_status)| {
+                let script = self.resolve_script(
+                    script_status.script,
+                    script_status.address.as_deref(),
+                    &format!("scripts[{}]", i),
+                )?;
+                Ok((script.into(), script_status.block_number.into()))
             })
-            .collect();
+            .collect::<Result<_>>()?;
 
         self.storage.update_filter_scripts(scripts);
         Ok(())
@@ -265,6 +751,7 @@ impl BlockFilterRpc for BlockFilterRpcImpl {
         Ok(scripts
             .into_iter()
             .map(|(script, block_number)| ScriptStatus {
+                address: Some(Address::new(self.network, script.clone()).to_string()),
                 script: script.into(),
                 block_number: block_number.into(),
             })
@@ -278,123 +765,160 @@ impl BlockFilterRpc for BlockFilterRpcImpl {
         limit: Uint32,
         after_cursor: Option<JsonBytes>,
     ) -> Result<Pagination<Cell>> {
-        let (prefix, from_key, direction, skip) = build_query_options(
+        let search_key = self.resolve_search_key(search_key)?;
+        let snapshot = self.storage.snapshot();
+        let after_cursor = after_cursor.map(Cursor::decode).transpose()?;
+        if let Some(cursor) = after_cursor.as_ref() {
+            cursor.verify(&snapshot)?;
+        }
+        // the pending cells are newer than all indexed cells, they are the last segment of the
+        // pages in asc order and the first one in desc order, the cursor of the pending segment
+        // is marked by `PENDING_CELL_CURSOR_PREFIX`
+        let (pending_cursor, indexed_cursor) = match after_cursor.as_ref() {
+            Some(cursor) if cursor.key.first() == Some(&PENDING_CELL_CURSOR_PREFIX) => {
+                (Some(&cursor.key[1..]), None)
+            }
+            cursor => (None, cursor),
+        };
+        let pending_spent_out_points = self.get_pending_spent_out_points();
+        let is_desc_order = matches!(order, Order::Desc);
+        let (prefix, from_key, direction, skip_from_key) = build_query_options(
             &search_key,
             KeyPrefix::CellLockScript,
             KeyPrefix::CellTypeScript,
             order,
-            after_cursor,
+            indexed_cursor,
         )?;
-        let filter_script_type = match search_key.script_type {
-            ScriptType::Lock => ScriptType::Type,
-            ScriptType::Type => ScriptType::Lock,
-        };
-        let (
-            filter_prefix,
-            filter_output_data_len_range,
-            filter_output_capacity_range,
-            filter_block_range,
-        ) = build_filter_options(search_key)?;
-        let mode = IteratorMode::From(from_key.as_ref(), direction);
-        let snapshot = self.storage.db.snapshot();
-        let iter = snapshot.iterator(mode).skip(skip);
-
-        let mut last_key = Vec::new();
-        let cells = iter
-            .take_while(|(key, _value)| key.starts_with(&prefix))
-            .filter_map(|(key, value)| {
-                let tx_hash = packed::Byte32::from_slice(&value).expect("stored tx hash");
-                let output_index = u32::from_be_bytes(
-                    key[key.len() - 4..]
-                        .try_into()
-                        .expect("stored output_index"),
-                );
-                let tx_index = u32::from_be_bytes(
-                    key[key.len() - 8..key.len() - 4]
-                        .try_into()
-                        .expect("stored tx_index"),
-                );
-                let block_number = u64::from_be_bytes(
-                    key[key.len() - 16..key.len() - 8]
-                        .try_into()
-                        .expect("stored block_number"),
-                );
+        let pending_cursor = pending_cursor
+            .map(|key| {
+                key.strip_prefix(prefix.as_slice()).ok_or_else(|| {
+                    Error::invalid_params("the cursor doesn't belong to the search_key")
+                })
+            })
+            .transpose()?;
+        let filter_options = build_filter_options(&search_key)?;
+        let with_data = search_key.with_data.unwrap_or(true);
+        let limit = limit.value() as usize;
 
-                let tx = packed::Transaction::from_slice(
-                    &snapshot
-                        .get(Key::TxHash(&tx_hash).into_vec())
-                        .expect("get tx should be OK")
-                        .expect("stored tx")[12..],
-                )
-                .expect("from stored tx slice should be OK");
-                let output = tx
-                    .raw()
-                    .outputs()
-                    .get(output_index as usize)
-                    .expect("get output by index should be OK");
-                let output_data = tx
-                    .raw()
-                    .outputs_data()
-                    .get(output_index as usize)
-                    .expect("get output data by index should be OK");
-
-                if let Some(prefix) = filter_prefix.as_ref() {
-                    match filter_script_type {
-                        ScriptType::Lock => {
-                            if !extract_raw_data(&output.lock())
-                                .as_slice()
-                                .starts_with(prefix)
-                            {
-                                return None;
-                            }
-                        }
-                        ScriptType::Type => {
-                            if output.type_().is_none()
-                                || !extract_raw_data(&output.type_().to_opt().unwrap())
-                                    .as_slice()
-                                    .starts_with(prefix)
-                            {
-                                return None;
-                            }
-                        }
-                    }
-                }
+        // returns the out point, output, output data, block number and tx index of the cell if
+        // it matches the search key, the output data is only loaded when `with_data` is true or
+        // it's required by the filters, the malformed or dangling cell values are skipped and
+        // reported by `check`
+        let match_cell = |key: &[u8], value: &[u8], with_data: bool| {
+            let cell = CellValue::from_slice(value)?;
+            let output_index = u32::from_be_bytes(
+                key[key.len() - 4..]
+                    .try_into()
+                    .expect("stored output_index"),
+            );
+            let out_point = cell.out_point(output_index);
+            if pending_spent_out_points.contains(&out_point) {
+                return None;
+            }
+            let tx_index = u32::from_be_bytes(
+                key[key.len() - 8..key.len() - 4]
+                    .try_into()
+                    .expect("stored tx_index"),
+            );
+            let block_number = u64::from_be_bytes(
+                key[key.len() - 16..key.len() - 8]
+                    .try_into()
+                    .expect("stored block_number"),
+            );
+            if !filter_options.match_block_number(block_number) {
+                return None;
+            }
 
-                if let Some([r0, r1]) = filter_output_data_len_range {
-                    if output_data.len() < r0 || output_data.len() >= r1 {
-                        return None;
-                    }
-                }
+            let output_data = if with_data || filter_options.requires_output_data() {
+                Some(snapshot.get_cell_data(&out_point)?)
+            } else {
+                None
+            };
+            if !filter_options.match_cell(
+                &cell.output,
+                cell.data_len as usize,
+                output_data.as_ref().map(|data| data.raw_data()).as_deref(),
+            ) {
+                return None;
+            }
 
-                if let Some([r0, r1]) = filter_output_capacity_range {
-                    let capacity: core::Capacity = output.capacity().unpack();
-                    if capacity < r0 || capacity >= r1 {
-                        return None;
-                    }
-                }
+            Some((out_point, cell.output, output_data, block_number, tx_index))
+        };
+        let indexed_cells = |limit: usize, last_key: &mut Vec<u8>| {
+            snapshot
+                .iter(&from_key, direction)
+                .skip_while(|(key, _value)| skip_from_key && key.as_ref() == from_key.as_slice())
+                .take_while(|(key, _value)| key.starts_with(&prefix))
+                .filter_map(|(key, value)| {
+                    let (out_point, output, output_data, block_number, tx_index) =
+                        match_cell(&key, &value, with_data)?;
+                    *last_key = key.to_vec();
+
+                    Some(Cell {
+                        output: output.into(),
+                        output_data: output_data.filter(|_| with_data).map(Into::into),
+                        out_point: out_point.into(),
+                        block_number: Some(block_number.into()),
+                        tx_index: Some(tx_index.into()),
+                    })
+                })
+                .take(limit)
+                .collect::<Vec<_>>()
+        };
 
-                if let Some([r0, r1]) = filter_block_range {
-                    if block_number < r0 || block_number >= r1 {
-                        return None;
-                    }
+        let all_pending_cells = self.get_pending_cells(&search_key)?;
+        let pending_count = all_pending_cells.len();
+        let mut pending_cells = all_pending_cells
+            .into_iter()
+            .map(|mut cell| {
+                let key = pending_cell_key(&cell.out_point.clone().into());
+                if !with_data {
+                    cell.output_data = None;
                 }
-
-                last_key = key.to_vec();
-
-                Some(Cell {
-                    output: output.into(),
-                    output_data: output_data.into(),
-                    out_point: packed::OutPoint::new(tx_hash, output_index).into(),
-                    block_number: block_number.into(),
-                    tx_index: tx_index.into(),
-                })
+                (key, cell)
+            })
+            .filter(|(key, _cell)| match pending_cursor {
+                Some(cursor) if is_desc_order => key.as_slice() < cursor,
+                Some(cursor) => key.as_slice() > cursor,
+                None => true,
             })
-            .take(limit.value() as usize)
             .collect::<Vec<_>>();
+        if is_desc_order {
+            pending_cells.reverse();
+            // the pending segment is finished when the cursor is in the indexed segment
+            if indexed_cursor.is_some() {
+                pending_cells.clear();
+            }
+        }
+
+        let mut last_key = Vec::new();
+        let mut cells = Vec::new();
+        if !is_desc_order && pending_cursor.is_none() {
+            cells = indexed_cells(limit, &mut last_key);
+        }
+        for (key, cell) in pending_cells.into_iter().take(limit - cells.len()) {
+            last_key = [vec![PENDING_CELL_CURSOR_PREFIX], prefix.clone(), key].concat();
+            cells.push(cell);
+        }
+        if is_desc_order && cells.len() < limit {
+            cells.extend(indexed_cells(limit - cells.len(), &mut last_key));
+        }
+
+        let total_count = if search_key.with_total_count.unwrap_or_default() {
+            let count = snapshot
+                .iter(&prefix, Direction::Forward)
+                .take_while(|(key, _value)| key.starts_with(&prefix))
+                .filter(|(key, value)| match_cell(key, value, false).is_some())
+                .count();
+            Some(((count + pending_count) as u64).into())
+        } else {
+            None
+        };
 
         Ok(Pagination {
             objects: cells,
-            last_cursor: JsonBytes::from_vec(last_key),
+            last_cursor: Cursor::encode(&snapshot, last_key),
+            total_count,
         })
     }
 
@@ -405,49 +929,101 @@ impl BlockFilterRpc for BlockFilterRpcImpl {
         limit: Uint32,
         after_cursor: Option<JsonBytes>,
     ) -> Result<Pagination<Tx>> {
-        let (prefix, from_key, direction, skip) = build_query_options(
+        let search_key = self.resolve_search_key(search_key)?;
+        let snapshot = self.storage.snapshot();
+        let after_cursor = after_cursor.map(Cursor::decode).transpose()?;
+        if let Some(cursor) = after_cursor.as_ref() {
+            cursor.verify(&snapshot)?;
+        }
+        let (prefix, from_key, direction, skip_from_key) = build_query_options(
             &search_key,
             KeyPrefix::TxLockScript,
             KeyPrefix::TxTypeScript,
             order,
-            after_cursor,
+            after_cursor.as_ref(),
         )?;
         let limit = limit.value() as usize;
 
-        let (filter_script, filter_block_range) = if let Some(filter) = search_key.filter.as_ref() {
-            if filter.output_data_len_range.is_some() {
-                return Err(Error::invalid_params(
-                    "doesn't support search_key.filter.output_data_len_range parameter",
-                ));
+        let filter_options = build_filter_options(&search_key)?;
+        let get_tx = |tx_hash: &packed::Byte32| {
+            snapshot
+                .get(Key::TxHash(tx_hash).into_vec())
+                .expect("get tx should be OK")
+                .map(|value| {
+                    packed::Transaction::from_slice(&value[12..])
+                        .expect("from stored tx slice should be OK")
+                })
+        };
+
+        // returns the tx hash, block number, tx index, io index and io type of the tx history
+        // entry if it matches the search key, the dangling entries are skipped and reported by
+        // `check`
+        let match_tx = |key: &[u8], value: &[u8]| {
+            let tx_hash = packed::Byte32::from_slice(value).expect("stored tx hash");
+            let block_number = u64::from_be_bytes(
+                key[key.len() - 17..key.len() - 9]
+                    .try_into()
+                    .expect("stored block_number"),
+            );
+            let tx_index = u32::from_be_bytes(
+                key[key.len() - 9..key.len() - 5]
+                    .try_into()
+                    .expect("stored tx_index"),
+            );
+            let io_index = u32::from_be_bytes(
+                key[key.len() - 5..key.len() - 1]
+                    .try_into()
+                    .expect("stored io_index"),
+            );
+            let io_type = if *key.last().expect("stored io_type") == 0 {
+                CellType::Input
+            } else {
+                CellType::Output
+            };
+
+            if !filter_options.match_block_number(block_number) {
+                return None;
             }
-            if filter.output_capacity_range.is_some() {
-                return Err(Error::invalid_params(
-                    "doesn't support search_key.filter.output_capacity_range parameter",
-                ));
+
+            if filter_options.has_cell_filters() {
+                let tx = get_tx(&tx_hash)?;
+                // the cell is created by the output, or consumed by the input
+                let out_point = match io_type {
+                    CellType::Input => tx
+                        .raw()
+                        .inputs()
+                        .get(io_index as usize)
+                        .expect("get input by index should be OK")
+                        .previous_output(),
+                    CellType::Output => packed::OutPoint::new(tx_hash.clone(), io_index),
+                };
+                let cell_tx = if out_point.tx_hash() == tx_hash {
+                    tx
+                } else {
+                    get_tx(&out_point.tx_hash())?
+                };
+                let index: u32 = out_point.index().unpack();
+                let output = cell_tx.raw().outputs().get(index as usize)?;
+                let output_data = cell_tx.raw().outputs_data().get(index as usize)?.raw_data();
+                if !filter_options.match_cell(&output, output_data.len(), Some(&output_data)) {
+                    return None;
+                }
             }
-            let filter_script: Option<packed::Script> =
-                filter.script.as_ref().map(|script| script.clone().into());
-            let filter_block_range: Option<[core::BlockNumber; 2]> =
-                filter.block_range.map(|r| [r[0].into(), r[1].into()]);
-            (filter_script, filter_block_range)
-        } else {
-            (None, None)
-        };
 
-        let filter_script_type = match search_key.script_type {
-            ScriptType::Lock => ScriptType::Type,
-            ScriptType::Type => ScriptType::Lock,
+            Some((tx_hash, block_number, tx_index, io_index, io_type))
         };
 
-        let mode = IteratorMode::From(from_key.as_ref(), direction);
-        let snapshot = self.storage.db.snapshot();
-        let iter = snapshot.iterator(mode).skip(skip);
+        let iter = snapshot
+            .iter(&from_key, direction)
+            .skip_while(|(key, _value)| skip_from_key && key.as_ref() == from_key.as_slice())
+            .take_while(|(key, _value)| key.starts_with(&prefix));
+        let group_by_transaction = search_key.group_by_transaction.unwrap_or_default();
 
-        if search_key.group_by_transaction.unwrap_or_default() {
+        let (txs, last_key): (Vec<Tx>, Vec<u8>) = if group_by_transaction {
             let mut tx_with_cells: Vec<TxWithCells> = Vec::new();
             let mut last_key = Vec::new();
 
-            for (key, value) in iter.take_while(|(key, _value)| key.starts_with(&prefix)) {
+            for (key, value) in iter {
                 let tx_hash = packed::Byte32::from_slice(&value).expect("stored tx hash");
                 if tx_with_cells.len() == limit
                     && tx_with_cells.last_mut().unwrap().transaction.hash != tx_hash.unpack()
@@ -455,82 +1031,13 @@ impl BlockFilterRpc for BlockFilterRpcImpl {
                     break;
                 }
                 last_key = key.to_vec();
-                let tx = packed::Transaction::from_slice(
-                    &snapshot
-                        .get(Key::TxHash(&tx_hash).into_vec())
-                        .expect("get tx should be OK")
-                        .expect("stored tx")[12..],
-                )
-                .expect("from stored tx slice should be OK");
-
-                let block_number = u64::from_be_bytes(
-                    key[key.len() - 17..key.len() - 9]
-                        .try_into()
-                        .expect("stored block_number"),
-                );
-                let tx_index = u32::from_be_bytes(
-                    key[key.len() - 9..key.len() - 5]
-                        .try_into()
-                        .expect("stored tx_index"),
-                );
-                let io_index = u32::from_be_bytes(
-                    key[key.len() - 5..key.len() - 1]
-                        .try_into()
-                        .expect("stored io_index"),
-                );
-                let io_type = if *key.last().expect("stored io_type") == 0 {
-                    CellType::Input
-                } else {
-                    CellType::Output
-                };
 
-                if let Some(filter_script) = filter_script.as_ref() {
-                    let filter_script_matched = match filter_script_type {
-                        ScriptType::Lock => snapshot
-                            .get(
-                                Key::TxLockScript(
-                                    filter_script,
-                                    block_number,
-                                    tx_index,
-                                    io_index,
-                                    match io_type {
-                                        CellType::Input => storage::CellType::Input,
-                                        CellType::Output => storage::CellType::Output,
-                                    },
-                                )
-                                .into_vec(),
-                            )
-                            .expect("get TxLockScript should be OK")
-                            .is_some(),
-                        ScriptType::Type => snapshot
-                            .get(
-                                Key::TxTypeScript(
-                                    filter_script,
-                                    block_number,
-                                    tx_index,
-                                    io_index,
-                                    match io_type {
-                                        CellType::Input => storage::CellType::Input,
-                                        CellType::Output => storage::CellType::Output,
-                                    },
-                                )
-                                .into_vec(),
-                            )
-                            .expect("get TxTypeScript should be OK")
-                            .is_some(),
+                let (tx_hash, block_number, tx_index, io_index, io_type) =
+                    match match_tx(&key, &value) {
+                        Some(matched) => matched,
+                        None => continue,
                     };
 
-                    if !filter_script_matched {
-                        continue;
-                    }
-                }
-
-                if let Some([r0, r1]) = filter_block_range {
-                    if block_number < r0 || block_number >= r1 {
-                        continue;
-                    }
-                }
-
                 let last_tx_hash_is_same = tx_with_cells
                     .last_mut()
                     .map(|last| {
@@ -544,6 +1051,10 @@ impl BlockFilterRpc for BlockFilterRpcImpl {
                     .unwrap_or_default();
 
                 if !last_tx_hash_is_same {
+                    let tx = match get_tx(&tx_hash) {
+                        Some(tx) => tx,
+                        None => continue,
+                    };
                     tx_with_cells.push(TxWithCells {
                         transaction: tx.into_view().into(),
                         block_number: block_number.into(),
@@ -553,93 +1064,19 @@ impl BlockFilterRpc for BlockFilterRpcImpl {
                 }
             }
 
-            Ok(Pagination {
-                objects: tx_with_cells.into_iter().map(Tx::Grouped).collect(),
-                last_cursor: JsonBytes::from_vec(last_key),
-            })
+            (
+                tx_with_cells.into_iter().map(Tx::Grouped).collect(),
+                last_key,
+            )
         } else {
             let mut last_key = Vec::new();
             let txs = iter
-                .take_while(|(key, _value)| key.starts_with(&prefix))
                 .filter_map(|(key, value)| {
-                    let tx_hash = packed::Byte32::from_slice(&value).expect("stored tx hash");
-                    let tx = packed::Transaction::from_slice(
-                        &snapshot
-                            .get(Key::TxHash(&tx_hash).into_vec())
-                            .expect("get tx should be OK")
-                            .expect("stored tx")[12..],
-                    )
-                    .expect("from stored tx slice should be OK");
-
-                    let block_number = u64::from_be_bytes(
-                        key[key.len() - 17..key.len() - 9]
-                            .try_into()
-                            .expect("stored block_number"),
-                    );
-                    let tx_index = u32::from_be_bytes(
-                        key[key.len() - 9..key.len() - 5]
-                            .try_into()
-                            .expect("stored tx_index"),
-                    );
-                    let io_index = u32::from_be_bytes(
-                        key[key.len() - 5..key.len() - 1]
-                            .try_into()
-                            .expect("stored io_index"),
-                    );
-                    let io_type = if *key.last().expect("stored io_type") == 0 {
-                        CellType::Input
-                    } else {
-                        CellType::Output
-                    };
-
-                    if let Some(filter_script) = filter_script.as_ref() {
-                        match filter_script_type {
-                            ScriptType::Lock => {
-                                snapshot
-                                    .get(
-                                        Key::TxLockScript(
-                                            filter_script,
-                                            block_number,
-                                            tx_index,
-                                            io_index,
-                                            match io_type {
-                                                CellType::Input => storage::CellType::Input,
-                                                CellType::Output => storage::CellType::Output,
-                                            },
-                                        )
-                                        .into_vec(),
-                                    )
-                                    .expect("get TxLockScript should be OK")?;
-                            }
-                            ScriptType::Type => {
-                                snapshot
-                                    .get(
-                                        Key::TxTypeScript(
-                                            filter_script,
-                                            block_number,
-                                            tx_index,
-                                            io_index,
-                                            match io_type {
-                                                CellType::Input => storage::CellType::Input,
-                                                CellType::Output => storage::CellType::Output,
-                                            },
-                                        )
-                                        .into_vec(),
-                                    )
-                                    .expect("get TxTypeScript should be OK")?;
-                            }
-                        }
-                    }
-
-                    if let Some([r0, r1]) = filter_block_range {
-                        if block_number < r0 || block_number >= r1 {
-                            return None;
-                        }
-                    }
-
+                    let (tx_hash, block_number, tx_index, io_index, io_type) =
+                        match_tx(&key, &value)?;
                     last_key = key.to_vec();
                     Some(Tx::Ungrouped(TxWithCell {
-                        transaction: tx.into_view().into(),
+                        transaction: get_tx(&tx_hash)?.into_view().into(),
                         block_number: block_number.into(),
                         tx_index: tx_index.into(),
                         io_index: io_index.into(),
@@ -648,119 +1085,255 @@ impl BlockFilterRpc for BlockFilterRpcImpl {
                 })
                 .take(limit)
                 .collect::<Vec<_>>();
+            (txs, last_key)
+        };
 
-            Ok(Pagination {
-                objects: txs,
-                last_cursor: JsonBytes::from_vec(last_key),
-            })
-        }
-    }
-
-    fn get_cells_capacity(&self, search_key: SearchKey) -> Result<Capacity> {
-        let (prefix, from_key, direction, skip) = build_query_options(
-            &search_key,
-            KeyPrefix::CellLockScript,
+        let total_count = if search_key.with_total_count.unwrap_or_default() {
+            let matched = snapshot
+                .iter(&prefix, Direction::Forward)
+                .take_while(|(key, _value)| key.starts_with(&prefix))
+                .filter_map(|(key, value)| match_tx(&key, &value));
+            // the entries of a transaction are adjacent since they share the block number and
+            // the tx index
+            let count = if group_by_transaction {
+                matched
+                    .fold((0u64, None), |(count, last_tx_hash), (tx_hash, ..)| {
+                        if last_tx_hash.as_ref() == Some(&tx_hash) {
+                            (count, last_tx_hash)
+                        } else {
+                            (count + 1, Some(tx_hash))
+                        }
+                    })
+                    .0
+            } else {
+                matched.count() as u64
+            };
+            Some(count.into())
+        } else {
+            None
+        };
+
+        Ok(Pagination {
+            objects: txs,
+            last_cursor: Cursor::encode(&snapshot, last_key),
+            total_count,
+        })
+    }
+
+    fn get_cells_capacity(&self, search_key: SearchKey) -> Result<Capacity> {
+        let search_key = self.resolve_search_key(search_key)?;
+        let pending_capacity: u64 = self
+            .get_pending_cells(&search_key)?
+            .into_iter()
+            .map(|cell| cell.output.capacity.value())
+            .sum();
+        let pending_spent_out_points = self.get_pending_spent_out_points();
+        let (prefix, from_key, direction, _) = build_query_options(
+            &search_key,
+            KeyPrefix::CellLockScript,
             KeyPrefix::CellTypeScript,
             Order::Asc,
             None,
         )?;
-        let filter_script_type = match search_key.script_type {
-            ScriptType::Lock => ScriptType::Type,
-            ScriptType::Type => ScriptType::Lock,
-        };
-        let (
-            filter_prefix,
-            filter_output_data_len_range,
-            filter_output_capacity_range,
-            filter_block_range,
-        ) = build_filter_options(search_key)?;
-        let mode = IteratorMode::From(from_key.as_ref(), direction);
-        let snapshot = self.storage.db.snapshot();
-        let iter = snapshot.iterator(mode).skip(skip);
+        let filter_options = build_filter_options(&search_key)?;
+        let snapshot = self.storage.snapshot();
+        let iter = snapshot.iter(&from_key, direction);
 
         let capacity: u64 = iter
             .take_while(|(key, _value)| key.starts_with(&prefix))
             .filter_map(|(key, value)| {
-                let tx_hash = packed::Byte32::from_slice(&value).expect("stored tx hash");
+                let cell = CellValue::from_slice(&value)?;
                 let output_index = u32::from_be_bytes(
                     key[key.len() - 4..]
                         .try_into()
                         .expect("stored output_index"),
                 );
+                let out_point = cell.out_point(output_index);
+                if pending_spent_out_points.contains(&out_point) {
+                    return None;
+                }
                 let block_number = u64::from_be_bytes(
                     key[key.len() - 16..key.len() - 8]
                         .try_into()
                         .expect("stored block_number"),
                 );
+                if !filter_options.match_block_number(block_number) {
+                    return None;
+                }
 
-                let tx = packed::Transaction::from_slice(
-                    &snapshot
-                        .get(Key::TxHash(&tx_hash).into_vec())
-                        .expect("get tx should be OK")
-                        .expect("stored tx")[12..],
-                )
-                .expect("from stored tx slice should be OK");
-                let output = tx
-                    .raw()
-                    .outputs()
-                    .get(output_index as usize)
-                    .expect("get output by index should be OK");
-                let output_data = tx
-                    .raw()
-                    .outputs_data()
-                    .get(output_index as usize)
-                    .expect("get output data by index should be OK");
-
-                if let Some(prefix) = filter_prefix.as_ref() {
-                    match filter_script_type {
-                        ScriptType::Lock => {
-                            if !extract_raw_data(&output.lock())
-                                .as_slice()
-                                .starts_with(prefix)
-                            {
-                                return None;
-                            }
-                        }
-                        ScriptType::Type => {
-                            if output.type_().is_none()
-                                || !extract_raw_data(&output.type_().to_opt().unwrap())
-                                    .as_slice()
-                                    .starts_with(prefix)
-                            {
-                                return None;
-                            }
-                        }
-                    }
+                let output_data = if filter_options.requires_output_data() {
+                    Some(snapshot.get_cell_data(&out_point)?)
+                } else {
+                    None
+                };
+                if !filter_options.match_cell(
+                    &cell.output,
+                    cell.data_len as usize,
+                    output_data.map(|data| data.raw_data()).as_deref(),
+                ) {
+                    return None;
                 }
 
-                if let Some([r0, r1]) = filter_output_data_len_range {
-                    if output_data.len() < r0 || output_data.len() >= r1 {
-                        return None;
-                    }
+                Some(Unpack::<core::Capacity>::unpack(&cell.output.capacity()).as_u64())
+            })
+            .sum();
+
+        Ok((capacity + pending_capacity).into())
+    }
+
+    fn get_udt_balance(&self, search_key: SearchKey, udt_type_script: Script) -> Result<Uint128> {
+        let search_key = self.resolve_search_key(search_key)?;
+        if !matches!(search_key.script_type, ScriptType::Lock) {
+            return Err(Error::invalid_params(
+                "search_key.script_type should be lock",
+            ));
+        }
+        let udt_type_script: packed::Script = udt_type_script.into();
+        let is_udt_cell = |output: &packed::CellOutput| {
+            output.type_().to_opt().as_ref() == Some(&udt_type_script)
+        };
+        let pending_cells = self
+            .get_pending_cells(&search_key)?
+            .into_iter()
+            .filter(|cell| is_udt_cell(&cell.output.clone().into()))
+            .filter_map(|cell| cell.output_data.map(JsonBytes::into_bytes));
+        let pending_spent_out_points = self.get_pending_spent_out_points();
+
+        let lock_script: packed::Script = search_key.script.clone().into();
+        if lock_script.args().len() > MAX_PREFIX_SEARCH_SIZE {
+            return Err(Error::invalid_params(format!(
+                "search_key.script.args len should be less than {}",
+                MAX_PREFIX_SEARCH_SIZE
+            )));
+        }
+        let lock_prefix = extract_raw_data(&lock_script);
+        let filter_options = build_filter_options(&search_key)?;
+
+        // the udt cells are looked up by the type script index, which is much smaller than
+        // the lock script one for the locks holding many kinds of cells
+        let mut prefix = vec![KeyPrefix::CellTypeScript as u8];
+        prefix.extend_from_slice(extract_raw_data(&udt_type_script).as_slice());
+        let key_len = prefix.len() + 16;
+        let snapshot = self.storage.snapshot();
+        let cells = snapshot
+            .iter(&prefix, Direction::Forward)
+            .take_while(|(key, _value)| key.starts_with(&prefix))
+            // the longer keys belong to the type scripts which args start with the udt args
+            .filter(|(key, _value)| key.len() == key_len)
+            .filter_map(|(key, value)| {
+                let cell = CellValue::from_slice(&value)?;
+                let output_index = u32::from_be_bytes(
+                    key[key.len() - 4..]
+                        .try_into()
+                        .expect("stored output_index"),
+                );
+                let out_point = cell.out_point(output_index);
+                if pending_spent_out_points.contains(&out_point) {
+                    return None;
+                }
+                let block_number = u64::from_be_bytes(
+                    key[key.len() - 16..key.len() - 8]
+                        .try_into()
+                        .expect("stored block_number"),
+                );
+                if !filter_options.match_block_number(block_number) {
+                    return None;
                 }
 
-                if let Some([r0, r1]) = filter_output_capacity_range {
-                    let capacity: core::Capacity = output.capacity().unpack();
-                    if capacity < r0 || capacity >= r1 {
-                        return None;
-                    }
+                if !extract_raw_data(&cell.output.lock()).starts_with(&lock_prefix) {
+                    return None;
                 }
 
-                if let Some([r0, r1]) = filter_block_range {
-                    if block_number < r0 || block_number >= r1 {
-                        return None;
-                    }
+                // the data is always required by the udt amount
+                let output_data = snapshot.get_cell_data(&out_point)?.raw_data();
+                if !filter_options.match_cell(&cell.output, output_data.len(), Some(&output_data)) {
+                    return None;
                 }
 
-                Some(Unpack::<core::Capacity>::unpack(&output.capacity()).as_u64())
+                Some(output_data)
+            });
+
+        let balance = cells
+            .chain(pending_cells)
+            .filter_map(|data| parse_udt_amount(&data))
+            .try_fold(0u128, |balance, amount| balance.checked_add(amount))
+            .ok_or_else(|| Error::invalid_params("the udt balance overflows"))?;
+        Ok(balance.into())
+    }
+
+    fn add_watch_wallet(
+        &self,
+        xpub: String,
+        path: String,
+        gap_limit: Uint32,
+        block_number: BlockNumber,
+    ) -> Result<()> {
+        let gap_limit = gap_limit.value();
+        if gap_limit == 0 || gap_limit > MAX_GAP_LIMIT {
+            return Err(Error::invalid_params(format!(
+                "gap_limit should be in [1, {}]",
+                MAX_GAP_LIMIT
+            )));
+        }
+        let wallet = Self::parse_watch_wallet(&xpub, &path, gap_limit)?;
+        if !self.storage.add_watch_wallet(&wallet, block_number.value()) {
+            return Err(Error::invalid_params("the wallet is already registered"));
+        }
+        Ok(())
+    }
+
+    fn get_watch_wallets(&self) -> Result<Vec<WatchWalletStatus>> {
+        Ok(self
+            .storage
+            .get_watch_wallets()
+            .into_iter()
+            .map(|(wallet, derived_count)| WatchWalletStatus {
+                xpub: wallet.xpub.to_string(),
+                path: wallet.path.to_string(),
+                gap_limit: wallet.gap_limit.into(),
+                derived_count: derived_count.into(),
             })
-            .sum();
+            .collect())
+    }
 
-        Ok(capacity.into())
+    fn remove_watch_wallet(&self, xpub: String, path: String) -> Result<()> {
+        let wallet = Self::parse_watch_wallet(&xpub, &path, 0)?;
+        if !self.storage.remove_watch_wallet(&wallet) {
+            return Err(Error::invalid_params("the wallet is not registered"));
+        }
+        Ok(())
     }
 }
 
 impl NetRpc for NetRpcImpl {
+    fn local_node_info(&self) -> Result<LocalNode> {
+        Ok(LocalNode {
+            version: self.network_controller.version().to_owned(),
+            node_id: self.network_controller.node_id(),
+            active: self.network_controller.is_active(),
+            addresses: self
+                .network_controller
+                .public_urls(MAX_ADDRS)
+                .into_iter()
+                .map(|(address, score)| NodeAddress {
+                    address,
+                    score: u64::from(score).into(),
+                })
+                .collect(),
+            protocols: self
+                .network_controller
+                .protocols()
+                .into_iter()
+                .map(|(protocol_id, name, versions)| LocalNodeProtocol {
+                    id: (protocol_id.value() as u64).into(),
+                    name,
+                    support_versions: versions,
+                })
+                .collect(),
+            connections: (self.network_controller.connected_peers().len() as u64).into(),
+        })
+    }
+
     fn get_peers(&self) -> Result<Vec<RemoteNode>> {
         let peers: Vec<RemoteNode> = self
             .network_controller
@@ -801,6 +1374,9 @@ impl NetRpc for NetRpcImpl {
                         .as_millis() as u64)
                         .into(),
                     sync_state: self.peers.get_state(peer_index).map(|state| PeerSyncState {
+                        announced_tip_header: state
+                            .get_last_state()
+                            .map(|last_state| last_state.tip_header.header().to_owned().into()),
                         requested_best_known_header: state
                             .get_prove_request()
                             .map(|request| request.get_last_header().header().to_owned().into()),
@@ -821,18 +1397,257 @@ impl NetRpc for NetRpcImpl {
             .collect();
         Ok(peers)
     }
+
+    fn add_node(&self, peer_id: String, address: String) -> Result<()> {
+        let peer_id = peer_id.parse::<PeerId>().map_err(|_| {
+            Error::invalid_params(format!("expected a valid peer id, got {}", peer_id))
+        })?;
+        let mut address = address.parse::<Multiaddr>().map_err(|_| {
+            Error::invalid_params(format!("expected a valid multiaddr, got {}", address))
+        })?;
+        address.push(Protocol::P2P(Cow::Borrowed(peer_id.as_bytes())));
+        self.network_controller.add_node(address);
+        Ok(())
+    }
+
+    fn remove_node(&self, peer_id: String) -> Result<()> {
+        let peer_id = peer_id.parse::<PeerId>().map_err(|_| {
+            Error::invalid_params(format!("expected a valid peer id, got {}", peer_id))
+        })?;
+        self.network_controller.remove_node(&peer_id);
+        Ok(())
+    }
+
+    fn set_ban(
+        &self,
+        address: String,
+        command: String,
+        ban_time: Option<Timestamp>,
+        absolute: Option<bool>,
+        reason: Option<String>,
+    ) -> Result<()> {
+        match parse_ban_command(
+            &address,
+            &command,
+            ban_time,
+            absolute,
+            unix_time_as_millis(),
+        )? {
+            BanCommand::Insert { address, ban_until } => self
+                .network_controller
+                .ban(address, ban_until, reason.unwrap_or_default())
+                .map_err(|err| Error::invalid_params(format!("failed to ban since {}", err))),
+            BanCommand::Delete { address } => {
+                self.network_controller.unban(&address);
+                Ok(())
+            }
+        }
+    }
+
+    fn get_banned_addresses(&self) -> Result<Vec<BannedAddr>> {
+        Ok(self
+            .network_controller
+            .get_banned_addrs()
+            .into_iter()
+            .map(|banned| BannedAddr {
+                address: banned.address.to_string(),
+                ban_until: banned.ban_until.into(),
+                ban_reason: banned.ban_reason,
+                created_at: banned.created_at.into(),
+            })
+            .collect())
+    }
+
+    fn clear_banned_addresses(&self) -> Result<()> {
+        self.network_controller.clear_banned_addrs();
+        Ok(())
+    }
+}
+
+const MAX_ADDRS: usize = 50;
+const DEFAULT_BAN_DURATION: u64 = 24 * 60 * 60 * 1000; // 1 day
+
+/// The validated arguments of `set_ban`, the address is an IP network, e.g. `192.168.0.2` or
+/// `192.168.0.0/24`.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum BanCommand<A> {
+    Insert { address: A, ban_until: u64 },
+    Delete { address: A },
+}
+
+pub(crate) fn parse_ban_command<A: FromStr>(
+    address: &str,
+    command: &str,
+    ban_time: Option<Timestamp>,
+    absolute: Option<bool>,
+    now: u64,
+) -> Result<BanCommand<A>> {
+    let parsed_address = address.parse().map_err(|_| {
+        Error::invalid_params(format!("expected a valid IP address, got {}", address))
+    })?;
+    match command {
+        "insert" => {
+            let ban_until = if absolute.unwrap_or(false) {
+                ban_time.unwrap_or_default().into()
+            } else {
+                now + ban_time
+                    .unwrap_or_else(|| DEFAULT_BAN_DURATION.into())
+                    .value()
+            };
+            Ok(BanCommand::Insert {
+                address: parsed_address,
+                ban_until,
+            })
+        }
+        "delete" => Ok(BanCommand::Delete {
+            address: parsed_address,
+        }),
+        _ => Err(Error::invalid_params(
+            "expected `insert` or `delete` as the value of command",
+        )),
+    }
 }
 
 const MAX_PREFIX_SEARCH_SIZE: usize = u16::max_value() as usize;
+// the scripts of the watch-only wallets are derived in the filtering, a large gap limit slows it
+// down
+const MAX_GAP_LIMIT: u32 = 1000;
+// the size of the secp256k1 recoverable signature
+const SIGNATURE_SIZE: usize = 65;
+
+const CURSOR_VERSION: u8 = 1;
+// version + tip block number + tip block hash + rollback count
+const CURSOR_HEADER_LEN: usize = 1 + 8 + 32 + 8;
+const CURSOR_ROLLED_BACK_ERROR_CODE: i64 = -32001;
+// the first byte of the cursor keys of the pending cells, which is not used by any `KeyPrefix`
+const PENDING_CELL_CURSOR_PREFIX: u8 = 0xff;
+
+/// The opaque pagination cursor, which ties the key of the last item of a page to the chain
+/// state the page is read at:
+///
+/// version (1) | tip block number (8) | tip block hash (32) | rollback count (8) | key
+struct Cursor {
+    tip_number: core::BlockNumber,
+    tip_hash: packed::Byte32,
+    rollback_count: u64,
+    key: Vec<u8>,
+}
+
+impl Cursor {
+    // an empty cursor is returned when the page is empty
+    fn encode(snapshot: &Snapshot, key: Vec<u8>) -> JsonBytes {
+        if key.is_empty() {
+            return JsonBytes::default();
+        }
+        let (tip_number, tip_hash): (core::BlockNumber, packed::Byte32) = snapshot
+            .get_tip_header()
+            .map(|header| (header.raw().number().unpack(), header.calc_header_hash()))
+            .unwrap_or_default();
+        let mut encoded = Vec::with_capacity(CURSOR_HEADER_LEN + key.len());
+        encoded.push(CURSOR_VERSION);
+        encoded.extend_from_slice(&tip_number.to_be_bytes());
+        encoded.extend_from_slice(tip_hash.as_slice());
+        encoded.extend_from_slice(&snapshot.get_rollback_count().to_be_bytes());
+        encoded.extend_from_slice(&key);
+        JsonBytes::from_vec(encoded)
+    }
+
+    fn decode(json_bytes: JsonBytes) -> Result<Self> {
+        let bytes = json_bytes.as_bytes();
+        if bytes.len() <= CURSOR_HEADER_LEN {
+            return Err(Error::invalid_params("invalid cursor"));
+        }
+        if bytes[0] != CURSOR_VERSION {
+            return Err(Error::invalid_params(format!(
+                "unsupported cursor version {}, expect {}",
+                bytes[0], CURSOR_VERSION
+            )));
+        }
+        Ok(Self {
+            tip_number: u64::from_be_bytes(bytes[1..9].try_into().expect("checked length")),
+            tip_hash: packed::Byte32::from_slice(&bytes[9..41]).expect("checked length"),
+            rollback_count: u64::from_be_bytes(bytes[41..49].try_into().expect("checked length")),
+            key: bytes[CURSOR_HEADER_LEN..].to_vec(),
+        })
+    }
+
+    // the pages after a rollback to a block not higher than the tip of the cursor may skip or
+    // duplicate items, the pagination should be restarted. The indexed data is only changed by
+    // the logged rollbacks, a reorg above the filtered blocks doesn't invalidate the cursor.
+    fn verify(&self, snapshot: &Snapshot) -> Result<()> {
+        match snapshot.get_rolled_back_to(self.rollback_count) {
+            Some(rolled_back_to) if rolled_back_to <= self.tip_number => Err(Error {
+                code: ErrorCode::ServerError(CURSOR_ROLLED_BACK_ERROR_CODE),
+                message: format!(
+                    "the chain state of the cursor (block {} {:#x}) has been rolled back to block {}, \
+                     please query from the first page",
+                    self.tip_number, self.tip_hash, rolled_back_to
+                ),
+                data: None,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+// the sort key of the pending cells: tx hash | output index (big endian)
+fn pending_cell_key(out_point: &packed::OutPoint) -> Vec<u8> {
+    let index: u32 = out_point.index().unpack();
+    [out_point.tx_hash().as_slice(), &index.to_be_bytes()].concat()
+}
+
+fn storage_error(err: crate::error::Error) -> Error {
+    Error {
+        code: ErrorCode::InternalError,
+        message: err.to_string(),
+        data: None,
+    }
+}
+
+// the same as `DaoCalculator::calculate_maximum_withdraw` of the full node, only the
+// occupied capacity isn't counted for the interest
+fn calculate_maximum_withdraw(
+    output: &packed::CellOutput,
+    output_data_len: usize,
+    deposit_header: &core::HeaderView,
+    withdrawing_header: &core::HeaderView,
+) -> Result<core::Capacity> {
+    let capacity_error = |err: core::CapacityError| {
+        Error::invalid_params(format!(
+            "failed to calculate the withdraw capacity: {}",
+            err
+        ))
+    };
+    let deposit_ar = extract_dao_ar(&deposit_header.dao());
+    let withdrawing_ar = extract_dao_ar(&withdrawing_header.dao());
+    let occupied_capacity = output
+        .occupied_capacity(core::Capacity::bytes(output_data_len).map_err(capacity_error)?)
+        .map_err(capacity_error)?;
+    let output_capacity: core::Capacity = output.capacity().unpack();
+    let counted_capacity = output_capacity
+        .safe_sub(occupied_capacity)
+        .map_err(capacity_error)?;
+    let withdraw_counted_capacity =
+        u128::from(counted_capacity.as_u64()) * u128::from(withdrawing_ar) / u128::from(deposit_ar);
+    core::Capacity::shannons(withdraw_counted_capacity as u64)
+        .safe_add(occupied_capacity)
+        .map_err(capacity_error)
+}
+
+// the accumulated rate is the second u64 of the dao field
+fn extract_dao_ar(dao: &packed::Byte32) -> u64 {
+    u64::from_le_bytes(dao.as_slice()[8..16].try_into().expect("dao field"))
+}
 
-// a helper fn to build query options from search paramters, returns prefix, from_key, direction and skip offset
+// a helper fn to build query options from search paramters, returns prefix, from_key, direction
+// and whether to skip the from_key, which is the key of the last item of the previous page
 fn build_query_options(
     search_key: &SearchKey,
     lock_prefix: KeyPrefix,
     type_prefix: KeyPrefix,
     order: Order,
-    after_cursor: Option<JsonBytes>,
-) -> Result<(Vec<u8>, Vec<u8>, Direction, usize)> {
+    after_cursor: Option<&Cursor>,
+) -> Result<(Vec<u8>, Vec<u8>, Direction, bool)> {
     let mut prefix = match search_key.script_type {
         ScriptType::Lock => vec![lock_prefix as u8],
         ScriptType::Type => vec![type_prefix as u8],
@@ -846,11 +1661,20 @@ fn build_query_options(
         )));
     }
     prefix.extend_from_slice(extract_raw_data(&script).as_slice());
+    if let Some(cursor) = after_cursor {
+        if !cursor.key.starts_with(&prefix) {
+            return Err(Error::invalid_params(
+                "the cursor doesn't belong to the search_key",
+            ));
+        }
+    }
 
-    let (from_key, direction, skip) = match order {
+    // the item of the cursor may be deleted since the previous page, so it's skipped by the
+    // key instead of the offset
+    let (from_key, direction, skip_from_key) = match order {
         Order::Asc => after_cursor.map_or_else(
-            || (prefix.clone(), Direction::Forward, 0),
-            |json_bytes| (json_bytes.as_bytes().into(), Direction::Forward, 1),
+            || (prefix.clone(), Direction::Forward, false),
+            |cursor| (cursor.key.clone(), Direction::Forward, true),
         ),
         Order::Desc => after_cursor.map_or_else(
             || {
@@ -861,85 +1685,487 @@ fn build_query_options(
                     ]
                     .concat(),
                     Direction::Reverse,
-                    0,
+                    false,
                 )
             },
-            |json_bytes| (json_bytes.as_bytes().into(), Direction::Reverse, 1),
+            |cursor| (cursor.key.clone(), Direction::Reverse, true),
         ),
     };
 
-    Ok((prefix, from_key, direction, skip))
-}
-
-// a helper fn to build filter options from search paramters, returns prefix, output_data_len_range, output_capacity_range and block_range
-#[allow(clippy::type_complexity)]
-fn build_filter_options(
-    search_key: SearchKey,
-) -> Result<(
-    Option<Vec<u8>>,
-    Option<[usize; 2]>,
-    Option<[core::Capacity; 2]>,
-    Option<[core::BlockNumber; 2]>,
-)> {
-    let SearchKey {
-        script: _,
-        script_type: _,
-        filter,
-        group_by_transaction: _,
-    } = search_key;
-    let filter = filter.unwrap_or_default();
-    let filter_script_prefix = if let Some(script) = filter.script {
-        let script: packed::Script = script.into();
+    Ok((prefix, from_key, direction, skip_from_key))
+}
+
+// both sUDT and xUDT cells store the amount as the first 16 bytes (u128, little endian) of the
+// output data, followed by the optional extension data
+fn parse_udt_amount(data: &[u8]) -> Option<u128> {
+    data.get(0..16)
+        .map(|amount| u128::from_le_bytes(amount.try_into().expect("checked length")))
+}
+
+// the filters of `search_key.filter`, which are checked against the cells
+struct FilterOptions {
+    // the type of the filter script, which is the other script of the cell
+    script_type: ScriptType,
+    script_prefix: Option<Vec<u8>>,
+    script_len_range: Option<[usize; 2]>,
+    output_data: Option<(Vec<u8>, SearchMode)>,
+    output_data_len_range: Option<[usize; 2]>,
+    output_capacity_range: Option<[core::Capacity; 2]>,
+    block_range: Option<[core::BlockNumber; 2]>,
+}
+
+impl FilterOptions {
+    // whether the output data of the cell is required by `match_cell`
+    fn requires_output_data(&self) -> bool {
+        self.output_data.is_some()
+    }
+
+    // whether the output or the output data of the cell is required by the filters
+    fn has_cell_filters(&self) -> bool {
+        self.script_prefix.is_some()
+            || self.script_len_range.is_some()
+            || self.output_data.is_some()
+            || self.output_data_len_range.is_some()
+            || self.output_capacity_range.is_some()
+    }
+
+    // `output_data` could be `None` if it's not required by the filters
+    fn match_cell(
+        &self,
+        output: &packed::CellOutput,
+        output_data_len: usize,
+        output_data: Option<&[u8]>,
+    ) -> bool {
+        let script = match self.script_type {
+            ScriptType::Lock => Some(output.lock()),
+            ScriptType::Type => output.type_().to_opt(),
+        };
+        let script_raw_data = script.as_ref().map(extract_raw_data);
+
+        if let Some(prefix) = self.script_prefix.as_ref() {
+            match script_raw_data.as_ref() {
+                Some(raw_data) if raw_data.starts_with(prefix) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some([r0, r1]) = self.script_len_range {
+            let script_len = script_raw_data
+                .as_ref()
+                .map(|raw_data| raw_data.len())
+                .unwrap_or_default();
+            if script_len < r0 || script_len >= r1 {
+                return false;
+            }
+        }
+
+        if let Some((data, mode)) = self.output_data.as_ref() {
+            let output_data = output_data.expect("output data is required by the filter");
+            let matched = match mode {
+                SearchMode::Prefix => output_data.starts_with(data),
+                SearchMode::Exact => output_data == data.as_slice(),
+            };
+            if !matched {
+                return false;
+            }
+        }
+
+        if let Some([r0, r1]) = self.output_data_len_range {
+            if output_data_len < r0 || output_data_len >= r1 {
+                return false;
+            }
+        }
+
+        if let Some([r0, r1]) = self.output_capacity_range {
+            let capacity: core::Capacity = output.capacity().unpack();
+            if capacity < r0 || capacity >= r1 {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn match_block_number(&self, block_number: core::BlockNumber) -> bool {
+        self.block_range
+            .map(|[r0, r1]| block_number >= r0 && block_number < r1)
+            .unwrap_or(true)
+    }
+}
+
+// a helper fn to build filter options from search paramters
+fn build_filter_options(search_key: &SearchKey) -> Result<FilterOptions> {
+    let default_filter = SearchKeyFilter::default();
+    let filter = search_key.filter.as_ref().unwrap_or(&default_filter);
+    let script_type = match search_key.script_type {
+        ScriptType::Lock => ScriptType::Type,
+        ScriptType::Type => ScriptType::Lock,
+    };
+    let script_prefix = if let Some(script) = filter.script.as_ref() {
+        let script: packed::Script = script.clone().into();
         if script.args().len() > MAX_PREFIX_SEARCH_SIZE {
             return Err(Error::invalid_params(format!(
                 "search_key.filter.script.args len should be less than {}",
                 MAX_PREFIX_SEARCH_SIZE
             )));
         }
-        let mut prefix = Vec::new();
-        prefix.extend_from_slice(extract_raw_data(&script).as_slice());
-        Some(prefix)
+        Some(extract_raw_data(&script))
     } else {
         None
     };
-
-    let filter_output_data_len_range = filter.output_data_len_range.map(|[r0, r1]| {
+    let output_data = filter.output_data.as_ref().map(|data| {
+        (
+            data.as_bytes().to_vec(),
+            filter.output_data_filter_mode.unwrap_or(SearchMode::Prefix),
+        )
+    });
+    let to_usize_range = |[r0, r1]: [Uint64; 2]| {
         [
             Into::<u64>::into(r0) as usize,
             Into::<u64>::into(r1) as usize,
         ]
-    });
-    let filter_output_capacity_range = filter.output_capacity_range.map(|[r0, r1]| {
-        [
-            core::Capacity::shannons(r0.into()),
-            core::Capacity::shannons(r1.into()),
-        ]
-    });
-    let filter_block_range = filter.block_range.map(|r| [r[0].into(), r[1].into()]);
+    };
 
-    Ok((
-        filter_script_prefix,
-        filter_output_data_len_range,
-        filter_output_capacity_range,
-        filter_block_range,
-    ))
+    Ok(FilterOptions {
+        script_type,
+        script_prefix,
+        script_len_range: filter.script_len_range.map(to_usize_range),
+        output_data,
+        output_data_len_range: filter.output_data_len_range.map(to_usize_range),
+        output_capacity_range: filter.output_capacity_range.map(|[r0, r1]| {
+            [
+                core::Capacity::shannons(r0.into()),
+                core::Capacity::shannons(r1.into()),
+            ]
+        }),
+        block_range: filter.block_range.map(|r| [r[0].into(), r[1].into()]),
+    })
 }
 
+#[allow(clippy::mutable_key_type)]
 impl TransactionRpc for TransactionRpcImpl {
-    fn send_transaction(&self, tx: Transaction) -> Result<H256> {
+    fn send_transaction(&self, tx: Transaction) -> Result<SendTransactionResult> {
         let tx: packed::Transaction = tx.into();
         let tx = tx.into_view();
-        let cycles = verify_tx(tx.clone(), &self.swl, &self.consensus)
+        let (cycles, fee) = verify_tx(tx.clone(), &self.swl, &self.consensus, &self.system_scripts)
             .map_err(|e| Error::invalid_params(format!("invalid transaction: {:?}", e)))?;
+
+        // full nodes drop the transactions which fee rate is lower than their min fee rate silently
+        let tx_size = tx.data().serialized_size_in_block() as u64;
+        let min_fee = self.min_fee_rate.fee(tx_size);
+        if fee < min_fee {
+            return Err(Error::invalid_params(format!(
+                "transaction fee {} is lower than the min fee {} (min fee rate: {} shannons/KB, size: {} bytes)",
+                fee.as_u64(),
+                min_fee.as_u64(),
+                self.min_fee_rate.as_u64(),
+                tx_size
+            )));
+        }
+
         self.pending_txs
             .write()
             .expect("pending_txs lock is poisoned")
             .push(tx.clone(), cycles);
 
-        Ok(tx.hash().unpack())
+        Ok(SendTransactionResult {
+            tx_hash: tx.hash().unpack(),
+            fee: fee.as_u64().into(),
+            fee_rate: core::FeeRate::calculate(fee, tx_size).as_u64().into(),
+        })
+    }
+
+    fn get_transaction_status(&self, tx_hash: H256) -> Result<TransactionStatus> {
+        let tx_hash = tx_hash.pack();
+        let relay_state = self
+            .pending_txs
+            .read()
+            .expect("pending_txs lock is poisoned")
+            .get_relay_state(&tx_hash);
+        let (sent_to, accepted_by, retries): (Vec<String>, Vec<String>, u32) = relay_state
+            .as_ref()
+            .map(|state| {
+                (
+                    state.sent_peers.iter().map(|p| p.to_base58()).collect(),
+                    state.accepted_peers.iter().map(|p| p.to_base58()).collect(),
+                    state.retries,
+                )
+            })
+            .unwrap_or_default();
+        let status = if self
+            .swl
+            .storage()
+            .get_transaction_with_header(&tx_hash)
+            .is_some()
+        {
+            TxStatus::Committed
+        } else {
+            match relay_state.map(|state| state.status) {
+                Some(RelayStatus::Pending) => TxStatus::Pending,
+                Some(RelayStatus::Relayed) => TxStatus::Relayed,
+                Some(RelayStatus::Accepted) => TxStatus::Accepted,
+                Some(RelayStatus::Rejected) => TxStatus::Rejected,
+                None => TxStatus::Unknown,
+            }
+        };
+        Ok(TransactionStatus {
+            status,
+            sent_to,
+            accepted_by,
+            retries: retries.into(),
+        })
+    }
+
+    fn build_transfer(
+        &self,
+        from_scripts: Vec<Script>,
+        to: Script,
+        capacity: Capacity,
+        fee_rate: Uint64,
+    ) -> Result<TransferTransaction> {
+        if from_scripts.is_empty() {
+            return Err(Error::invalid_params("from_scripts should not be empty"));
+        }
+        let sighash = self
+            .system_scripts
+            .secp256k1_blake160_sighash_all
+            .as_ref()
+            .ok_or_else(|| {
+                Error::invalid_params(
+                    "the secp256k1 blake160 sighash lock is not deployed in the genesis block",
+                )
+            })?;
+        let from_scripts: Vec<packed::Script> = from_scripts.into_iter().map(Into::into).collect();
+        for (i, script) in from_scripts.iter().enumerate() {
+            if !is_sighash_lock(script, &sighash.type_hash) {
+                return Err(Error::invalid_params(format!(
+                    "from_scripts[{}] should be a secp256k1 blake160 sighash lock script",
+                    i
+                )));
+            }
+        }
+        if fee_rate.value() < self.min_fee_rate.as_u64() {
+            return Err(Error::invalid_params(format!(
+                "fee_rate should not be lower than the min fee rate {} shannons/KB",
+                self.min_fee_rate.as_u64()
+            )));
+        }
+        let fee_rate = core::FeeRate::from_u64(fee_rate.value());
+        let capacity = core::Capacity::shannons(capacity.value());
+        let cell_dep = sighash.cell_dep.clone();
+
+        let to_output = packed::CellOutput::new_builder()
+            .capacity(capacity.pack())
+            .lock(to.into())
+            .build();
+        let occupied_capacity = to_output
+            .occupied_capacity(core::Capacity::zero())
+            .map_err(|_| Error::invalid_params("the occupied capacity of the output overflows"))?;
+        if capacity < occupied_capacity {
+            return Err(Error::invalid_params(format!(
+                "capacity should not be lower than the occupied capacity {} of the output",
+                occupied_capacity.as_u64()
+            )));
+        }
+        let change_output = packed::CellOutput::new_builder()
+            .lock(from_scripts[0].clone())
+            .build();
+        let change_occupied_capacity = change_output
+            .occupied_capacity(core::Capacity::zero())
+            .expect("the occupied capacity of a sighash lock cell");
+
+        let spent_out_points = self
+            .pending_txs
+            .read()
+            .expect("pending_txs lock is poisoned")
+            .spent_out_points();
+        // the cellbase outputs can't be spent until they are mature
+        let tip_epoch = self.swl.storage().get_tip_header().into_view().epoch();
+        let is_mature = |block_hash: &packed::Byte32| {
+            self.swl
+                .get_header(block_hash)
+                .map(|header| {
+                    let threshold = header.epoch().to_rational()
+                        + self.consensus.cellbase_maturity().to_rational();
+                    tip_epoch.to_rational() >= threshold
+                })
+                .unwrap_or(false)
+        };
+        // the size of the transaction is increased by each input: the cell input, the offset and
+        // the header of its witness, and the placeholder witness for the first input of a group
+        let mut tx_size = build_transfer_tx(
+            &[],
+            &cell_dep,
+            vec![to_output.clone(), change_output.clone()],
+        )
+        .data()
+        .serialized_size_in_block();
+        let placeholder_size = placeholder_witness().len();
+
+        let snapshot = self.swl.storage().snapshot();
+        let mut inputs = Vec::new();
+        let mut inputs_capacity = core::Capacity::zero();
+        let mut seen_scripts = HashSet::new();
+        for script in from_scripts
+            .iter()
+            .filter(|script| seen_scripts.insert(*script))
+        {
+            let mut is_first_of_group = true;
+            let mut prefix = vec![KeyPrefix::CellLockScript as u8];
+            prefix.extend_from_slice(&extract_raw_data(script));
+            for (key, value) in snapshot
+                .iter(&prefix, Direction::Forward)
+                .take_while(|(key, _value)| key.starts_with(&prefix))
+            {
+                let cell = match CellValue::from_slice(&value) {
+                    Some(cell) => cell,
+                    None => continue,
+                };
+                // the prefix also matches the scripts with longer args, and only the plain
+                // capacity cells are collected
+                if &cell.output.lock() != script
+                    || cell.output.type_().to_opt().is_some()
+                    || cell.data_len != 0
+                {
+                    continue;
+                }
+                let output_index = u32::from_be_bytes(
+                    key[key.len() - 4..]
+                        .try_into()
+                        .expect("stored output_index"),
+                );
+                let tx_index = u32::from_be_bytes(
+                    key[key.len() - 8..key.len() - 4]
+                        .try_into()
+                        .expect("stored tx_index"),
+                );
+                let out_point = packed::OutPoint::new(cell.tx_hash, output_index);
+                if spent_out_points.contains(&out_point)
+                    || (tx_index == 0 && !is_mature(&cell.block_hash))
+                {
+                    continue;
+                }
+                let cell_capacity: core::Capacity = cell.output.capacity().unpack();
+                inputs_capacity = inputs_capacity
+                    .safe_add(cell_capacity)
+                    .map_err(|_| Error::invalid_params("the capacity of the inputs overflows"))?;
+                inputs.push((out_point, script.clone()));
+                tx_size += packed::CellInput::TOTAL_SIZE + 8;
+                if is_first_of_group {
+                    tx_size += placeholder_size;
+                    is_first_of_group = false;
+                }
+
+                // the capacity of the change doesn't change the size of the transaction
+                let fee = fee_rate.fee(tx_size as u64);
+                let required_capacity = capacity
+                    .safe_add(fee)
+                    .and_then(|required| required.safe_add(change_occupied_capacity))
+                    .map_err(|_| Error::invalid_params("the required capacity overflows"))?;
+                if inputs_capacity < required_capacity {
+                    continue;
+                }
+                let change_capacity = inputs_capacity
+                    .safe_sub(capacity)
+                    .and_then(|change| change.safe_sub(fee))
+                    .expect("checked capacity");
+                let change_output = change_output
+                    .as_builder()
+                    .capacity(change_capacity.pack())
+                    .build();
+                let tx = build_transfer_tx(&inputs, &cell_dep, vec![to_output, change_output]);
+                debug_assert_eq!(tx.data().serialized_size_in_block(), tx_size);
+                return Ok(TransferTransaction {
+                    signing_messages: signing_messages(&tx, &inputs),
+                    transaction: tx.data().into(),
+                    fee: fee.as_u64().into(),
+                });
+            }
+        }
+        Err(Error::invalid_params(format!(
+            "the capacity of the live cells {} is not enough for the transfer",
+            inputs_capacity.as_u64()
+        )))
     }
 }
 
+fn is_sighash_lock(script: &packed::Script, type_hash: &packed::Byte32) -> bool {
+    &script.code_hash() == type_hash
+        && script.hash_type() == core::ScriptHashType::Type.into()
+        && script.args().raw_data().len() == 20
+}
+
+fn placeholder_witness() -> Bytes {
+    packed::WitnessArgs::new_builder()
+        .lock(Some(Bytes::from(vec![0u8; SIGNATURE_SIZE]).pack()).pack())
+        .build()
+        .as_bytes()
+}
+
+// the first witness of each lock group is the placeholder of the signature, the others are empty
+fn build_transfer_tx(
+    inputs: &[(packed::OutPoint, packed::Script)],
+    cell_dep: &packed::CellDep,
+    outputs: Vec<packed::CellOutput>,
+) -> core::TransactionView {
+    let placeholder = placeholder_witness();
+    let witnesses = inputs.iter().enumerate().map(|(i, (_out_point, lock))| {
+        if inputs[..i].iter().any(|(_out_point, other)| other == lock) {
+            Bytes::new().pack()
+        } else {
+            placeholder.pack()
+        }
+    });
+    core::TransactionBuilder::default()
+        .cell_dep(cell_dep.clone())
+        .inputs(
+            inputs
+                .iter()
+                .map(|(out_point, _lock)| packed::CellInput::new(out_point.clone(), 0)),
+        )
+        .outputs_data(outputs.iter().map(|_| Bytes::new().pack()))
+        .outputs(outputs)
+        .witnesses(witnesses)
+        .build()
+}
+
+// the message of the secp256k1 blake160 sighash lock is the hash of the transaction hash and the
+// witnesses of the group, each witness is prefixed by its length in u64 little endian
+fn signing_messages(
+    tx: &core::TransactionView,
+    inputs: &[(packed::OutPoint, packed::Script)],
+) -> Vec<SigningMessage> {
+    let mut groups: Vec<(&packed::Script, Vec<usize>)> = Vec::new();
+    for (i, (_out_point, lock)) in inputs.iter().enumerate() {
+        match groups
+            .iter_mut()
+            .find(|(group_lock, _indexes)| *group_lock == lock)
+        {
+            Some((_group_lock, indexes)) => indexes.push(i),
+            None => groups.push((lock, vec![i])),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(lock, indexes)| {
+            let mut hasher = new_blake2b();
+            hasher.update(tx.hash().as_slice());
+            for i in &indexes {
+                let witness = tx.witnesses().get(*i).expect("witness of input").raw_data();
+                hasher.update(&(witness.len() as u64).to_le_bytes());
+                hasher.update(&witness);
+            }
+            let mut message = [0u8; 32];
+            hasher.finalize(&mut message);
+            SigningMessage {
+                lock: lock.clone().into(),
+                witness_index: (indexes[0] as u32).into(),
+                message: message.into(),
+            }
+        })
+        .collect()
+}
+
 impl ChainRpc for ChainRpcImpl {
     fn get_tip_header(&self) -> Result<HeaderView> {
         Ok(self.swl.storage().get_tip_header().into_view().into())
@@ -949,6 +2175,36 @@ impl ChainRpc for ChainRpcImpl {
         Ok(self.swl.get_header(&block_hash.pack()).map(Into::into))
     }
 
+    fn get_header_by_number(&self, block_number: BlockNumber) -> Result<Option<HeaderView>> {
+        Ok(self
+            .swl
+            .get_header_by_number(block_number.into())
+            .map(Into::into))
+    }
+
+    fn get_blockchain_info(&self) -> Result<BlockchainInfo> {
+        let (total_difficulty, tip_header) = self.swl.storage().get_last_state();
+        let tip_header = tip_header.into_view();
+        Ok(BlockchainInfo {
+            chain: self.consensus.identify_name(),
+            genesis_hash: self.consensus.genesis_hash().unpack(),
+            epoch: tip_header.epoch().full_value().into(),
+            tip_header: tip_header.into(),
+            total_difficulty,
+        })
+    }
+
+    fn get_current_epoch(&self) -> Result<EpochView> {
+        let tip_header = self.swl.storage().get_tip_header().into_view();
+        let epoch = tip_header.epoch();
+        Ok(EpochView {
+            number: epoch.number().into(),
+            start_number: (tip_header.number() - epoch.index()).into(),
+            length: epoch.length().into(),
+            compact_target: tip_header.compact_target().into(),
+        })
+    }
+
     fn get_transaction(&self, tx_hash: H256) -> Result<Option<TransactionWithHeader>> {
         let transaction_with_header = self
             .swl
@@ -961,19 +2217,312 @@ impl ChainRpc for ChainRpcImpl {
 
         Ok(transaction_with_header)
     }
+
+    fn get_system_scripts(&self) -> Result<Vec<SystemScriptInfo>> {
+        let system_scripts = [
+            (
+                "secp256k1_blake160_sighash_all",
+                &self.system_scripts.secp256k1_blake160_sighash_all,
+            ),
+            (
+                "secp256k1_blake160_multisig_all",
+                &self.system_scripts.secp256k1_blake160_multisig_all,
+            ),
+            ("dao", &self.system_scripts.dao),
+        ];
+        Ok(system_scripts
+            .into_iter()
+            .filter_map(|(name, system_script)| {
+                system_script.as_ref().map(
+                    |SystemScript {
+                         type_hash,
+                         cell_dep,
+                     }| SystemScriptInfo {
+                        name: name.to_owned(),
+                        code_hash: type_hash.unpack(),
+                        hash_type: ScriptHashType::Type,
+                        cell_dep: cell_dep.clone().into(),
+                    },
+                )
+            })
+            .collect())
+    }
+}
+
+impl DaoRpc for DaoRpcImpl {
+    fn get_dao_cells(&self, lock_script: Script) -> Result<Vec<DaoCell>> {
+        let dao_type_hash = self
+            .consensus
+            .dao_type_hash()
+            .ok_or_else(|| Error::invalid_params("NervosDAO is not enabled in this chain"))?;
+        let dao_type_script = packed::Script::new_builder()
+            .code_hash(dao_type_hash)
+            .hash_type(core::ScriptHashType::Type.into())
+            .build();
+        let lock_script: packed::Script = lock_script.into();
+        let tip_header = self.swl.storage().get_tip_header().into_view();
+        let mut prefix = vec![KeyPrefix::CellLockScript as u8];
+        prefix.extend_from_slice(&extract_raw_data(&lock_script));
+
+        let snapshot = self.swl.storage().snapshot();
+        let mut dao_cells = Vec::new();
+        let mut missing_headers = Vec::new();
+        for (key, value) in snapshot
+            .iter(&prefix, Direction::Forward)
+            .take_while(|(key, _value)| key.starts_with(&prefix))
+        {
+            let cell = match CellValue::from_slice(&value) {
+                Some(cell) => cell,
+                None => continue,
+            };
+            // the DAO cells are recognized without loading the transactions
+            if cell.output.type_().to_opt().as_ref() != Some(&dao_type_script) || cell.data_len != 8
+            {
+                continue;
+            }
+            let tx_hash = cell.tx_hash;
+            let output = cell.output;
+            let output_index = u32::from_be_bytes(
+                key[key.len() - 4..]
+                    .try_into()
+                    .expect("stored output_index"),
+            );
+            let tx_index = u32::from_be_bytes(
+                key[key.len() - 8..key.len() - 4]
+                    .try_into()
+                    .expect("stored tx_index"),
+            );
+            let block_number = u64::from_be_bytes(
+                key[key.len() - 16..key.len() - 8]
+                    .try_into()
+                    .expect("stored block_number"),
+            );
+            // the dangling cells are skipped and reported by `check`
+            let tx = match snapshot
+                .get(Key::TxHash(&tx_hash).into_vec())
+                .expect("get tx should be OK")
+            {
+                Some(value) => packed::Transaction::from_slice(&value[12..])
+                    .expect("from stored tx slice should be OK"),
+                None => continue,
+            };
+            let output_data = tx
+                .raw()
+                .outputs_data()
+                .get(output_index as usize)
+                .expect("get output data by index should be OK")
+                .raw_data();
+
+            let cell_header = self.swl.get_header(&cell.block_hash);
+            if cell_header.is_none() {
+                missing_headers.push(cell.block_hash.clone());
+            }
+            // the data of a withdrawing cell is the number of its deposit block
+            let deposit_block_number =
+                u64::from_le_bytes(output_data[..].try_into().expect("checked length"));
+            let (phase, deposit_header, withdrawing_header) = if deposit_block_number == 0 {
+                (DaoPhase::Deposited, cell_header, Some(tip_header.clone()))
+            } else {
+                let deposit_header =
+                    self.find_deposit_header(&tx, deposit_block_number, &mut missing_headers);
+                (DaoPhase::Withdrawing, deposit_header, cell_header)
+            };
+            let (header_deps, maximum_withdraw) = match (deposit_header, withdrawing_header) {
+                (Some(deposit_header), Some(withdrawing_header)) => {
+                    let header_deps = if phase == DaoPhase::Deposited {
+                        vec![deposit_header.hash().unpack()]
+                    } else {
+                        vec![
+                            deposit_header.hash().unpack(),
+                            withdrawing_header.hash().unpack(),
+                        ]
+                    };
+                    let maximum_withdraw = calculate_maximum_withdraw(
+                        &output,
+                        output_data.len(),
+                        &deposit_header,
+                        &withdrawing_header,
+                    )?;
+                    (Some(header_deps), Some(maximum_withdraw.as_u64().into()))
+                }
+                _ => (None, None),
+            };
+
+            dao_cells.push(DaoCell {
+                cell: Cell {
+                    output: output.into(),
+                    output_data: Some(JsonBytes::from_bytes(output_data)),
+                    out_point: packed::OutPoint::new(tx_hash, output_index).into(),
+                    block_number: Some(block_number.into()),
+                    tx_index: Some(tx_index.into()),
+                },
+                phase,
+                header_deps,
+                maximum_withdraw,
+            });
+        }
+        if !missing_headers.is_empty() {
+            self.peers.fetch_headers(missing_headers);
+        }
+        Ok(dao_cells)
+    }
+}
+
+impl DaoRpcImpl {
+    // the deposit header is one of the header deps of the withdrawing transaction, the header
+    // deps which are not stored are collected to be fetched when it's not found
+    fn find_deposit_header(
+        &self,
+        withdrawing_tx: &packed::Transaction,
+        deposit_block_number: core::BlockNumber,
+        missing_headers: &mut Vec<packed::Byte32>,
+    ) -> Option<core::HeaderView> {
+        let mut missing = Vec::new();
+        for block_hash in withdrawing_tx.raw().header_deps().into_iter() {
+            match self.swl.get_header(&block_hash) {
+                Some(header) if header.number() == deposit_block_number => return Some(header),
+                Some(_) => {}
+                None => missing.push(block_hash),
+            }
+        }
+        missing_headers.extend(missing);
+        None
+    }
+}
+
+impl IndexerRpc for IndexerRpcImpl {
+    fn get_indexer_tip(&self) -> Result<Option<IndexerTip>> {
+        Ok(self.indexer_tip().map(|header| IndexerTip {
+            block_hash: header.hash().unpack(),
+            block_number: header.number().into(),
+        }))
+    }
+
+    fn get_cells(
+        &self,
+        search_key: SearchKey,
+        order: Order,
+        limit: Uint32,
+        after_cursor: Option<JsonBytes>,
+    ) -> Result<Pagination<IndexerCell>> {
+        let cells = self.block_filter_rpc.get_cells(
+            indexer_search_key(search_key),
+            order,
+            limit,
+            after_cursor,
+        )?;
+        Ok(Pagination {
+            objects: cells
+                .objects
+                .into_iter()
+                .map(|cell| IndexerCell {
+                    output: cell.output,
+                    output_data: cell.output_data,
+                    out_point: cell.out_point,
+                    block_number: cell.block_number.expect("not a pending cell"),
+                    tx_index: cell.tx_index.expect("not a pending cell"),
+                })
+                .collect(),
+            last_cursor: cells.last_cursor,
+            total_count: None,
+        })
+    }
+
+    fn get_transactions(
+        &self,
+        search_key: SearchKey,
+        order: Order,
+        limit: Uint32,
+        after_cursor: Option<JsonBytes>,
+    ) -> Result<Pagination<IndexerTx>> {
+        let txs = self.block_filter_rpc.get_transactions(
+            indexer_search_key(search_key),
+            order,
+            limit,
+            after_cursor,
+        )?;
+        Ok(Pagination {
+            objects: txs
+                .objects
+                .into_iter()
+                .map(|tx| match tx {
+                    Tx::Ungrouped(tx) => IndexerTx::Ungrouped(IndexerTxWithCell {
+                        tx_hash: tx.transaction.hash,
+                        block_number: tx.block_number,
+                        tx_index: tx.tx_index,
+                        io_index: tx.io_index,
+                        io_type: tx.io_type,
+                    }),
+                    Tx::Grouped(tx) => IndexerTx::Grouped(IndexerTxWithCells {
+                        tx_hash: tx.transaction.hash,
+                        block_number: tx.block_number,
+                        tx_index: tx.tx_index,
+                        cells: tx.cells,
+                    }),
+                })
+                .collect(),
+            last_cursor: txs.last_cursor,
+            total_count: None,
+        })
+    }
+
+    fn get_cells_capacity(&self, search_key: SearchKey) -> Result<Option<IndexerCellsCapacity>> {
+        let header = match self.indexer_tip() {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        let capacity = self
+            .block_filter_rpc
+            .get_cells_capacity(indexer_search_key(search_key))?;
+        Ok(Some(IndexerCellsCapacity {
+            capacity,
+            block_hash: header.hash().unpack(),
+            block_number: header.number().into(),
+        }))
+    }
+}
+
+impl IndexerRpcImpl {
+    // the cells are indexed to the minimal filtered block number of all filter scripts
+    fn indexer_tip(&self) -> Option<core::HeaderView> {
+        self.swl
+            .storage()
+            .get_filter_scripts()
+            .values()
+            .min()
+            .and_then(|block_number| self.swl.get_header_by_number(*block_number))
+    }
+}
+
+// ckb-indexer knows nothing about the pending transactions and the total count
+fn indexer_search_key(search_key: SearchKey) -> SearchKey {
+    SearchKey {
+        include_pending: None,
+        with_total_count: None,
+        ..search_key
+    }
 }
 
 pub(crate) struct Service {
     listen_address: String,
+    indexer_compatible: bool,
 }
 
 impl Service {
     pub fn new(listen_address: &str) -> Self {
         Self {
             listen_address: listen_address.to_string(),
+            indexer_compatible: false,
         }
     }
 
+    /// Replaces `get_cells`, `get_transactions` and `get_cells_capacity` with the ones of
+    /// ckb-indexer, and adds `get_indexer_tip`.
+    pub fn indexer_compatible(mut self, enabled: bool) -> Self {
+        self.indexer_compatible = enabled;
+        self
+    }
+
     pub fn start(
         &self,
         network_controller: NetworkController,
@@ -982,17 +2531,41 @@ impl Service {
         peers: Arc<Peers>,
         pending_txs: Arc<RwLock<PendingTxs>>,
         consensus: Consensus,
+        min_fee_rate: core::FeeRate,
     ) -> Server {
         let mut io_handler = IoHandler::new();
+        let network = NetworkType::from_chain_name(&consensus.id);
+        let system_scripts = Arc::new(SystemScripts::new(&storage.get_genesis_block(), &consensus));
         let block_filter_rpc_impl = BlockFilterRpcImpl {
             storage: storage.clone(),
+            pending_txs: Arc::clone(&pending_txs),
+            network,
+        };
+        let swl = StorageWithLastHeaders::new(storage.clone(), last_headers);
+        let indexer_rpc_impl = IndexerRpcImpl {
+            block_filter_rpc: BlockFilterRpcImpl {
+                storage,
+                pending_txs: Arc::clone(&pending_txs),
+                network,
+            },
+            swl: swl.clone(),
+        };
+        let chain_rpc_impl = ChainRpcImpl {
+            swl: swl.clone(),
+            consensus: consensus.clone(),
+            system_scripts: Arc::clone(&system_scripts),
+        };
+        let dao_rpc_impl = DaoRpcImpl {
+            swl: swl.clone(),
+            peers: Arc::clone(&peers),
+            consensus: consensus.clone(),
         };
-        let swl = StorageWithLastHeaders::new(storage, last_headers);
-        let chain_rpc_impl = ChainRpcImpl { swl: swl.clone() };
         let transaction_rpc_impl = TransactionRpcImpl {
             pending_txs,
             swl,
             consensus,
+            system_scripts,
+            min_fee_rate,
         };
         let net_rpc_impl = NetRpcImpl {
             network_controller,
@@ -1001,7 +2574,12 @@ impl Service {
         io_handler.extend_with(block_filter_rpc_impl.to_delegate());
         io_handler.extend_with(chain_rpc_impl.to_delegate());
         io_handler.extend_with(transaction_rpc_impl.to_delegate());
+        io_handler.extend_with(dao_rpc_impl.to_delegate());
         io_handler.extend_with(net_rpc_impl.to_delegate());
+        // the methods of the same names are overridden
+        if self.indexer_compatible {
+            io_handler.extend_with(indexer_rpc_impl.to_delegate());
+        }
 
         ServerBuilder::new(io_handler)
             .cors(DomainsValidation::AllowOnly(vec![
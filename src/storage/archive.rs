@@ -0,0 +1,245 @@
+//! A portable archive of the whole store, used to bootstrap new instances without
+//! re-syncing the filters.
+//!
+//! +-----------------------+------------------------------------------------+
+//! | magic                 | b"CKBLCDB\0"                                   |
+//! | version               | u32, big endian                                |
+//! | genesis hash          | 32 bytes                                       |
+//! | entries               | (key len: u32, key, value len: u32, value) ... |
+//! | end of entries        | u32::MAX                                       |
+//! | entries count         | u64, big endian                                |
+//! | checksum              | blake2b of all the above bytes                 |
+//! +-----------------------+------------------------------------------------+
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use ckb_hash::{new_blake2b, Blake2b};
+use ckb_types::{packed::Byte32, prelude::*};
+
+use super::{Direction, Key, Storage, GENESIS_BLOCK_KEY, SCHEMA_VERSION_KEY};
+use crate::error::{Error, Result};
+
+const MAGIC: &[u8; 8] = b"CKBLCDB\0";
+const VERSION: u32 = 1;
+const END_OF_ENTRIES: u32 = u32::MAX;
+const BATCH_SIZE: usize = 10_000;
+
+impl Storage {
+    /// Writes all key-value pairs in a consistent snapshot to the writer, returns the count
+    /// of the entries.
+    pub fn export_to<W: Write>(&self, writer: W) -> Result<u64> {
+        let snapshot = self.snapshot();
+        let genesis_hash = snapshot
+            .get(Key::Meta(GENESIS_BLOCK_KEY).into_vec())?
+            .map(|v| v[0..32].to_vec())
+            .ok_or_else(|| Error::runtime("the store is not initialized"))?;
+
+        let mut writer = HashWriter::new(writer);
+        writer.write_all(MAGIC).map_err(io_error)?;
+        writer.write_all(&VERSION.to_be_bytes()).map_err(io_error)?;
+        writer.write_all(&genesis_hash).map_err(io_error)?;
+
+        let mut count = 0u64;
+        for (key, value) in snapshot.iter(&[] as &[u8], Direction::Forward) {
+            writer
+                .write_all(&(key.len() as u32).to_be_bytes())
+                .map_err(io_error)?;
+            writer.write_all(&key).map_err(io_error)?;
+            writer
+                .write_all(&(value.len() as u32).to_be_bytes())
+                .map_err(io_error)?;
+            writer.write_all(&value).map_err(io_error)?;
+            count += 1;
+        }
+        writer
+            .write_all(&END_OF_ENTRIES.to_be_bytes())
+            .map_err(io_error)?;
+        writer.write_all(&count.to_be_bytes()).map_err(io_error)?;
+        let (mut inner, checksum) = writer.finalize();
+        inner.write_all(&checksum).map_err(io_error)?;
+        inner.flush().map_err(io_error)?;
+        Ok(count)
+    }
+
+    /// Verifies the archive and imports all its entries into this store, which should be
+    /// empty, returns the count of the entries.
+    pub fn import_from<R: Read + Seek>(
+        &self,
+        mut reader: R,
+        expected_genesis_hash: &Byte32,
+    ) -> Result<u64> {
+        // the schema version is written when the store is opened
+        let version_key = Key::Meta(SCHEMA_VERSION_KEY).into_vec();
+        if self
+            .snapshot()
+            .iter(&[] as &[u8], Direction::Forward)
+            .any(|(key, _value)| key.as_ref() != version_key.as_slice())
+        {
+            return Err(Error::runtime("the store to import into is not empty"));
+        }
+
+        // verify the whole archive before writing anything
+        let count = read_archive(&mut reader, expected_genesis_hash, |_, _| Ok(()))?;
+
+        reader.seek(SeekFrom::Start(0)).map_err(io_error)?;
+        let mut batch = self.batch();
+        // the archives of the previous versions are migrated after the import
+        batch.delete(&version_key)?;
+        let mut batch_len = 0;
+        read_archive(&mut reader, expected_genesis_hash, |key, value| {
+            batch.put(key, value)?;
+            batch_len += 1;
+            if batch_len >= BATCH_SIZE {
+                std::mem::replace(&mut batch, self.batch()).commit()?;
+                batch_len = 0;
+            }
+            Ok(())
+        })?;
+        batch.commit()?;
+        self.migrate()?;
+        Ok(count)
+    }
+}
+
+fn read_archive<R: Read, F: FnMut(Vec<u8>, Vec<u8>) -> Result<()>>(
+    reader: R,
+    expected_genesis_hash: &Byte32,
+    mut f: F,
+) -> Result<u64> {
+    let mut reader = HashReader::new(reader);
+
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic).map_err(io_error)?;
+    if &magic != MAGIC {
+        return Err(Error::runtime("not a light client archive"));
+    }
+    let version = read_u32(&mut reader)?;
+    if version != VERSION {
+        let errmsg = format!(
+            "unsupported archive version {}, expect {}",
+            version, VERSION
+        );
+        return Err(Error::runtime(errmsg));
+    }
+    let mut genesis_hash = [0u8; 32];
+    reader.read_exact(&mut genesis_hash).map_err(io_error)?;
+    if genesis_hash != expected_genesis_hash.as_slice() {
+        let errmsg = format!(
+            "genesis hash mismatch: archive={:#x}, chain spec={:#x}",
+            Byte32::from_slice(&genesis_hash).expect("checked length"),
+            expected_genesis_hash
+        );
+        return Err(Error::runtime(errmsg));
+    }
+
+    let mut count = 0u64;
+    loop {
+        let key_len = read_u32(&mut reader)?;
+        if key_len == END_OF_ENTRIES {
+            break;
+        }
+        let key = read_bytes(&mut reader, key_len)?;
+        let value_len = read_u32(&mut reader)?;
+        let value = read_bytes(&mut reader, value_len)?;
+        f(key, value)?;
+        count += 1;
+    }
+    let mut stored_count = [0u8; 8];
+    reader.read_exact(&mut stored_count).map_err(io_error)?;
+    if u64::from_be_bytes(stored_count) != count {
+        return Err(Error::runtime("the entries count of the archive mismatch"));
+    }
+
+    let (mut inner, checksum) = reader.finalize();
+    let mut stored_checksum = [0u8; 32];
+    inner.read_exact(&mut stored_checksum).map_err(io_error)?;
+    if stored_checksum != checksum {
+        return Err(Error::runtime("the checksum of the archive mismatch"));
+    }
+    Ok(count)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes).map_err(io_error)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+// the length is read from the archive before the checksum is verified, the buffer grows with
+// the read bytes instead of being allocated by the length
+fn read_bytes<R: Read>(reader: &mut R, len: u32) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader
+        .by_ref()
+        .take(len as u64)
+        .read_to_end(&mut bytes)
+        .map_err(io_error)?;
+    if bytes.len() != len as usize {
+        return Err(Error::runtime("the archive is truncated"));
+    }
+    Ok(bytes)
+}
+
+fn io_error(err: io::Error) -> Error {
+    Error::runtime(format!("failed to access the archive since {}", err))
+}
+
+struct HashWriter<W> {
+    inner: W,
+    hasher: Blake2b,
+}
+
+impl<W: Write> HashWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: new_blake2b(),
+        }
+    }
+
+    fn finalize(self) -> (W, [u8; 32]) {
+        let mut hash = [0u8; 32];
+        self.hasher.finalize(&mut hash);
+        (self.inner, hash)
+    }
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let size = self.inner.write(buf)?;
+        self.hasher.update(&buf[..size]);
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct HashReader<R> {
+    inner: R,
+    hasher: Blake2b,
+}
+
+impl<R: Read> HashReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: new_blake2b(),
+        }
+    }
+
+    fn finalize(self) -> (R, [u8; 32]) {
+        let mut hash = [0u8; 32];
+        self.hasher.finalize(&mut hash);
+        (self.inner, hash)
+    }
+}
+
+impl<R: Read> Read for HashReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.inner.read(buf)?;
+        self.hasher.update(&buf[..size]);
+        Ok(size)
+    }
+}
@@ -0,0 +1,286 @@
+//! Offline integrity check of the store.
+//!
+//! Every entry is verified against the entries it refers to:
+//! cells / tx history -> tx -> block number -> block hash -> header, and the outputs and the
+//! block hashes carried by the cells should be same as the referred ones.
+//! The dangling entries could be deleted, then the affected scripts are rewound to re-filter
+//! the blocks which contain the deleted entries.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use ckb_traits::HeaderProvider;
+use ckb_types::{
+    bytes::Bytes,
+    core::BlockNumber,
+    packed::{self, Byte32, Header, Script, Transaction},
+    prelude::*,
+};
+
+use super::{CellValue, Key, KeyPrefix, Storage, GENESIS_BLOCK_KEY, LAST_STATE_KEY};
+use crate::error::Result;
+
+// code hash + hash type
+const SCRIPT_FIXED_LEN: usize = 32 + 1;
+// block number + tx index + output index
+const CELL_KEY_SUFFIX_LEN: usize = 8 + 4 + 4;
+// block number + tx index + io index + io type
+const TX_KEY_SUFFIX_LEN: usize = 8 + 4 + 4 + 1;
+
+pub struct DanglingEntry {
+    pub key: Vec<u8>,
+    pub reason: String,
+}
+
+impl fmt::Display for DanglingEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x")?;
+        for byte in &self.key {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, ": {}", self.reason)
+    }
+}
+
+#[derive(Default)]
+pub struct CheckReport {
+    /// The count of the checked entries.
+    pub entries: u64,
+    pub dangling: Vec<DanglingEntry>,
+    /// The missing meta keys, the store should be re-initialized if any.
+    pub missing_meta: Vec<&'static str>,
+    /// The scripts which are rewound to the block number to re-filter the deleted entries.
+    pub rewound_scripts: Vec<(Script, BlockNumber)>,
+}
+
+impl CheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.dangling.is_empty() && self.missing_meta.is_empty()
+    }
+
+    fn dangling<S: ToString>(&mut self, key: &[u8], reason: S) {
+        self.dangling.push(DanglingEntry {
+            key: key.to_vec(),
+            reason: reason.to_string(),
+        });
+    }
+}
+
+#[derive(Default)]
+struct Checker {
+    report: CheckReport,
+    bad_block_hashes: HashSet<Byte32>,
+    bad_block_numbers: HashSet<BlockNumber>,
+    bad_txs: HashSet<Byte32>,
+    // the lowest block number of the dangling entries of each script
+    affected_scripts: HashMap<Script, BlockNumber>,
+}
+
+impl Storage {
+    /// Walks all entries of the store and verifies the cross-references, deletes the dangling
+    /// entries and rewinds the affected scripts when `repair` is true.
+    pub fn check(&self, repair: bool) -> Result<CheckReport> {
+        let mut checker = Checker::default();
+        self.check_headers(&mut checker);
+        self.check_block_numbers(&mut checker);
+        self.check_transactions(&mut checker);
+        for prefix in [KeyPrefix::CellLockScript, KeyPrefix::CellTypeScript] {
+            self.check_script_entries(&mut checker, prefix, CELL_KEY_SUFFIX_LEN);
+        }
+        for prefix in [KeyPrefix::TxLockScript, KeyPrefix::TxTypeScript] {
+            self.check_script_entries(&mut checker, prefix, TX_KEY_SUFFIX_LEN);
+        }
+        self.check_meta(&mut checker);
+
+        let Checker {
+            mut report,
+            affected_scripts,
+            ..
+        } = checker;
+        if repair && !report.dangling.is_empty() {
+            let mut batch = self.batch();
+            for entry in &report.dangling {
+                batch.delete(&entry.key)?;
+            }
+            report.rewound_scripts = self.rewind_filter_scripts(&mut batch, affected_scripts)?;
+            batch.commit()?;
+        }
+        Ok(report)
+    }
+
+    fn check_headers(&self, checker: &mut Checker) {
+        for (key, value) in self.store.prefix_iter(&[KeyPrefix::BlockHash as u8]) {
+            checker.report.entries += 1;
+            let block_hash = Byte32::from_slice(&key[1..]).ok();
+            let header = Header::from_slice(&value).ok();
+            match (block_hash, header) {
+                (Some(block_hash), Some(header)) => {
+                    if header.calc_header_hash() != block_hash {
+                        checker.report.dangling(&key, "header hash mismatch");
+                        checker.bad_block_hashes.insert(block_hash);
+                    }
+                }
+                (Some(block_hash), None) => {
+                    checker.report.dangling(&key, "malformed header");
+                    checker.bad_block_hashes.insert(block_hash);
+                }
+                _ => checker.report.dangling(&key, "malformed block hash"),
+            }
+        }
+    }
+
+    fn check_block_numbers(&self, checker: &mut Checker) {
+        for (key, value) in self.store.prefix_iter(&[KeyPrefix::BlockNumber as u8]) {
+            checker.report.entries += 1;
+            let block_number = match key[1..].try_into() {
+                Ok(bytes) => BlockNumber::from_be_bytes(bytes),
+                Err(_) => {
+                    checker.report.dangling(&key, "malformed block number");
+                    continue;
+                }
+            };
+            let reason = match Byte32::from_slice(&value) {
+                Ok(block_hash) if checker.bad_block_hashes.contains(&block_hash) => {
+                    Some("refers to a bad header")
+                }
+                Ok(block_hash) => match self.get_header(&block_hash) {
+                    Some(header) if header.number() == block_number => None,
+                    Some(_) => Some("block number mismatch"),
+                    None => Some("header is missing"),
+                },
+                Err(_) => Some("malformed block hash"),
+            };
+            if let Some(reason) = reason {
+                checker.report.dangling(&key, reason);
+                checker.bad_block_numbers.insert(block_number);
+            }
+        }
+    }
+
+    fn check_transactions(&self, checker: &mut Checker) {
+        for (key, value) in self.store.prefix_iter(&[KeyPrefix::TxHash as u8]) {
+            checker.report.entries += 1;
+            let tx_hash = match Byte32::from_slice(&key[1..]) {
+                Ok(tx_hash) => tx_hash,
+                Err(_) => {
+                    checker.report.dangling(&key, "malformed tx hash");
+                    continue;
+                }
+            };
+            let reason = if value.len() < 12 {
+                Some("malformed transaction")
+            } else {
+                let block_number =
+                    BlockNumber::from_be_bytes(value[0..8].try_into().expect("checked length"));
+                match Transaction::from_slice(&value[12..]) {
+                    Ok(tx) if tx.calc_tx_hash() != tx_hash => Some("tx hash mismatch"),
+                    Ok(_) if checker.bad_block_numbers.contains(&block_number) => {
+                        Some("refers to a bad block number")
+                    }
+                    Ok(_) => self
+                        .get(Key::BlockNumber(block_number).into_vec())
+                        .expect("db get should be ok")
+                        .map_or(Some("block number / hash mapping is missing"), |_| None),
+                    Err(_) => Some("malformed transaction"),
+                }
+            };
+            if let Some(reason) = reason {
+                checker.report.dangling(&key, reason);
+                checker.bad_txs.insert(tx_hash);
+            }
+        }
+    }
+
+    // checks the cells and the tx history, which are indexed by scripts
+    fn check_script_entries(&self, checker: &mut Checker, prefix: KeyPrefix, suffix_len: usize) {
+        for (key, value) in self.store.prefix_iter(&[prefix as u8]) {
+            checker.report.entries += 1;
+            if key.len() < 1 + SCRIPT_FIXED_LEN + suffix_len {
+                checker.report.dangling(&key, "malformed key");
+                continue;
+            }
+            let script_end = key.len() - suffix_len;
+            let block_number = BlockNumber::from_be_bytes(
+                key[script_end..script_end + 8]
+                    .try_into()
+                    .expect("checked length"),
+            );
+            // the cells carry the block hash and the output besides the tx hash
+            let value = if suffix_len == CELL_KEY_SUFFIX_LEN {
+                CellValue::from_slice(&value)
+                    .map(|cell| (cell.tx_hash.clone(), Some(cell)))
+                    .ok_or("malformed cell value")
+            } else {
+                Byte32::from_slice(&value)
+                    .map(|tx_hash| (tx_hash, None))
+                    .map_err(|_| "malformed tx hash")
+            };
+            let reason = match value {
+                Ok((tx_hash, _)) if checker.bad_txs.contains(&tx_hash) => {
+                    Some("refers to a bad transaction")
+                }
+                Ok((tx_hash, cell)) => match self.get_transaction(&tx_hash) {
+                    Some((stored_block_number, _, tx)) => {
+                        if stored_block_number != block_number {
+                            Some("block number mismatch")
+                        } else if let Some(cell) = cell {
+                            let output_index = u32::from_be_bytes(
+                                key[key.len() - 4..].try_into().expect("checked length"),
+                            );
+                            match tx.raw().outputs().get(output_index as usize) {
+                                None => Some("output index out of bound"),
+                                Some(output) if output.as_slice() != cell.output.as_slice() => {
+                                    Some("output mismatch")
+                                }
+                                Some(_)
+                                    if self
+                                        .get(Key::BlockNumber(block_number).into_vec())
+                                        .expect("db get should be ok")
+                                        .as_deref()
+                                        != Some(cell.block_hash.as_slice()) =>
+                                {
+                                    Some("block hash mismatch")
+                                }
+                                Some(_) => None,
+                            }
+                        } else {
+                            None
+                        }
+                    }
+                    None => Some("transaction is missing"),
+                },
+                Err(reason) => Some(reason),
+            };
+            if let Some(reason) = reason {
+                checker.report.dangling(&key, reason);
+                let script = script_from_raw_data(&key[1..script_end]);
+                let lowest = checker
+                    .affected_scripts
+                    .entry(script)
+                    .or_insert(block_number);
+                *lowest = (*lowest).min(block_number);
+            }
+        }
+    }
+
+    fn check_meta(&self, checker: &mut Checker) {
+        for meta_key in [LAST_STATE_KEY, GENESIS_BLOCK_KEY] {
+            let key = Key::Meta(meta_key).into_vec();
+            checker.report.entries += 1;
+            if self.get(&key).expect("db get should be ok").is_none() {
+                checker.report.missing_meta.push(meta_key);
+            }
+        }
+    }
+}
+
+// the reverse of `extract_raw_data`
+pub(super) fn script_from_raw_data(raw_data: &[u8]) -> Script {
+    Script::new_builder()
+        .code_hash(Byte32::from_slice(&raw_data[0..32]).expect("checked length"))
+        .hash_type(packed::Byte::new(raw_data[32]))
+        .args(Bytes::from(raw_data[SCRIPT_FIXED_LEN..].to_vec()).pack())
+        .build()
+}
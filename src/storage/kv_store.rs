@@ -0,0 +1,90 @@
+use crate::error::Result;
+
+/// The key-value pairs yielded by the iterators of a [`KeyValueStore`].
+pub type KVPair = (Box<[u8]>, Box<[u8]>);
+pub type KVIter<'a> = Box<dyn Iterator<Item = KVPair> + 'a>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Reverse,
+}
+
+/// The backend of [`Storage`](super::Storage).
+pub trait KeyValueStore: Send + Sync {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()>;
+
+    /// Applies all operations in the batch atomically.
+    fn write(&self, batch: &WriteBatch) -> Result<()>;
+
+    /// Iterates the key-value pairs starting from `from_key` (inclusive) in the direction,
+    /// callers should stop the iteration themselves, e.g. when the key prefix is changed.
+    fn iter(&self, from_key: &[u8], direction: Direction) -> KVIter<'_>;
+
+    /// Iterates the key-value pairs whose keys start with the prefix in ascending order.
+    fn prefix_iter(&self, prefix: &[u8]) -> KVIter<'_> {
+        let prefix = prefix.to_vec();
+        Box::new(
+            self.iter(&prefix, Direction::Forward)
+                .take_while(move |(key, _value)| key.starts_with(&prefix)),
+        )
+    }
+
+    /// Returns a consistent read-only view of the store.
+    fn snapshot(&self) -> Box<dyn KeyValueSnapshot + '_>;
+}
+
+pub trait KeyValueSnapshot {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    fn iter(&self, from_key: &[u8], direction: Direction) -> KVIter<'_>;
+}
+
+pub enum BatchOperation {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+}
+
+#[derive(Default)]
+pub struct WriteBatch {
+    operations: Vec<BatchOperation>,
+}
+
+impl WriteBatch {
+    pub fn put<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) {
+        self.operations.push(BatchOperation::Put(
+            key.as_ref().to_vec(),
+            value.as_ref().to_vec(),
+        ));
+    }
+
+    pub fn delete<K: AsRef<[u8]>>(&mut self, key: K) {
+        self.operations
+            .push(BatchOperation::Delete(key.as_ref().to_vec()));
+    }
+
+    pub fn operations(&self) -> &[BatchOperation] {
+        &self.operations
+    }
+}
+
+/// A snapshot of the storage, all reads through it see the same state.
+pub struct Snapshot<'a> {
+    inner: Box<dyn KeyValueSnapshot + 'a>,
+}
+
+impl<'a> Snapshot<'a> {
+    pub(crate) fn new(inner: Box<dyn KeyValueSnapshot + 'a>) -> Self {
+        Self { inner }
+    }
+
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>> {
+        self.inner.get(key.as_ref())
+    }
+
+    pub fn iter<K: AsRef<[u8]>>(&self, from_key: K, direction: Direction) -> KVIter<'_> {
+        self.inner.iter(from_key.as_ref(), direction)
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    collections::BTreeMap,
+    ops::Bound,
+    sync::{Arc, RwLock},
+};
+
+use super::kv_store::{
+    BatchOperation, Direction, KVIter, KVPair, KeyValueSnapshot, KeyValueStore, WriteBatch,
+};
+use crate::error::Result;
+
+type Map = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// Keeps all data in memory, for tests and ephemeral nodes.
+///
+/// The map is copied on write when any snapshot or iterator still holds it, so taking a
+/// snapshot is cheap.
+#[derive(Default)]
+pub struct MemoryStore {
+    map: RwLock<Arc<Map>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn current(&self) -> Arc<Map> {
+        Arc::clone(&self.map.read().expect("poisoned"))
+    }
+}
+
+impl KeyValueStore for MemoryStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.map.read().expect("poisoned").get(key).cloned())
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let mut map = self.map.write().expect("poisoned");
+        Arc::make_mut(&mut map).insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn write(&self, batch: &WriteBatch) -> Result<()> {
+        let mut map = self.map.write().expect("poisoned");
+        let map = Arc::make_mut(&mut map);
+        for operation in batch.operations() {
+            match operation {
+                BatchOperation::Put(key, value) => {
+                    map.insert(key.clone(), value.clone());
+                }
+                BatchOperation::Delete(key) => {
+                    map.remove(key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn iter(&self, from_key: &[u8], direction: Direction) -> KVIter<'_> {
+        Box::new(MemoryIter::new(self.current(), from_key, direction))
+    }
+
+    fn snapshot(&self) -> Box<dyn KeyValueSnapshot + '_> {
+        Box::new(MemorySnapshot {
+            map: self.current(),
+        })
+    }
+}
+
+struct MemorySnapshot {
+    map: Arc<Map>,
+}
+
+impl KeyValueSnapshot for MemorySnapshot {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.map.get(key).cloned())
+    }
+
+    fn iter(&self, from_key: &[u8], direction: Direction) -> KVIter<'_> {
+        Box::new(MemoryIter::new(Arc::clone(&self.map), from_key, direction))
+    }
+}
+
+// an iterator over a frozen map, it looks up the next key on every step
+// since it can't borrow the map it owns
+struct MemoryIter {
+    map: Arc<Map>,
+    bound: Bound<Vec<u8>>,
+    direction: Direction,
+}
+
+impl MemoryIter {
+    fn new(map: Arc<Map>, from_key: &[u8], direction: Direction) -> Self {
+        Self {
+            map,
+            bound: Bound::Included(from_key.to_vec()),
+            direction,
+        }
+    }
+}
+
+impl Iterator for MemoryIter {
+    type Item = KVPair;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bound = match &self.bound {
+            Bound::Included(key) => Bound::Included(key),
+            Bound::Excluded(key) => Bound::Excluded(key),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let next = match self.direction {
+            Direction::Forward => self
+                .map
+                .range::<Vec<u8>, _>((bound, Bound::Unbounded))
+                .next(),
+            Direction::Reverse => self
+                .map
+                .range::<Vec<u8>, _>((Bound::Unbounded, bound))
+                .next_back(),
+        };
+        next.map(|(key, value)| {
+            self.bound = Bound::Excluded(key.clone());
+            (
+                key.clone().into_boxed_slice(),
+                value.clone().into_boxed_slice(),
+            )
+        })
+    }
+}
@@ -0,0 +1,1571 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use ckb_traits::{CellDataProvider, HeaderProvider};
+use ckb_types::{
+    bytes::Bytes,
+    core::{
+        cell::{CellMeta, CellProvider, CellStatus},
+        BlockNumber, HeaderView, TransactionInfo,
+    },
+    packed::{self, Block, Byte32, CellOutput, Header, OutPoint, Script, Transaction},
+    prelude::*,
+    U256,
+};
+
+use crate::{error::Result, wallet::WatchWallet};
+
+mod archive;
+mod check;
+mod kv_store;
+mod memory_store;
+mod prune;
+mod rocksdb_store;
+
+pub use check::{CheckReport, DanglingEntry};
+pub use kv_store::{
+    BatchOperation, Direction, KVIter, KVPair, KeyValueSnapshot, KeyValueStore, Snapshot,
+    WriteBatch,
+};
+pub use memory_store::MemoryStore;
+pub(crate) use prune::Pruner;
+pub use prune::{PruneReport, RetentionPolicy};
+pub use rocksdb_store::{CompressionType, RocksdbOptions, RocksdbStore};
+
+const LAST_STATE_KEY: &str = "LAST_STATE";
+const GENESIS_BLOCK_KEY: &str = "GENESIS_BLOCK";
+const FILTER_SCRIPTS_KEY: &str = "FILTER_SCRIPTS";
+const ROLLBACKS_KEY: &str = "ROLLBACKS";
+const WALLETS_KEY: &str = "WALLETS";
+const WALLET_SCRIPTS_KEY: &str = "WALLET_SCRIPTS";
+const SCHEMA_VERSION_KEY: &str = "SCHEMA_VERSION";
+
+// the count of the rollbacks which are kept to verify the pagination cursors
+const MAX_ROLLBACK_LOGS: usize = 64;
+
+// the version of the stored data, it's increased when a migration is added
+const SCHEMA_VERSION: u32 = 2;
+
+// the count of the operations which are committed in a batch by the migrations
+const BATCH_SIZE: usize = 10_000;
+
+#[derive(Clone)]
+pub struct Storage {
+    pub(crate) store: Arc<dyn KeyValueStore>,
+}
+
+#[allow(clippy::mutable_key_type)]
+impl Storage {
+    /// Opens a RocksDB store at the path.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self::with_store(RocksdbStore::open(path)).expect("open storage")
+    }
+
+    /// Opens a RocksDB store at the path with the tuning options.
+    pub fn open<P: AsRef<Path>>(path: P, options: &RocksdbOptions) -> Result<Self> {
+        RocksdbStore::open_with_options(path, options).and_then(Self::with_store)
+    }
+
+    /// Wraps the store, the data of the previous schema versions is migrated.
+    pub fn with_store<S: KeyValueStore + 'static>(store: S) -> Result<Self> {
+        Self::with_shared_store(Arc::new(store))
+    }
+
+    pub(crate) fn with_shared_store(store: Arc<dyn KeyValueStore>) -> Result<Self> {
+        let storage = Self { store };
+        storage.migrate()?;
+        Ok(storage)
+    }
+
+    // the migrations only run once, the version is stored after all of them are done, a store
+    // without the version is either empty or written by the versions before the migrations
+    fn migrate(&self) -> Result<()> {
+        let version_key = Key::Meta(SCHEMA_VERSION_KEY).into_vec();
+        let version = self
+            .get(&version_key)?
+            .map(|value| u32::from_be_bytes(value[0..4].try_into().expect("stored version")))
+            .unwrap_or_default();
+        if version >= SCHEMA_VERSION {
+            return Ok(());
+        }
+        if version < 1 {
+            let (migrated, deleted) = self.migrate_cell_values()?;
+            if migrated > 0 || deleted > 0 {
+                log::info!(
+                    "migrated {} cell values, deleted {} dangling ones",
+                    migrated,
+                    deleted
+                );
+            }
+        }
+        if version < 2 {
+            let (cells, tx_history) = self.index_type_scripts()?;
+            if cells > 0 || tx_history > 0 {
+                log::info!(
+                    "indexed {} cells and {} tx history entries by type scripts",
+                    cells,
+                    tx_history
+                );
+            }
+        }
+        self.store.put(&version_key, &SCHEMA_VERSION.to_be_bytes())
+    }
+
+    // rewrites the cell values of the previous versions, which only carry the tx hash, the
+    // dangling ones can't be rewritten and are deleted, then the affected scripts are rewound to
+    // index the cells again when the blocks are re-filtered
+    fn migrate_cell_values(&self) -> Result<(usize, usize)> {
+        let snapshot = self.snapshot();
+        let mut batch = self.batch();
+        let mut batch_len = 0;
+        // the lowest block number of the deleted cells of each script in the batch
+        let mut affected_scripts: HashMap<Script, BlockNumber> = HashMap::new();
+        let mut migrated = 0;
+        let mut deleted = 0;
+        for prefix in [KeyPrefix::CellLockScript, KeyPrefix::CellTypeScript] {
+            for (key, value) in snapshot
+                .iter([prefix as u8], Direction::Forward)
+                .take_while(|(key, _value)| key[0] == prefix as u8)
+            {
+                if value.len() != 32 {
+                    continue;
+                }
+                let tx_hash = cell_value_tx_hash(&value);
+                let output_index = OutputIndex::from_be_bytes(
+                    key[key.len() - 4..].try_into().expect("stored OutputIndex"),
+                );
+                let cell_value =
+                    self.get_transaction(&tx_hash)
+                        .and_then(|(block_number, _tx_index, tx)| {
+                            let block_hash = self.get_block_hash(block_number)?;
+                            let output = tx.raw().outputs().get(output_index as usize)?;
+                            let output_data = tx.raw().outputs_data().get(output_index as usize)?;
+                            Some(CellValue::new(tx_hash, block_hash, output, &output_data))
+                        });
+                if let Some(cell_value) = cell_value {
+                    batch.put_kv(key.to_vec(), Value::Cell(&cell_value))?;
+                    migrated += 1;
+                } else {
+                    batch.delete(&key)?;
+                    deleted += 1;
+                    // the key ends with the block number, the tx index and the output index
+                    let script_end = key.len() - 16;
+                    let block_number = BlockNumber::from_be_bytes(
+                        key[script_end..script_end + 8]
+                            .try_into()
+                            .expect("stored BlockNumber"),
+                    );
+                    let script = check::script_from_raw_data(&key[1..script_end]);
+                    let lowest = affected_scripts.entry(script).or_insert(block_number);
+                    *lowest = (*lowest).min(block_number);
+                }
+                batch_len += 1;
+                if batch_len >= BATCH_SIZE {
+                    let mut full_batch = std::mem::replace(&mut batch, self.batch());
+                    self.rewind_filter_scripts(&mut full_batch, affected_scripts.drain())?;
+                    full_batch.commit()?;
+                    batch_len = 0;
+                }
+            }
+        }
+        self.rewind_filter_scripts(&mut batch, affected_scripts.drain())?;
+        batch.commit()?;
+        Ok((migrated, deleted))
+    }
+
+    // indexes the cells and the tx history by type scripts, which are only indexed by lock
+    // scripts in the blocks filtered by the versions before the type script index, the consumed
+    // cells of the inputs whose transactions are pruned can't be indexed
+    fn index_type_scripts(&self) -> Result<(usize, usize)> {
+        let snapshot = self.snapshot();
+        let mut batch = self.batch();
+        let mut batch_len = 0;
+        let mut cells = 0;
+        let mut tx_history = 0;
+        let mut put = |key: Vec<u8>, value: Vec<u8>| -> Result<()> {
+            batch.put(key, value)?;
+            batch_len += 1;
+            if batch_len >= BATCH_SIZE {
+                std::mem::replace(&mut batch, self.batch()).commit()?;
+                batch_len = 0;
+            }
+            Ok(())
+        };
+
+        let prefix = KeyPrefix::CellLockScript as u8;
+        for (key, value) in snapshot
+            .iter([prefix], Direction::Forward)
+            .take_while(|(key, _value)| key[0] == prefix)
+        {
+            let type_script =
+                match CellValue::from_slice(&value).and_then(|cell| cell.output.type_().to_opt()) {
+                    Some(type_script) => type_script,
+                    None => continue,
+                };
+            let (block_number, tx_index, output_index) = parse_key_suffix(&key[key.len() - 16..]);
+            let key = Key::CellTypeScript(&type_script, block_number, tx_index, output_index);
+            put(key.into_vec(), value.to_vec())?;
+            cells += 1;
+        }
+
+        let prefix = KeyPrefix::TxLockScript as u8;
+        for (key, value) in snapshot
+            .iter([prefix], Direction::Forward)
+            .take_while(|(key, _value)| key[0] == prefix)
+        {
+            let (block_number, tx_index, io_index) =
+                parse_key_suffix(&key[key.len() - 17..key.len() - 1]);
+            let tx = match Byte32::from_slice(&value)
+                .ok()
+                .and_then(|tx_hash| self.get_transaction(&tx_hash))
+            {
+                Some((_block_number, _tx_index, tx)) => tx,
+                None => continue,
+            };
+            let io_type = if key[key.len() - 1] == 0 {
+                CellType::Input
+            } else {
+                CellType::Output
+            };
+            let output = if matches!(io_type, CellType::Input) {
+                tx.raw().inputs().get(io_index as usize).and_then(|input| {
+                    let previous_output = input.previous_output();
+                    let (_, _, previous_tx) = self.get_transaction(&previous_output.tx_hash())?;
+                    let index: OutputIndex = previous_output.index().unpack();
+                    previous_tx.raw().outputs().get(index as usize)
+                })
+            } else {
+                tx.raw().outputs().get(io_index as usize)
+            };
+            let type_script = match output.and_then(|output| output.type_().to_opt()) {
+                Some(type_script) => type_script,
+                None => continue,
+            };
+            let key = Key::TxTypeScript(&type_script, block_number, tx_index, io_index, io_type);
+            put(key.into_vec(), value.to_vec())?;
+            tx_history += 1;
+        }
+        drop(put);
+        batch.commit()?;
+        Ok((cells, tx_history))
+    }
+
+    // rewinds the filter scripts to re-filter the blocks since the block numbers, the scripts
+    // which aren't filter scripts or are filtered below the block numbers are ignored, returns
+    // the rewound scripts and the block numbers they are rewound to
+    fn rewind_filter_scripts<I: IntoIterator<Item = (Script, BlockNumber)>>(
+        &self,
+        batch: &mut Batch,
+        scripts: I,
+    ) -> Result<Vec<(Script, BlockNumber)>> {
+        let filter_scripts = self.get_filter_scripts();
+        let mut rewound_scripts = Vec::new();
+        for (script, block_number) in scripts {
+            if let Some(filtered_block_number) = filter_scripts.get(&script) {
+                let rewind_to = block_number.saturating_sub(1);
+                if *filtered_block_number > rewind_to {
+                    let key = [
+                        Key::Meta(FILTER_SCRIPTS_KEY).into_vec(),
+                        script.as_slice().to_vec(),
+                    ]
+                    .concat();
+                    batch.put(key, rewind_to.to_be_bytes())?;
+                    rewound_scripts.push((script, rewind_to));
+                }
+            }
+        }
+        Ok(rewound_scripts)
+    }
+
+    pub(crate) fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>> {
+        self.store.get(key.as_ref())
+    }
+
+    // fn exists<K: AsRef<[u8]>>(&self, key: K) -> Result<bool> {
+    //     self.store
+    //         .get(key.as_ref())
+    //         .map(|v| v.is_some())
+    // }
+
+    pub(crate) fn snapshot(&self) -> Snapshot<'_> {
+        Snapshot::new(self.store.snapshot())
+    }
+
+    fn batch(&self) -> Batch {
+        Batch {
+            store: Arc::clone(&self.store),
+            wb: WriteBatch::default(),
+        }
+    }
+
+    pub fn init_genesis_block(&self, block: Block) {
+        let genesis_hash = block.calc_header_hash();
+        let genesis_block_key = Key::Meta(GENESIS_BLOCK_KEY).into_vec();
+        if let Some(stored_genesis_hash) = self
+            .get(genesis_block_key.as_slice())
+            .expect("get genesis block")
+            .map(|v| v[0..32].to_vec())
+        {
+            if genesis_hash.as_slice() != stored_genesis_hash.as_slice() {
+                panic!(
+                    "genesis hash mismatch: stored={:#?}, new={}",
+                    stored_genesis_hash, genesis_hash
+                );
+            }
+        } else {
+            let mut batch = self.batch();
+            let block_hash = block.calc_header_hash();
+            batch
+                .put_kv(Key::Meta(LAST_STATE_KEY), block.header().as_slice())
+                .expect("batch put should be ok");
+            batch
+                .put_kv(Key::BlockHash(&block_hash), block.header().as_slice())
+                .expect("batch put should be ok");
+            batch
+                .put_kv(Key::BlockNumber(0), block_hash.as_slice())
+                .expect("batch put should be ok");
+            let mut genesis_hash_and_txs_hash = genesis_hash.as_slice().to_vec();
+            block
+                .transactions()
+                .into_iter()
+                .enumerate()
+                .for_each(|(tx_index, tx)| {
+                    let tx_hash = tx.calc_tx_hash();
+                    genesis_hash_and_txs_hash.extend_from_slice(tx_hash.as_slice());
+                    let key = Key::TxHash(&tx_hash).into_vec();
+                    let value = Value::Transaction(0, tx_index as TxIndex, &tx);
+                    batch.put_kv(key, value).expect("batch put should be ok");
+                });
+            batch
+                .put_kv(genesis_block_key, genesis_hash_and_txs_hash.as_slice())
+                .expect("batch put should be ok");
+            batch.commit().expect("batch commit should be ok");
+            self.update_last_state(&U256::zero(), &block.header());
+        }
+    }
+
+    pub fn get_genesis_block(&self) -> Block {
+        let genesis_hash_and_txs_hash = self
+            .get(Key::Meta(GENESIS_BLOCK_KEY).into_vec())
+            .expect("get genesis block")
+            .expect("inited storage");
+        let genesis_hash = Byte32::from_slice(&genesis_hash_and_txs_hash[0..32])
+            .expect("stored genesis block hash");
+        let genesis_header = Header::from_slice(
+            &self
+                .get(Key::BlockHash(&genesis_hash).into_vec())
+                .expect("db get should be ok")
+                .expect("stored block hash / header mapping"),
+        )
+        .expect("stored header should be OK");
+
+        let transactions: Vec<Transaction> = genesis_hash_and_txs_hash[32..]
+            .chunks_exact(32)
+            .map(|tx_hash| {
+                Transaction::from_slice(
+                    &self
+                        .get(
+                            Key::TxHash(
+                                &Byte32::from_slice(tx_hash).expect("stored genesis block tx hash"),
+                            )
+                            .into_vec(),
+                        )
+                        .expect("db get should be ok")
+                        .expect("stored genesis block tx")[12..],
+                )
+                .expect("stored Transaction")
+            })
+            .collect();
+
+        Block::new_builder()
+            .header(genesis_header)
+            .transactions(transactions.pack())
+            .build()
+    }
+
+    pub fn get_filter_scripts(&self) -> HashMap<Script, BlockNumber> {
+        let key_prefix = Key::Meta(FILTER_SCRIPTS_KEY).into_vec();
+
+        self.store
+            .prefix_iter(&key_prefix)
+            .map(|(key, value)| {
+                let script = Script::from_slice(&key[key_prefix.len()..]).expect("stored Script");
+                let block_number = BlockNumber::from_be_bytes(
+                    value.as_ref().try_into().expect("stored BlockNumber"),
+                );
+                (script, block_number)
+            })
+            .collect()
+    }
+
+    /// Update all filter scripts' status to the specified block number and delete the outdated ones.
+    ///
+    /// The scripts of the watch-only wallets are kept until the wallets are removed.
+    pub fn update_filter_scripts(&self, scripts: HashMap<Script, BlockNumber>) {
+        let should_filter_genesis_block =
+            scripts.iter().any(|(_, block_number)| *block_number == 0);
+        let mut batch = self.batch();
+
+        let key_prefix = Key::Meta(FILTER_SCRIPTS_KEY).into_vec();
+
+        self.store
+            .prefix_iter(&key_prefix)
+            .filter(|(key, _value)| {
+                let script = Script::from_slice(&key[key_prefix.len()..]).expect("stored Script");
+                self.get_wallet_script(&script).is_none()
+            })
+            .for_each(|(key, _value)| {
+                batch.delete(key).expect("batch delete should be ok");
+            });
+
+        for (script, block_number) in scripts {
+            let key = [key_prefix.as_ref(), script.as_slice()].concat();
+            batch
+                .put(key, block_number.to_be_bytes())
+                .expect("batch put should be ok");
+        }
+        batch.commit().expect("batch commit should be ok");
+
+        if should_filter_genesis_block {
+            let block = self.get_genesis_block();
+            self.filter_block(block);
+        }
+    }
+
+    /// Registers a watch-only wallet, the lock scripts of its first `gap_limit` indexes are
+    /// filtered from the block number. Returns false if the wallet is already registered.
+    pub fn add_watch_wallet(&self, wallet: &WatchWallet, block_number: BlockNumber) -> bool {
+        let wallet_id = wallet.id();
+        let key = [Key::Meta(WALLETS_KEY).into_vec(), wallet_id.to_vec()].concat();
+        if self.get(&key).expect("db get should be ok").is_some() {
+            return false;
+        }
+        let mut batch = self.batch();
+        let scripts =
+            self.put_wallet_scripts(&mut batch, wallet, 0, wallet.gap_limit, block_number);
+        batch.commit().expect("batch commit should be ok");
+
+        if block_number == 0 && !scripts.is_empty() {
+            let block = self.get_genesis_block();
+            self.filter_block(block);
+        }
+        true
+    }
+
+    /// Returns the watch-only wallets and how many lock scripts are derived for each of them.
+    pub fn get_watch_wallets(&self) -> Vec<(WatchWallet, u32)> {
+        let key_prefix = Key::Meta(WALLETS_KEY).into_vec();
+        self.store
+            .prefix_iter(&key_prefix)
+            .map(|(_key, value)| {
+                let derived_count =
+                    u32::from_be_bytes(value[0..4].try_into().expect("stored derived count"));
+                let wallet = WatchWallet::from_slice(&value[4..]).expect("stored WatchWallet");
+                (wallet, derived_count)
+            })
+            .collect()
+    }
+
+    /// Removes a watch-only wallet and stops filtering its lock scripts, the indexed cells and
+    /// transactions are kept. Returns false if the wallet is not registered.
+    pub fn remove_watch_wallet(&self, wallet: &WatchWallet) -> bool {
+        let wallet_id = wallet.id();
+        let key = [Key::Meta(WALLETS_KEY).into_vec(), wallet_id.to_vec()].concat();
+        let derived_count = match self.get(&key).expect("db get should be ok") {
+            Some(value) => {
+                u32::from_be_bytes(value[0..4].try_into().expect("stored derived count"))
+            }
+            None => return false,
+        };
+        let mut batch = self.batch();
+        batch.delete(key).expect("batch delete should be ok");
+        for (_index, script) in wallet.lock_scripts(0, derived_count) {
+            let key = [
+                Key::Meta(FILTER_SCRIPTS_KEY).into_vec(),
+                script.as_slice().to_vec(),
+            ]
+            .concat();
+            batch.delete(key).expect("batch delete should be ok");
+            let key = [
+                Key::Meta(WALLET_SCRIPTS_KEY).into_vec(),
+                script.as_slice().to_vec(),
+            ]
+            .concat();
+            batch.delete(key).expect("batch delete should be ok");
+        }
+        batch.commit().expect("batch commit should be ok");
+        true
+    }
+
+    // returns the id of the wallet which derives the script, and the index of the script
+    fn get_wallet_script(&self, script: &Script) -> Option<([u8; 32], u32)> {
+        let key = [
+            Key::Meta(WALLET_SCRIPTS_KEY).into_vec(),
+            script.as_slice().to_vec(),
+        ]
+        .concat();
+        self.get(key).expect("db get should be ok").map(|value| {
+            (
+                value[0..32].try_into().expect("stored wallet id"),
+                u32::from_be_bytes(value[32..36].try_into().expect("stored index")),
+            )
+        })
+    }
+
+    // derives the lock scripts of the wallet in the range of indexes, the new scripts are
+    // filtered from the block number, the existing filter scripts are not changed
+    fn put_wallet_scripts(
+        &self,
+        batch: &mut Batch,
+        wallet: &WatchWallet,
+        start: u32,
+        end: u32,
+        block_number: BlockNumber,
+    ) -> HashMap<Script, BlockNumber> {
+        let wallet_id = wallet.id();
+        let mut scripts = HashMap::new();
+        for (index, script) in wallet.lock_scripts(start, end) {
+            let key = [
+                Key::Meta(WALLET_SCRIPTS_KEY).into_vec(),
+                script.as_slice().to_vec(),
+            ]
+            .concat();
+            let value = [wallet_id.as_ref(), index.to_be_bytes().as_ref()].concat();
+            batch.put(key, value).expect("batch put should be ok");
+            let key = [
+                Key::Meta(FILTER_SCRIPTS_KEY).into_vec(),
+                script.as_slice().to_vec(),
+            ]
+            .concat();
+            if self.get(&key).expect("db get should be ok").is_none() {
+                batch
+                    .put(key, block_number.to_be_bytes())
+                    .expect("batch put should be ok");
+                scripts.insert(script, block_number);
+            }
+        }
+        let key = [Key::Meta(WALLETS_KEY).into_vec(), wallet_id.to_vec()].concat();
+        let value = [end.to_be_bytes().to_vec(), wallet.serialize()].concat();
+        batch.put(key, value).expect("batch put should be ok");
+        scripts
+    }
+
+    // extends the wallets which own the matched scripts to keep `gap_limit` unused scripts after
+    // the last used one, returns the new scripts
+    fn extend_watch_wallets(
+        &self,
+        matched_scripts: &HashSet<Script>,
+        block_number: BlockNumber,
+    ) -> HashMap<Script, BlockNumber> {
+        let mut last_used = HashMap::new();
+        for script in matched_scripts {
+            if let Some((wallet_id, index)) = self.get_wallet_script(script) {
+                let last = last_used.entry(wallet_id).or_insert(index);
+                *last = (*last).max(index);
+            }
+        }
+        let mut batch = self.batch();
+        let mut scripts = HashMap::new();
+        for (wallet_id, index) in last_used {
+            let key = [Key::Meta(WALLETS_KEY).into_vec(), wallet_id.to_vec()].concat();
+            let value = match self.get(&key).expect("db get should be ok") {
+                Some(value) => value,
+                None => continue,
+            };
+            let derived_count =
+                u32::from_be_bytes(value[0..4].try_into().expect("stored derived count"));
+            let wallet = WatchWallet::from_slice(&value[4..]).expect("stored WatchWallet");
+            let end = index.saturating_add(1).saturating_add(wallet.gap_limit);
+            if end > derived_count {
+                scripts.extend(self.put_wallet_scripts(
+                    &mut batch,
+                    &wallet,
+                    derived_count,
+                    end,
+                    block_number,
+                ));
+            }
+        }
+        batch.commit().expect("batch commit should be ok");
+        scripts
+    }
+
+    // get scripts hash that should be filtered below the given block number
+    pub fn get_scripts_hash(&self, block_number: BlockNumber) -> Vec<Byte32> {
+        let key_prefix = Key::Meta(FILTER_SCRIPTS_KEY).into_vec();
+
+        self.store
+            .prefix_iter(&key_prefix)
+            .filter_map(|(key, value)| {
+                let stored_block_number = BlockNumber::from_be_bytes(
+                    value.as_ref().try_into().expect("stored BlockNumber"),
+                );
+                if stored_block_number < block_number {
+                    let script =
+                        Script::from_slice(&key[key_prefix.len()..]).expect("stored Script");
+                    Some(script.calc_script_hash())
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn update_last_state(&self, total_difficulty: &U256, tip_header: &Header) {
+        let key = Key::Meta(LAST_STATE_KEY).into_vec();
+        let mut value = total_difficulty.to_le_bytes().to_vec();
+        value.extend(tip_header.as_slice());
+        self.store
+            .put(&key, &value)
+            .expect("db put last state should be ok");
+    }
+
+    pub fn get_last_state(&self) -> (U256, Header) {
+        let key = Key::Meta(LAST_STATE_KEY).into_vec();
+        self.store
+            .get(&key)
+            .expect("db get last state should be ok")
+            .map(|data| {
+                let mut total_difficulty_bytes = [0u8; 32];
+                total_difficulty_bytes.copy_from_slice(&data[0..32]);
+                let total_difficulty = U256::from_le_bytes(&total_difficulty_bytes);
+                let header = packed::HeaderReader::from_slice_should_be_ok(&data[32..]).to_entity();
+                (total_difficulty, header)
+            })
+            .expect("tip header should be inited")
+    }
+
+    pub fn get_tip_header(&self) -> Header {
+        self.get_last_state().1
+    }
+
+    pub fn update_block_number(&self, block_number: BlockNumber) {
+        let key_prefix = Key::Meta(FILTER_SCRIPTS_KEY).into_vec();
+
+        let mut batch = self.batch();
+        self.store
+            .prefix_iter(&key_prefix)
+            .for_each(|(key, value)| {
+                let stored_block_number = BlockNumber::from_be_bytes(
+                    value.as_ref().try_into().expect("stored BlockNumber"),
+                );
+                if stored_block_number < block_number {
+                    batch
+                        .put(key, block_number.to_be_bytes())
+                        .expect("batch put should be ok")
+                }
+            });
+        batch.commit().expect("batch commit should be ok");
+    }
+
+    /// Returns whether the block contains any cells of the filter scripts.
+    pub fn filter_block(&self, block: Block) -> bool {
+        let block_number: BlockNumber = block.header().raw().number().unpack();
+        let mut scripts = self.get_filter_scripts();
+        let mut filter_matched = false;
+        // the scripts which are derived by the watch-only wallets for the activity in this block
+        // may also own cells in it, so the block is filtered again by them
+        while !scripts.is_empty() {
+            let matched_scripts = self.filter_block_by_scripts(&block, &scripts);
+            filter_matched |= !matched_scripts.is_empty();
+            scripts = self.extend_watch_wallets(&matched_scripts, block_number);
+        }
+        filter_matched
+    }
+
+    // returns the lock scripts which own the cells created or consumed in the block
+    fn filter_block_by_scripts(
+        &self,
+        block: &Block,
+        scripts: &HashMap<Script, BlockNumber>,
+    ) -> HashSet<Script> {
+        let block_number: BlockNumber = block.header().raw().number().unpack();
+        let block_hash = block.calc_header_hash();
+        let mut matched_scripts = HashSet::new();
+        let mut batch = self.batch();
+        block
+            .transactions()
+            .into_iter()
+            .enumerate()
+            .for_each(|(tx_index, tx)| {
+                tx.raw()
+                    .inputs()
+                    .into_iter()
+                    .enumerate()
+                    .for_each(|(input_index, input)| {
+                        if let Some((
+                            generated_by_block_number,
+                            generated_by_tx_index,
+                            previous_tx,
+                        )) = self.get_transaction(&input.previous_output().tx_hash())
+                        {
+                            let previous_output_index = input.previous_output().index().unpack();
+                            if let Some(previous_output) =
+                                previous_tx.raw().outputs().get(previous_output_index)
+                            {
+                                let script = previous_output.lock();
+                                if scripts.contains_key(&script) {
+                                    matched_scripts.insert(script.clone());
+                                    // delete utxo
+                                    let key = Key::CellLockScript(
+                                        &script,
+                                        generated_by_block_number,
+                                        generated_by_tx_index,
+                                        previous_output_index as OutputIndex,
+                                    )
+                                    .into_vec();
+                                    batch.delete(key).expect("batch delete should be ok");
+                                    // insert tx history
+                                    let key = Key::TxLockScript(
+                                        &script,
+                                        block_number,
+                                        tx_index as TxIndex,
+                                        input_index as CellIndex,
+                                        CellType::Input,
+                                    )
+                                    .into_vec();
+                                    let tx_hash = tx.calc_tx_hash();
+                                    batch
+                                        .put(key, tx_hash.as_slice())
+                                        .expect("batch put should be ok");
+                                    // index the spent cell by its type script too
+                                    if let Some(type_script) = previous_output.type_().to_opt() {
+                                        let key = Key::CellTypeScript(
+                                            &type_script,
+                                            generated_by_block_number,
+                                            generated_by_tx_index,
+                                            previous_output_index as OutputIndex,
+                                        )
+                                        .into_vec();
+                                        batch.delete(key).expect("batch delete should be ok");
+                                        let key = Key::TxTypeScript(
+                                            &type_script,
+                                            block_number,
+                                            tx_index as TxIndex,
+                                            input_index as CellIndex,
+                                            CellType::Input,
+                                        )
+                                        .into_vec();
+                                        batch
+                                            .put(key, tx_hash.as_slice())
+                                            .expect("batch put should be ok");
+                                    }
+                                    // insert tx
+                                    let key = Key::TxHash(&tx_hash).into_vec();
+                                    let value =
+                                        Value::Transaction(block_number, tx_index as TxIndex, &tx);
+                                    batch.put_kv(key, value).expect("batch put should be ok");
+                                }
+                            }
+                        }
+                    });
+
+                tx.raw()
+                    .outputs()
+                    .into_iter()
+                    .enumerate()
+                    .for_each(|(output_index, output)| {
+                        let script = output.lock();
+                        if scripts.contains_key(&script) {
+                            matched_scripts.insert(script.clone());
+                            let tx_hash = tx.calc_tx_hash();
+                            let output_data = tx
+                                .raw()
+                                .outputs_data()
+                                .get(output_index)
+                                .expect("output_data's index should be same as output");
+                            let cell_value = CellValue::new(
+                                tx_hash.clone(),
+                                block_hash.clone(),
+                                output.clone(),
+                                &output_data,
+                            );
+                            // insert utxo
+                            let key = Key::CellLockScript(
+                                &script,
+                                block_number,
+                                tx_index as TxIndex,
+                                output_index as OutputIndex,
+                            )
+                            .into_vec();
+                            batch
+                                .put_kv(key, Value::Cell(&cell_value))
+                                .expect("batch put should be ok");
+                            // insert tx history
+                            let key = Key::TxLockScript(
+                                &script,
+                                block_number,
+                                tx_index as TxIndex,
+                                output_index as CellIndex,
+                                CellType::Output,
+                            )
+                            .into_vec();
+                            batch
+                                .put(key, tx_hash.as_slice())
+                                .expect("batch put should be ok");
+                            // index the cell by its type script too
+                            if let Some(type_script) = output.type_().to_opt() {
+                                let key = Key::CellTypeScript(
+                                    &type_script,
+                                    block_number,
+                                    tx_index as TxIndex,
+                                    output_index as OutputIndex,
+                                )
+                                .into_vec();
+                                batch
+                                    .put_kv(key, Value::Cell(&cell_value))
+                                    .expect("batch put should be ok");
+                                let key = Key::TxTypeScript(
+                                    &type_script,
+                                    block_number,
+                                    tx_index as TxIndex,
+                                    output_index as CellIndex,
+                                    CellType::Output,
+                                )
+                                .into_vec();
+                                batch
+                                    .put(key, tx_hash.as_slice())
+                                    .expect("batch put should be ok");
+                            }
+                            // insert tx
+                            let key = Key::TxHash(&tx_hash).into_vec();
+                            let value = Value::Transaction(block_number, tx_index as TxIndex, &tx);
+                            batch.put_kv(key, value).expect("batch put should be ok");
+                        }
+                    });
+            });
+        if !matched_scripts.is_empty() {
+            batch
+                .put(
+                    Key::BlockHash(&block_hash).into_vec(),
+                    block.header().as_slice(),
+                )
+                .expect("batch put should be ok");
+            batch
+                .put(
+                    Key::BlockNumber(block.header().raw().number().unpack()).into_vec(),
+                    block_hash.as_slice(),
+                )
+                .expect("batch put should be ok");
+        }
+        batch.commit().expect("batch commit should be ok");
+        matched_scripts
+    }
+
+    /// Rollback filtered block data since the specified block number, the scripts are filtered
+    /// again from the block.
+    pub fn rollback_to_block(&self, to_number: BlockNumber) {
+        let scripts = self.get_filter_scripts();
+        let mut batch = self.batch();
+
+        for (script, filtered_block_number) in scripts {
+            if filtered_block_number >= to_number {
+                let mut key_prefix = vec![KeyPrefix::TxLockScript as u8];
+                key_prefix.extend_from_slice(&extract_raw_data(&script));
+                let mut start_key = key_prefix.clone();
+                start_key.extend_from_slice(BlockNumber::MAX.to_be_bytes().as_ref());
+                let key_prefix_len = key_prefix.len();
+
+                self.store
+                    .iter(&start_key, Direction::Reverse)
+                    .take_while(|(key, _value)| {
+                        key.starts_with(&key_prefix)
+                            && BlockNumber::from_be_bytes(
+                                key[key_prefix_len..key_prefix_len + 8]
+                                    .try_into()
+                                    .expect("stored BlockNumber"),
+                            ) >= to_number
+                    })
+                    .for_each(|(key, value)| {
+                        let block_number = BlockNumber::from_be_bytes(
+                            key[key_prefix_len..key_prefix_len + 8]
+                                .try_into()
+                                .expect("stored BlockNumber"),
+                        );
+                        let tx_index = TxIndex::from_be_bytes(
+                            key[key_prefix_len + 8..key_prefix_len + 12]
+                                .try_into()
+                                .expect("stored TxIndex"),
+                        );
+                        let cell_index = CellIndex::from_be_bytes(
+                            key[key_prefix_len + 12..key_prefix_len + 16]
+                                .try_into()
+                                .expect("stored CellIndex"),
+                        );
+                        let tx_hash =
+                            packed::Byte32Reader::from_slice_should_be_ok(&value).to_entity();
+                        if key[key_prefix_len + 16] == 0 {
+                            let (_, _, tx) = self
+                                .get_transaction(&tx_hash)
+                                .expect("stored transaction history");
+                            let input = tx.raw().inputs().get(cell_index as usize).unwrap();
+                            if let Some((
+                                generated_by_block_number,
+                                generated_by_tx_index,
+                                previous_tx,
+                            )) = self.get_transaction(&input.previous_output().tx_hash())
+                            {
+                                let previous_output_index: OutputIndex =
+                                    input.previous_output().index().unpack();
+                                let previous_output = previous_tx
+                                    .raw()
+                                    .outputs()
+                                    .get(previous_output_index as usize)
+                                    .expect("stored previous output");
+                                let previous_output_data = previous_tx
+                                    .raw()
+                                    .outputs_data()
+                                    .get(previous_output_index as usize)
+                                    .expect("stored previous output data");
+                                let generated_by_block_hash = self
+                                    .get_block_hash(generated_by_block_number)
+                                    .expect("stored block number / hash mapping");
+                                let cell_value = CellValue::new(
+                                    input.previous_output().tx_hash(),
+                                    generated_by_block_hash,
+                                    previous_output.clone(),
+                                    &previous_output_data,
+                                );
+                                let key = Key::CellLockScript(
+                                    &script,
+                                    generated_by_block_number,
+                                    generated_by_tx_index,
+                                    previous_output_index,
+                                );
+                                batch
+                                    .put_kv(key, Value::Cell(&cell_value))
+                                    .expect("batch put should be ok");
+                                if let Some(type_script) = previous_output.type_().to_opt() {
+                                    let key = Key::CellTypeScript(
+                                        &type_script,
+                                        generated_by_block_number,
+                                        generated_by_tx_index,
+                                        previous_output_index,
+                                    );
+                                    batch
+                                        .put_kv(key, Value::Cell(&cell_value))
+                                        .expect("batch put should be ok");
+                                    let key = Key::TxTypeScript(
+                                        &type_script,
+                                        block_number,
+                                        tx_index,
+                                        cell_index,
+                                        CellType::Input,
+                                    )
+                                    .into_vec();
+                                    batch.delete(key).expect("batch delete should be ok");
+                                }
+                            };
+                            // delete tx history
+                            let key = Key::TxLockScript(
+                                &script,
+                                block_number,
+                                tx_index,
+                                cell_index,
+                                CellType::Input,
+                            )
+                            .into_vec();
+                            batch.delete(key).expect("batch delete should be ok");
+                        } else {
+                            // delete utxo
+                            let key =
+                                Key::CellLockScript(&script, block_number, tx_index, cell_index)
+                                    .into_vec();
+                            batch.delete(key).expect("batch delete should be ok");
+                            if let Some(type_script) = self
+                                .get_transaction(&tx_hash)
+                                .and_then(|(_, _, tx)| tx.raw().outputs().get(cell_index as usize))
+                                .and_then(|output| output.type_().to_opt())
+                            {
+                                let key = Key::CellTypeScript(
+                                    &type_script,
+                                    block_number,
+                                    tx_index,
+                                    cell_index,
+                                )
+                                .into_vec();
+                                batch.delete(key).expect("batch delete should be ok");
+                                let key = Key::TxTypeScript(
+                                    &type_script,
+                                    block_number,
+                                    tx_index,
+                                    cell_index,
+                                    CellType::Output,
+                                )
+                                .into_vec();
+                                batch.delete(key).expect("batch delete should be ok");
+                            }
+
+                            // delete tx history
+                            let key = Key::TxLockScript(
+                                &script,
+                                block_number,
+                                tx_index,
+                                cell_index,
+                                CellType::Output,
+                            )
+                            .into_vec();
+                            batch.delete(key).expect("batch delete should be ok");
+                        };
+                    });
+
+                // update script filter block number, the stored number is the last filtered one
+                {
+                    let mut key = Key::Meta(FILTER_SCRIPTS_KEY).into_vec();
+                    key.extend_from_slice(script.as_slice());
+                    let value = to_number.saturating_sub(1).to_be_bytes().to_vec();
+                    batch.put(key, value).expect("batch put should be ok");
+                }
+            }
+        }
+
+        // log the rollback, the pagination cursors issued above the block are invalidated
+        let snapshot = self.snapshot();
+        let rollback_count = snapshot.get_rollback_count() + 1;
+        let prefix = Key::Meta(ROLLBACKS_KEY).into_vec();
+        // the logged rollbacks which are not lower than this one are redundant, since the
+        // cursors are checked against the lowest block rolled back to after them
+        let mut kept = Vec::new();
+        for (key, value) in snapshot
+            .iter(&prefix, Direction::Forward)
+            .take_while(|(key, _value)| key.starts_with(&prefix))
+        {
+            let rolled_back_to =
+                BlockNumber::from_be_bytes(value.as_ref().try_into().expect("stored BlockNumber"));
+            if rolled_back_to >= to_number {
+                batch.delete(key).expect("batch delete should be ok");
+            } else {
+                kept.push((key, rolled_back_to));
+            }
+        }
+        // the kept ones are in ascending order of the block number, the oldest ones are merged
+        // into the next one when the log is too long, which only invalidates more old cursors
+        if kept.len() >= MAX_ROLLBACK_LOGS {
+            let merged = kept.len() + 1 - MAX_ROLLBACK_LOGS;
+            for (key, _rolled_back_to) in &kept[..merged] {
+                batch.delete(key).expect("batch delete should be ok");
+            }
+            batch
+                .put(&kept[merged].0, kept[0].1.to_be_bytes())
+                .expect("batch put should be ok");
+        }
+        let key = [prefix.as_slice(), &rollback_count.to_be_bytes()].concat();
+        batch
+            .put(key, to_number.to_be_bytes())
+            .expect("batch put should be ok");
+
+        batch.commit().expect("batch commit should be ok");
+    }
+
+    fn get_transaction(&self, tx_hash: &Byte32) -> Option<(BlockNumber, TxIndex, Transaction)> {
+        self.get(Key::TxHash(tx_hash).into_vec())
+            .map(|v| {
+                v.map(|v| {
+                    (
+                        BlockNumber::from_be_bytes(v[0..8].try_into().expect("stored BlockNumber")),
+                        TxIndex::from_be_bytes(v[8..12].try_into().expect("stored TxIndex")),
+                        Transaction::from_slice(&v[12..]).expect("stored Transaction"),
+                    )
+                })
+            })
+            .expect("db get should be ok")
+    }
+
+    fn get_block_hash(&self, block_number: BlockNumber) -> Option<Byte32> {
+        self.get(Key::BlockNumber(block_number).into_vec())
+            .expect("db get should be ok")
+            .map(|block_hash| Byte32::from_slice(&block_hash).expect("stored block hash"))
+    }
+
+    /// Stores a proved header which isn't in any filtered block.
+    /// The fetched headers are stored by hash only, they could be on a stale fork after a
+    /// reorg, which is not rolled back by `rollback_to_block`.
+    pub fn add_fetched_header(&self, header: &Header) {
+        let block_hash = header.calc_header_hash();
+        let mut batch = self.batch();
+        batch
+            .put_kv(Key::BlockHash(&block_hash), header.as_slice())
+            .expect("batch put should be ok");
+        batch.commit().expect("batch commit should be ok");
+    }
+
+    /// Only the headers of the genesis block and the filtered blocks are indexed by number.
+    pub fn get_header_by_number(&self, block_number: BlockNumber) -> Option<Header> {
+        self.get(Key::BlockNumber(block_number).into_vec())
+            .expect("db get should be ok")
+            .and_then(|block_hash| {
+                let block_hash =
+                    Byte32::from_slice(&block_hash).expect("stored block hash should be OK");
+                self.get(Key::BlockHash(&block_hash).into_vec())
+                    .expect("db get should be ok")
+            })
+            .map(|header| Header::from_slice(&header).expect("stored header should be OK"))
+    }
+
+    pub fn get_transaction_with_header(&self, tx_hash: &Byte32) -> Option<(Transaction, Header)> {
+        self.get_transaction(tx_hash)
+            .map(|(block_number, _tx_index, tx)| {
+                let block_hash = Byte32::from_slice(
+                    &self
+                        .get(Key::BlockNumber(block_number).into_vec())
+                        .expect("db get should be ok")
+                        .expect("stored block number / hash mapping"),
+                )
+                .expect("stored block hash should be OK");
+
+                let header = Header::from_slice(
+                    &self
+                        .get(Key::BlockHash(&block_hash).into_vec())
+                        .expect("db get should be ok")
+                        .expect("stored block hash / header mapping"),
+                )
+                .expect("stored header should be OK");
+                (tx, header)
+            })
+    }
+}
+
+impl CellProvider for Storage {
+    // assume all cells are live and load data eagerly
+    fn cell(&self, out_point: &OutPoint, _eager_load: bool) -> CellStatus {
+        if let Some((block_number, tx_index, tx)) = self.get_transaction(&out_point.tx_hash()) {
+            let block_hash = Byte32::from_slice(
+                &self
+                    .get(Key::BlockNumber(block_number).into_vec())
+                    .expect("db get should be ok")
+                    .expect("stored block number / hash mapping"),
+            )
+            .expect("stored block hash should be OK");
+
+            let header = Header::from_slice(
+                &self
+                    .get(Key::BlockHash(&block_hash).into_vec())
+                    .expect("db get should be ok")
+                    .expect("stored block hash / header mapping"),
+            )
+            .expect("stored header should be OK")
+            .into_view();
+
+            let output_index = out_point.index().unpack();
+            let tx = tx.into_view();
+            if let Some(cell_output) = tx.outputs().get(output_index) {
+                let output_data = tx
+                    .outputs_data()
+                    .get(output_index)
+                    .expect("output_data's index should be same as output")
+                    .raw_data();
+                let output_data_data_hash = CellOutput::calc_data_hash(&output_data);
+                let cell_meta = CellMeta {
+                    out_point: out_point.clone(),
+                    cell_output,
+                    transaction_info: Some(TransactionInfo {
+                        block_hash,
+                        block_epoch: header.epoch(),
+                        block_number,
+                        index: tx_index as usize,
+                    }),
+                    data_bytes: output_data.len() as u64,
+                    mem_cell_data: Some(output_data),
+                    mem_cell_data_hash: Some(output_data_data_hash),
+                };
+                return CellStatus::Live(cell_meta);
+            }
+        }
+        CellStatus::Unknown
+    }
+}
+
+impl CellDataProvider for Storage {
+    // we load all cells data eagerly in Storage's CellProivder impl
+    fn get_cell_data(&self, _out_point: &OutPoint) -> Option<Bytes> {
+        unreachable!()
+    }
+
+    fn get_cell_data_hash(&self, _out_point: &OutPoint) -> Option<Byte32> {
+        unreachable!()
+    }
+}
+
+impl HeaderProvider for Storage {
+    fn get_header(&self, hash: &Byte32) -> Option<HeaderView> {
+        self.get(Key::BlockHash(hash).into_vec())
+            .map(|v| v.map(|v| Header::from_slice(&v).expect("stored Header").into_view()))
+            .expect("db get should be ok")
+    }
+}
+
+impl<'a> Snapshot<'a> {
+    /// Returns the tip header of the last state, `None` if the store is not initialized.
+    pub(crate) fn get_tip_header(&self) -> Option<Header> {
+        self.get(Key::Meta(LAST_STATE_KEY).into_vec())
+            .expect("snapshot get last state should be ok")
+            .map(|data| packed::HeaderReader::from_slice_should_be_ok(&data[32..]).to_entity())
+    }
+
+    /// Returns the output data of the cell, which is read in place from the stored transaction.
+    pub(crate) fn get_cell_data(&self, out_point: &OutPoint) -> Option<packed::Bytes> {
+        let output_index: OutputIndex = out_point.index().unpack();
+        self.get(Key::TxHash(&out_point.tx_hash()).into_vec())
+            .expect("snapshot get should be ok")
+            .and_then(|value| {
+                packed::TransactionReader::new_unchecked(&value[12..])
+                    .raw()
+                    .outputs_data()
+                    .get(output_index as usize)
+                    .map(|data| data.to_entity())
+            })
+    }
+
+    /// Returns the count of all rollbacks of the store.
+    pub(crate) fn get_rollback_count(&self) -> u64 {
+        let prefix = Key::Meta(ROLLBACKS_KEY).into_vec();
+        let from_key = [prefix.as_slice(), &u64::MAX.to_be_bytes()].concat();
+        self.iter(&from_key, Direction::Reverse)
+            .next()
+            .filter(|(key, _value)| key.starts_with(&prefix))
+            .map(|(key, _value)| {
+                u64::from_be_bytes(
+                    key[prefix.len()..]
+                        .try_into()
+                        .expect("stored rollback count"),
+                )
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the lowest block number rolled back to by the rollbacks after the first
+    /// `rollback_count` ones.
+    pub(crate) fn get_rolled_back_to(&self, rollback_count: u64) -> Option<BlockNumber> {
+        let prefix = Key::Meta(ROLLBACKS_KEY).into_vec();
+        let from_key = [
+            prefix.as_slice(),
+            &rollback_count.saturating_add(1).to_be_bytes(),
+        ]
+        .concat();
+        self.iter(&from_key, Direction::Forward)
+            .take_while(|(key, _value)| key.starts_with(&prefix))
+            .map(|(_key, value)| {
+                BlockNumber::from_be_bytes(value.as_ref().try_into().expect("stored BlockNumber"))
+            })
+            .min()
+    }
+}
+
+#[derive(Clone)]
+pub struct StorageWithLastHeaders {
+    storage: Storage,
+    last_headers: Arc<RwLock<Vec<HeaderView>>>,
+}
+
+impl StorageWithLastHeaders {
+    pub fn new(storage: Storage, last_headers: Arc<RwLock<Vec<HeaderView>>>) -> Self {
+        Self {
+            storage,
+            last_headers,
+        }
+    }
+
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    pub fn get_header_by_number(&self, block_number: BlockNumber) -> Option<HeaderView> {
+        self.storage
+            .get_header_by_number(block_number)
+            .map(|header| header.into_view())
+            .or_else(|| {
+                self.last_headers
+                    .read()
+                    .expect("poisoned")
+                    .iter()
+                    .find(|header| header.number() == block_number)
+                    .cloned()
+            })
+    }
+}
+
+impl HeaderProvider for StorageWithLastHeaders {
+    fn get_header(&self, hash: &packed::Byte32) -> Option<HeaderView> {
+        self.storage.get_header(hash).or_else(|| {
+            self.last_headers
+                .read()
+                .expect("poisoned")
+                .iter()
+                .find(|header| header.hash().eq(hash))
+                .cloned()
+        })
+    }
+}
+
+impl CellDataProvider for StorageWithLastHeaders {
+    fn get_cell_data(&self, out_point: &OutPoint) -> Option<Bytes> {
+        self.storage.get_cell_data(out_point)
+    }
+
+    fn get_cell_data_hash(&self, out_point: &OutPoint) -> Option<Byte32> {
+        self.storage.get_cell_data_hash(out_point)
+    }
+}
+
+impl CellProvider for StorageWithLastHeaders {
+    fn cell(&self, out_point: &OutPoint, eager_load: bool) -> CellStatus {
+        self.storage.cell(out_point, eager_load)
+    }
+}
+
+pub struct Batch {
+    store: Arc<dyn KeyValueStore>,
+    wb: WriteBatch,
+}
+
+impl Batch {
+    fn put_kv<K: Into<Vec<u8>>, V: Into<Vec<u8>>>(&mut self, key: K, value: V) -> Result<()> {
+        self.put(&Into::<Vec<u8>>::into(key), &Into::<Vec<u8>>::into(value))
+    }
+
+    fn put<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) -> Result<()> {
+        self.wb.put(key, value);
+        Ok(())
+    }
+
+    fn delete<K: AsRef<[u8]>>(&mut self, key: K) -> Result<()> {
+        self.wb.delete(key.as_ref());
+        Ok(())
+    }
+
+    fn commit(self) -> Result<()> {
+        self.store.write(&self.wb)
+    }
+}
+
+pub type TxIndex = u32;
+pub type OutputIndex = u32;
+pub type CellIndex = u32;
+pub enum CellType {
+    Input,
+    Output,
+}
+
+///
+/// +--------------+--------------------+--------------------------+
+/// | KeyPrefix::  | Key::              | Value::                  |
+/// +--------------+--------------------+--------------------------+
+/// | 0            | TxHash             | Transaction              |
+/// | 32           | CellLockScript     | Cell                     |
+/// | 64           | CellTypeScript     | Cell                     |
+/// | 96           | TxLockScript       | TxHash                   |
+/// | 128          | TxTypeScript       | TxHash                   |
+/// | 160          | BlockHash          | Header                   |
+/// | 192          | BlockNumber        | BlockHash                |
+/// | 224          | Meta               | Meta                |
+/// +--------------+--------------------+--------------------------+
+///
+pub enum Key<'a> {
+    TxHash(&'a Byte32),
+    CellLockScript(&'a Script, BlockNumber, TxIndex, OutputIndex),
+    CellTypeScript(&'a Script, BlockNumber, TxIndex, OutputIndex),
+    TxLockScript(&'a Script, BlockNumber, TxIndex, CellIndex, CellType),
+    TxTypeScript(&'a Script, BlockNumber, TxIndex, CellIndex, CellType),
+    BlockHash(&'a Byte32),
+    BlockNumber(BlockNumber),
+    Meta(&'a str),
+}
+
+pub enum Value<'a> {
+    Transaction(BlockNumber, TxIndex, &'a Transaction),
+    Cell(&'a CellValue),
+    TxHash(&'a Byte32),
+    Header(&'a Header),
+    BlockHash(&'a Byte32),
+    Meta(Vec<u8>),
+}
+
+#[repr(u8)]
+pub enum KeyPrefix {
+    TxHash = 0,
+    CellLockScript = 32,
+    CellTypeScript = 64,
+    TxLockScript = 96,
+    TxTypeScript = 128,
+    BlockHash = 160,
+    BlockNumber = 192,
+    Meta = 224,
+}
+
+impl<'a> Key<'a> {
+    pub fn into_vec(self) -> Vec<u8> {
+        self.into()
+    }
+}
+
+impl<'a> From<Key<'a>> for Vec<u8> {
+    fn from(key: Key<'a>) -> Vec<u8> {
+        let mut encoded = Vec::new();
+
+        match key {
+            Key::TxHash(tx_hash) => {
+                encoded.push(KeyPrefix::TxHash as u8);
+                encoded.extend_from_slice(tx_hash.as_slice());
+            }
+            Key::CellLockScript(script, block_number, tx_index, output_index) => {
+                encoded.push(KeyPrefix::CellLockScript as u8);
+                append_key(&mut encoded, script, block_number, tx_index, output_index);
+            }
+            Key::CellTypeScript(script, block_number, tx_index, output_index) => {
+                encoded.push(KeyPrefix::CellTypeScript as u8);
+                append_key(&mut encoded, script, block_number, tx_index, output_index);
+            }
+            Key::TxLockScript(script, block_number, tx_index, io_index, io_type) => {
+                encoded.push(KeyPrefix::TxLockScript as u8);
+                append_key(&mut encoded, script, block_number, tx_index, io_index);
+                match io_type {
+                    CellType::Input => encoded.push(0),
+                    CellType::Output => encoded.push(1),
+                }
+            }
+            Key::TxTypeScript(script, block_number, tx_index, io_index, io_type) => {
+                encoded.push(KeyPrefix::TxTypeScript as u8);
+                append_key(&mut encoded, script, block_number, tx_index, io_index);
+                match io_type {
+                    CellType::Input => encoded.push(0),
+                    CellType::Output => encoded.push(1),
+                }
+            }
+            Key::BlockHash(block_hash) => {
+                encoded.push(KeyPrefix::BlockHash as u8);
+                encoded.extend_from_slice(block_hash.as_slice());
+            }
+            Key::BlockNumber(block_number) => {
+                encoded.push(KeyPrefix::BlockNumber as u8);
+                encoded.extend_from_slice(&block_number.to_be_bytes());
+            }
+            Key::Meta(meta_key) => {
+                encoded.push(KeyPrefix::Meta as u8);
+                encoded.extend_from_slice(meta_key.as_bytes());
+            }
+        }
+        encoded
+    }
+}
+
+impl<'a> From<Value<'a>> for Vec<u8> {
+    fn from(value: Value<'a>) -> Vec<u8> {
+        match value {
+            Value::Transaction(block_number, tx_index, transaction) => {
+                let mut encoded = Vec::new();
+                encoded.extend_from_slice(&block_number.to_be_bytes());
+                encoded.extend_from_slice(&tx_index.to_be_bytes());
+                encoded.extend_from_slice(transaction.as_slice());
+                encoded
+            }
+            Value::Cell(cell) => [
+                cell.tx_hash.as_slice(),
+                cell.block_hash.as_slice(),
+                cell.data_hash.as_slice(),
+                &cell.data_len.to_be_bytes(),
+                cell.output.as_slice(),
+            ]
+            .concat(),
+            Value::TxHash(tx_hash) => tx_hash.as_slice().into(),
+            Value::Header(header) => header.as_slice().into(),
+            Value::BlockHash(block_hash) => block_hash.as_slice().into(),
+            Value::Meta(meta_value) => meta_value,
+        }
+    }
+}
+
+// tx hash + block hash + data hash + data length
+const CELL_VALUE_FIXED_LEN: usize = 32 + 32 + 32 + 8;
+
+/// The value of the cell indexes, which carries the cell so the cell queries don't have to load
+/// the whole transaction.
+///
+/// The encoding is: tx hash | block hash | data hash | data length (u64, big endian) | output
+#[derive(Clone, Debug)]
+pub struct CellValue {
+    pub tx_hash: Byte32,
+    /// The hash of the block which creates the cell.
+    pub block_hash: Byte32,
+    pub output: CellOutput,
+    pub data_hash: Byte32,
+    pub data_len: u64,
+}
+
+impl CellValue {
+    pub fn new(
+        tx_hash: Byte32,
+        block_hash: Byte32,
+        output: CellOutput,
+        output_data: &packed::Bytes,
+    ) -> Self {
+        let output_data = output_data.raw_data();
+        Self {
+            tx_hash,
+            block_hash,
+            output,
+            data_hash: CellOutput::calc_data_hash(&output_data),
+            data_len: output_data.len() as u64,
+        }
+    }
+
+    /// Returns `None` if the value is malformed, e.g. the values of the previous versions which
+    /// only carry the tx hash.
+    pub fn from_slice(value: &[u8]) -> Option<Self> {
+        if value.len() < CELL_VALUE_FIXED_LEN {
+            return None;
+        }
+        Some(Self {
+            tx_hash: Byte32::from_slice(&value[0..32]).ok()?,
+            block_hash: Byte32::from_slice(&value[32..64]).ok()?,
+            data_hash: Byte32::from_slice(&value[64..96]).ok()?,
+            data_len: u64::from_be_bytes(value[96..104].try_into().ok()?),
+            output: CellOutput::from_slice(&value[CELL_VALUE_FIXED_LEN..]).ok()?,
+        })
+    }
+
+    pub fn out_point(&self, output_index: OutputIndex) -> OutPoint {
+        OutPoint::new(self.tx_hash.clone(), output_index)
+    }
+}
+
+/// Returns the hash of the transaction which creates the cell, it's the prefix of the cell
+/// value of all versions.
+pub fn cell_value_tx_hash(value: &[u8]) -> Byte32 {
+    Byte32::from_slice(&value[0..32]).expect("stored tx hash")
+}
+
+// parses the block number, the tx index and the output / io index at the end of the keys of the
+// cells and the tx history
+fn parse_key_suffix(suffix: &[u8]) -> (BlockNumber, TxIndex, u32) {
+    (
+        BlockNumber::from_be_bytes(suffix[0..8].try_into().expect("stored BlockNumber")),
+        TxIndex::from_be_bytes(suffix[8..12].try_into().expect("stored TxIndex")),
+        u32::from_be_bytes(suffix[12..16].try_into().expect("stored index")),
+    )
+}
+
+fn append_key(
+    encoded: &mut Vec<u8>,
+    script: &Script,
+    block_number: u64,
+    tx_index: u32,
+    io_index: u32,
+) {
+    encoded.extend_from_slice(&extract_raw_data(script));
+    encoded.extend_from_slice(&block_number.to_be_bytes());
+    encoded.extend_from_slice(&tx_index.to_be_bytes());
+    encoded.extend_from_slice(&io_index.to_be_bytes());
+}
+
+// a helper fn extracts script fields raw data
+pub fn extract_raw_data(script: &Script) -> Vec<u8> {
+    [
+        script.code_hash().as_slice(),
+        script.hash_type().as_slice(),
+        &script.args().raw_data(),
+    ]
+    .concat()
+}
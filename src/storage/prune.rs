@@ -0,0 +1,230 @@
+//! Pruning of the transaction history by a retention policy.
+//!
+//! The transactions which own live cells are never pruned since `CellProvider::cell` loads the
+//! cells from them, neither are the transactions of the genesis block.
+
+use std::{
+    collections::HashSet,
+    sync::mpsc::{self, RecvTimeoutError, SyncSender},
+    thread,
+    time::Duration,
+};
+
+use ckb_types::{
+    core::BlockNumber,
+    packed::{Byte32, Transaction},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{cell_value_tx_hash, Direction, KVPair, Key, KeyPrefix, Snapshot, Storage};
+use crate::{
+    error::{Error, Result},
+    protocols::LAST_N_BLOCKS,
+};
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const BATCH_SIZE: usize = 10_000;
+// block number + tx index + io index + io type
+const TX_KEY_SUFFIX_LEN: usize = 8 + 4 + 4 + 1;
+
+/// How much transaction history is kept in the store.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum RetentionPolicy {
+    KeepAll,
+    /// Keeps the transaction history of the last N filtered blocks.
+    KeepBlocks {
+        blocks: BlockNumber,
+    },
+    /// Keeps only the transactions which still own live cells.
+    LiveCells,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self::KeepAll
+    }
+}
+
+impl RetentionPolicy {
+    // the history below the returned block number could be pruned, the last `LAST_N_BLOCKS`
+    // blocks are always kept since they could be rolled back
+    fn prune_below(&self, filtered_block_number: BlockNumber) -> Option<BlockNumber> {
+        let keep = match self {
+            Self::KeepAll => return None,
+            Self::KeepBlocks { blocks } => (*blocks).max(LAST_N_BLOCKS),
+            Self::LiveCells => LAST_N_BLOCKS,
+        };
+        Some(filtered_block_number.saturating_sub(keep)).filter(|number| *number > 0)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PruneReport {
+    /// The count of the pruned transactions.
+    pub transactions: u64,
+    /// The count of the pruned tx history entries.
+    pub tx_history: u64,
+}
+
+impl Storage {
+    /// Prunes the transaction history which isn't retained by the policy.
+    ///
+    /// The history is counted back from the lowest filtered block number of all filter
+    /// scripts, so the blocks which are being filtered are never touched.
+    pub fn prune(&self, policy: RetentionPolicy) -> Result<PruneReport> {
+        let mut report = PruneReport::default();
+        let filtered_block_number = match self.lowest_filtered_block_number() {
+            Some(block_number) => block_number,
+            None => return Ok(report),
+        };
+        let prune_below = match policy.prune_below(filtered_block_number) {
+            Some(block_number) => block_number,
+            None => return Ok(report),
+        };
+
+        let snapshot = self.snapshot();
+        let retained_txs = retained_transactions(&snapshot, prune_below);
+        let mut batch = self.batch();
+        let mut batch_len = 0;
+        let mut delete = |key: &[u8]| -> Result<()> {
+            batch.delete(key)?;
+            batch_len += 1;
+            if batch_len >= BATCH_SIZE {
+                self.check_filtered_block_number(filtered_block_number)?;
+                std::mem::replace(&mut batch, self.batch()).commit()?;
+                batch_len = 0;
+            }
+            Ok(())
+        };
+
+        for prefix in [KeyPrefix::TxLockScript, KeyPrefix::TxTypeScript] {
+            for (key, _value) in prefix_iter(&snapshot, prefix) {
+                if tx_history_block_number(&key) < prune_below {
+                    delete(&key)?;
+                    report.tx_history += 1;
+                }
+            }
+        }
+        for (key, value) in prefix_iter(&snapshot, KeyPrefix::TxHash) {
+            let block_number =
+                BlockNumber::from_be_bytes(value[0..8].try_into().expect("stored BlockNumber"));
+            if block_number > 0 && block_number < prune_below && !retained_txs.contains(&key[1..]) {
+                delete(&key)?;
+                report.transactions += 1;
+            }
+        }
+        drop(delete);
+        self.check_filtered_block_number(filtered_block_number)?;
+        batch.commit()?;
+        Ok(report)
+    }
+
+    fn lowest_filtered_block_number(&self) -> Option<BlockNumber> {
+        self.get_filter_scripts().into_values().min()
+    }
+
+    // the filter scripts could be rewound to re-filter the pruned blocks while pruning
+    fn check_filtered_block_number(&self, filtered_block_number: BlockNumber) -> Result<()> {
+        match self.lowest_filtered_block_number() {
+            Some(block_number) if block_number < filtered_block_number => Err(Error::runtime(
+                "the filter scripts are rewound, stop pruning",
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Prunes the store periodically in the background.
+pub(crate) struct Pruner {
+    storage: Storage,
+    policy: RetentionPolicy,
+}
+
+impl Pruner {
+    pub(crate) fn new(storage: Storage, policy: RetentionPolicy) -> Self {
+        Self { storage, policy }
+    }
+
+    /// Starts the pruner thread, which stops when the returned sender is dropped.
+    pub(crate) fn start(self) -> Result<SyncSender<()>> {
+        let (stop_tx, stop_rx) = mpsc::sync_channel(0);
+        thread::Builder::new()
+            .name("pruner".to_owned())
+            .spawn(move || loop {
+                match stop_rx.recv_timeout(PRUNE_INTERVAL) {
+                    Err(RecvTimeoutError::Timeout) => match self.storage.prune(self.policy) {
+                        Ok(report) => log::info!(
+                            "pruned {} transactions and {} tx history entries",
+                            report.transactions,
+                            report.tx_history
+                        ),
+                        Err(err) => log::warn!("failed to prune the store since {}", err),
+                    },
+                    _ => break,
+                }
+            })
+            .map_err(|err| {
+                let errmsg = format!("failed to spawn pruner since {}", err);
+                Error::runtime(errmsg)
+            })?;
+        Ok(stop_tx)
+    }
+}
+
+// the transactions which own live cells, and the ones which own the cells spent in the kept
+// history, the spent cells are restored from them when the history is rolled back
+fn retained_transactions(snapshot: &Snapshot, prune_below: BlockNumber) -> HashSet<Vec<u8>> {
+    let mut retained_txs: HashSet<Vec<u8>> = HashSet::new();
+    for prefix in [KeyPrefix::CellLockScript, KeyPrefix::CellTypeScript] {
+        retained_txs.extend(
+            prefix_iter(snapshot, prefix)
+                .map(|(_key, value)| cell_value_tx_hash(&value).as_slice().to_vec()),
+        );
+    }
+    for prefix in [KeyPrefix::TxLockScript, KeyPrefix::TxTypeScript] {
+        for (key, value) in prefix_iter(snapshot, prefix) {
+            // io type 0 is input
+            if tx_history_block_number(&key) < prune_below || key[key.len() - 1] != 0 {
+                continue;
+            }
+            let input_index = u32::from_be_bytes(
+                key[key.len() - 5..key.len() - 1]
+                    .try_into()
+                    .expect("stored CellIndex"),
+            );
+            let tx_hash = Byte32::from_slice(&value).expect("stored tx hash");
+            if let Some(input) = snapshot
+                .get(Key::TxHash(&tx_hash).into_vec())
+                .expect("db get should be ok")
+                .and_then(|tx| {
+                    Transaction::from_slice(&tx[12..])
+                        .expect("stored Transaction")
+                        .raw()
+                        .inputs()
+                        .get(input_index as usize)
+                })
+            {
+                retained_txs.insert(input.previous_output().tx_hash().as_slice().to_vec());
+            }
+        }
+    }
+    retained_txs
+}
+
+fn prefix_iter<'a>(snapshot: &'a Snapshot, prefix: KeyPrefix) -> impl Iterator<Item = KVPair> + 'a {
+    let prefix = prefix as u8;
+    snapshot
+        .iter([prefix], Direction::Forward)
+        .take_while(move |(key, _value)| key[0] == prefix)
+}
+
+fn tx_history_block_number(key: &[u8]) -> BlockNumber {
+    let start = key.len() - TX_KEY_SUFFIX_LEN;
+    BlockNumber::from_be_bytes(
+        key[start..start + 8]
+            .try_into()
+            .expect("stored BlockNumber"),
+    )
+}
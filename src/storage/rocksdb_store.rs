@@ -0,0 +1,247 @@
+use std::path::Path;
+
+use rocksdb::{
+    prelude::*, BlockBasedOptions, Cache, ColumnFamily, ColumnFamilyDescriptor, DBCompressionType,
+    IteratorMode, Options, Snapshot, DB,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    kv_store::{BatchOperation, Direction, KVIter, KeyValueSnapshot, KeyValueStore, WriteBatch},
+    KeyPrefix,
+};
+use crate::error::Result;
+
+/// The column families in the order of their key ranges, with the lowest key prefix of each.
+///
+/// A key is stored in the last column family whose lowest key prefix is not greater than the
+/// first byte of the key, so iterating the column families in this order visits the keys in
+/// the same order as a single keyspace.
+const COLUMN_FAMILIES: [(&str, u8); 5] = [
+    ("transactions", KeyPrefix::TxHash as u8),
+    ("cells", KeyPrefix::CellLockScript as u8),
+    ("tx_history", KeyPrefix::TxLockScript as u8),
+    ("headers", KeyPrefix::BlockHash as u8),
+    ("meta", KeyPrefix::Meta as u8),
+];
+
+const MIGRATION_BATCH_SIZE: usize = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionType {
+    None,
+    Snappy,
+}
+
+impl From<CompressionType> for DBCompressionType {
+    fn from(compression: CompressionType) -> Self {
+        match compression {
+            CompressionType::None => DBCompressionType::None,
+            CompressionType::Snappy => DBCompressionType::Snappy,
+        }
+    }
+}
+
+/// The tuning options of a [`RocksdbStore`], RocksDB defaults are used for the unset ones.
+#[derive(Clone, Debug, Default)]
+pub struct RocksdbOptions {
+    /// The capacity (bytes) of the block cache shared by all column families.
+    pub cache_size: Option<usize>,
+    pub compression: Option<CompressionType>,
+    pub max_open_files: Option<i32>,
+    /// The size (bytes) of the memtable of each column family.
+    pub write_buffer_size: Option<usize>,
+}
+
+impl RocksdbOptions {
+    fn build(&self) -> Result<(Options, Vec<ColumnFamilyDescriptor>)> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        if let Some(max_open_files) = self.max_open_files {
+            opts.set_max_open_files(max_open_files);
+        }
+
+        let cache = self.cache_size.map(Cache::new_lru_cache).transpose()?;
+        let cf_descriptors = COLUMN_FAMILIES
+            .iter()
+            .map(|(name, _)| {
+                let mut cf_opts = Options::default();
+                if let Some(compression) = self.compression {
+                    cf_opts.set_compression_type(compression.into());
+                }
+                if let Some(write_buffer_size) = self.write_buffer_size {
+                    cf_opts.set_write_buffer_size(write_buffer_size);
+                }
+                if let Some(cache) = cache.as_ref() {
+                    let mut block_opts = BlockBasedOptions::default();
+                    block_opts.set_block_cache(cache);
+                    cf_opts.set_block_based_table_factory(&block_opts);
+                }
+                ColumnFamilyDescriptor::new(*name, cf_opts)
+            })
+            .collect();
+        Ok((opts, cf_descriptors))
+    }
+}
+
+/// Stores the keys in column families grouped by the key prefix, so the headers, the
+/// transactions and the script indexes could be compacted and tuned independently.
+pub struct RocksdbStore {
+    db: DB,
+}
+
+impl RocksdbStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Self {
+        Self::open_with_options(path, &RocksdbOptions::default()).expect("Failed to open rocksdb")
+    }
+
+    pub fn open_with_options<P: AsRef<Path>>(path: P, options: &RocksdbOptions) -> Result<Self> {
+        let (opts, cf_descriptors) = options.build()?;
+        let db = DB::open_cf_descriptors(&opts, path, cf_descriptors)?;
+        let store = Self { db };
+        store.migrate_default_column_family()?;
+        Ok(store)
+    }
+
+    // moves the keys written by the previous versions, which put everything in the default
+    // column family, into their own column families
+    fn migrate_default_column_family(&self) -> Result<()> {
+        let mut migrated = 0;
+        loop {
+            let mut wb = rocksdb::WriteBatch::default();
+            let mut batch_len = 0;
+            for (key, value) in self.db.iterator(IteratorMode::Start) {
+                wb.put_cf(cf_handle(&self.db, &key), &key, &value)?;
+                wb.delete(&key)?;
+                batch_len += 1;
+                if batch_len >= MIGRATION_BATCH_SIZE {
+                    break;
+                }
+            }
+            if batch_len == 0 {
+                break;
+            }
+            self.db.write(&wb)?;
+            migrated += batch_len;
+        }
+        if migrated > 0 {
+            log::info!("migrated {} entries into the column families", migrated);
+        }
+        Ok(())
+    }
+}
+
+impl KeyValueStore for RocksdbStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.db
+            .get_cf(cf_handle(&self.db, key), key)
+            .map(|v| v.map(|vi| vi.to_vec()))
+            .map_err(Into::into)
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.db
+            .put_cf(cf_handle(&self.db, key), key, value)
+            .map_err(Into::into)
+    }
+
+    fn write(&self, batch: &WriteBatch) -> Result<()> {
+        let mut wb = rocksdb::WriteBatch::default();
+        for operation in batch.operations() {
+            match operation {
+                BatchOperation::Put(key, value) => {
+                    wb.put_cf(cf_handle(&self.db, key), key, value)?
+                }
+                BatchOperation::Delete(key) => wb.delete_cf(cf_handle(&self.db, key), key)?,
+            }
+        }
+        self.db.write(&wb).map_err(Into::into)
+    }
+
+    fn iter(&self, from_key: &[u8], direction: Direction) -> KVIter<'_> {
+        iter_column_families(from_key, direction, |name, mode| {
+            let cf = self.db.cf_handle(name).expect("column family should exist");
+            self.db
+                .iterator_cf(cf, mode)
+                .expect("rocksdb iterator should be ok")
+        })
+    }
+
+    fn snapshot(&self) -> Box<dyn KeyValueSnapshot + '_> {
+        Box::new(RocksdbSnapshot {
+            db: &self.db,
+            inner: self.db.snapshot(),
+        })
+    }
+}
+
+struct RocksdbSnapshot<'a> {
+    db: &'a DB,
+    inner: Snapshot<'a>,
+}
+
+impl<'a> KeyValueSnapshot for RocksdbSnapshot<'a> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.inner
+            .get_cf(cf_handle(self.db, key), key)
+            .map(|v| v.map(|vi| vi.to_vec()))
+            .map_err(Into::into)
+    }
+
+    fn iter(&self, from_key: &[u8], direction: Direction) -> KVIter<'_> {
+        iter_column_families(from_key, direction, |name, mode| {
+            let cf = self.db.cf_handle(name).expect("column family should exist");
+            self.inner
+                .iterator_cf(cf, mode)
+                .expect("rocksdb iterator should be ok")
+        })
+    }
+}
+
+fn column_family_index(key: &[u8]) -> usize {
+    let prefix = key.first().copied().unwrap_or_default();
+    COLUMN_FAMILIES
+        .iter()
+        .rposition(|(_, lowest_prefix)| *lowest_prefix <= prefix)
+        .expect("the lowest key prefix of the first column family is 0")
+}
+
+fn cf_handle<'a>(db: &'a DB, key: &[u8]) -> &'a ColumnFamily {
+    let (name, _) = COLUMN_FAMILIES[column_family_index(key)];
+    db.cf_handle(name).expect("column family should exist")
+}
+
+// seeks `from_key` in its column family, then chains the whole following (or preceding, when
+// iterating in reverse) column families
+fn iter_column_families<'a, I, F>(from_key: &[u8], direction: Direction, iter_cf: F) -> KVIter<'a>
+where
+    I: Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a,
+    F: Fn(&'static str, IteratorMode) -> I,
+{
+    let index = column_family_index(from_key);
+    let (name, _) = COLUMN_FAMILIES[index];
+    let mut iters = Vec::with_capacity(COLUMN_FAMILIES.len());
+    match direction {
+        Direction::Forward => {
+            iters.push(iter_cf(
+                name,
+                IteratorMode::From(from_key, rocksdb::Direction::Forward),
+            ));
+            for (name, _) in &COLUMN_FAMILIES[index + 1..] {
+                iters.push(iter_cf(name, IteratorMode::Start));
+            }
+        }
+        Direction::Reverse => {
+            iters.push(iter_cf(
+                name,
+                IteratorMode::From(from_key, rocksdb::Direction::Reverse),
+            ));
+            for (name, _) in COLUMN_FAMILIES[..index].iter().rev() {
+                iters.push(iter_cf(name, IteratorMode::End));
+            }
+        }
+    }
+    Box::new(iters.into_iter().flatten())
+}
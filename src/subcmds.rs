@@ -1,20 +1,16 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter},
+};
 
 use ckb_async_runtime::new_global_runtime;
-use ckb_chain_spec::ChainSpec;
-use ckb_network::{
-    CKBProtocol, CKBProtocolHandler, DefaultExitHandler, ExitHandler, NetworkService, NetworkState,
-    SupportProtocols,
-};
-use ckb_resource::Resource;
+use ckb_network::ExitHandler;
+use ckb_types::{core::FeeRate, prelude::*};
 
 use crate::{
-    config::RunConfig,
+    client::{load_chain_spec, LightClientBuilder},
+    config::{DbCheckConfig, ExportConfig, ImportConfig, RunConfig},
     error::{Error, Result},
-    protocols::{
-        FilterProtocol, LightClientProtocol, Peers, PendingTxs, RelayProtocol, SyncProtocol,
-    },
-    service::Service,
     storage::Storage,
     utils,
 };
@@ -23,104 +19,128 @@ impl RunConfig {
     pub(crate) fn execute(self) -> Result<()> {
         log::info!("Run ...");
 
-        utils::fs::need_directory(&self.run_env.network.path)?;
+        let chain_spec = load_chain_spec(&self.run_env.chain)?;
+        let (handle, _stop_handler) = new_global_runtime();
+        let client = LightClientBuilder::new(
+            chain_spec,
+            self.run_env.store.path.clone(),
+            self.run_env.network,
+        )
+        .store_options(self.run_env.store.rocksdb_options())
+        .retention_policy(self.run_env.store.retention)
+        .min_fee_rate(FeeRate::from_u64(self.run_env.tx_pool.min_fee_rate))
+        .start(&handle)?;
+
+        if let Some(metrics_config) = self.run_env.metrics.as_ref() {
+            client.start_metrics_service(&metrics_config.listen_address)?;
+        }
+        let rpc_server =
+            client.start_rpc_service("127.0.0.1:9000", self.run_env.rpc.indexer_compatible);
 
-        let storage = Storage::new(&self.run_env.store.path);
-        let chain_spec = ChainSpec::load_from(&match self.run_env.chain.as_str() {
-            "mainnet" => Resource::bundled("specs/mainnet.toml".to_string()),
-            "testnet" => Resource::bundled("specs/testnet.toml".to_string()),
-            path => Resource::file_system(path.into()),
+        let exit_handler = client.exit_handler();
+        ctrlc::set_handler(move || {
+            exit_handler.notify_exit();
         })
-        .expect("load spec should be OK");
+        .map_err(|err| {
+            let errmsg = format!("failed to set Ctrl-C handler since {}", err);
+            Error::runtime(errmsg)
+        })?;
+        client.wait_for_exit();
+        rpc_server.close();
+        client.shutdown();
+        Ok(())
+    }
+}
+
+impl ExportConfig {
+    pub(crate) fn execute(self) -> Result<()> {
+        log::info!("Export ...");
+
+        let chain_spec = load_chain_spec(&self.run_env.chain)?;
         let consensus = chain_spec
             .build_consensus()
-            .expect("build consensus should be OK");
+            .map_err(|err| Error::config(format!("failed to build consensus since {}", err)))?;
+        let storage = Storage::open(
+            &self.run_env.store.path,
+            &self.run_env.store.rocksdb_options(),
+        )?;
         storage.init_genesis_block(consensus.genesis_block().data());
 
-        let pending_txs = Arc::new(RwLock::new(PendingTxs::new(64)));
-        let network_state = NetworkState::from_config(self.run_env.network)
-            .map(Arc::new)
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&self.target)
             .map_err(|err| {
-                let errmsg = format!("failed to initialize network state since {}", err);
+                let errmsg = format!("failed to create {} since {}", self.target.display(), err);
                 Error::runtime(errmsg)
             })?;
-        let required_protocol_ids = vec![
-            SupportProtocols::Sync.protocol_id(),
-            SupportProtocols::LightClient.protocol_id(),
-            SupportProtocols::Filter.protocol_id(),
-        ];
-
-        let last_headers = Arc::new(RwLock::new(Vec::new()));
-        let peers = Arc::new(Peers::new(Arc::clone(&last_headers)));
-        let sync_protocol = SyncProtocol::new(storage.clone());
-        let relay_protocol = RelayProtocol::new(pending_txs.clone(), Arc::clone(&peers));
-        let light_client: Box<dyn CKBProtocolHandler> = Box::new(LightClientProtocol::new(
-            storage.clone(),
-            Arc::clone(&peers),
-            consensus.clone(),
-        ));
-        let filter_protocol = FilterProtocol::new(storage.clone(), Arc::clone(&peers));
-
-        let protocols = vec![
-            CKBProtocol::new_with_support_protocol(
-                SupportProtocols::Sync,
-                Box::new(sync_protocol),
-                Arc::clone(&network_state),
-            ),
-            CKBProtocol::new_with_support_protocol(
-                SupportProtocols::RelayV2,
-                Box::new(relay_protocol),
-                Arc::clone(&network_state),
-            ),
-            CKBProtocol::new_with_support_protocol(
-                SupportProtocols::LightClient,
-                light_client,
-                Arc::clone(&network_state),
-            ),
-            CKBProtocol::new_with_support_protocol(
-                SupportProtocols::Filter,
-                Box::new(filter_protocol),
-                Arc::clone(&network_state),
-            ),
-        ];
+        let count = storage.export_to(BufWriter::new(file))?;
+        log::info!("Exported {} entries to {}", count, self.target.display());
+        Ok(())
+    }
+}
 
-        let (handle, _stop_handler) = new_global_runtime();
-        let exit_handler = DefaultExitHandler::default();
+impl ImportConfig {
+    pub(crate) fn execute(self) -> Result<()> {
+        log::info!("Import ...");
 
-        let network_controller = NetworkService::new(
-            Arc::clone(&network_state),
-            protocols,
-            required_protocol_ids,
-            consensus.identify_name(),
-            clap::crate_version!().to_owned(),
-            exit_handler.clone(),
-        )
-        .start(&handle)
-        .map_err(|err| {
-            let errmsg = format!("failed to start network since {}", err);
+        let chain_spec = load_chain_spec(&self.run_env.chain)?;
+        let consensus = chain_spec
+            .build_consensus()
+            .map_err(|err| Error::config(format!("failed to build consensus since {}", err)))?;
+        let file = File::open(&self.source).map_err(|err| {
+            let errmsg = format!("failed to open {} since {}", self.source.display(), err);
             Error::runtime(errmsg)
         })?;
+        let storage = Storage::open(
+            &self.run_env.store.path,
+            &self.run_env.store.rocksdb_options(),
+        )?;
+        let count = storage.import_from(BufReader::new(file), &consensus.genesis_hash())?;
+        // check the imported genesis block again, as `run` does
+        storage.init_genesis_block(consensus.genesis_block().data());
+        log::info!("Imported {} entries from {}", count, self.source.display());
+        Ok(())
+    }
+}
+
+impl DbCheckConfig {
+    pub(crate) fn execute(self) -> Result<()> {
+        log::info!("Check the store ...");
 
-        let service = Service::new("127.0.0.1:9000");
-        let rpc_server = service.start(
-            network_controller,
-            storage,
-            last_headers,
-            peers,
-            pending_txs,
-            consensus,
+        utils::fs::need_directory(&self.run_env.store.path)?;
+        let storage = Storage::open(
+            &self.run_env.store.path,
+            &self.run_env.store.rocksdb_options(),
+        )?;
+        let report = storage.check(self.repair)?;
+        for entry in &report.dangling {
+            log::warn!("dangling entry {}", entry);
+        }
+        for meta_key in &report.missing_meta {
+            log::error!("meta {} is missing, the store should be rebuilt", meta_key);
+        }
+        for (script, block_number) in &report.rewound_scripts {
+            log::info!(
+                "script {:#x} is rewound to block#{}",
+                script.calc_script_hash(),
+                block_number
+            );
+        }
+        log::info!(
+            "Checked {} entries, found {} dangling entries",
+            report.entries,
+            report.dangling.len()
         );
 
-        let exit_handler_clone = exit_handler.clone();
-        ctrlc::set_handler(move || {
-            exit_handler_clone.notify_exit();
-        })
-        .map_err(|err| {
-            let errmsg = format!("failed to set Ctrl-C handler since {}", err);
-            Error::runtime(errmsg)
-        })?;
-        exit_handler.wait_for_exit();
-        rpc_server.close();
+        if !report.missing_meta.is_empty() || (!self.repair && !report.is_ok()) {
+            let errmsg = format!(
+                "the store is inconsistent, {} dangling entries and {} missing meta",
+                report.dangling.len(),
+                report.missing_meta.len()
+            );
+            return Err(Error::runtime(errmsg));
+        }
         Ok(())
     }
 }
@@ -0,0 +1,139 @@
+//! The system scripts which are deployed in the genesis block.
+//!
+//! The lock scripts and the NervosDAO type script are referenced by their type hashes, which are
+//! calculated by [`Consensus`], the cells of their cell deps are read from the stored genesis
+//! block once and kept in memory.
+
+use std::collections::HashMap;
+
+use ckb_chain_spec::consensus::Consensus;
+use ckb_types::{
+    core::{
+        cell::{CellMeta, TransactionInfo},
+        BlockView, DepType,
+    },
+    packed::{self, Byte32, CellDep, OutPoint, OutPointVec},
+    prelude::*,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SystemScript {
+    /// The hash of the type script of the code cell, which is the code hash of the script with
+    /// the `type` hash type.
+    pub type_hash: Byte32,
+    pub cell_dep: CellDep,
+}
+
+#[derive(Clone, Default)]
+pub struct SystemScripts {
+    pub secp256k1_blake160_sighash_all: Option<SystemScript>,
+    pub secp256k1_blake160_multisig_all: Option<SystemScript>,
+    pub dao: Option<SystemScript>,
+    // the cells of the cell deps and the cells in the dep groups
+    cells: HashMap<OutPoint, CellMeta>,
+}
+
+impl SystemScripts {
+    /// The secp256k1 locks are referenced by the dep groups which also contain the secp256k1
+    /// data, the NervosDAO is referenced by its code cell.
+    #[allow(clippy::mutable_key_type)]
+    pub fn new(genesis: &packed::Block, consensus: &Consensus) -> Self {
+        let genesis = genesis.clone().into_view();
+        let mut cells = HashMap::new();
+        let mut resolve = |type_hash: Option<Byte32>, dep_type: DepType| {
+            let type_hash = type_hash?;
+            let code_out_point = find_code_cell(&genesis, &type_hash)?;
+            let out_point = match dep_type {
+                DepType::Code => code_out_point,
+                DepType::DepGroup => find_dep_group_cell(&genesis, &code_out_point)?,
+            };
+            let cell = genesis_cell(&genesis, &out_point)?;
+            if dep_type == DepType::DepGroup {
+                let data = cell.mem_cell_data.clone().expect("loaded cell data");
+                for sub_out_point in OutPointVec::from_slice(&data).expect("checked dep group") {
+                    let sub_cell = genesis_cell(&genesis, &sub_out_point)?;
+                    cells.insert(sub_out_point, sub_cell);
+                }
+            }
+            cells.insert(out_point.clone(), cell);
+            Some(SystemScript {
+                type_hash,
+                cell_dep: CellDep::new_builder()
+                    .out_point(out_point)
+                    .dep_type(dep_type.into())
+                    .build(),
+            })
+        };
+        let secp256k1_blake160_sighash_all = resolve(
+            consensus.secp256k1_blake160_sighash_all_type_hash(),
+            DepType::DepGroup,
+        );
+        let secp256k1_blake160_multisig_all = resolve(
+            consensus.secp256k1_blake160_multisig_all_type_hash(),
+            DepType::DepGroup,
+        );
+        let dao = resolve(consensus.dao_type_hash(), DepType::Code);
+        Self {
+            secp256k1_blake160_sighash_all,
+            secp256k1_blake160_multisig_all,
+            dao,
+            cells,
+        }
+    }
+
+    /// Returns the cell of the system cell deps, including the cells in the dep groups.
+    pub fn cell(&self, out_point: &OutPoint) -> Option<&CellMeta> {
+        self.cells.get(out_point)
+    }
+}
+
+// the code cells are the outputs of the cellbase
+fn find_code_cell(genesis: &BlockView, type_hash: &Byte32) -> Option<OutPoint> {
+    let cellbase = genesis.transactions().into_iter().next()?;
+    cellbase
+        .outputs()
+        .into_iter()
+        .position(|output| {
+            output
+                .type_()
+                .to_opt()
+                .map(|script| &script.calc_script_hash() == type_hash)
+                .unwrap_or(false)
+        })
+        .map(|index| OutPoint::new(cellbase.hash(), index as u32))
+}
+
+fn find_dep_group_cell(genesis: &BlockView, code_out_point: &OutPoint) -> Option<OutPoint> {
+    genesis.transactions().into_iter().skip(1).find_map(|tx| {
+        tx.outputs_data()
+            .into_iter()
+            .position(|data| {
+                OutPointVec::from_slice(&data.raw_data())
+                    .map(|out_points| out_points.into_iter().any(|o| &o == code_out_point))
+                    .unwrap_or(false)
+            })
+            .map(|index| OutPoint::new(tx.hash(), index as u32))
+    })
+}
+
+fn genesis_cell(genesis: &BlockView, out_point: &OutPoint) -> Option<CellMeta> {
+    let (tx_index, tx) = genesis
+        .transactions()
+        .into_iter()
+        .enumerate()
+        .find(|(_tx_index, tx)| tx.hash() == out_point.tx_hash())?;
+    let (cell_output, data) = tx.output_with_data(out_point.index().unpack())?;
+    Some(CellMeta {
+        out_point: out_point.clone(),
+        cell_output,
+        transaction_info: Some(TransactionInfo {
+            block_hash: genesis.hash(),
+            block_epoch: genesis.epoch(),
+            block_number: 0,
+            index: tx_index,
+        }),
+        data_bytes: data.len() as u64,
+        mem_cell_data_hash: Some(packed::CellOutput::calc_data_hash(&data)),
+        mem_cell_data: Some(data),
+    })
+}
@@ -0,0 +1,81 @@
+use std::str::FromStr;
+
+use ckb_types::{bytes::Bytes, core::ScriptHashType, h256, packed, prelude::*};
+
+use crate::address::{Address, NetworkType};
+
+fn sighash_script(hash_type: ScriptHashType) -> packed::Script {
+    packed::Script::new_builder()
+        .code_hash(
+            h256!("0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8").pack(),
+        )
+        .hash_type(hash_type.into())
+        .args(Bytes::from(hex("b39bbc0b3673c7d36450bc14cfcdad2d559c6c64")).pack())
+        .build()
+}
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+// the test vectors of RFC 21
+#[test]
+fn encode_and_decode_address() {
+    let address = Address::new(NetworkType::Mainnet, sighash_script(ScriptHashType::Type));
+    let full = "ckb1qzda0cr08m85hc8jlnfp3zer7xulejywt49kt2rr0vthywaa50xwsqdnnw7qkdnnclfkg59uzn8umtfd2kwxceqxwquc4";
+    let short = "ckb1qyqt8xaupvm8837nv3gtc9x0ekkj64vud3jqfwyw5v";
+    assert_eq!(full, address.to_string());
+    assert_eq!(Some(short.to_owned()), address.to_short_string());
+    assert_eq!(address, Address::from_str(full).unwrap());
+    assert_eq!(address, Address::from_str(short).unwrap());
+
+    // the deprecated full formats
+    let full_type = "ckb1qjda0cr08m85hc8jlnfp3zer7xulejywt49kt2rr0vthywaa50xw3vumhs9nvu786dj9p0q5elx66t24n3kxgj53qks";
+    assert_eq!(address, Address::from_str(full_type).unwrap());
+    let full_data = "ckb1q2da0cr08m85hc8jlnfp3zer7xulejywt49kt2rr0vthywaa50xw3vumhs9nvu786dj9p0q5elx66t24n3kxgdwd2q8";
+    let address = Address::new(NetworkType::Mainnet, sighash_script(ScriptHashType::Data));
+    assert_eq!(address, Address::from_str(full_data).unwrap());
+    assert!(address.to_short_string().is_none());
+
+    let address = Address::new(NetworkType::Testnet, sighash_script(ScriptHashType::Type));
+    let full = "ckt1qzda0cr08m85hc8jlnfp3zer7xulejywt49kt2rr0vthywaa50xwsqdnnw7qkdnnclfkg59uzn8umtfd2kwxceqgutnjd";
+    assert_eq!(full, address.to_string());
+    assert_eq!(address, Address::from_str(full).unwrap());
+}
+
+#[test]
+fn decode_invalid_address() {
+    // unknown prefix
+    let address = Address::new(NetworkType::Mainnet, sighash_script(ScriptHashType::Type));
+    let other = address.to_string().replacen("ckb", "ckx", 1);
+    assert!(Address::from_str(&other).is_err());
+    // bad checksum
+    assert!(Address::from_str("ckb1qyqt8xaupvm8837nv3gtc9x0ekkj64vud3jqfwyw5w").is_err());
+    // the short format with a wrong args length
+    let script = packed::Script::new_builder()
+        .code_hash(
+            h256!("0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8").pack(),
+        )
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(vec![0u8; 21]).pack())
+        .build();
+    assert!(Address::new(NetworkType::Mainnet, script)
+        .to_short_string()
+        .is_none());
+}
+
+#[test]
+fn network_from_chain_name() {
+    assert_eq!(NetworkType::Mainnet, NetworkType::from_chain_name("ckb"));
+    assert_eq!(
+        NetworkType::Testnet,
+        NetworkType::from_chain_name("ckb_testnet")
+    );
+    assert_eq!(
+        NetworkType::Testnet,
+        NetworkType::from_chain_name("ckb_dev")
+    );
+}
@@ -0,0 +1,62 @@
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::{Arc, RwLock},
+};
+
+use ckb_async_runtime::new_global_runtime;
+use ckb_chain_spec::consensus::Consensus;
+
+use crate::{
+    metrics::{Metrics, MetricsService},
+    protocols::{Peers, PendingTxs},
+    storage::{MemoryStore, Storage},
+};
+
+fn request(address: &str, request: &[u8]) -> String {
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.write_all(request).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn scrape_metrics() {
+    let tmp_dir = tempfile::Builder::new()
+        .prefix("scrape_metrics")
+        .tempdir()
+        .unwrap();
+    let storage = Storage::with_store(MemoryStore::new()).unwrap();
+    storage.init_genesis_block(Consensus::default().genesis_block().data());
+    // the metrics of another light client are not mixed in
+    let other_metrics = Metrics::new();
+    other_metrics.filter_matched_blocks.inc();
+    let service = MetricsService::new(
+        Metrics::new(),
+        storage,
+        tmp_dir.path().to_path_buf(),
+        Arc::new(Peers::new(Arc::new(RwLock::new(Vec::new())))),
+        Arc::new(RwLock::new(PendingTxs::new(64))),
+    );
+    let (handle, _stop_handler) = new_global_runtime();
+    let address = service.start("127.0.0.1:0", &handle).unwrap().to_string();
+
+    // an idle connection doesn't block the others
+    let _idle = TcpStream::connect(&address).unwrap();
+
+    let response = request(
+        &address,
+        b"GET /metrics HTTP/1.1\r\nConnection: close\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("ckb_light_client_proved_tip_number 0"));
+    assert!(response.contains("ckb_light_client_pending_txs 0"));
+    assert!(response.contains("ckb_light_client_filter_matched_blocks_total 0"));
+
+    let response = request(
+        &address,
+        b"GET /other HTTP/1.1\r\nConnection: close\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+}
@@ -2,6 +2,11 @@
 mod prelude;
 
 // The unit tests for modules which are in the root path of this crate.
+mod address;
+mod metrics;
 mod protocols;
 mod service;
+mod storage;
+mod system_scripts;
 mod verify;
+mod wallet;
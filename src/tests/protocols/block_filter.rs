@@ -1,6 +1,6 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::sync::RwLock;
-use std::time::Instant;
 
 use golomb_coded_set::{GCSFilterWriter, SipHasher24Builder, M, P};
 
@@ -15,8 +15,9 @@ use ckb_types::{
     H256, U256,
 };
 
+use crate::metrics::Metrics;
 use crate::protocols::{
-    FilterProtocol, LastState, Peers, ProveRequest, ProveState, BAD_MESSAGE_BAN_TIME,
+    Clock, FilterProtocol, LastState, Peers, ProveRequest, ProveState, BAD_MESSAGE_BAN_TIME,
     GET_BLOCK_FILTERS_TOKEN,
 };
 
@@ -28,7 +29,7 @@ async fn test_block_filter_malformed_message() {
     let nc = Arc::new(MockProtocolContext::new(SupportProtocols::Filter));
     let (storage, _) = setup("test-block-filter");
     let peers = Arc::new(Peers::default());
-    let mut protocol = FilterProtocol::new(storage, peers);
+    let mut protocol = FilterProtocol::new(storage, peers, Metrics::new());
 
     let peer_index = PeerIndex::new(3);
     let data = Bytes::from(vec![2, 3, 4, 5]);
@@ -77,7 +78,7 @@ async fn test_block_filter_ignore_start_number() {
         peers.commit_prove_state(peer_index, prove_state);
         peers
     };
-    let mut protocol = FilterProtocol::new(storage, peers);
+    let mut protocol = FilterProtocol::new(storage, peers, Metrics::new());
     let content = packed::BlockFilters::new_builder()
         .start_number((min_filtered_block_number - 1).pack())
         .block_hashes(vec![H256(rand::random()).pack(), H256(rand::random()).pack()].pack())
@@ -133,7 +134,7 @@ async fn test_block_filter_empty_filters() {
         peers.commit_prove_state(peer_index, prove_state);
         peers
     };
-    let mut protocol = FilterProtocol::new(storage, peers);
+    let mut protocol = FilterProtocol::new(storage, peers, Metrics::new());
     let content = packed::BlockFilters::new_builder()
         .start_number((min_filtered_block_number + 1).pack())
         .block_hashes(vec![].pack())
@@ -189,7 +190,7 @@ async fn test_block_filter_invalid_filters_count() {
         peers.commit_prove_state(peer_index, prove_state);
         peers
     };
-    let mut protocol = FilterProtocol::new(storage, peers);
+    let mut protocol = FilterProtocol::new(storage, peers, Metrics::new());
     let content = packed::BlockFilters::new_builder()
         .start_number((min_filtered_block_number + 1).pack())
         .block_hashes(vec![H256(rand::random()).pack(), H256(rand::random()).pack()].pack())
@@ -250,7 +251,7 @@ async fn test_block_filter_start_number_greater_then_proved_number() {
         peers.commit_prove_state(peer_index, prove_state);
         peers
     };
-    let mut protocol = FilterProtocol::new(storage, peers);
+    let mut protocol = FilterProtocol::new(storage, peers, Metrics::new());
     let content = packed::BlockFilters::new_builder()
         .start_number(start_number.pack())
         .block_hashes(vec![H256(rand::random()).pack(), H256(rand::random()).pack()].pack())
@@ -308,7 +309,7 @@ async fn test_block_filter_ok_with_blocks_not_matched() {
         peers.commit_prove_state(peer_index, prove_state);
         peers
     };
-    let mut protocol = FilterProtocol::new(storage, peers);
+    let mut protocol = FilterProtocol::new(storage, peers, Metrics::new());
     let content = packed::BlockFilters::new_builder()
         .start_number(start_number.pack())
         .block_hashes(vec![H256(rand::random()).pack(), H256(rand::random()).pack()].pack())
@@ -385,7 +386,7 @@ async fn test_block_filter_ok_with_blocks_matched() {
         peers.commit_prove_state(peer_index, prove_state);
         (peers, prove_state_block_hash)
     };
-    let mut protocol = FilterProtocol::new(storage, peers);
+    let mut protocol = FilterProtocol::new(storage, peers, Metrics::new());
 
     let filter_data = {
         let mut writer = std::io::Cursor::new(Vec::new());
@@ -486,7 +487,7 @@ async fn test_block_filter_notify_ask_filters() {
         peers.commit_prove_state(peer_index, prove_state);
         peers
     };
-    let mut protocol = FilterProtocol::new(storage, peers);
+    let mut protocol = FilterProtocol::new(storage, peers, Metrics::new());
 
     let nc_clone = Arc::clone(&nc) as Arc<dyn CKBProtocolContext + Sync>;
     protocol.notify(nc_clone, GET_BLOCK_FILTERS_TOKEN).await;
@@ -521,7 +522,7 @@ async fn test_block_filter_notify_no_proved_peers() {
         peers.add_peer(peer_index);
         peers
     };
-    let mut protocol = FilterProtocol::new(storage, peers);
+    let mut protocol = FilterProtocol::new(storage, peers, Metrics::new());
 
     let nc_clone = Arc::clone(&nc) as Arc<dyn CKBProtocolContext + Sync>;
     protocol.notify(nc_clone, GET_BLOCK_FILTERS_TOKEN).await;
@@ -565,8 +566,9 @@ async fn test_block_filter_notify_not_reach_ask() {
         peers.commit_prove_state(peer_index, prove_state);
         peers
     };
-    let mut protocol = FilterProtocol::new(storage, peers);
-    protocol.pending_peer.last_ask_time = Arc::new(RwLock::new(Some(Instant::now())));
+    let mut protocol = FilterProtocol::new(storage, peers, Metrics::new());
+    protocol.pending_peer.last_ask_time =
+        Arc::new(RwLock::new(Some(protocol.pending_peer.clock.now())));
 
     let nc_clone = Arc::clone(&nc) as Arc<dyn CKBProtocolContext + Sync>;
     protocol.notify(nc_clone, GET_BLOCK_FILTERS_TOKEN).await;
@@ -574,6 +576,30 @@ async fn test_block_filter_notify_not_reach_ask() {
     assert!(nc.sent_messages.borrow().is_empty());
 }
 
+#[test]
+fn test_block_filter_should_ask_after_timeout() {
+    let storage = {
+        let (storage, _) = setup("test-block-filter");
+        storage.update_filter_scripts(vec![(Script::default(), 3)].into_iter().collect());
+        storage
+    };
+    let now = Arc::new(AtomicU64::new(1_000_000));
+    let clock = {
+        let now = Arc::clone(&now);
+        Clock::new(move || now.load(Ordering::SeqCst))
+    };
+    let protocol =
+        FilterProtocol::new(storage, Arc::new(Peers::default()), Metrics::new()).with_clock(clock);
+    assert!(protocol.pending_peer.should_ask());
+
+    protocol.pending_peer.update_block_number(4);
+    assert!(!protocol.pending_peer.should_ask());
+    now.fetch_add(15 * 1000, Ordering::SeqCst);
+    assert!(!protocol.pending_peer.should_ask());
+    now.fetch_add(1, Ordering::SeqCst);
+    assert!(protocol.pending_peer.should_ask());
+}
+
 #[tokio::test]
 async fn test_block_filter_notify_proved_number_not_big_enough() {
     let nc = Arc::new(MockProtocolContext::new(SupportProtocols::Filter));
@@ -610,7 +636,7 @@ async fn test_block_filter_notify_proved_number_not_big_enough() {
         peers.commit_prove_state(peer_index, prove_state);
         peers
     };
-    let mut protocol = FilterProtocol::new(storage, peers);
+    let mut protocol = FilterProtocol::new(storage, peers, Metrics::new());
 
     let nc_clone = Arc::clone(&nc) as Arc<dyn CKBProtocolContext + Sync>;
     protocol.notify(nc_clone, GET_BLOCK_FILTERS_TOKEN).await;
@@ -0,0 +1,385 @@
+use std::sync::{Arc, RwLock};
+
+use ckb_chain_spec::consensus::{Consensus, ConsensusBuilder};
+use ckb_network::{
+    bytes::Bytes, CKBProtocolContext, CKBProtocolHandler, PeerIndex, ProtocolId, SupportProtocols,
+};
+use ckb_types::{
+    core::{
+        capacity_bytes, BlockNumber, Capacity, ScriptHashType, TransactionBuilder, TransactionView,
+    },
+    packed::{self, CellInput, CellOutput, Script},
+    prelude::*,
+    H256,
+};
+
+use crate::metrics::Metrics;
+use crate::protocols::{
+    FilterProtocol, LightClientProtocol, Peers, PendingTxs, SyncProtocol, GET_BLOCK_FILTERS_TOKEN,
+    REFRESH_PEERS_DURATION, REFRESH_PEERS_TOKEN,
+};
+use crate::storage::{MemoryStore, Storage};
+
+use super::mock_context::MockProtocolContext;
+use super::mock_node::{Lie, MockChain, MockFullNode};
+
+/// A light client which is connected to a mock full node.
+struct LightClientUnderTest {
+    storage: Storage,
+    pending_txs: Arc<RwLock<PendingTxs>>,
+    peer: PeerIndex,
+    light_client: LightClientProtocol,
+    filter: FilterProtocol,
+    sync: SyncProtocol,
+    light_client_nc: Arc<MockProtocolContext>,
+    filter_nc: Arc<MockProtocolContext>,
+    sync_nc: Arc<MockProtocolContext>,
+    faketime_file: tempfile::NamedTempFile,
+    now: u64,
+}
+
+impl LightClientUnderTest {
+    fn new(consensus: &Consensus, scripts: Vec<Script>) -> Self {
+        let storage = Storage::with_store(MemoryStore::new()).unwrap();
+        storage.init_genesis_block(consensus.genesis_block().data());
+        storage.update_filter_scripts(scripts.into_iter().map(|script| (script, 0)).collect());
+        let peers = Arc::new(Peers::default());
+        let pending_txs = Arc::new(RwLock::new(PendingTxs::new(64)));
+        let faketime_file = tempfile::NamedTempFile::new().expect("create faketime file");
+        let now = 1_600_000_000_000;
+        faketime::write_millis(faketime_file.path(), now).expect("write faketime file");
+        faketime::enable(faketime_file.path());
+        Self {
+            light_client: LightClientProtocol::new(
+                storage.clone(),
+                Arc::clone(&peers),
+                consensus.clone(),
+                Metrics::new(),
+            ),
+            filter: FilterProtocol::new(storage.clone(), peers, Metrics::new()),
+            sync: SyncProtocol::new(storage.clone(), Arc::clone(&pending_txs), Metrics::new()),
+            storage,
+            pending_txs,
+            peer: PeerIndex::new(1),
+            light_client_nc: Arc::new(MockProtocolContext::new(SupportProtocols::LightClient)),
+            filter_nc: Arc::new(MockProtocolContext::new(SupportProtocols::Filter)),
+            sync_nc: Arc::new(MockProtocolContext::new(SupportProtocols::Sync)),
+            faketime_file,
+            now,
+        }
+    }
+
+    async fn connect(&mut self, node: &mut MockFullNode) {
+        let nc = Arc::clone(&self.filter_nc) as Arc<dyn CKBProtocolContext + Sync>;
+        self.filter.connected(nc, self.peer, "2").await;
+        let nc = Arc::clone(&self.sync_nc) as Arc<dyn CKBProtocolContext + Sync>;
+        self.sync.connected(nc, self.peer, "2").await;
+        let nc = Arc::clone(&self.light_client_nc) as Arc<dyn CKBProtocolContext + Sync>;
+        self.light_client.connected(nc, self.peer, "2").await;
+        self.run(node).await;
+    }
+
+    /// Asks the node to prove its last state again, as the timer does.
+    async fn refresh(&mut self, node: &mut MockFullNode) {
+        self.now += REFRESH_PEERS_DURATION.as_millis() as u64 + 1;
+        faketime::write_millis(self.faketime_file.path(), self.now).expect("write faketime file");
+        let nc = Arc::clone(&self.light_client_nc) as Arc<dyn CKBProtocolContext + Sync>;
+        self.light_client.notify(nc, REFRESH_PEERS_TOKEN).await;
+        self.run(node).await;
+    }
+
+    /// Downloads the filters and the matched blocks, as the timer does.
+    async fn sync_filters(&mut self, node: &mut MockFullNode) {
+        // don't wait for the timeout of the last request
+        *self.filter.pending_peer.last_ask_time.write().unwrap() = None;
+        let nc = Arc::clone(&self.filter_nc) as Arc<dyn CKBProtocolContext + Sync>;
+        self.filter.notify(nc, GET_BLOCK_FILTERS_TOKEN).await;
+        self.run(node).await;
+    }
+
+    /// Exchanges messages with the node until both sides have nothing to send.
+    async fn run(&mut self, node: &mut MockFullNode) {
+        loop {
+            for nc in [&self.light_client_nc, &self.filter_nc, &self.sync_nc] {
+                let messages: Vec<_> = nc.sent_messages.borrow_mut().drain(..).collect();
+                for (protocol_id, _, data) in messages {
+                    node.handle(protocol_id, &data);
+                }
+            }
+            let responses = node.poll();
+            if responses.is_empty() && !node.has_pending_responses() {
+                break;
+            }
+            for (protocol_id, data) in responses {
+                self.deliver(protocol_id, data).await;
+            }
+        }
+    }
+
+    async fn deliver(&mut self, protocol_id: ProtocolId, data: Bytes) {
+        if protocol_id == SupportProtocols::LightClient.protocol_id() {
+            let nc = Arc::clone(&self.light_client_nc) as Arc<dyn CKBProtocolContext + Sync>;
+            self.light_client.received(nc, self.peer, data).await;
+        } else if protocol_id == SupportProtocols::Filter.protocol_id() {
+            let nc = Arc::clone(&self.filter_nc) as Arc<dyn CKBProtocolContext + Sync>;
+            self.filter.received(nc, self.peer, data).await;
+        } else {
+            let nc = Arc::clone(&self.sync_nc) as Arc<dyn CKBProtocolContext + Sync>;
+            self.sync.received(nc, self.peer, data).await;
+        }
+    }
+
+    fn tip_number(&self) -> BlockNumber {
+        self.storage.get_tip_header().raw().number().unpack()
+    }
+
+    fn filtered_block_number(&self) -> BlockNumber {
+        self.storage
+            .get_filter_scripts()
+            .values()
+            .min()
+            .cloned()
+            .expect("has scripts")
+    }
+
+    fn has_transaction(&self, tx_hash: &packed::Byte32) -> bool {
+        self.storage.get_transaction_with_header(tx_hash).is_some()
+    }
+}
+
+pub(crate) fn new_script() -> Script {
+    Script::new_builder()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Data.into())
+        .args(Bytes::from(vec![1, 2, 3]).pack())
+        .build()
+}
+
+// Pushes a block with a transaction which spends a cellbase output to the script.
+pub(crate) fn push_payment(chain: &mut MockChain, script: &Script) -> packed::Byte32 {
+    let tx = new_payment(chain, script);
+    chain.push_block(vec![tx.clone()]);
+    tx.hash()
+}
+
+// A transaction which spends a cellbase output to the script, the output data is random, so the
+// transactions have different hashes.
+pub(crate) fn new_payment(chain: &MockChain, script: &Script) -> TransactionView {
+    let cellbase = chain
+        .block(1)
+        .expect("checked: block#1 is existed")
+        .transaction(0)
+        .expect("checked: cellbase is existed");
+    TransactionBuilder::default()
+        .input(CellInput::new(cellbase.output_pts()[0].clone(), 0))
+        .output(
+            CellOutput::new_builder()
+                .capacity(capacity_bytes!(100).pack())
+                .lock(script.clone())
+                .build(),
+        )
+        .output_data(Bytes::from(rand::random::<[u8; 32]>().to_vec()).pack())
+        .build()
+}
+
+#[tokio::test]
+async fn sync_filtered_blocks_from_mock_node() {
+    let consensus = ConsensusBuilder::default().build();
+    let script = new_script();
+    let mut chain = MockChain::new(&consensus);
+    chain.generate(120);
+    let tx_hash_in_middle = push_payment(&mut chain, &script);
+    chain.generate(178);
+    let tx_hash_at_tip = push_payment(&mut chain, &script);
+    let tip_number = chain.tip_number();
+    let mut node = MockFullNode::new(chain);
+
+    let mut client = LightClientUnderTest::new(&consensus, vec![script]);
+    client.connect(&mut node).await;
+    assert_eq!(client.tip_number(), tip_number);
+
+    client.sync_filters(&mut node).await;
+    assert_eq!(client.filtered_block_number(), tip_number);
+    assert!(client.has_transaction(&tx_hash_in_middle));
+    assert!(client.has_transaction(&tx_hash_at_tip));
+    for name in [
+        packed::GetLastState::NAME,
+        packed::GetBlockSamples::NAME,
+        packed::GetBlockFilters::NAME,
+        packed::GetBlockProof::NAME,
+        packed::GetBlocks::NAME,
+    ] {
+        assert!(node.received().contains(&name), "{} is not received", name);
+    }
+    assert!(client.light_client_nc.banned_peers.borrow().is_empty());
+}
+
+#[tokio::test]
+async fn rollback_filtered_blocks_after_fork() {
+    let consensus = ConsensusBuilder::default().build();
+    let script = new_script();
+    let mut chain = MockChain::new(&consensus);
+    chain.generate(25);
+    let stale_tx_hash = push_payment(&mut chain, &script);
+    chain.generate(4);
+    let mut node = MockFullNode::new(chain);
+
+    let mut client = LightClientUnderTest::new(&consensus, vec![script.clone()]);
+    client.connect(&mut node).await;
+    client.sync_filters(&mut node).await;
+    assert_eq!(client.filtered_block_number(), 30);
+    assert!(client.has_transaction(&stale_tx_hash));
+
+    // A longer fork since block#25.
+    node.chain_mut().rewind(25);
+    node.chain_mut().generate(3);
+    let tx_hash = push_payment(node.chain_mut(), &script);
+    node.chain_mut().generate(6);
+    let tip_number = node.chain().tip_number();
+    node.announce_last_state();
+    client.run(&mut node).await;
+    client.refresh(&mut node).await;
+    assert_eq!(client.tip_number(), tip_number);
+    assert!(!client.has_transaction(&stale_tx_hash));
+
+    client.sync_filters(&mut node).await;
+    assert_eq!(client.filtered_block_number(), tip_number);
+    assert!(client.has_transaction(&tx_hash));
+    assert!(!client.has_transaction(&stale_tx_hash));
+}
+
+#[tokio::test]
+async fn remove_committed_pending_transactions() {
+    let consensus = ConsensusBuilder::default().build();
+    let script = new_script();
+    let mut chain = MockChain::new(&consensus);
+    chain.generate(10);
+    let committed_tx = new_payment(&chain, &script);
+    let uncommitted_tx = new_payment(&chain, &script);
+    chain.push_block(vec![committed_tx.clone()]);
+    chain.generate(10);
+    let mut node = MockFullNode::new(chain);
+
+    let mut client = LightClientUnderTest::new(&consensus, vec![script]);
+    for tx in [&committed_tx, &uncommitted_tx] {
+        client.pending_txs.write().unwrap().push(tx.clone(), 0);
+    }
+    client.connect(&mut node).await;
+    client.sync_filters(&mut node).await;
+    assert!(client.has_transaction(&committed_tx.hash()));
+    let pending_txs = client.pending_txs.read().unwrap();
+    assert!(pending_txs.get_relay_state(&committed_tx.hash()).is_none());
+    assert!(pending_txs
+        .get_relay_state(&uncommitted_tx.hash())
+        .is_some());
+}
+
+#[tokio::test]
+async fn refilter_first_block_of_fork() {
+    let consensus = ConsensusBuilder::default().build();
+    let script = new_script();
+    let mut chain = MockChain::new(&consensus);
+    chain.generate(24);
+    let stale_tx_hash = push_payment(&mut chain, &script);
+    chain.generate(5);
+    let mut node = MockFullNode::new(chain);
+
+    let mut client = LightClientUnderTest::new(&consensus, vec![script.clone()]);
+    client.connect(&mut node).await;
+    client.sync_filters(&mut node).await;
+    assert_eq!(client.filtered_block_number(), 30);
+    assert!(client.has_transaction(&stale_tx_hash));
+
+    // A longer fork since block#25, the new transaction is in the first block of the fork.
+    node.chain_mut().rewind(25);
+    let tx_hash = push_payment(node.chain_mut(), &script);
+    node.chain_mut().generate(9);
+    let tip_number = node.chain().tip_number();
+    node.announce_last_state();
+    client.run(&mut node).await;
+    client.refresh(&mut node).await;
+    assert_eq!(client.tip_number(), tip_number);
+    assert_eq!(client.filtered_block_number(), 24);
+    assert!(!client.has_transaction(&stale_tx_hash));
+
+    client.sync_filters(&mut node).await;
+    assert_eq!(client.filtered_block_number(), tip_number);
+    assert!(client.has_transaction(&tx_hash));
+    assert!(!client.has_transaction(&stale_tx_hash));
+}
+
+#[tokio::test]
+async fn ban_mock_node_which_sends_invalid_proof() {
+    let consensus = ConsensusBuilder::default().build();
+    let mut chain = MockChain::new(&consensus);
+    chain.generate(150);
+    let mut node = MockFullNode::new(chain);
+    node.lie(Lie::InvalidProof);
+
+    let mut client = LightClientUnderTest::new(&consensus, vec![new_script()]);
+    client.connect(&mut node).await;
+    assert!(client.light_client_nc.has_banned(client.peer).is_some());
+    assert_eq!(client.tip_number(), 0);
+}
+
+#[tokio::test]
+async fn lies_by_omission_are_not_detected() {
+    let consensus = ConsensusBuilder::default().build();
+    let script = new_script();
+    let mut chain = MockChain::new(&consensus);
+    chain.generate(10);
+    let tx_hash = push_payment(&mut chain, &script);
+    chain.generate(10);
+    let tip_number = chain.tip_number();
+
+    // The filters hide the transaction.
+    let mut node = MockFullNode::new(chain);
+    node.lie(Lie::EmptyFilters);
+    let mut client = LightClientUnderTest::new(&consensus, vec![script.clone()]);
+    client.connect(&mut node).await;
+    client.sync_filters(&mut node).await;
+    assert_eq!(client.filtered_block_number(), tip_number);
+    assert!(!client.has_transaction(&tx_hash));
+    assert!(!node.received().contains(&packed::GetBlockProof::NAME));
+
+    // The proof is refused, so the block is never requested.
+    let mut node = MockFullNode::new(MockChain::new(&consensus));
+    let tx_hash = {
+        let chain = node.chain_mut();
+        chain.generate(10);
+        let tx_hash = push_payment(chain, &script);
+        chain.generate(10);
+        tx_hash
+    };
+    node.lie(Lie::UnknownTip);
+    let mut client = LightClientUnderTest::new(&consensus, vec![script]);
+    client.connect(&mut node).await;
+    client.sync_filters(&mut node).await;
+    assert!(node.received().contains(&packed::GetBlockProof::NAME));
+    assert!(!node.received().contains(&packed::GetBlocks::NAME));
+    assert!(!client.has_transaction(&tx_hash));
+    assert!(client.light_client_nc.banned_peers.borrow().is_empty());
+}
+
+#[tokio::test]
+async fn delayed_and_dropped_responses() {
+    let consensus = ConsensusBuilder::default().build();
+    let script = new_script();
+    let mut chain = MockChain::new(&consensus);
+    chain.generate(10);
+    let tx_hash = push_payment(&mut chain, &script);
+    chain.generate(10);
+    let tip_number = chain.tip_number();
+    let mut node = MockFullNode::new(chain);
+    node.delay(packed::SendBlockSamples::NAME, 3);
+    node.delay(packed::BlockFilters::NAME, 1);
+    node.drop_responses(packed::SendBlock::NAME);
+
+    let mut client = LightClientUnderTest::new(&consensus, vec![script]);
+    client.connect(&mut node).await;
+    assert_eq!(client.tip_number(), tip_number);
+    client.sync_filters(&mut node).await;
+    assert_eq!(client.filtered_block_number(), tip_number);
+    assert!(node.received().contains(&packed::GetBlocks::NAME));
+    assert!(!client.has_transaction(&tx_hash));
+}
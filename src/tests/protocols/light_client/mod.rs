@@ -1,22 +1,29 @@
 use std::sync::Arc;
 
+use ckb_network::{CKBProtocolContext, CKBProtocolHandler, PeerIndex, SupportProtocols};
 use ckb_types::{
-    core::{BlockNumber, EpochNumberWithFraction, HeaderBuilder},
+    core::{BlockNumber, EpochNumberWithFraction, HeaderBuilder, HeaderView},
+    packed,
     prelude::*,
     utilities::merkle_mountain_range::VerifiableHeader,
-    U256,
+    H256, U256,
 };
 
-use crate::protocols::{LightClientProtocol, PeerState, Peers, LAST_N_BLOCKS};
+use crate::metrics::Metrics;
+use crate::protocols::{
+    reorg_rollback_number, LastState, LightClientProtocol, PeerState, Peers, ProveRequest,
+    BAD_MESSAGE_BAN_TIME, FETCHING_HEADER_EXPIRY, LAST_N_BLOCKS, MAX_FETCHING_HEADERS,
+};
 
 use super::super::verify::setup;
+use super::mock_context::MockProtocolContext;
 
 #[test]
 fn build_prove_request_content() {
     let (storage, consensus) = setup("test-light-client");
 
     let peers = Arc::new(Peers::default());
-    let protocol = LightClientProtocol::new(storage.clone(), peers, consensus);
+    let protocol = LightClientProtocol::new(storage.clone(), peers, consensus, Metrics::new());
 
     let peer_state = PeerState::default();
     let last_number = 50;
@@ -117,3 +124,105 @@ fn build_prove_request_content() {
         }
     }
 }
+
+#[tokio::test]
+async fn ban_peer_which_sends_block_samples_without_last_n_headers() {
+    let nc = Arc::new(MockProtocolContext::new(SupportProtocols::LightClient));
+    let (storage, consensus) = setup("test-light-client");
+    let peer_index = PeerIndex::new(3);
+    let peers = {
+        let tip_header = VerifiableHeader::new(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 10, 100).pack())
+                .number(10u64.pack())
+                .build(),
+            Default::default(),
+            None,
+        );
+        let last_state = LastState::new(tip_header, U256::from(10u64));
+        let peers = Arc::new(Peers::default());
+        peers.add_peer(peer_index);
+        peers.submit_prove_request(
+            peer_index,
+            ProveRequest::new(last_state, Default::default()),
+        );
+        peers
+    };
+    let mut protocol = LightClientProtocol::new(storage, peers, consensus, Metrics::new());
+
+    let content = packed::SendBlockSamples::new_builder().build();
+    let message = packed::LightClientMessage::new_builder()
+        .set(content)
+        .build();
+    let nc_clone = Arc::clone(&nc) as Arc<dyn CKBProtocolContext + Sync>;
+    protocol
+        .received(nc_clone, peer_index, message.as_bytes())
+        .await;
+
+    assert_eq!(
+        nc.has_banned(peer_index).map(|(duration, _)| duration),
+        Some(BAD_MESSAGE_BAN_TIME)
+    );
+}
+
+#[test]
+fn fetch_headers_limit_and_expiry() {
+    let faketime_file = tempfile::NamedTempFile::new().unwrap();
+    faketime::write_millis(faketime_file.path(), 1_000).unwrap();
+    faketime::enable(faketime_file.path());
+
+    let peers = Peers::default();
+    let block_hashes = (0..MAX_FETCHING_HEADERS + 10)
+        .map(|_| H256(rand::random()).pack())
+        .collect::<Vec<packed::Byte32>>();
+    // the duplicated hashes are fetched once
+    peers.fetch_headers(vec![block_hashes[0].clone(), block_hashes[0].clone()]);
+    assert_eq!(peers.get_headers_to_fetch().len(), 1);
+    peers.fetch_headers(block_hashes.clone());
+    assert_eq!(peers.get_headers_to_fetch().len(), MAX_FETCHING_HEADERS);
+    assert!(!peers.is_fetching_header(&block_hashes[MAX_FETCHING_HEADERS]));
+
+    // the requested headers are not fetched again before the request is timeout
+    peers.mark_headers_fetching(&block_hashes[..10]);
+    assert_eq!(
+        peers.get_headers_to_fetch().len(),
+        MAX_FETCHING_HEADERS - 10
+    );
+
+    faketime::write_millis(faketime_file.path(), 1_000 + FETCHING_HEADER_EXPIRY + 1).unwrap();
+    assert!(peers.get_headers_to_fetch().is_empty());
+    assert!(!peers.is_fetching_header(&block_hashes[0]));
+}
+
+#[test]
+fn reorg_rollback_number_of_fork() {
+    // the headers of the same number on different forks differ in the timestamp
+    let headers = |numbers: std::ops::RangeInclusive<BlockNumber>, fork: u64| {
+        numbers
+            .map(|number| {
+                HeaderBuilder::default()
+                    .number(number.pack())
+                    .timestamp(fork.pack())
+                    .build()
+            })
+            .collect::<Vec<HeaderView>>()
+    };
+    let last_headers = headers(10..=20, 0);
+
+    // the common ancestor is block#17 which is in the reorg last headers
+    let reorg_last_headers = [headers(15..=17, 0), headers(18..=20, 1)].concat();
+    assert_eq!(
+        reorg_rollback_number(&last_headers, &reorg_last_headers),
+        Some(18)
+    );
+
+    // there is no common block in the reorg last headers
+    let reorg_last_headers = headers(15..=20, 1);
+    assert_eq!(
+        reorg_rollback_number(&last_headers, &reorg_last_headers),
+        Some(15)
+    );
+
+    // no reorg
+    assert_eq!(reorg_rollback_number(&last_headers, &[]), None);
+}
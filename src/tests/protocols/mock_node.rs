@@ -0,0 +1,723 @@
+//! An in-process full node which serves a generated chain to the light client protocols.
+//!
+//! The chain has real MMR roots in the block extensions and real GCS block filters, so the
+//! responses pass all checks of the client, unless a test asks the node to lie.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use ckb_chain_spec::consensus::Consensus;
+use ckb_merkle_mountain_range::{
+    leaf_index_to_mmr_size, leaf_index_to_pos, MMRStore, Result as MMRResult,
+};
+use ckb_network::{bytes::Bytes, ProtocolId, SupportProtocols};
+use ckb_types::{
+    core::{
+        capacity_bytes, BlockBuilder, BlockNumber, BlockView, Capacity, EpochNumberWithFraction,
+        TransactionBuilder, TransactionView,
+    },
+    packed::{self, CellInput, CellOutput, Script},
+    prelude::*,
+    utilities::{compact_to_difficulty, merkle_mountain_range::ChainRootMMR},
+    U256,
+};
+use golomb_coded_set::{GCSFilterWriter, SipHasher24Builder, M, P};
+
+// same as the full node
+const GET_BLOCK_FILTERS_LIMIT: u64 = 1000;
+const BLOCK_INTERVAL_MILLIS: u64 = 8000;
+
+/// Keeps all MMR nodes in a vector which is indexed by the MMR position.
+#[derive(Default)]
+struct MemoryMMRStore(RefCell<Vec<packed::HeaderDigest>>);
+
+impl MMRStore<packed::HeaderDigest> for &MemoryMMRStore {
+    fn get_elem(&self, pos: u64) -> MMRResult<Option<packed::HeaderDigest>> {
+        Ok(self.0.borrow().get(pos as usize).cloned())
+    }
+
+    fn append(&mut self, pos: u64, elems: Vec<packed::HeaderDigest>) -> MMRResult<()> {
+        let mut nodes = self.0.borrow_mut();
+        assert_eq!(pos as usize, nodes.len(), "MMR nodes should be appended");
+        nodes.extend(elems);
+        Ok(())
+    }
+}
+
+/// A generated chain which starts from the genesis block of the consensus.
+///
+/// All blocks have the same compact target and the same epoch length as the genesis block.
+pub(crate) struct MockChain {
+    blocks: Vec<BlockView>,
+    filters: Vec<Bytes>,
+    total_difficulties: Vec<U256>,
+    mmr_store: MemoryMMRStore,
+    mmr_size: u64,
+    // outputs of all generated transactions, used to build filters for inputs
+    cells: HashMap<packed::OutPoint, CellOutput>,
+    // blocks which were removed from the main chain by forks
+    stale_blocks: HashMap<packed::Byte32, BlockView>,
+    nonce: u128,
+}
+
+impl MockChain {
+    pub(crate) fn new(consensus: &Consensus) -> Self {
+        let mut chain = Self {
+            blocks: Vec::new(),
+            filters: Vec::new(),
+            total_difficulties: Vec::new(),
+            mmr_store: Default::default(),
+            mmr_size: 0,
+            cells: HashMap::new(),
+            stale_blocks: HashMap::new(),
+            nonce: 0,
+        };
+        chain.insert_block(consensus.genesis_block().to_owned());
+        chain
+    }
+
+    pub(crate) fn tip(&self) -> &BlockView {
+        self.blocks
+            .last()
+            .expect("checked: genesis block is always existed")
+    }
+
+    pub(crate) fn tip_number(&self) -> BlockNumber {
+        self.tip().number()
+    }
+
+    pub(crate) fn block(&self, number: BlockNumber) -> Option<&BlockView> {
+        self.blocks.get(number as usize)
+    }
+
+    /// Finds a block in the main chain or in the stale forks.
+    pub(crate) fn block_by_hash(&self, hash: &packed::Byte32) -> Option<&BlockView> {
+        self.main_chain_number(hash)
+            .and_then(|number| self.block(number))
+            .or_else(|| self.stale_blocks.get(hash))
+    }
+
+    pub(crate) fn main_chain_number(&self, hash: &packed::Byte32) -> Option<BlockNumber> {
+        self.blocks
+            .iter()
+            .rev()
+            .find(|block| &block.hash() == hash)
+            .map(|block| block.number())
+    }
+
+    /// Returns the total difficulty of all blocks from the genesis block to the block.
+    pub(crate) fn total_difficulty(&self, number: BlockNumber) -> U256 {
+        self.total_difficulties[number as usize].clone()
+    }
+
+    /// Appends empty blocks which only have a cellbase transaction.
+    pub(crate) fn generate(&mut self, count: usize) {
+        for _ in 0..count {
+            self.push_block(Vec::new());
+        }
+    }
+
+    /// Appends a block with the transactions after the cellbase transaction.
+    pub(crate) fn push_block(&mut self, transactions: Vec<TransactionView>) -> BlockView {
+        let parent = self.tip().clone();
+        let number = parent.number() + 1;
+        let epoch_length = parent.epoch().length();
+        let epoch = EpochNumberWithFraction::new(
+            number / epoch_length,
+            number % epoch_length,
+            epoch_length,
+        );
+        let cellbase = TransactionBuilder::default()
+            .input(CellInput::new_cellbase_input(number))
+            .output(
+                CellOutput::new_builder()
+                    .capacity(capacity_bytes!(1000).pack())
+                    .lock(Script::default())
+                    .build(),
+            )
+            .output_data(Bytes::new().pack())
+            .build();
+        // a nonce for each block, so blocks in different forks have different hashes
+        self.nonce += 1;
+        let chain_root = self.chain_root(number - 1);
+        let block = BlockBuilder::default()
+            .parent_hash(parent.hash())
+            .number(number.pack())
+            .epoch(epoch.pack())
+            .timestamp((parent.timestamp() + BLOCK_INTERVAL_MILLIS).pack())
+            .compact_target(parent.compact_target().pack())
+            .nonce(self.nonce.pack())
+            .transaction(cellbase)
+            .transactions(transactions)
+            .extension(Some(chain_root.calc_mmr_hash().as_bytes().pack()))
+            .build();
+        self.insert_block(block.clone());
+        block
+    }
+
+    /// Removes the blocks since the block number from the main chain.
+    ///
+    /// The removed blocks are still served by hashes, as a full node does for stale blocks.
+    pub(crate) fn rewind(&mut self, number: BlockNumber) {
+        assert!(number > 0, "the genesis block can not be removed");
+        for block in self.blocks.drain(number as usize..) {
+            self.stale_blocks.insert(block.hash(), block);
+        }
+        self.filters.truncate(number as usize);
+        self.total_difficulties.truncate(number as usize);
+        self.mmr_size = leaf_index_to_mmr_size(number - 1);
+        self.mmr_store
+            .0
+            .borrow_mut()
+            .truncate(self.mmr_size as usize);
+    }
+
+    fn insert_block(&mut self, block: BlockView) {
+        let filter = self.build_filter(&block);
+        for tx in block.transactions() {
+            for (output, out_point) in tx.outputs().into_iter().zip(tx.output_pts()) {
+                self.cells.insert(out_point, output);
+            }
+        }
+        let block_difficulty = compact_to_difficulty(block.compact_target());
+        let total_difficulty = self
+            .total_difficulties
+            .last()
+            .map(|total| total + &block_difficulty)
+            .unwrap_or(block_difficulty);
+        {
+            let mut mmr = ChainRootMMR::new(self.mmr_size, &self.mmr_store);
+            mmr.push(block.header().digest())
+                .expect("push into MMR should be OK");
+            self.mmr_size = mmr.mmr_size();
+            mmr.commit().expect("commit MMR should be OK");
+        }
+        self.blocks.push(block);
+        self.filters.push(filter);
+        self.total_difficulties.push(total_difficulty);
+    }
+
+    fn build_filter(&self, block: &BlockView) -> Bytes {
+        let mut writer = std::io::Cursor::new(Vec::new());
+        let mut filter = GCSFilterWriter::new(&mut writer, SipHasher24Builder::new(0, 0), M, P);
+        for tx in block.transactions() {
+            if !tx.is_cellbase() {
+                for out_point in tx.input_pts_iter() {
+                    if let Some(output) = self.cells.get(&out_point) {
+                        filter.add_element(output.calc_lock_hash().as_slice());
+                        if let Some(script) = output.type_().to_opt() {
+                            filter.add_element(script.calc_script_hash().as_slice());
+                        }
+                    }
+                }
+            }
+            for output in tx.outputs() {
+                filter.add_element(output.calc_lock_hash().as_slice());
+                if let Some(script) = output.type_().to_opt() {
+                    filter.add_element(script.calc_script_hash().as_slice());
+                }
+            }
+        }
+        filter
+            .finish()
+            .expect("flush to memory writer should be OK");
+        Bytes::from(writer.into_inner())
+    }
+
+    /// Returns the root of the MMR which contains all blocks from the genesis block to the block.
+    fn chain_root(&self, number: BlockNumber) -> packed::HeaderDigest {
+        ChainRootMMR::new(leaf_index_to_mmr_size(number), &self.mmr_store)
+            .get_root()
+            .expect("get MMR root should be OK")
+    }
+
+    /// Returns the MMR proof for the blocks, the MMR contains all blocks from the genesis block
+    /// to the last block.
+    fn proof(&self, last_number: BlockNumber, numbers: &[BlockNumber]) -> packed::HeaderDigestVec {
+        let mut positions: Vec<u64> = numbers.iter().map(|n| leaf_index_to_pos(*n)).collect();
+        positions.sort_unstable();
+        positions.dedup();
+        let items = if positions.is_empty() {
+            Vec::new()
+        } else {
+            ChainRootMMR::new(leaf_index_to_mmr_size(last_number), &self.mmr_store)
+                .gen_proof(positions)
+                .expect("generate MMR proof should be OK")
+                .proof_items()
+                .to_owned()
+        };
+        packed::HeaderDigestVec::new_builder().set(items).build()
+    }
+
+    fn verifiable_header(&self, number: BlockNumber) -> packed::VerifiableHeader {
+        let block = &self.blocks[number as usize];
+        packed::VerifiableHeader::new_builder()
+            .header(block.header().data())
+            .uncles_hash(block.data().calc_uncles_hash())
+            .extension(
+                packed::BytesOpt::new_builder()
+                    .set(block.extension())
+                    .build(),
+            )
+            .build()
+    }
+
+    fn verifiable_header_with_chain_root(
+        &self,
+        number: BlockNumber,
+    ) -> packed::VerifiableHeaderWithChainRoot {
+        let block = &self.blocks[number as usize];
+        let chain_root = if number == 0 {
+            Default::default()
+        } else {
+            self.chain_root(number - 1)
+        };
+        packed::VerifiableHeaderWithChainRoot::new_builder()
+            .header(block.header().data())
+            .uncles_hash(block.data().calc_uncles_hash())
+            .extension(
+                packed::BytesOpt::new_builder()
+                    .set(block.extension())
+                    .build(),
+            )
+            .chain_root(chain_root)
+            .build()
+    }
+
+    // The first block whose total difficulty is not less than the difficulty.
+    fn first_block_reaching(&self, difficulty: &U256) -> BlockNumber {
+        self.total_difficulties
+            .partition_point(|total| total < difficulty) as BlockNumber
+    }
+}
+
+/// The ways for a mock full node to lie to the light client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Lie {
+    /// Sends chain roots which don't match the MMR proofs.
+    InvalidProof,
+    /// Answers every `GetBlockProof` as if the tip was not on the chain.
+    UnknownTip,
+    /// Sends block filters which match nothing.
+    EmptyFilters,
+}
+
+struct Response {
+    protocol_id: ProtocolId,
+    data: Bytes,
+    delay: usize,
+}
+
+/// A full node which answers the requests of a light client.
+///
+/// Requests are handled by [`handle`](Self::handle) and the responses are queued, tests take
+/// them out by [`poll`](Self::poll), after the delays which are set for their message types.
+pub(crate) struct MockFullNode {
+    chain: MockChain,
+    lies: HashSet<Lie>,
+    delays: HashMap<&'static str, usize>,
+    dropped: HashSet<&'static str>,
+    outbox: Vec<Response>,
+    received: Vec<&'static str>,
+    // the transactions which are relayed by the light client
+    tx_pool: HashMap<packed::Byte32, packed::Transaction>,
+    reject_transactions: bool,
+}
+
+impl MockFullNode {
+    pub(crate) fn new(chain: MockChain) -> Self {
+        Self {
+            chain,
+            lies: HashSet::new(),
+            delays: HashMap::new(),
+            dropped: HashSet::new(),
+            outbox: Vec::new(),
+            received: Vec::new(),
+            tx_pool: HashMap::new(),
+            reject_transactions: false,
+        }
+    }
+
+    pub(crate) fn chain(&self) -> &MockChain {
+        &self.chain
+    }
+
+    pub(crate) fn chain_mut(&mut self) -> &mut MockChain {
+        &mut self.chain
+    }
+
+    pub(crate) fn lie(&mut self, lie: Lie) {
+        self.lies.insert(lie);
+    }
+
+    /// Holds the responses of the message type for some more polls.
+    pub(crate) fn delay(&mut self, item_name: &'static str, polls: usize) {
+        self.delays.insert(item_name, polls);
+    }
+
+    /// Never sends the responses of the message type.
+    pub(crate) fn drop_responses(&mut self, item_name: &'static str) {
+        self.dropped.insert(item_name);
+    }
+
+    /// Keeps the relayed transactions out of the tx-pool, so they are never announced back.
+    pub(crate) fn reject_transactions(&mut self) {
+        self.reject_transactions = true;
+    }
+
+    /// The names of all requests which were received, in order.
+    pub(crate) fn received(&self) -> &[&'static str] {
+        &self.received
+    }
+
+    pub(crate) fn has_pending_responses(&self) -> bool {
+        !self.outbox.is_empty()
+    }
+
+    /// Takes the responses which are ready to be sent.
+    pub(crate) fn poll(&mut self) -> Vec<(ProtocolId, Bytes)> {
+        let mut ready = Vec::new();
+        let mut pending = Vec::new();
+        for mut response in self.outbox.drain(..) {
+            if response.delay == 0 {
+                ready.push((response.protocol_id, response.data));
+            } else {
+                response.delay -= 1;
+                pending.push(response);
+            }
+        }
+        self.outbox = pending;
+        ready
+    }
+
+    /// Announces the current tip, as a full node does when its tip is changed.
+    pub(crate) fn announce_last_state(&mut self) {
+        self.send_last_state();
+    }
+
+    pub(crate) fn handle(&mut self, protocol_id: ProtocolId, data: &[u8]) {
+        if protocol_id == SupportProtocols::LightClient.protocol_id() {
+            let message = packed::LightClientMessageReader::from_slice(data)
+                .expect("light client sends a valid message")
+                .to_enum();
+            self.received.push(message.item_name());
+            match message {
+                packed::LightClientMessageUnionReader::GetLastState(_) => self.send_last_state(),
+                packed::LightClientMessageUnionReader::GetBlockSamples(reader) => {
+                    self.send_block_samples(reader.to_entity())
+                }
+                packed::LightClientMessageUnionReader::GetBlockProof(reader) => {
+                    self.send_block_proof(reader.to_entity())
+                }
+                _ => panic!("light client sends an unexpected message"),
+            }
+        } else if protocol_id == SupportProtocols::Filter.protocol_id() {
+            let message = packed::BlockFilterMessageReader::from_slice(data)
+                .expect("light client sends a valid message")
+                .to_enum();
+            self.received.push(message.item_name());
+            match message {
+                packed::BlockFilterMessageUnionReader::GetBlockFilters(reader) => {
+                    self.send_block_filters(reader.start_number().unpack())
+                }
+                _ => panic!("light client sends an unexpected message"),
+            }
+        } else if protocol_id == SupportProtocols::Sync.protocol_id() {
+            let message = packed::SyncMessageReader::from_compatible_slice(data)
+                .expect("light client sends a valid message")
+                .to_enum();
+            self.received.push(message.item_name());
+            match message {
+                packed::SyncMessageUnionReader::GetBlocks(reader) => {
+                    self.send_blocks(reader.block_hashes().to_entity())
+                }
+                _ => panic!("light client sends an unexpected message"),
+            }
+        } else if protocol_id == SupportProtocols::RelayV2.protocol_id() {
+            let message = packed::RelayMessageReader::from_compatible_slice(data)
+                .expect("light client sends a valid message")
+                .to_enum();
+            self.received.push(message.item_name());
+            match message {
+                packed::RelayMessageUnionReader::RelayTransactionHashes(reader) => {
+                    self.get_relay_transactions(reader.tx_hashes().to_entity())
+                }
+                packed::RelayMessageUnionReader::RelayTransactions(reader) => {
+                    self.accept_relay_transactions(reader.transactions().to_entity())
+                }
+                _ => panic!("light client sends an unexpected message"),
+            }
+        } else {
+            panic!("light client sends a message in an unknown protocol");
+        }
+    }
+
+    fn respond(&mut self, protocol: SupportProtocols, item_name: &'static str, data: Bytes) {
+        if self.dropped.contains(item_name) {
+            return;
+        }
+        let delay = self.delays.get(item_name).cloned().unwrap_or_default();
+        self.outbox.push(Response {
+            protocol_id: protocol.protocol_id(),
+            data,
+            delay,
+        });
+    }
+
+    fn forge_root(&self, root: packed::HeaderDigest) -> packed::HeaderDigest {
+        if self.lies.contains(&Lie::InvalidProof) {
+            let children_hash = root.calc_mmr_hash();
+            root.as_builder().children_hash(children_hash).build()
+        } else {
+            root
+        }
+    }
+
+    fn send_last_state(&mut self) {
+        let tip_number = self.chain.tip_number();
+        let content = packed::SendLastState::new_builder()
+            .tip_header(self.chain.verifiable_header(tip_number))
+            .total_difficulty(self.chain.total_difficulty(tip_number).pack())
+            .build();
+        let message = packed::LightClientMessage::new_builder()
+            .set(content)
+            .build();
+        self.respond(
+            SupportProtocols::LightClient,
+            packed::SendLastState::NAME,
+            message.as_bytes(),
+        );
+    }
+
+    fn send_block_samples(&mut self, request: packed::GetBlockSamples) {
+        let last_number = if let Some(number) = self.chain.main_chain_number(&request.last_hash()) {
+            number
+        } else {
+            // the last state which is requested is stale, send the current one
+            self.send_last_state();
+            return;
+        };
+        let start_number: BlockNumber = request.start_number().unpack();
+        let last_n_blocks: BlockNumber = request.last_n_blocks().unpack();
+        let difficulty_boundary: U256 = request.difficulty_boundary().unpack();
+        let mut difficulties: Vec<U256> = request
+            .difficulties()
+            .into_iter()
+            .map(|item| item.unpack())
+            .collect();
+        assert!(
+            start_number < last_number,
+            "light client requests no blocks"
+        );
+
+        let reorg_numbers: Vec<BlockNumber> = if self
+            .chain
+            .main_chain_number(&request.start_hash())
+            .is_some()
+        {
+            Vec::new()
+        } else {
+            // The genesis block doesn't have a chain root.
+            let min_number = start_number - start_number.saturating_sub(1).min(last_n_blocks);
+            (min_number..start_number).collect()
+        };
+        let (sampled_numbers, last_n_numbers) = if last_number - start_number <= last_n_blocks {
+            (Vec::new(), (start_number..last_number).collect::<Vec<_>>())
+        } else {
+            let mut boundary_number = self
+                .chain
+                .first_block_reaching(&difficulty_boundary)
+                .max(start_number)
+                .min(last_number);
+            if last_number - boundary_number < last_n_blocks {
+                boundary_number = last_number - last_n_blocks;
+            }
+            let mut sampled_numbers = Vec::new();
+            if boundary_number > 0 {
+                let total_difficulty = self.chain.total_difficulty(boundary_number - 1);
+                difficulties.retain(|difficulty| difficulty <= &total_difficulty);
+                for difficulty in &difficulties {
+                    let number = self
+                        .chain
+                        .first_block_reaching(difficulty)
+                        .max(start_number);
+                    if number < boundary_number && sampled_numbers.last() != Some(&number) {
+                        sampled_numbers.push(number);
+                    }
+                }
+            }
+            (sampled_numbers, (boundary_number..last_number).collect())
+        };
+
+        let numbers: Vec<BlockNumber> = reorg_numbers
+            .iter()
+            .chain(sampled_numbers.iter())
+            .chain(last_n_numbers.iter())
+            .cloned()
+            .collect();
+        let headers = |numbers: &[BlockNumber]| {
+            packed::VerifiableHeaderWithChainRootVec::new_builder()
+                .set(
+                    numbers
+                        .iter()
+                        .map(|number| self.chain.verifiable_header_with_chain_root(*number))
+                        .collect(),
+                )
+                .build()
+        };
+        let content = packed::SendBlockSamples::new_builder()
+            .root(self.forge_root(self.chain.chain_root(last_number - 1)))
+            .proof(self.chain.proof(last_number - 1, &numbers))
+            .reorg_last_n_headers(headers(&reorg_numbers))
+            .sampled_headers(headers(&sampled_numbers))
+            .last_n_headers(headers(&last_n_numbers))
+            .build();
+        let message = packed::LightClientMessage::new_builder()
+            .set(content)
+            .build();
+        self.respond(
+            SupportProtocols::LightClient,
+            packed::SendBlockSamples::NAME,
+            message.as_bytes(),
+        );
+    }
+
+    fn send_block_proof(&mut self, request: packed::GetBlockProof) {
+        let tip_number = self.chain.main_chain_number(&request.tip_hash());
+        let numbers: Option<Vec<BlockNumber>> = request
+            .block_hashes()
+            .into_iter()
+            .map(|hash| {
+                self.chain
+                    .main_chain_number(&hash)
+                    .filter(|number| Some(*number) < tip_number)
+            })
+            .collect();
+        let content = match (tip_number, numbers) {
+            (Some(tip_number), Some(numbers)) if !self.lies.contains(&Lie::UnknownTip) => {
+                let headers: Vec<packed::Header> = numbers
+                    .iter()
+                    .map(|number| {
+                        self.chain
+                            .block(*number)
+                            .expect("checked: block is in the main chain")
+                            .header()
+                            .data()
+                    })
+                    .collect();
+                packed::SendBlockProof::new_builder()
+                    .root(self.forge_root(self.chain.chain_root(tip_number - 1)))
+                    .proof(self.chain.proof(tip_number - 1, &numbers))
+                    .tip_header(self.chain.verifiable_header(tip_number))
+                    .headers(packed::HeaderVec::new_builder().set(headers).build())
+                    .build()
+            }
+            _ => Default::default(),
+        };
+        let message = packed::LightClientMessage::new_builder()
+            .set(content)
+            .build();
+        self.respond(
+            SupportProtocols::LightClient,
+            packed::SendBlockProof::NAME,
+            message.as_bytes(),
+        );
+    }
+
+    fn send_block_filters(&mut self, start_number: BlockNumber) {
+        let tip_number = self.chain.tip_number();
+        let numbers = if start_number > tip_number {
+            Vec::new()
+        } else {
+            let end_number = tip_number.min(start_number + GET_BLOCK_FILTERS_LIMIT - 1);
+            (start_number..=end_number).collect()
+        };
+        let block_hashes: Vec<packed::Byte32> = numbers
+            .iter()
+            .map(|number| self.chain.blocks[*number as usize].hash())
+            .collect();
+        let filters: Vec<packed::Bytes> = numbers
+            .iter()
+            .map(|number| {
+                if self.lies.contains(&Lie::EmptyFilters) {
+                    // the filter of a block without any transactions
+                    self.chain.build_filter(&BlockBuilder::default().build())
+                } else {
+                    self.chain.filters[*number as usize].clone()
+                }
+            })
+            .map(|filter| filter.pack())
+            .collect();
+        let content = packed::BlockFilters::new_builder()
+            .start_number(start_number.pack())
+            .block_hashes(block_hashes.pack())
+            .filters(filters.pack())
+            .build();
+        let message = packed::BlockFilterMessage::new_builder()
+            .set(content)
+            .build();
+        self.respond(
+            SupportProtocols::Filter,
+            packed::BlockFilters::NAME,
+            message.as_bytes(),
+        );
+    }
+
+    fn send_blocks(&mut self, block_hashes: packed::Byte32Vec) {
+        for hash in block_hashes.into_iter() {
+            if let Some(block) = self.chain.block_by_hash(&hash) {
+                let content = packed::SendBlock::new_builder().block(block.data()).build();
+                let message = packed::SyncMessage::new_builder().set(content).build();
+                self.respond(
+                    SupportProtocols::Sync,
+                    packed::SendBlock::NAME,
+                    message.as_bytes(),
+                );
+            }
+        }
+    }
+
+    fn get_relay_transactions(&mut self, tx_hashes: packed::Byte32Vec) {
+        let tx_hashes: Vec<packed::Byte32> = tx_hashes
+            .into_iter()
+            .filter(|tx_hash| !self.tx_pool.contains_key(tx_hash))
+            .collect();
+        if tx_hashes.is_empty() {
+            return;
+        }
+        let content = packed::GetRelayTransactions::new_builder()
+            .tx_hashes(tx_hashes.pack())
+            .build();
+        let message = packed::RelayMessage::new_builder().set(content).build();
+        self.respond(
+            SupportProtocols::RelayV2,
+            packed::GetRelayTransactions::NAME,
+            message.as_bytes(),
+        );
+    }
+
+    // Full nodes announce the transactions which are accepted by their tx-pool.
+    fn accept_relay_transactions(&mut self, transactions: packed::RelayTransactionVec) {
+        if self.reject_transactions {
+            return;
+        }
+        let tx_hashes: Vec<packed::Byte32> = transactions
+            .into_iter()
+            .map(|relay_tx| {
+                let tx = relay_tx.transaction();
+                let tx_hash = tx.calc_tx_hash();
+                self.tx_pool.insert(tx_hash.clone(), tx);
+                tx_hash
+            })
+            .collect();
+        let content = packed::RelayTransactionHashes::new_builder()
+            .tx_hashes(tx_hashes.pack())
+            .build();
+        let message = packed::RelayMessage::new_builder().set(content).build();
+        self.respond(
+            SupportProtocols::RelayV2,
+            packed::RelayTransactionHashes::NAME,
+            message.as_bytes(),
+        );
+    }
+}
@@ -1,3 +1,8 @@
 mod block_filter;
+mod end_to_end;
 mod light_client;
 mod mock_context;
+mod mock_node;
+mod peer_churn;
+mod relayer;
+mod simulation;
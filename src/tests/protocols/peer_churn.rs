@@ -0,0 +1,251 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use ckb_chain_spec::consensus::{Consensus, ConsensusBuilder};
+use ckb_network::{bytes::Bytes, SupportProtocols};
+use ckb_types::{
+    core::{capacity_bytes, BlockNumber, Capacity, TransactionBuilder, TransactionView},
+    packed::{self, CellOutput, Script},
+    prelude::*,
+};
+
+use crate::metrics::Metrics;
+use crate::protocols::{
+    FilterProtocol, LightClientProtocol, Peers, PendingTxs, RelayProtocol, RelayStatus,
+    SyncProtocol,
+};
+use crate::storage::{MemoryStore, Storage};
+
+use super::end_to_end::{new_script, push_payment};
+use super::mock_node::{Lie, MockChain, MockFullNode};
+use super::simulation::Simulation;
+
+// the interval of the GetBlockFilters timer
+const GET_BLOCK_FILTERS_INTERVAL: Duration = Duration::from_secs(3);
+
+async fn start(
+    consensus: &Consensus,
+    scripts: Vec<Script>,
+) -> (Simulation, Storage, Arc<RwLock<PendingTxs>>) {
+    // the virtual clock should be enabled before any timestamp is taken
+    let mut simulation = Simulation::new();
+    let storage = Storage::with_store(MemoryStore::new()).unwrap();
+    storage.init_genesis_block(consensus.genesis_block().data());
+    storage.update_filter_scripts(scripts.into_iter().map(|script| (script, 0)).collect());
+    let peers = Arc::new(Peers::default());
+    let metrics = Metrics::new();
+    let pending_txs = Arc::new(RwLock::new(
+        PendingTxs::new(64).with_clock(simulation.clock()),
+    ));
+    let light_client = LightClientProtocol::new(
+        storage.clone(),
+        Arc::clone(&peers),
+        consensus.clone(),
+        metrics.clone(),
+    );
+    simulation
+        .add_protocol(SupportProtocols::LightClient, Box::new(light_client))
+        .await;
+    let filter = FilterProtocol::new(storage.clone(), Arc::clone(&peers), metrics.clone())
+        .with_clock(simulation.clock());
+    simulation
+        .add_protocol(SupportProtocols::Filter, Box::new(filter))
+        .await;
+    let sync = SyncProtocol::new(storage.clone(), Arc::clone(&pending_txs), metrics.clone());
+    simulation
+        .add_protocol(SupportProtocols::Sync, Box::new(sync))
+        .await;
+    let relay = RelayProtocol::new(Arc::clone(&pending_txs), peers, metrics);
+    simulation
+        .add_protocol(SupportProtocols::RelayV2, Box::new(relay))
+        .await;
+    (simulation, storage, pending_txs)
+}
+
+// A chain with a payment to the script in the middle.
+fn new_chain(consensus: &Consensus, script: &Script, length: usize) -> (MockChain, packed::Byte32) {
+    let mut chain = MockChain::new(consensus);
+    chain.generate(10);
+    let tx_hash = push_payment(&mut chain, script);
+    chain.generate(length - 11);
+    (chain, tx_hash)
+}
+
+fn new_transaction() -> TransactionView {
+    TransactionBuilder::default()
+        .output(
+            CellOutput::new_builder()
+                .capacity(capacity_bytes!(100).pack())
+                .lock(new_script())
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .build()
+}
+
+fn tip_number(storage: &Storage) -> BlockNumber {
+    storage.get_tip_header().raw().number().unpack()
+}
+
+fn filtered_block_number(storage: &Storage) -> BlockNumber {
+    storage
+        .get_filter_scripts()
+        .values()
+        .min()
+        .cloned()
+        .expect("has scripts")
+}
+
+#[tokio::test]
+async fn sync_from_the_heaviest_peer() {
+    let consensus = ConsensusBuilder::default().build();
+    let script = new_script();
+    let (light_chain, _) = new_chain(&consensus, &script, 20);
+    let (heavy_chain, tx_hash) = new_chain(&consensus, &script, 30);
+
+    let (mut simulation, storage, _) = start(&consensus, vec![script]).await;
+    let light = simulation.connect(MockFullNode::new(light_chain)).await;
+    let heavy = simulation.connect(MockFullNode::new(heavy_chain)).await;
+    assert_eq!(tip_number(&storage), 30);
+
+    simulation.advance(GET_BLOCK_FILTERS_INTERVAL).await;
+    assert_eq!(filtered_block_number(&storage), 30);
+    assert!(storage.get_transaction_with_header(&tx_hash).is_some());
+    let asked = |peer| {
+        simulation
+            .node(peer)
+            .received()
+            .contains(&packed::GetBlockFilters::NAME)
+    };
+    assert!(asked(heavy));
+    assert!(!asked(light));
+}
+
+#[tokio::test]
+async fn disconnect_peer_which_withholds_block_proofs() {
+    let consensus = ConsensusBuilder::default().build();
+    let script = new_script();
+    let (chain, _) = new_chain(&consensus, &script, 20);
+    let (heavier_chain, tx_hash) = new_chain(&consensus, &script, 21);
+    let mut withholder = MockFullNode::new(heavier_chain);
+    withholder.drop_responses(packed::SendBlockProof::NAME);
+
+    let (mut simulation, storage, _) = start(&consensus, vec![script]).await;
+    let honest = simulation.connect(MockFullNode::new(chain)).await;
+    let withholder = simulation.connect(withholder).await;
+    simulation.advance(GET_BLOCK_FILTERS_INTERVAL).await;
+    assert!(simulation
+        .node(withholder)
+        .received()
+        .contains(&packed::GetBlockProof::NAME));
+
+    // timeout of the request, plus the interval of the checking timer
+    simulation.advance(Duration::from_secs(70)).await;
+    assert!(!simulation.is_connected(withholder));
+    assert!(simulation.banned(withholder).is_none());
+    assert!(simulation.is_connected(honest));
+    assert!(storage.get_transaction_with_header(&tx_hash).is_none());
+}
+
+#[tokio::test]
+async fn ban_lying_peer_and_sync_from_another_peer() {
+    let consensus = ConsensusBuilder::default().build();
+    let script = new_script();
+    let (chain, tx_hash) = new_chain(&consensus, &script, 20);
+    let (heavier_chain, _) = new_chain(&consensus, &script, 40);
+    let mut liar = MockFullNode::new(heavier_chain);
+    liar.lie(Lie::InvalidProof);
+
+    let (mut simulation, storage, _) = start(&consensus, vec![script]).await;
+    let liar = simulation.connect(liar).await;
+    assert!(simulation.banned(liar).is_some());
+    assert!(!simulation.is_connected(liar));
+    assert_eq!(tip_number(&storage), 0);
+
+    let honest = simulation.connect(MockFullNode::new(chain)).await;
+    simulation.advance(GET_BLOCK_FILTERS_INTERVAL).await;
+    assert!(simulation.is_connected(honest));
+    assert_eq!(tip_number(&storage), 20);
+    assert_eq!(filtered_block_number(&storage), 20);
+    assert!(storage.get_transaction_with_header(&tx_hash).is_some());
+}
+
+#[tokio::test]
+async fn resume_syncing_after_peer_churn() {
+    let consensus = ConsensusBuilder::default().build();
+    let script = new_script();
+    let (first_chain, _) = new_chain(&consensus, &script, 20);
+    // a peer is proved only if its chain is heavier than the current one
+    let (second_chain, tx_hash) = new_chain(&consensus, &script, 21);
+
+    let (mut simulation, storage, _) = start(&consensus, vec![script]).await;
+    let first = simulation.connect(MockFullNode::new(first_chain)).await;
+    simulation
+        .node_mut(first)
+        .drop_responses(packed::BlockFilters::NAME);
+    simulation.advance(GET_BLOCK_FILTERS_INTERVAL).await;
+    assert!(simulation
+        .node(first)
+        .received()
+        .contains(&packed::GetBlockFilters::NAME));
+    assert_eq!(filtered_block_number(&storage), 0);
+
+    simulation.disconnect(first).await;
+    assert!(!simulation.is_connected(first));
+    let second = simulation.connect(MockFullNode::new(second_chain)).await;
+    simulation.advance(GET_BLOCK_FILTERS_INTERVAL).await;
+    assert!(simulation
+        .node(second)
+        .received()
+        .contains(&packed::GetBlockFilters::NAME));
+    assert_eq!(filtered_block_number(&storage), 21);
+    assert!(storage.get_transaction_with_header(&tx_hash).is_some());
+}
+
+#[tokio::test]
+async fn relay_transaction_to_connected_peer() {
+    let consensus = ConsensusBuilder::default().build();
+    let (chain, _) = new_chain(&consensus, &new_script(), 20);
+    let tx = new_transaction();
+
+    let (mut simulation, _, pending_txs) = start(&consensus, vec![]).await;
+    pending_txs.write().unwrap().push(tx.clone(), 0);
+    let peer = simulation.connect(MockFullNode::new(chain)).await;
+    for name in [
+        packed::RelayTransactionHashes::NAME,
+        packed::RelayTransactions::NAME,
+    ] {
+        assert!(simulation.node(peer).received().contains(&name));
+    }
+    let status = pending_txs
+        .read()
+        .unwrap()
+        .get_relay_state(&tx.hash())
+        .map(|state| state.status);
+    assert_eq!(status, Some(RelayStatus::Accepted));
+}
+
+#[tokio::test]
+async fn reject_transaction_which_is_never_accepted() {
+    let consensus = ConsensusBuilder::default().build();
+    let (chain, _) = new_chain(&consensus, &new_script(), 20);
+    let mut node = MockFullNode::new(chain);
+    node.reject_transactions();
+    let tx = new_transaction();
+
+    let (mut simulation, _, pending_txs) = start(&consensus, vec![]).await;
+    pending_txs.write().unwrap().push(tx.clone(), 0);
+    simulation.connect(node).await;
+    let status = |pending_txs: &RwLock<PendingTxs>| {
+        pending_txs
+            .read()
+            .unwrap()
+            .get_relay_state(&tx.hash())
+            .map(|state| state.status)
+    };
+    assert_eq!(status(&pending_txs), Some(RelayStatus::Relayed));
+
+    // all retries with exponential backoff take about half an hour
+    simulation.advance(Duration::from_secs(3600)).await;
+    assert_eq!(status(&pending_txs), Some(RelayStatus::Rejected));
+}
@@ -0,0 +1,72 @@
+use ckb_network::PeerId;
+use ckb_types::{core::TransactionBuilder, packed, prelude::*, H256};
+
+use crate::protocols::{Clock, PendingTxs, RelayStatus};
+
+#[test]
+fn test_pending_txs_relay_state() {
+    // the transactions are pushed at 0
+    let mut pending_txs = PendingTxs::new(64).with_clock(Clock::new(|| 0));
+    let tx1 = TransactionBuilder::default().build();
+    let tx2 = TransactionBuilder::default().version(1u32.pack()).build();
+    pending_txs.push(tx1.clone(), 0);
+    pending_txs.push(tx2.clone(), 0);
+
+    let peer1 = PeerId::random();
+    let peer2 = PeerId::random();
+    let status = |pending_txs: &PendingTxs, tx_hash: &packed::Byte32| {
+        pending_txs
+            .get_relay_state(tx_hash)
+            .map(|state| state.status)
+    };
+    assert_eq!(
+        status(&pending_txs, &tx1.hash()),
+        Some(RelayStatus::Pending)
+    );
+
+    pending_txs.mark_as_sent(&tx1.hash(), peer1.clone());
+    assert_eq!(
+        status(&pending_txs, &tx1.hash()),
+        Some(RelayStatus::Relayed)
+    );
+
+    assert!(pending_txs.mark_as_accepted(&tx2.hash(), peer2.clone()));
+    assert_eq!(
+        status(&pending_txs, &tx2.hash()),
+        Some(RelayStatus::Accepted)
+    );
+    assert!(!pending_txs.mark_as_accepted(&H256(rand::random()).pack(), peer2));
+
+    // no retry before the deadline
+    let mut now = 0;
+    assert_eq!(0, pending_txs.retry_unaccepted_txs(now));
+
+    // only the unaccepted transaction is retried
+    for retries in 1..=5 {
+        now += 3600 * 1000;
+        assert_eq!(1, pending_txs.retry_unaccepted_txs(now));
+        let state = pending_txs.get_relay_state(&tx1.hash()).unwrap();
+        assert_eq!(retries, state.retries);
+        assert_eq!(vec![peer1.clone()], state.sent_peers);
+    }
+
+    // dropped after all retries
+    now += 3600 * 1000;
+    assert_eq!(0, pending_txs.retry_unaccepted_txs(now));
+    assert_eq!(
+        status(&pending_txs, &tx1.hash()),
+        Some(RelayStatus::Rejected)
+    );
+    assert_eq!(
+        status(&pending_txs, &tx2.hash()),
+        Some(RelayStatus::Accepted)
+    );
+    assert!(pending_txs.remove(&tx1.hash()).is_none());
+
+    // the accepted transaction which is not committed is dropped after the expiry
+    assert_eq!(0, pending_txs.expire_accepted_txs(now));
+    now += 12 * 3600 * 1000;
+    assert_eq!(1, pending_txs.expire_accepted_txs(now));
+    assert_eq!(status(&pending_txs, &tx2.hash()), None);
+    assert!(pending_txs.is_empty());
+}
@@ -0,0 +1,455 @@
+//! A deterministic network of mock full nodes which are connected to the client protocols.
+//!
+//! Messages are routed between the protocol handlers and the nodes by the protocol id, the
+//! notify timers are driven by a virtual clock which is shared with `faketime`, and peers are
+//! connected, disconnected or banned only when a test or a protocol asks for it.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ckb_network::{
+    async_trait,
+    bytes::Bytes as P2pBytes,
+    multiaddr::{Multiaddr, Protocol},
+    Behaviour, CKBProtocolContext, CKBProtocolHandler, Error, Peer, PeerId, PeerIndex, ProtocolId,
+    SessionType, SupportProtocols, TargetSession,
+};
+
+use crate::protocols::Clock;
+
+use super::mock_node::MockFullNode;
+
+const START_TIME: u64 = 1_600_000_000_000;
+
+struct Timer {
+    protocol_id: ProtocolId,
+    token: u64,
+    interval: u64,
+    next_at: u64,
+}
+
+#[derive(Default)]
+struct Network {
+    // unix timestamp in milliseconds
+    now: u64,
+    peers: Vec<(PeerIndex, Peer)>,
+    outbox: Vec<(ProtocolId, PeerIndex, P2pBytes)>,
+    timers: Vec<Timer>,
+    disconnecting: Vec<PeerIndex>,
+    banned: Vec<(PeerIndex, Duration, String)>,
+}
+
+impl Network {
+    fn is_connected(&self, peer_index: PeerIndex) -> bool {
+        self.peers.iter().any(|(index, _)| *index == peer_index)
+            && !self.disconnecting.contains(&peer_index)
+    }
+}
+
+/// The protocol context of one protocol handler in the simulation.
+struct SimulatedContext {
+    protocol: SupportProtocols,
+    network: Rc<RefCell<Network>>,
+}
+
+// test simulation with single thread
+unsafe impl Send for SimulatedContext {}
+unsafe impl Sync for SimulatedContext {}
+
+#[async_trait]
+impl CKBProtocolContext for SimulatedContext {
+    async fn set_notify(&self, interval: Duration, token: u64) -> Result<(), Error> {
+        let mut network = self.network.borrow_mut();
+        let interval = interval.as_millis() as u64;
+        let next_at = network.now + interval;
+        network.timers.push(Timer {
+            protocol_id: self.protocol_id(),
+            token,
+            interval,
+            next_at,
+        });
+        Ok(())
+    }
+    async fn remove_notify(&self, token: u64) -> Result<(), Error> {
+        let protocol_id = self.protocol_id();
+        self.network
+            .borrow_mut()
+            .timers
+            .retain(|timer| timer.protocol_id != protocol_id || timer.token != token);
+        Ok(())
+    }
+    async fn async_quick_send_message(
+        &self,
+        proto_id: ProtocolId,
+        peer_index: PeerIndex,
+        data: P2pBytes,
+    ) -> Result<(), Error> {
+        self.send_message(proto_id, peer_index, data)
+    }
+    async fn async_quick_send_message_to(
+        &self,
+        peer_index: PeerIndex,
+        data: P2pBytes,
+    ) -> Result<(), Error> {
+        self.send_message_to(peer_index, data)
+    }
+    async fn async_quick_filter_broadcast(
+        &self,
+        _target: TargetSession,
+        _data: P2pBytes,
+    ) -> Result<(), Error> {
+        unimplemented!();
+    }
+    async fn async_future_task(
+        &self,
+        _task: Pin<Box<dyn Future<Output = ()> + 'static + Send>>,
+        _blocking: bool,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn async_send_message(
+        &self,
+        proto_id: ProtocolId,
+        peer_index: PeerIndex,
+        data: P2pBytes,
+    ) -> Result<(), Error> {
+        self.send_message(proto_id, peer_index, data)
+    }
+    async fn async_send_message_to(
+        &self,
+        peer_index: PeerIndex,
+        data: P2pBytes,
+    ) -> Result<(), Error> {
+        self.send_message_to(peer_index, data)
+    }
+    fn quick_send_message(
+        &self,
+        proto_id: ProtocolId,
+        peer_index: PeerIndex,
+        data: P2pBytes,
+    ) -> Result<(), Error> {
+        self.send_message(proto_id, peer_index, data)
+    }
+    fn quick_send_message_to(&self, peer_index: PeerIndex, data: P2pBytes) -> Result<(), Error> {
+        self.send_message_to(peer_index, data)
+    }
+
+    async fn async_filter_broadcast(
+        &self,
+        _target: TargetSession,
+        _data: P2pBytes,
+    ) -> Result<(), Error> {
+        unimplemented!();
+    }
+    async fn async_disconnect(&self, peer_index: PeerIndex, message: &str) -> Result<(), Error> {
+        self.disconnect(peer_index, message)
+    }
+    fn quick_filter_broadcast(&self, _target: TargetSession, _data: P2pBytes) -> Result<(), Error> {
+        unimplemented!();
+    }
+    fn future_task(
+        &self,
+        _task: Pin<Box<dyn Future<Output = ()> + 'static + Send>>,
+        _blocking: bool,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+    fn send_message(
+        &self,
+        proto_id: ProtocolId,
+        peer_index: PeerIndex,
+        data: P2pBytes,
+    ) -> Result<(), Error> {
+        let mut network = self.network.borrow_mut();
+        // messages to the closed sessions are lost, as in the real network
+        if network.is_connected(peer_index) {
+            network.outbox.push((proto_id, peer_index, data));
+        }
+        Ok(())
+    }
+    fn send_message_to(&self, peer_index: PeerIndex, data: P2pBytes) -> Result<(), Error> {
+        let protocol_id = self.protocol_id();
+        self.send_message(protocol_id, peer_index, data)
+    }
+
+    fn filter_broadcast(&self, _target: TargetSession, _data: P2pBytes) -> Result<(), Error> {
+        unimplemented!();
+    }
+    fn disconnect(&self, peer_index: PeerIndex, _message: &str) -> Result<(), Error> {
+        let mut network = self.network.borrow_mut();
+        if network.is_connected(peer_index) {
+            network.disconnecting.push(peer_index);
+        }
+        Ok(())
+    }
+    fn get_peer(&self, peer_index: PeerIndex) -> Option<Peer> {
+        self.network
+            .borrow()
+            .peers
+            .iter()
+            .find(|(index, _)| *index == peer_index)
+            .map(|(_, peer)| peer.clone())
+    }
+    fn with_peer_mut(&self, peer_index: PeerIndex, f: Box<dyn FnOnce(&mut Peer)>) {
+        if let Some((_, peer)) = self
+            .network
+            .borrow_mut()
+            .peers
+            .iter_mut()
+            .find(|(index, _)| *index == peer_index)
+        {
+            f(peer);
+        }
+    }
+    fn connected_peers(&self) -> Vec<PeerIndex> {
+        let network = self.network.borrow();
+        network
+            .peers
+            .iter()
+            .map(|(index, _)| *index)
+            .filter(|index| network.is_connected(*index))
+            .collect()
+    }
+    fn report_peer(&self, _peer_index: PeerIndex, _behaviour: Behaviour) {
+        unimplemented!();
+    }
+    fn ban_peer(&self, peer_index: PeerIndex, duration: Duration, reason: String) {
+        self.network
+            .borrow_mut()
+            .banned
+            .push((peer_index, duration, reason));
+        let _ = self.disconnect(peer_index, "banned");
+    }
+    fn protocol_id(&self) -> ProtocolId {
+        self.protocol.protocol_id()
+    }
+}
+
+/// Runs protocol handlers against several mock full nodes on a virtual clock.
+///
+/// Nothing happens in the background: messages are exchanged in `run` until all parties are
+/// idle, and timers fire only in `advance`, in the order of their deadlines.
+pub(crate) struct Simulation {
+    network: Rc<RefCell<Network>>,
+    handlers: Vec<(Box<dyn CKBProtocolHandler>, Arc<SimulatedContext>)>,
+    nodes: Vec<(PeerIndex, MockFullNode)>,
+    next_peer_index: usize,
+    faketime_file: tempfile::NamedTempFile,
+}
+
+impl Simulation {
+    pub(crate) fn new() -> Self {
+        let faketime_file = tempfile::NamedTempFile::new().expect("create faketime file");
+        faketime::write_millis(faketime_file.path(), START_TIME).expect("write faketime file");
+        faketime::enable(faketime_file.path());
+        let network = Network {
+            now: START_TIME,
+            ..Default::default()
+        };
+        Self {
+            network: Rc::new(RefCell::new(network)),
+            handlers: Vec::new(),
+            nodes: Vec::new(),
+            next_peer_index: 1,
+            faketime_file,
+        }
+    }
+
+    /// The virtual clock for the request timers of the protocols.
+    pub(crate) fn clock(&self) -> Clock {
+        Clock::new(faketime::unix_time_as_millis)
+    }
+
+    pub(crate) async fn add_protocol(
+        &mut self,
+        protocol: SupportProtocols,
+        mut handler: Box<dyn CKBProtocolHandler>,
+    ) {
+        let nc = Arc::new(SimulatedContext {
+            protocol,
+            network: Rc::clone(&self.network),
+        });
+        handler
+            .init(Arc::clone(&nc) as Arc<dyn CKBProtocolContext + Sync>)
+            .await;
+        self.handlers.push((handler, nc));
+        self.run().await;
+    }
+
+    /// Connects a node with all protocols, returns the index of the new peer.
+    pub(crate) async fn connect(&mut self, node: MockFullNode) -> PeerIndex {
+        let peer_index = PeerIndex::new(self.next_peer_index);
+        self.next_peer_index += 1;
+        let peer_id = PeerId::random();
+        let mut address = "/ip4/127.0.0.1/tcp/8115"
+            .parse::<Multiaddr>()
+            .expect("valid multiaddr");
+        address.push(Protocol::P2P(Cow::Borrowed(peer_id.as_bytes())));
+        let peer = Peer::new(peer_index, SessionType::Outbound, address, false);
+        self.network.borrow_mut().peers.push((peer_index, peer));
+        self.nodes.push((peer_index, node));
+        for (handler, nc) in self.handlers.iter_mut() {
+            let nc = Arc::clone(nc) as Arc<dyn CKBProtocolContext + Sync>;
+            handler.connected(nc, peer_index, "2").await;
+        }
+        self.run().await;
+        peer_index
+    }
+
+    pub(crate) async fn disconnect(&mut self, peer_index: PeerIndex) {
+        let mut network = self.network.borrow_mut();
+        if network.is_connected(peer_index) {
+            network.disconnecting.push(peer_index);
+        }
+        drop(network);
+        self.run().await;
+    }
+
+    pub(crate) fn is_connected(&self, peer_index: PeerIndex) -> bool {
+        self.network.borrow().is_connected(peer_index)
+    }
+
+    pub(crate) fn banned(&self, peer_index: PeerIndex) -> Option<(Duration, String)> {
+        self.network
+            .borrow()
+            .banned
+            .iter()
+            .find(|(index, _, _)| *index == peer_index)
+            .map(|(_, duration, reason)| (*duration, reason.clone()))
+    }
+
+    /// The node is kept after it is disconnected, so tests could check what it received.
+    pub(crate) fn node(&self, peer_index: PeerIndex) -> &MockFullNode {
+        self.nodes
+            .iter()
+            .find(|(index, _)| *index == peer_index)
+            .map(|(_, node)| node)
+            .expect("node is existed")
+    }
+
+    pub(crate) fn node_mut(&mut self, peer_index: PeerIndex) -> &mut MockFullNode {
+        self.nodes
+            .iter_mut()
+            .find(|(index, _)| *index == peer_index)
+            .map(|(_, node)| node)
+            .expect("node is existed")
+    }
+
+    /// Lets the virtual time pass, fires all timers which are due and handles the messages
+    /// after each of them.
+    pub(crate) async fn advance(&mut self, duration: Duration) {
+        let deadline = self.network.borrow().now + duration.as_millis() as u64;
+        loop {
+            let due = {
+                let mut network = self.network.borrow_mut();
+                let next = network
+                    .timers
+                    .iter_mut()
+                    .filter(|timer| timer.next_at <= deadline)
+                    .min_by_key(|timer| timer.next_at);
+                if let Some(timer) = next {
+                    let fired_at = timer.next_at;
+                    timer.next_at += timer.interval;
+                    let due = (timer.protocol_id, timer.token);
+                    network.now = fired_at;
+                    Some(due)
+                } else {
+                    network.now = deadline;
+                    None
+                }
+            };
+            self.write_faketime();
+            let (protocol_id, token) = if let Some(due) = due {
+                due
+            } else {
+                break;
+            };
+            if let Some((handler, nc)) = self
+                .handlers
+                .iter_mut()
+                .find(|(_, nc)| nc.protocol_id() == protocol_id)
+            {
+                let nc = Arc::clone(nc) as Arc<dyn CKBProtocolContext + Sync>;
+                handler.notify(nc, token).await;
+            }
+            self.run().await;
+        }
+    }
+
+    /// Exchanges messages and closes the sessions which are asked to be closed, until both
+    /// sides have nothing to do.
+    pub(crate) async fn run(&mut self) {
+        loop {
+            let mut idle = true;
+
+            let disconnecting: Vec<_> = self.network.borrow_mut().disconnecting.drain(..).collect();
+            for peer_index in disconnecting {
+                idle = false;
+                self.network
+                    .borrow_mut()
+                    .peers
+                    .retain(|(index, _)| *index != peer_index);
+                for (handler, nc) in self.handlers.iter_mut() {
+                    let nc = Arc::clone(nc) as Arc<dyn CKBProtocolContext + Sync>;
+                    handler.disconnected(nc, peer_index).await;
+                }
+            }
+
+            let messages: Vec<_> = self.network.borrow_mut().outbox.drain(..).collect();
+            for (protocol_id, peer_index, data) in messages {
+                idle = false;
+                if let Some((_, node)) = self
+                    .nodes
+                    .iter_mut()
+                    .find(|(index, _)| *index == peer_index)
+                {
+                    node.handle(protocol_id, &data);
+                }
+            }
+
+            let mut responses = Vec::new();
+            for (peer_index, node) in self.nodes.iter_mut() {
+                if !self.network.borrow().is_connected(*peer_index) {
+                    continue;
+                }
+                responses.extend(
+                    node.poll()
+                        .into_iter()
+                        .map(|(protocol_id, data)| (*peer_index, protocol_id, data)),
+                );
+                if node.has_pending_responses() {
+                    idle = false;
+                }
+            }
+            for (peer_index, protocol_id, data) in responses {
+                idle = false;
+                // the session could be closed by a previous message
+                if !self.network.borrow().is_connected(peer_index) {
+                    continue;
+                }
+                if let Some((handler, nc)) = self
+                    .handlers
+                    .iter_mut()
+                    .find(|(_, nc)| nc.protocol_id() == protocol_id)
+                {
+                    let nc = Arc::clone(nc) as Arc<dyn CKBProtocolContext + Sync>;
+                    handler.received(nc, peer_index, data).await;
+                }
+            }
+
+            if idle {
+                break;
+            }
+        }
+    }
+
+    fn write_faketime(&self) {
+        let now = self.network.borrow().now;
+        faketime::write_millis(self.faketime_file.path(), now).expect("write faketime file");
+    }
+}
@@ -1,26 +1,40 @@
 use std::{
     collections::HashMap,
+    net::IpAddr,
+    str::FromStr,
     sync::{Arc, RwLock},
 };
 
+use ckb_chain_spec::consensus::{Consensus, ConsensusBuilder};
+use ckb_crypto::secp::Privkey;
+use ckb_hash::blake2b_256;
+use ckb_jsonrpc_types::JsonBytes;
 use ckb_types::{
     bytes::Bytes,
     core::{
-        capacity_bytes, BlockBuilder, Capacity, EpochNumberWithFraction, HeaderBuilder,
+        capacity_bytes, BlockBuilder, Capacity, EpochNumberWithFraction, FeeRate, HeaderBuilder,
         ScriptHashType, TransactionBuilder,
     },
-    packed::{CellInput, CellOutputBuilder, OutPoint, Script, ScriptBuilder},
+    h256,
+    packed::{self, CellInput, CellOutputBuilder, OutPoint, Script, ScriptBuilder, WitnessArgs},
     prelude::*,
-    H256,
+    H256, U256,
 };
 use tempfile;
 
 use crate::{
+    address::{Address, NetworkType},
+    protocols::{Peers, PendingTxs},
     service::{
-        BlockFilterRpc, BlockFilterRpcImpl, ChainRpc, ChainRpcImpl, Order, ScriptStatus, SearchKey,
-        SearchKeyFilter, TransactionWithHeader,
+        parse_ban_command, BanCommand, BlockFilterRpc, BlockFilterRpcImpl, ChainRpc, ChainRpcImpl,
+        DaoPhase, DaoRpc, DaoRpcImpl, IndexerRpc, IndexerRpcImpl, Order, PeerSyncState,
+        ScriptStatus, ScriptType, SearchKey, SearchKeyFilter, SearchMode, TransactionRpc,
+        TransactionRpcImpl, TransactionWithHeader,
     },
-    storage::{Storage, StorageWithLastHeaders},
+    storage::{Key, Storage, StorageWithLastHeaders, WriteBatch},
+    system_scripts::SystemScripts,
+    tests::verify::setup,
+    wallet::{DerivationPath, ExtendedPubKey, WatchWallet},
 };
 
 fn new_storage(prefix: &str) -> Storage {
@@ -33,6 +47,8 @@ fn rpc() {
     let storage = new_storage("rpc");
     let rpc = BlockFilterRpcImpl {
         storage: storage.clone(),
+        pending_txs: Arc::new(RwLock::new(PendingTxs::new(64))),
+        network: NetworkType::Testnet,
     };
 
     // setup test data
@@ -125,6 +141,7 @@ fn rpc() {
     // test set_scripts rpc
     rpc.set_scripts(vec![ScriptStatus {
         script: lock_script1.clone().into(),
+        address: None,
         block_number: 0.into(),
     }])
     .unwrap();
@@ -543,7 +560,11 @@ fn rpc() {
         Arc::new(RwLock::new(vec![extra_header.clone()])),
     );
 
-    let rpc = ChainRpcImpl { swl };
+    let rpc = ChainRpcImpl {
+        swl,
+        consensus: Consensus::default(),
+        system_scripts: Default::default(),
+    };
     let header = rpc
         .get_header(pre_block.header().hash().unpack())
         .unwrap()
@@ -555,6 +576,19 @@ fn rpc() {
         .unwrap();
     assert_eq!(extra_header.number(), header.inner.number.value(),);
 
+    // test get_header_by_number rpc
+    let header = rpc
+        .get_header_by_number(pre_block.header().number().into())
+        .unwrap()
+        .unwrap();
+    assert_eq!(pre_block.header().hash(), header.hash.pack());
+    let header = rpc
+        .get_header_by_number(extra_header.number().into())
+        .unwrap()
+        .unwrap();
+    assert_eq!(extra_header.hash(), header.hash.pack());
+    assert!(rpc.get_header_by_number(400.into()).unwrap().is_none());
+
     // test get_transaction rpc
     let TransactionWithHeader {
         transaction,
@@ -573,13 +607,15 @@ fn rpc() {
 
     let scripts = storage.get_filter_scripts();
     assert_eq!(
-        total_blocks - 2,
+        total_blocks - 3,
         *scripts.values().max().unwrap(),
         "rollback should update script filter block number"
     );
 
     let rpc = BlockFilterRpcImpl {
         storage: storage.clone(),
+        pending_txs: Arc::new(RwLock::new(PendingTxs::new(64))),
+        network: NetworkType::Testnet,
     };
 
     // test get_cells rpc after rollback
@@ -653,11 +689,115 @@ fn rpc() {
     );
 }
 
+#[test]
+fn get_blockchain_info_and_current_epoch() {
+    let (storage, consensus) = setup("get_blockchain_info_and_current_epoch");
+    let tip_header = HeaderBuilder::default()
+        .epoch(EpochNumberWithFraction::new(3, 34, 1800).pack())
+        .number(5434.pack())
+        .compact_target(0x1e08_3126u32.pack())
+        .build();
+    storage.update_last_state(&U256::from(1000u64), &tip_header.data());
+    let rpc = ChainRpcImpl {
+        swl: StorageWithLastHeaders::new(storage, Arc::new(RwLock::new(Vec::new()))),
+        consensus: consensus.clone(),
+        system_scripts: Default::default(),
+    };
+
+    let info = rpc.get_blockchain_info().unwrap();
+    assert_eq!("ckb_testnet", info.chain);
+    assert_eq!(consensus.genesis_hash(), info.genesis_hash.pack());
+    assert_eq!(tip_header.hash(), info.tip_header.hash.pack());
+    assert_eq!(U256::from(1000u64), info.total_difficulty);
+    assert_eq!(
+        EpochNumberWithFraction::new(3, 34, 1800).full_value(),
+        info.epoch.value()
+    );
+
+    // the epoch starts at the block whose index is 0
+    let epoch = rpc.get_current_epoch().unwrap();
+    assert_eq!(3, epoch.number.value());
+    assert_eq!(5400, epoch.start_number.value());
+    assert_eq!(1800, epoch.length.value());
+    assert_eq!(0x1e08_3126, epoch.compact_target.value());
+}
+
+#[test]
+fn set_ban_arguments() {
+    let now = 1_000_000;
+    let address = "192.168.0.2".parse::<IpAddr>().unwrap();
+    let parse = |address, command, ban_time: Option<u64>, absolute| {
+        parse_ban_command::<IpAddr>(address, command, ban_time.map(Into::into), absolute, now)
+    };
+
+    // the ban time is relative to now by default
+    assert_eq!(
+        BanCommand::Insert {
+            address,
+            ban_until: now + 3000
+        },
+        parse("192.168.0.2", "insert", Some(3000), None).unwrap()
+    );
+    assert_eq!(
+        BanCommand::Insert {
+            address,
+            ban_until: now + 24 * 60 * 60 * 1000
+        },
+        parse("192.168.0.2", "insert", None, None).unwrap()
+    );
+    assert_eq!(
+        BanCommand::Insert {
+            address,
+            ban_until: 5_000_000
+        },
+        parse("192.168.0.2", "insert", Some(5_000_000), Some(true)).unwrap()
+    );
+    assert_eq!(
+        BanCommand::Delete { address },
+        parse("192.168.0.2", "delete", None, None).unwrap()
+    );
+
+    let err = parse("192.168.0.2", "ban", None, None).unwrap_err();
+    assert_eq!(
+        "expected `insert` or `delete` as the value of command",
+        err.message
+    );
+    let err = parse("192.168.0", "insert", None, None).unwrap_err();
+    assert_eq!("expected a valid IP address, got 192.168.0", err.message);
+}
+
+#[test]
+fn peer_sync_state_serialization() {
+    let header = HeaderBuilder::default().number(100.pack()).build();
+    let hash: H256 = header.hash().unpack();
+    let state = PeerSyncState {
+        announced_tip_header: Some(header.into()),
+        requested_best_known_header: None,
+        proved_best_known_header: None,
+    };
+    let value = serde_json::to_value(&state).unwrap();
+    assert_eq!(
+        serde_json::to_value(&hash).unwrap(),
+        value["announced_tip_header"]["hash"]
+    );
+    assert_eq!("0x64", value["announced_tip_header"]["number"]);
+    assert!(value["requested_best_known_header"].is_null());
+    assert!(value["proved_best_known_header"].is_null());
+
+    let state: PeerSyncState = serde_json::from_value(value).unwrap();
+    assert_eq!(
+        Some(hash),
+        state.announced_tip_header.map(|header| header.hash)
+    );
+}
+
 #[test]
 fn get_cells_capacity_bug() {
     let storage = new_storage("get_cells_capacity_bug");
     let rpc = BlockFilterRpcImpl {
         storage: storage.clone(),
+        pending_txs: Arc::new(RwLock::new(PendingTxs::new(64))),
+        network: NetworkType::Testnet,
     };
 
     // setup test data
@@ -770,11 +910,102 @@ fn get_cells_capacity_bug() {
     assert_eq!((222 + 3000) * 100000000, capacity.value());
 }
 
+#[test]
+fn skip_dangling_cells_and_transactions() {
+    let storage = new_storage("skip_dangling_cells_and_transactions");
+    let rpc = BlockFilterRpcImpl {
+        storage: storage.clone(),
+        pending_txs: Arc::new(RwLock::new(PendingTxs::new(64))),
+        network: NetworkType::Testnet,
+    };
+
+    let lock_script = ScriptBuilder::default()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Data.into())
+        .args(Bytes::from(b"lock_script".to_vec()).pack())
+        .build();
+    let block0 = BlockBuilder::default()
+        .header(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 0, 1000).pack())
+                .number(0.pack())
+                .build(),
+        )
+        .build();
+    storage.init_genesis_block(block0.data());
+    storage.update_filter_scripts(HashMap::from([(lock_script.clone(), 0)]));
+
+    let new_tx = |capacity: Capacity| {
+        TransactionBuilder::default()
+            .output(
+                CellOutputBuilder::default()
+                    .capacity(capacity.pack())
+                    .lock(lock_script.clone())
+                    .build(),
+            )
+            .output_data(Bytes::from(vec![1]).pack())
+            .build()
+    };
+    let tx10 = new_tx(capacity_bytes!(100));
+    let tx11 = new_tx(capacity_bytes!(200));
+    let block1 = BlockBuilder::default()
+        .transaction(tx10.clone())
+        .transaction(tx11.clone())
+        .header(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 1, 1000).pack())
+                .number(1.pack())
+                .build(),
+        )
+        .build();
+    storage.filter_block(block1.data());
+
+    // the transaction of a cell and a tx history entry is missing
+    let mut batch = WriteBatch::default();
+    batch.delete(Key::TxHash(&tx10.hash()).into_vec());
+    storage.store.write(&batch).unwrap();
+
+    let search_key = || SearchKey {
+        script: lock_script.clone().into(),
+        filter: Some(SearchKeyFilter {
+            output_data: Some(JsonBytes::from_vec(vec![1])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let cells = rpc
+        .get_cells(search_key(), Order::Asc, 10.into(), None)
+        .unwrap();
+    assert_eq!(1, cells.objects.len());
+    assert_eq!(tx11.hash(), cells.objects[0].out_point.tx_hash.pack());
+    let capacity = rpc.get_cells_capacity(search_key()).unwrap();
+    assert_eq!(200 * 100000000, capacity.value());
+
+    for group_by_transaction in [false, true] {
+        let txs = rpc
+            .get_transactions(
+                SearchKey {
+                    script: lock_script.clone().into(),
+                    group_by_transaction: Some(group_by_transaction),
+                    ..Default::default()
+                },
+                Order::Asc,
+                10.into(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(1, txs.objects.len());
+        assert_eq!(tx11.hash(), txs.objects[0].tx_hash().pack());
+    }
+}
+
 #[test]
 fn get_cells_after_rollback_bug() {
     let storage = new_storage("get_cells_after_rollback_bug");
     let rpc = BlockFilterRpcImpl {
         storage: storage.clone(),
+        pending_txs: Arc::new(RwLock::new(PendingTxs::new(64))),
+        network: NetworkType::Testnet,
     };
 
     // setup test data
@@ -952,3 +1183,1258 @@ fn get_cells_after_rollback_bug() {
         .unwrap();
     assert_eq!(3, txs.objects.len());
 }
+
+#[test]
+fn get_cells_include_pending() {
+    let storage = new_storage("get_cells_include_pending");
+    let pending_txs = Arc::new(RwLock::new(PendingTxs::new(64)));
+    let rpc = BlockFilterRpcImpl {
+        storage: storage.clone(),
+        pending_txs: Arc::clone(&pending_txs),
+        network: NetworkType::Testnet,
+    };
+
+    // setup test data
+    let lock_script1 = ScriptBuilder::default()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Data.into())
+        .args(Bytes::from(b"lock_script1".to_vec()).pack())
+        .build();
+
+    let tx00 = TransactionBuilder::default()
+        .output(
+            CellOutputBuilder::default()
+                .capacity(capacity_bytes!(222).pack())
+                .lock(lock_script1.clone())
+                .build(),
+        )
+        .output(
+            CellOutputBuilder::default()
+                .capacity(capacity_bytes!(333).pack())
+                .lock(lock_script1.clone())
+                .build(),
+        )
+        .output_data(Default::default())
+        .output_data(Default::default())
+        .build();
+
+    let block0 = BlockBuilder::default()
+        .transaction(tx00.clone())
+        .header(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 0, 1000).pack())
+                .number(0.pack())
+                .build(),
+        )
+        .build();
+    storage.init_genesis_block(block0.data());
+    storage.update_filter_scripts(HashMap::from([(lock_script1.clone(), 0)]));
+
+    let lock_script2 = ScriptBuilder::default()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Data.into())
+        .args(Bytes::from(b"lock_script2".to_vec()).pack())
+        .build();
+
+    // spend the 333 cell, send 100 to lock_script2 and the change back to lock_script1
+    let tx10 = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::new(tx00.hash(), 1), 0))
+        .output(
+            CellOutputBuilder::default()
+                .capacity(capacity_bytes!(100).pack())
+                .lock(lock_script2.clone())
+                .build(),
+        )
+        .output(
+            CellOutputBuilder::default()
+                .capacity(capacity_bytes!(232).pack())
+                .lock(lock_script1.clone())
+                .build(),
+        )
+        .output_data(Default::default())
+        .output_data(Default::default())
+        .build();
+    pending_txs.write().unwrap().push(tx10.clone(), 0);
+
+    let search_key = |include_pending| SearchKey {
+        script: lock_script1.clone().into(),
+        include_pending,
+        ..Default::default()
+    };
+
+    // the cell spent by the pending transaction is excluded even if the pending cells are not
+    // included
+    let capacity = rpc.get_cells_capacity(search_key(None)).unwrap();
+    assert_eq!(222 * 100000000, capacity.value());
+    let cells = rpc
+        .get_cells(search_key(None), Order::Asc, 150.into(), None)
+        .unwrap();
+    assert_eq!(1, cells.objects.len());
+    let capacity = rpc.get_cells_capacity(search_key(Some(true))).unwrap();
+    assert_eq!((222 + 232) * 100000000, capacity.value());
+
+    let cells = rpc
+        .get_cells(search_key(Some(true)), Order::Asc, 150.into(), None)
+        .unwrap();
+    assert_eq!(2, cells.objects.len());
+    assert!(cells.objects[0].block_number.is_some());
+    assert_eq!(
+        cells.objects[1].out_point,
+        ckb_jsonrpc_types::OutPoint::from(OutPoint::new(tx10.hash(), 1))
+    );
+    assert!(cells.objects[1].block_number.is_none());
+
+    // the cells are released after the pending transaction is removed
+    pending_txs.write().unwrap().remove(&tx10.hash());
+    let capacity = rpc.get_cells_capacity(search_key(Some(true))).unwrap();
+    assert_eq!((222 + 333) * 100000000, capacity.value());
+}
+
+#[test]
+fn get_cells_include_pending_pagination() {
+    let storage = new_storage("get_cells_include_pending_pagination");
+    let pending_txs = Arc::new(RwLock::new(PendingTxs::new(64)));
+    let rpc = BlockFilterRpcImpl {
+        storage: storage.clone(),
+        pending_txs: Arc::clone(&pending_txs),
+        network: NetworkType::Testnet,
+    };
+
+    let lock_script1 = ScriptBuilder::default()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Data.into())
+        .args(Bytes::from(b"lock_script1".to_vec()).pack())
+        .build();
+    let output = |capacity| {
+        CellOutputBuilder::default()
+            .capacity(capacity)
+            .lock(lock_script1.clone())
+            .build()
+    };
+
+    // 3 indexed cells and 3 pending cells
+    let tx00 = TransactionBuilder::default()
+        .outputs((0..3).map(|i| output(capacity_bytes!(100 + i).pack())))
+        .outputs_data((0..3).map(|_| Bytes::new().pack()))
+        .build();
+    let block0 = BlockBuilder::default()
+        .transaction(tx00)
+        .header(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 0, 1000).pack())
+                .number(0.pack())
+                .build(),
+        )
+        .build();
+    storage.init_genesis_block(block0.data());
+    storage.update_filter_scripts(HashMap::from([(lock_script1.clone(), 0)]));
+    let tx10 = TransactionBuilder::default()
+        .outputs((0..3).map(|i| output(capacity_bytes!(200 + i).pack())))
+        .outputs_data((0..3).map(|_| Bytes::new().pack()))
+        .build();
+    pending_txs.write().unwrap().push(tx10, 0);
+
+    let search_key = || SearchKey {
+        script: lock_script1.clone().into(),
+        include_pending: Some(true),
+        with_total_count: Some(true),
+        ..Default::default()
+    };
+    for is_desc_order in [false, true] {
+        let order = || {
+            if is_desc_order {
+                Order::Desc
+            } else {
+                Order::Asc
+            }
+        };
+        let mut cells = Vec::new();
+        let mut after_cursor = None;
+        loop {
+            let page = rpc
+                .get_cells(search_key(), order(), 2.into(), after_cursor.take())
+                .unwrap();
+            assert_eq!(6, page.total_count.unwrap().value());
+            assert!(page.objects.len() <= 2);
+            if page.objects.is_empty() {
+                break;
+            }
+            cells.extend(page.objects);
+            after_cursor = Some(page.last_cursor);
+        }
+        let block_numbers = cells
+            .iter()
+            .map(|cell| cell.block_number.map(|number| number.value()))
+            .collect::<Vec<_>>();
+        let mut expected = vec![Some(0), Some(0), Some(0), None, None, None];
+        if is_desc_order {
+            expected.reverse();
+        }
+        assert_eq!(expected, block_numbers);
+        let mut capacities = cells
+            .iter()
+            .map(|cell| cell.output.capacity.value())
+            .collect::<Vec<_>>();
+        capacities.sort_unstable();
+        capacities.dedup();
+        assert_eq!(6, capacities.len());
+    }
+}
+
+#[test]
+fn get_dao_cells() {
+    let storage = new_storage("get_dao_cells");
+
+    // the type script of the 3rd output of the genesis cellbase is the NervosDAO type script
+    let dao_script = ScriptBuilder::default()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Data.into())
+        .build();
+    let cellbase0 = TransactionBuilder::default()
+        .input(CellInput::new_cellbase_input(0))
+        .witness(Script::default().into_witness())
+        .output(CellOutputBuilder::default().build())
+        .output(CellOutputBuilder::default().build())
+        .output(
+            CellOutputBuilder::default()
+                .type_(Some(dao_script.clone()).pack())
+                .build(),
+        )
+        .output_data(Default::default())
+        .output_data(Default::default())
+        .output_data(Default::default())
+        .build();
+    let genesis_block = Consensus::default()
+        .genesis_block()
+        .as_advanced_builder()
+        .set_transactions(vec![cellbase0])
+        .build();
+    let consensus = ConsensusBuilder::default()
+        .genesis_block(genesis_block)
+        .build();
+    storage.init_genesis_block(consensus.genesis_block().data());
+
+    let lock_script = ScriptBuilder::default()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(b"lock_script".to_vec()).pack())
+        .build();
+    storage.update_filter_scripts(HashMap::from([(lock_script.clone(), 0)]));
+
+    let dao_type_script = ScriptBuilder::default()
+        .code_hash(dao_script.calc_script_hash())
+        .hash_type(ScriptHashType::Type.into())
+        .build();
+    let dao_output = CellOutputBuilder::default()
+        .capacity(capacity_bytes!(1000).pack())
+        .lock(lock_script.clone())
+        .type_(Some(dao_type_script).pack())
+        .build();
+    let occupied_capacity = dao_output
+        .occupied_capacity(Capacity::bytes(8).unwrap())
+        .unwrap()
+        .as_u64();
+    let counted_capacity = capacity_bytes!(1000).as_u64() - occupied_capacity;
+    // only the accumulated rate is used to calculate the interest
+    let dao_field = |ar: u64| {
+        let mut dao = [0u8; 32];
+        dao[8..16].copy_from_slice(&ar.to_le_bytes());
+        H256(dao).pack()
+    };
+    let ar = 10_000_000_000_000_000u64;
+
+    // deposit 2 cells, then withdraw the first one
+    let deposit_tx = TransactionBuilder::default()
+        .output(dao_output.clone())
+        .output(dao_output.clone())
+        .output_data(Bytes::from(vec![0u8; 8]).pack())
+        .output_data(Bytes::from(vec![0u8; 8]).pack())
+        .build();
+    let block1 = BlockBuilder::default()
+        .transaction(deposit_tx.clone())
+        .header(
+            HeaderBuilder::default()
+                .number(1.pack())
+                .dao(dao_field(ar))
+                .build(),
+        )
+        .build();
+    storage.filter_block(block1.data());
+
+    let withdrawing_tx = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::new(deposit_tx.hash(), 0), 0))
+        .header_dep(block1.hash())
+        .output(dao_output.clone())
+        .output_data(Bytes::from(1u64.to_le_bytes().to_vec()).pack())
+        .build();
+    // the deposit header of this withdrawing cell isn't stored
+    let unknown_deposit_block_hash = H256(rand::random()).pack();
+    let other_withdrawing_tx = TransactionBuilder::default()
+        .input(CellInput::new(
+            OutPoint::new(H256(rand::random()).pack(), 0),
+            0,
+        ))
+        .header_dep(unknown_deposit_block_hash.clone())
+        .output(dao_output)
+        .output_data(Bytes::from(1u64.to_le_bytes().to_vec()).pack())
+        .build();
+    let block2 = BlockBuilder::default()
+        .transaction(withdrawing_tx.clone())
+        .transaction(other_withdrawing_tx)
+        .header(
+            HeaderBuilder::default()
+                .number(2.pack())
+                .dao(dao_field(ar / 10 * 11))
+                .build(),
+        )
+        .build();
+    storage.filter_block(block2.data());
+
+    let tip_header = HeaderBuilder::default()
+        .number(10.pack())
+        .dao(dao_field(ar * 2))
+        .build();
+    storage.update_last_state(&U256::one(), &tip_header.data());
+
+    let last_headers = Arc::new(RwLock::new(Vec::new()));
+    let peers = Arc::new(Peers::new(Arc::clone(&last_headers)));
+    let rpc = DaoRpcImpl {
+        swl: StorageWithLastHeaders::new(storage.clone(), last_headers),
+        peers: Arc::clone(&peers),
+        consensus,
+    };
+    let cells = rpc.get_dao_cells(lock_script.clone().into()).unwrap();
+    assert_eq!(3, cells.len());
+
+    // counted to the tip
+    assert_eq!(cells[0].phase, DaoPhase::Deposited);
+    assert_eq!(
+        cells[0].cell.out_point,
+        ckb_jsonrpc_types::OutPoint::from(OutPoint::new(deposit_tx.hash(), 1))
+    );
+    assert_eq!(cells[0].header_deps, Some(vec![block1.hash().unpack()]));
+    assert_eq!(
+        cells[0].maximum_withdraw.map(|capacity| capacity.value()),
+        Some(occupied_capacity + counted_capacity * 2)
+    );
+
+    // counted to the withdrawing block
+    assert_eq!(cells[1].phase, DaoPhase::Withdrawing);
+    assert_eq!(
+        cells[1].header_deps,
+        Some(vec![block1.hash().unpack(), block2.hash().unpack()])
+    );
+    assert_eq!(
+        cells[1].maximum_withdraw.map(|capacity| capacity.value()),
+        Some(occupied_capacity + counted_capacity / 10 * 11)
+    );
+
+    // the deposit header is being fetched
+    assert_eq!(cells[2].phase, DaoPhase::Withdrawing);
+    assert!(cells[2].header_deps.is_none());
+    assert!(cells[2].maximum_withdraw.is_none());
+    assert!(peers.is_fetching_header(&unknown_deposit_block_hash));
+
+    // the missing header of a deposited cell is fetched too
+    let mut batch = WriteBatch::default();
+    batch.delete(Key::BlockHash(&block1.hash()).into_vec());
+    storage.store.write(&batch).unwrap();
+    let cells = rpc.get_dao_cells(lock_script.into()).unwrap();
+    assert_eq!(cells[0].phase, DaoPhase::Deposited);
+    assert!(cells[0].header_deps.is_none());
+    assert!(peers.is_fetching_header(&block1.hash()));
+}
+
+#[test]
+fn get_udt_balance() {
+    let storage = new_storage("get_udt_balance");
+    let rpc = BlockFilterRpcImpl {
+        storage: storage.clone(),
+        pending_txs: Arc::new(RwLock::new(PendingTxs::new(64))),
+        network: NetworkType::Testnet,
+    };
+
+    // setup test data
+    let lock_script1 = ScriptBuilder::default()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Data.into())
+        .args(Bytes::from(b"lock_script1".to_vec()).pack())
+        .build();
+    let lock_script2 = ScriptBuilder::default()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Data.into())
+        .args(Bytes::from(b"lock_script2".to_vec()).pack())
+        .build();
+    // sUDT args: owner lock hash
+    let sudt_type_script = ScriptBuilder::default()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(lock_script2.calc_script_hash().as_slice().to_vec()).pack())
+        .build();
+    // xUDT args: owner lock hash + flags
+    let xudt_type_script = ScriptBuilder::default()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from([lock_script2.calc_script_hash().as_slice(), &[0u8; 4]].concat()).pack())
+        .build();
+    let udt_cell = |lock: &Script, udt_type: &Script| {
+        CellOutputBuilder::default()
+            .capacity(capacity_bytes!(142).pack())
+            .lock(lock.clone())
+            .type_(Some(udt_type.clone()).pack())
+            .build()
+    };
+    let udt_data = |amount: u128, extension: &[u8]| {
+        Bytes::from([&amount.to_le_bytes()[..], extension].concat()).pack()
+    };
+
+    let block0 = BlockBuilder::default()
+        .header(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 0, 1000).pack())
+                .number(0.pack())
+                .build(),
+        )
+        .build();
+    storage.init_genesis_block(block0.data());
+    storage.update_filter_scripts(HashMap::from([(lock_script1.clone(), 0)]));
+
+    let tx10 = TransactionBuilder::default()
+        .output(udt_cell(&lock_script1, &sudt_type_script))
+        .output(udt_cell(&lock_script1, &sudt_type_script))
+        .output(udt_cell(&lock_script1, &xudt_type_script))
+        .output(udt_cell(&lock_script2, &sudt_type_script))
+        .output(
+            CellOutputBuilder::default()
+                .capacity(capacity_bytes!(1000).pack())
+                .lock(lock_script1.clone())
+                .build(),
+        )
+        .output_data(udt_data(100, &[]))
+        .output_data(udt_data(50, b"sudt extra data"))
+        .output_data(udt_data(1000, b"xudt extension data"))
+        .output_data(udt_data(7, &[]))
+        .output_data(Default::default())
+        .build();
+
+    let block1 = BlockBuilder::default()
+        .transaction(tx10.clone())
+        .header(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 1, 1000).pack())
+                .number(1.pack())
+                .build(),
+        )
+        .build();
+    storage.filter_block(block1.data());
+
+    let tx20 = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::new(tx10.hash(), 0), 0))
+        .output(udt_cell(&lock_script1, &sudt_type_script))
+        .output(udt_cell(&lock_script2, &sudt_type_script))
+        .output_data(udt_data(30, &[]))
+        .output_data(udt_data(70, &[]))
+        .build();
+
+    let block2 = BlockBuilder::default()
+        .transaction(tx20.clone())
+        .header(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 2, 1000).pack())
+                .number(2.pack())
+                .build(),
+        )
+        .build();
+    storage.filter_block(block2.data());
+
+    let search_key = || SearchKey {
+        script: lock_script1.clone().into(),
+        ..Default::default()
+    };
+    let balance = rpc
+        .get_udt_balance(search_key(), sudt_type_script.clone().into())
+        .unwrap();
+    assert_eq!(50 + 30, balance.value());
+    let balance = rpc
+        .get_udt_balance(search_key(), xudt_type_script.clone().into())
+        .unwrap();
+    assert_eq!(1000, balance.value());
+
+    let cells_page = rpc
+        .get_cells(
+            SearchKey {
+                script: sudt_type_script.clone().into(),
+                script_type: ScriptType::Type,
+                ..Default::default()
+            },
+            Order::Asc,
+            10.into(),
+            None,
+        )
+        .unwrap();
+    assert_eq!(
+        2,
+        cells_page.objects.len(),
+        "only the cells of the filter scripts are indexed"
+    );
+
+    // the spent udt cell should be restored after rollback
+    storage.rollback_to_block(2);
+    let balance = rpc
+        .get_udt_balance(search_key(), sudt_type_script.into())
+        .unwrap();
+    assert_eq!(100 + 50, balance.value());
+}
+
+#[test]
+fn get_cells_with_cursor() {
+    let storage = new_storage("get_cells_with_cursor");
+    let rpc = BlockFilterRpcImpl {
+        storage: storage.clone(),
+        pending_txs: Arc::new(RwLock::new(PendingTxs::new(64))),
+        network: NetworkType::Testnet,
+    };
+
+    // setup test data
+    let lock_script1 = ScriptBuilder::default()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Data.into())
+        .args(Bytes::from(b"lock_script1".to_vec()).pack())
+        .build();
+    let search_key = || SearchKey {
+        script: lock_script1.clone().into(),
+        with_total_count: Some(true),
+        ..Default::default()
+    };
+
+    let block0 = BlockBuilder::default()
+        .header(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 0, 1000).pack())
+                .number(0.pack())
+                .build(),
+        )
+        .build();
+    storage.init_genesis_block(block0.data());
+    storage.update_filter_scripts(HashMap::from([(lock_script1.clone(), 0)]));
+
+    let tx10 = TransactionBuilder::default()
+        .outputs((0..3).map(|_| {
+            CellOutputBuilder::default()
+                .capacity(capacity_bytes!(100).pack())
+                .lock(lock_script1.clone())
+                .build()
+        }))
+        .outputs_data((0..3).map(|_| Bytes::default().pack()))
+        .build();
+    let header1 = HeaderBuilder::default()
+        .epoch(EpochNumberWithFraction::new(0, 1, 1000).pack())
+        .number(1.pack())
+        .build();
+    let block1 = BlockBuilder::default()
+        .transaction(tx10.clone())
+        .header(header1)
+        .build();
+    storage.filter_block(block1.data());
+    storage.update_last_state(&U256::one(), &block1.header().data());
+
+    let cells_page_1 = rpc
+        .get_cells(search_key(), Order::Asc, 1.into(), None)
+        .unwrap();
+    assert_eq!(1, cells_page_1.objects.len());
+    assert_eq!(Some(3.into()), cells_page_1.total_count);
+    assert_eq!(
+        cells_page_1.objects[0].out_point,
+        ckb_jsonrpc_types::OutPoint::from(OutPoint::new(tx10.hash(), 0))
+    );
+
+    // the cell of the cursor is spent before the next page
+    let tx20 = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::new(tx10.hash(), 0), 0))
+        .output(
+            CellOutputBuilder::default()
+                .capacity(capacity_bytes!(100).pack())
+                .lock(lock_script1.clone())
+                .build(),
+        )
+        .output_data(Default::default())
+        .build();
+    let header2 = HeaderBuilder::default()
+        .epoch(EpochNumberWithFraction::new(0, 2, 1000).pack())
+        .number(2.pack())
+        .build();
+    let block2 = BlockBuilder::default()
+        .transaction(tx20)
+        .header(header2.clone())
+        .build();
+    storage.filter_block(block2.data());
+    storage.update_last_state(&U256::from(2u64), &block2.header().data());
+
+    let cells_page_2 = rpc
+        .get_cells(
+            search_key(),
+            Order::Asc,
+            1.into(),
+            Some(cells_page_1.last_cursor),
+        )
+        .unwrap();
+    assert_eq!(
+        cells_page_2.objects[0].out_point,
+        ckb_jsonrpc_types::OutPoint::from(OutPoint::new(tx10.hash(), 1)),
+        "the next cell of the spent one should not be skipped"
+    );
+    assert_eq!(Some(3.into()), cells_page_2.total_count);
+
+    // rollback below the tip of the cursor
+    storage.rollback_to_block(2);
+    let err = rpc
+        .get_cells(
+            search_key(),
+            Order::Asc,
+            1.into(),
+            Some(cells_page_2.last_cursor),
+        )
+        .unwrap_err();
+    assert_eq!(jsonrpc_core::ErrorCode::ServerError(-32001), err.code);
+
+    // the cursors issued after the rollback are fine
+    let cells_page_1 = rpc
+        .get_cells(search_key(), Order::Asc, 2.into(), None)
+        .unwrap();
+    let cells_page_2 = rpc
+        .get_cells(
+            search_key(),
+            Order::Asc,
+            2.into(),
+            Some(cells_page_1.last_cursor),
+        )
+        .unwrap();
+    assert_eq!(2, cells_page_1.objects.len());
+    assert_eq!(1, cells_page_2.objects.len());
+    assert_eq!(Some(3.into()), cells_page_2.total_count);
+
+    // the cursor is still valid after a reorg which doesn't roll back any filtered block,
+    // the indexed cells are not changed
+    let cells_page_1 = rpc
+        .get_cells(search_key(), Order::Asc, 1.into(), None)
+        .unwrap();
+    let forked_header2 = header2.as_advanced_builder().timestamp(1.pack()).build();
+    storage.update_last_state(&U256::from(2u64), &forked_header2.data());
+    let cells_page_2 = rpc
+        .get_cells(
+            search_key(),
+            Order::Asc,
+            1.into(),
+            Some(cells_page_1.last_cursor),
+        )
+        .unwrap();
+    assert_eq!(1, cells_page_2.objects.len());
+
+    let txs_page = rpc
+        .get_transactions(search_key(), Order::Asc, 1.into(), None)
+        .unwrap();
+    assert_eq!(Some(3.into()), txs_page.total_count);
+
+    assert!(rpc
+        .get_cells(
+            search_key(),
+            Order::Asc,
+            1.into(),
+            Some(ckb_jsonrpc_types::JsonBytes::from_vec(vec![0xff; 64]))
+        )
+        .is_err());
+}
+
+#[test]
+fn get_cells_and_transactions_with_filters() {
+    let storage = new_storage("get_cells_and_transactions_with_filters");
+    let rpc = BlockFilterRpcImpl {
+        storage: storage.clone(),
+        pending_txs: Arc::new(RwLock::new(PendingTxs::new(64))),
+        network: NetworkType::Testnet,
+    };
+
+    // setup test data
+    let lock_script1 = ScriptBuilder::default()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Data.into())
+        .args(Bytes::from(b"lock_script1".to_vec()).pack())
+        .build();
+    let type_script1 = ScriptBuilder::default()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(b"type_script1".to_vec()).pack())
+        .build();
+    let cell = |capacity: Capacity, type_script: Option<Script>| {
+        CellOutputBuilder::default()
+            .capacity(capacity.pack())
+            .lock(lock_script1.clone())
+            .type_(type_script.pack())
+            .build()
+    };
+
+    let block0 = BlockBuilder::default()
+        .header(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 0, 1000).pack())
+                .number(0.pack())
+                .build(),
+        )
+        .build();
+    storage.init_genesis_block(block0.data());
+    storage.update_filter_scripts(HashMap::from([(lock_script1.clone(), 0)]));
+
+    let tx10 = TransactionBuilder::default()
+        .output(cell(capacity_bytes!(1000), None))
+        .output(cell(capacity_bytes!(200), Some(type_script1.clone())))
+        .output(cell(capacity_bytes!(300), Some(type_script1.clone())))
+        .output_data(Default::default())
+        .output_data(Bytes::from(b"hello world".to_vec()).pack())
+        .output_data(Bytes::from(b"hello".to_vec()).pack())
+        .build();
+    let block1 = BlockBuilder::default()
+        .transaction(tx10.clone())
+        .header(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 1, 1000).pack())
+                .number(1.pack())
+                .build(),
+        )
+        .build();
+    storage.filter_block(block1.data());
+
+    let tx20 = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::new(tx10.hash(), 0), 0))
+        .output(cell(capacity_bytes!(500), None))
+        .output_data(Bytes::from(b"hello".to_vec()).pack())
+        .build();
+    let block2 = BlockBuilder::default()
+        .transaction(tx20.clone())
+        .header(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 2, 1000).pack())
+                .number(2.pack())
+                .build(),
+        )
+        .build();
+    storage.filter_block(block2.data());
+
+    let search_key = |filter: SearchKeyFilter| SearchKey {
+        script: lock_script1.clone().into(),
+        filter: Some(filter),
+        ..Default::default()
+    };
+    let hello = || Some(JsonBytes::from_vec(b"hello".to_vec()));
+    let get_cells = |search_key: SearchKey| {
+        rpc.get_cells(search_key, Order::Asc, 10.into(), None)
+            .unwrap()
+            .objects
+    };
+    let get_transactions = |search_key: SearchKey| {
+        rpc.get_transactions(search_key, Order::Asc, 10.into(), None)
+            .unwrap()
+            .objects
+    };
+
+    // the live cells without a type script
+    let cells = get_cells(search_key(SearchKeyFilter {
+        script_len_range: Some([0.into(), 1.into()]),
+        ..Default::default()
+    }));
+    assert_eq!(1, cells.len());
+    assert_eq!(cells[0].out_point, OutPoint::new(tx20.hash(), 0).into());
+
+    let cells = get_cells(search_key(SearchKeyFilter {
+        output_data: hello(),
+        ..Default::default()
+    }));
+    assert_eq!(3, cells.len(), "matches the prefix by default");
+    let cells = get_cells(search_key(SearchKeyFilter {
+        output_data: hello(),
+        output_data_filter_mode: Some(SearchMode::Exact),
+        ..Default::default()
+    }));
+    assert_eq!(2, cells.len());
+
+    let cells = get_cells(SearchKey {
+        with_data: Some(false),
+        ..search_key(SearchKeyFilter::default())
+    });
+    assert_eq!(3, cells.len());
+    assert!(cells.iter().all(|cell| cell.output_data.is_none()));
+
+    // the cell is created by tx10 and consumed by tx20
+    let txs = get_transactions(search_key(SearchKeyFilter {
+        output_capacity_range: Some([
+            capacity_bytes!(1000).as_u64().into(),
+            capacity_bytes!(1001).as_u64().into(),
+        ]),
+        ..Default::default()
+    }));
+    assert_eq!(
+        vec![tx10.hash().unpack(), tx20.hash().unpack()],
+        txs.iter().map(|tx| tx.tx_hash()).collect::<Vec<H256>>()
+    );
+
+    let txs = get_transactions(search_key(SearchKeyFilter {
+        output_data: hello(),
+        output_data_filter_mode: Some(SearchMode::Exact),
+        ..Default::default()
+    }));
+    assert_eq!(2, txs.len());
+
+    let capacity = rpc
+        .get_cells_capacity(search_key(SearchKeyFilter {
+            output_data_len_range: Some([5.into(), 6.into()]),
+            ..Default::default()
+        }))
+        .unwrap();
+    assert_eq!(
+        capacity_bytes!(300).as_u64() + capacity_bytes!(500).as_u64(),
+        capacity.value()
+    );
+}
+
+#[test]
+fn indexer_compatible_rpc() {
+    let storage = new_storage("indexer_compatible_rpc");
+    let swl = StorageWithLastHeaders::new(storage.clone(), Arc::new(RwLock::new(Vec::new())));
+    let rpc = IndexerRpcImpl {
+        block_filter_rpc: BlockFilterRpcImpl {
+            storage: storage.clone(),
+            pending_txs: Arc::new(RwLock::new(PendingTxs::new(64))),
+            network: NetworkType::Testnet,
+        },
+        swl,
+    };
+
+    // setup test data
+    let lock_script1 = ScriptBuilder::default()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Data.into())
+        .args(Bytes::from(b"lock_script1".to_vec()).pack())
+        .build();
+    let search_key = || SearchKey {
+        script: lock_script1.clone().into(),
+        ..Default::default()
+    };
+
+    let block0 = BlockBuilder::default()
+        .header(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 0, 1000).pack())
+                .number(0.pack())
+                .build(),
+        )
+        .build();
+    storage.init_genesis_block(block0.data());
+    assert!(rpc.get_indexer_tip().unwrap().is_none());
+    assert!(rpc.get_cells_capacity(search_key()).unwrap().is_none());
+
+    storage.update_filter_scripts(HashMap::from([(lock_script1.clone(), 0)]));
+    let tip = rpc.get_indexer_tip().unwrap().unwrap();
+    assert_eq!(block0.hash(), tip.block_hash.pack());
+    assert_eq!(0, tip.block_number.value());
+
+    let tx10 = TransactionBuilder::default()
+        .output(
+            CellOutputBuilder::default()
+                .capacity(capacity_bytes!(100).pack())
+                .lock(lock_script1.clone())
+                .build(),
+        )
+        .output_data(Bytes::from(b"hello".to_vec()).pack())
+        .build();
+    let block1 = BlockBuilder::default()
+        .transaction(tx10.clone())
+        .header(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 1, 1000).pack())
+                .number(1.pack())
+                .build(),
+        )
+        .build();
+    storage.filter_block(block1.data());
+    storage.update_block_number(1);
+
+    let tip = rpc.get_indexer_tip().unwrap().unwrap();
+    assert_eq!(block1.hash(), tip.block_hash.pack());
+    assert_eq!(1, tip.block_number.value());
+
+    // the pending cells and the total count are never returned
+    let cells = rpc
+        .get_cells(
+            SearchKey {
+                include_pending: Some(true),
+                with_total_count: Some(true),
+                ..search_key()
+            },
+            Order::Asc,
+            10.into(),
+            None,
+        )
+        .unwrap();
+    assert!(cells.total_count.is_none());
+    assert_eq!(1, cells.objects.len());
+    assert_eq!(1, cells.objects[0].block_number.value());
+    assert_eq!(0, cells.objects[0].tx_index.value());
+    let cell = serde_json::to_value(&cells.objects[0]).unwrap();
+    assert_eq!("0x68656c6c6f", cell["output_data"]);
+
+    let txs = rpc
+        .get_transactions(search_key(), Order::Asc, 10.into(), None)
+        .unwrap();
+    assert_eq!(1, txs.objects.len());
+    let tx = serde_json::to_value(&txs.objects[0]).unwrap();
+    assert_eq!(serde_json::to_value(tx10.hash()).unwrap(), tx["tx_hash"]);
+    assert_eq!("output", tx["io_type"]);
+    assert!(tx.get("transaction").is_none());
+
+    let capacity = rpc.get_cells_capacity(search_key()).unwrap().unwrap();
+    assert_eq!(capacity_bytes!(100).as_u64(), capacity.capacity.value());
+    assert_eq!(block1.hash(), capacity.block_hash.pack());
+}
+
+#[test]
+fn set_scripts_and_get_cells_with_address() {
+    let storage = new_storage("set_scripts_and_get_cells_with_address");
+    let rpc = BlockFilterRpcImpl {
+        storage: storage.clone(),
+        pending_txs: Arc::new(RwLock::new(PendingTxs::new(64))),
+        network: NetworkType::Testnet,
+    };
+
+    let lock_script1 = ScriptBuilder::default()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(b"lock_script1".to_vec()).pack())
+        .build();
+    let address = Address::new(NetworkType::Testnet, lock_script1.clone()).to_string();
+
+    let block0 = BlockBuilder::default()
+        .transaction(
+            TransactionBuilder::default()
+                .output(
+                    CellOutputBuilder::default()
+                        .capacity(capacity_bytes!(100).pack())
+                        .lock(lock_script1.clone())
+                        .build(),
+                )
+                .output_data(Default::default())
+                .build(),
+        )
+        .header(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 0, 1000).pack())
+                .number(0.pack())
+                .build(),
+        )
+        .build();
+    storage.init_genesis_block(block0.data());
+
+    rpc.set_scripts(vec![ScriptStatus {
+        script: Default::default(),
+        address: Some(address.clone()),
+        block_number: 0.into(),
+    }])
+    .unwrap();
+    let scripts = rpc.get_scripts().unwrap();
+    assert_eq!(1, scripts.len());
+    assert_eq!(scripts[0].script, lock_script1.clone().into());
+    assert_eq!(scripts[0].address, Some(address.clone()));
+
+    let cells = rpc
+        .get_cells(
+            SearchKey {
+                address: Some(address),
+                ..Default::default()
+            },
+            Order::Asc,
+            10.into(),
+            None,
+        )
+        .unwrap();
+    assert_eq!(1, cells.objects.len());
+
+    // the address of another network
+    let mainnet_address = Address::new(NetworkType::Mainnet, lock_script1).to_string();
+    assert!(rpc
+        .get_cells_capacity(SearchKey {
+            address: Some(mainnet_address),
+            ..Default::default()
+        })
+        .is_err());
+    // neither the script nor the address is set
+    assert!(rpc.get_cells_capacity(SearchKey::default()).is_err());
+}
+
+#[test]
+fn watch_wallet_gap_limit() {
+    let storage = new_storage("watch_wallet_gap_limit");
+    let rpc = BlockFilterRpcImpl {
+        storage: storage.clone(),
+        pending_txs: Arc::new(RwLock::new(PendingTxs::new(64))),
+        network: NetworkType::Testnet,
+    };
+
+    let xpub = "xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB";
+    let wallet = WatchWallet {
+        xpub: ExtendedPubKey::from_str(xpub).unwrap(),
+        path: DerivationPath::from_str("m/0").unwrap(),
+        gap_limit: 2,
+    };
+    let lock_scripts: Vec<_> = wallet
+        .lock_scripts(0, 10)
+        .into_iter()
+        .map(|(_index, script)| script)
+        .collect();
+    let new_tx = |indexes: &[usize]| {
+        indexes
+            .iter()
+            .fold(TransactionBuilder::default(), |builder, index| {
+                builder
+                    .output(
+                        CellOutputBuilder::default()
+                            .capacity(capacity_bytes!(100).pack())
+                            .lock(lock_scripts[*index].clone())
+                            .build(),
+                    )
+                    .output_data(Default::default())
+            })
+            .build()
+    };
+
+    let block0 = BlockBuilder::default()
+        .transaction(new_tx(&[1]))
+        .header(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 0, 1000).pack())
+                .number(0.pack())
+                .build(),
+        )
+        .build();
+    storage.init_genesis_block(block0.data());
+
+    assert!(rpc
+        .add_watch_wallet(xpub.to_owned(), "m/0".to_owned(), 0.into(), 0.into())
+        .is_err());
+    assert!(rpc
+        .add_watch_wallet(xpub.to_owned(), "m/0'".to_owned(), 2.into(), 0.into())
+        .is_err());
+    rpc.add_watch_wallet(xpub.to_owned(), "m/0".to_owned(), 2.into(), 0.into())
+        .unwrap();
+    assert!(rpc
+        .add_watch_wallet(xpub.to_owned(), "m/0".to_owned(), 2.into(), 0.into())
+        .is_err());
+
+    // index 1 is used in the genesis block, indexes 2 and 3 are derived for the gap limit
+    let wallets = rpc.get_watch_wallets().unwrap();
+    assert_eq!(1, wallets.len());
+    assert_eq!("m/0", wallets[0].path);
+    assert_eq!(4, wallets[0].derived_count.value());
+
+    // index 5 is derived for the usage of index 3 in the same block
+    let block1 = BlockBuilder::default()
+        .transaction(new_tx(&[3, 5]))
+        .header(HeaderBuilder::default().number(1.pack()).build())
+        .build();
+    assert!(storage.filter_block(block1.data()));
+    assert_eq!(8, rpc.get_watch_wallets().unwrap()[0].derived_count.value());
+    let scripts = storage.get_filter_scripts();
+    assert_eq!(8, scripts.len());
+    for index in [1, 3, 5] {
+        let capacity = rpc
+            .get_cells_capacity(SearchKey {
+                script: lock_scripts[index].clone().into(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(capacity_bytes!(100).as_u64(), capacity.value());
+    }
+
+    // the wallet scripts are kept by `set_scripts`
+    let lock_script1 = ScriptBuilder::default()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(b"lock_script1".to_vec()).pack())
+        .build();
+    rpc.set_scripts(vec![ScriptStatus {
+        script: lock_script1.clone().into(),
+        address: None,
+        block_number: 1.into(),
+    }])
+    .unwrap();
+    assert_eq!(9, storage.get_filter_scripts().len());
+
+    rpc.remove_watch_wallet(xpub.to_owned(), "m/0".to_owned())
+        .unwrap();
+    assert!(rpc
+        .remove_watch_wallet(xpub.to_owned(), "m/0".to_owned())
+        .is_err());
+    assert!(rpc.get_watch_wallets().unwrap().is_empty());
+    let scripts = storage.get_filter_scripts();
+    assert_eq!(1, scripts.len());
+    assert!(scripts.contains_key(&lock_script1));
+}
+
+#[test]
+fn build_transfer() {
+    let (storage, consensus) = setup("build_transfer");
+    let privkey = Privkey::from_slice(&[1u8; 32]);
+    let pubkey = privkey.pubkey().unwrap();
+    let sighash_script = |args: &[u8]| -> Script {
+        ScriptBuilder::default()
+            .code_hash(
+                h256!("0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8").pack(),
+            )
+            .hash_type(ScriptHashType::Type.into())
+            .args(Bytes::from(args.to_vec()).pack())
+            .build()
+    };
+    let from_script = sighash_script(&blake2b_256(pubkey.serialize())[..20]);
+    let to_script = sighash_script(&[2u8; 20]);
+    storage.update_filter_scripts(vec![(from_script.clone(), 0)].into_iter().collect());
+
+    // the cell with data is not collected
+    let cells_tx = TransactionBuilder::default()
+        .output(
+            CellOutputBuilder::default()
+                .capacity(capacity_bytes!(100).pack())
+                .lock(from_script.clone())
+                .build(),
+        )
+        .output_data(Default::default())
+        .output(
+            CellOutputBuilder::default()
+                .capacity(capacity_bytes!(1000).pack())
+                .lock(from_script.clone())
+                .build(),
+        )
+        .output_data(Bytes::from(b"data".to_vec()).pack())
+        .output(
+            CellOutputBuilder::default()
+                .capacity(capacity_bytes!(200).pack())
+                .lock(from_script.clone())
+                .build(),
+        )
+        .output_data(Default::default())
+        .build();
+    // the cellbase output is not collected until it's mature
+    let cellbase = TransactionBuilder::default()
+        .input(CellInput::new_cellbase_input(1))
+        .output(
+            CellOutputBuilder::default()
+                .capacity(capacity_bytes!(1000).pack())
+                .lock(from_script.clone())
+                .build(),
+        )
+        .output_data(Default::default())
+        .build();
+    let block = BlockBuilder::default()
+        .transaction(cellbase.clone())
+        .transaction(cells_tx.clone())
+        .header(
+            HeaderBuilder::default()
+                .epoch(EpochNumberWithFraction::new(0, 1, 1000).pack())
+                .number(1.pack())
+                .build(),
+        )
+        .build();
+    storage.filter_block(block.data());
+
+    let rpc = TransactionRpcImpl {
+        pending_txs: Arc::new(RwLock::new(PendingTxs::new(64))),
+        system_scripts: Arc::new(SystemScripts::new(&storage.get_genesis_block(), &consensus)),
+        swl: StorageWithLastHeaders::new(storage, Default::default()),
+        consensus,
+        min_fee_rate: FeeRate::from_u64(1000),
+    };
+    let transfer = |capacity: Capacity, fee_rate: u64| {
+        rpc.build_transfer(
+            vec![from_script.clone().into()],
+            to_script.clone().into(),
+            capacity.as_u64().into(),
+            fee_rate.into(),
+        )
+    };
+
+    assert!(rpc
+        .build_transfer(
+            vec![
+                to_script.clone().into(),
+                ScriptBuilder::default().build().into()
+            ],
+            to_script.clone().into(),
+            capacity_bytes!(150).as_u64().into(),
+            1000.into(),
+        )
+        .is_err());
+    assert!(transfer(capacity_bytes!(150), 999).is_err());
+    assert!(transfer(capacity_bytes!(60), 1000).is_err());
+    assert!(transfer(capacity_bytes!(300), 1000).is_err());
+
+    let result = transfer(capacity_bytes!(150), 1000).unwrap();
+    let tx: packed::Transaction = result.transaction.clone().into();
+    let tx = tx.into_view();
+    assert_eq!(
+        tx.input_pts_iter().collect::<Vec<_>>(),
+        vec![
+            OutPoint::new(cells_tx.hash(), 0),
+            OutPoint::new(cells_tx.hash(), 2)
+        ]
+    );
+    // the dep group of the secp256k1 blake160 sighash lock in the genesis block of testnet
+    assert_eq!(
+        tx.cell_deps().get(0).unwrap().out_point(),
+        OutPoint::new(
+            h256!("0xf8de3bb47d055cdf460d93a2a6e1b05f7432f9777c8c474abf4eec1d4aee5d37").pack(),
+            0
+        )
+    );
+    let outputs_capacity = tx.outputs_capacity().unwrap();
+    assert_eq!(
+        capacity_bytes!(300).as_u64() - outputs_capacity.as_u64(),
+        result.fee.value()
+    );
+    let capacity: Capacity = tx.outputs().get(0).unwrap().capacity().unpack();
+    assert_eq!(capacity_bytes!(150), capacity);
+    assert_eq!(from_script, tx.outputs().get(1).unwrap().lock());
+    assert_eq!(1, result.signing_messages.len());
+    assert_eq!(0, result.signing_messages[0].witness_index.value());
+
+    // the transaction is accepted after it's signed
+    let signature = privkey
+        .sign_recoverable(&result.signing_messages[0].message)
+        .unwrap();
+    let witness = WitnessArgs::new_builder()
+        .lock(Some(Bytes::from(signature.serialize()).pack()).pack())
+        .build();
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(vec![
+            witness.as_bytes().pack(),
+            tx.witnesses().get(1).unwrap(),
+        ])
+        .build();
+    let sent = rpc.send_transaction(tx.data().into()).unwrap();
+    assert_eq!(tx.hash().unpack(), sent.tx_hash);
+    assert_eq!(result.fee.value(), sent.fee.value());
+
+    // the cells spent by the pending transaction are not collected again
+    assert!(transfer(capacity_bytes!(150), 1000).is_err());
+
+    // the cellbase output is collected after the cellbase maturity (4 epochs on testnet)
+    let tip_header = HeaderBuilder::default()
+        .epoch(EpochNumberWithFraction::new(4, 1, 1000).pack())
+        .number(4001.pack())
+        .build();
+    rpc.swl
+        .storage()
+        .update_last_state(&U256::one(), &tip_header.data());
+    let result = transfer(capacity_bytes!(150), 1000).unwrap();
+    let tx: packed::Transaction = result.transaction.into();
+    assert_eq!(
+        tx.into_view().input_pts_iter().collect::<Vec<_>>(),
+        vec![OutPoint::new(cellbase.hash(), 0)]
+    );
+}
@@ -0,0 +1,483 @@
+use std::io::Cursor;
+
+use ckb_chain_spec::consensus::Consensus;
+use ckb_traits::HeaderProvider;
+use ckb_types::{
+    bytes::Bytes,
+    core::{
+        capacity_bytes, BlockBuilder, Capacity, HeaderBuilder, ScriptHashType, TransactionBuilder,
+    },
+    packed::{self, CellInput, CellOutputBuilder, OutPoint, Script},
+    prelude::*,
+    H256,
+};
+use rocksdb::{prelude::*, DB};
+
+use crate::storage::{
+    CellValue, CompressionType, Direction, KVIter, Key, KeyPrefix, KeyValueStore, MemoryStore,
+    RetentionPolicy, RocksdbOptions, RocksdbStore, Storage, WriteBatch,
+};
+
+fn keys(iter: KVIter<'_>) -> Vec<Vec<u8>> {
+    iter.map(|(key, _value)| key.to_vec()).collect()
+}
+
+fn check_store<S: KeyValueStore>(store: S) {
+    store.put(b"a1", b"1").unwrap();
+    store.put(b"b1", b"2").unwrap();
+    store.put(b"b3", b"3").unwrap();
+    store.put(b"c1", b"4").unwrap();
+    assert_eq!(store.get(b"b1").unwrap(), Some(b"2".to_vec()));
+    assert_eq!(store.get(b"b2").unwrap(), None);
+
+    let snapshot = store.snapshot();
+
+    let mut batch = WriteBatch::default();
+    batch.put(b"b2", b"5");
+    batch.delete(b"b3");
+    store.write(&batch).unwrap();
+
+    assert_eq!(
+        keys(store.prefix_iter(b"b")),
+        vec![b"b1".to_vec(), b"b2".to_vec()]
+    );
+    assert_eq!(
+        keys(store.iter(b"b15", Direction::Forward)),
+        vec![b"b2".to_vec(), b"c1".to_vec()]
+    );
+    assert_eq!(
+        keys(store.iter(b"b2", Direction::Reverse)),
+        vec![b"b2".to_vec(), b"b1".to_vec(), b"a1".to_vec()]
+    );
+
+    // the snapshot isn't affected by the later writes
+    assert_eq!(snapshot.get(b"b2").unwrap(), None);
+    assert_eq!(snapshot.get(b"b3").unwrap(), Some(b"3".to_vec()));
+    assert_eq!(
+        keys(snapshot.iter(b"b", Direction::Forward)),
+        vec![b"b1".to_vec(), b"b3".to_vec(), b"c1".to_vec()]
+    );
+}
+
+#[test]
+fn memory_store() {
+    check_store(MemoryStore::new());
+}
+
+#[test]
+fn rocksdb_store() {
+    let tmp_dir = tempfile::Builder::new()
+        .prefix("rocksdb_store")
+        .tempdir()
+        .unwrap();
+    check_store(RocksdbStore::open(tmp_dir.path()));
+}
+
+#[test]
+fn rocksdb_store_column_families() {
+    let tmp_dir = tempfile::Builder::new()
+        .prefix("rocksdb_store_column_families")
+        .tempdir()
+        .unwrap();
+    // the layout of the previous versions, everything is in the default column family
+    {
+        let db = DB::open_default(tmp_dir.path()).unwrap();
+        for key in [[0u8, 1], [32, 1], [160, 1]] {
+            db.put(key, b"legacy").unwrap();
+        }
+    }
+
+    let options = RocksdbOptions {
+        cache_size: Some(1 << 20),
+        compression: Some(CompressionType::Snappy),
+        ..Default::default()
+    };
+    let store = RocksdbStore::open_with_options(tmp_dir.path(), &options).unwrap();
+    assert_eq!(store.get(&[32, 1]).unwrap(), Some(b"legacy".to_vec()));
+    store.put(&[96, 1], b"1").unwrap();
+    store.put(&[224, 1], b"2").unwrap();
+
+    // the keys are iterated across the column families in order
+    assert_eq!(
+        keys(store.iter(&[32, 0], Direction::Forward)),
+        vec![vec![32, 1], vec![96, 1], vec![160, 1], vec![224, 1]]
+    );
+    assert_eq!(
+        keys(store.iter(&[160, 0], Direction::Reverse)),
+        vec![vec![96, 1], vec![32, 1], vec![0, 1]]
+    );
+    assert_eq!(keys(store.prefix_iter(&[160])), vec![vec![160, 1]]);
+    assert_eq!(
+        keys(store.snapshot().iter(&[], Direction::Forward)).len(),
+        5
+    );
+}
+
+#[test]
+fn export_and_import() {
+    let consensus = Consensus::default();
+    let storage = Storage::with_store(MemoryStore::new()).unwrap();
+    storage.init_genesis_block(consensus.genesis_block().data());
+    let script = Script::new_builder()
+        .code_hash(H256(rand::random()).pack())
+        .build();
+    storage.update_filter_scripts(vec![(script, 42)].into_iter().collect());
+
+    let mut archive = Vec::new();
+    let count = storage.export_to(&mut archive).unwrap();
+    assert!(count > 0);
+
+    let imported = Storage::with_store(MemoryStore::new()).unwrap();
+    assert_eq!(
+        imported
+            .import_from(Cursor::new(&archive), &consensus.genesis_hash())
+            .unwrap(),
+        count
+    );
+    assert_eq!(imported.get_filter_scripts(), storage.get_filter_scripts());
+    assert_eq!(
+        imported.get_tip_header().as_slice(),
+        storage.get_tip_header().as_slice()
+    );
+
+    // the store to import into should be empty
+    assert!(imported
+        .import_from(Cursor::new(&archive), &consensus.genesis_hash())
+        .is_err());
+
+    // genesis hash mismatch
+    let other = Storage::with_store(MemoryStore::new()).unwrap();
+    assert!(other
+        .import_from(Cursor::new(&archive), &H256(rand::random()).pack())
+        .is_err());
+
+    // corrupted archive
+    let mut corrupted = archive.clone();
+    let middle = corrupted.len() / 2;
+    corrupted[middle] ^= 0xff;
+    assert!(other
+        .import_from(Cursor::new(&corrupted), &consensus.genesis_hash())
+        .is_err());
+    assert!(other
+        .get(Key::Meta("LAST_STATE").into_vec())
+        .unwrap()
+        .is_none());
+
+    // truncated archive with a huge entry length: magic (8) | version (4) | genesis hash (32)
+    let mut truncated = archive[..44].to_vec();
+    truncated.extend_from_slice(&(u32::MAX - 1).to_be_bytes());
+    truncated.extend_from_slice(b"key");
+    assert!(other
+        .import_from(Cursor::new(&truncated), &consensus.genesis_hash())
+        .is_err());
+}
+
+#[test]
+fn check_and_repair() {
+    let consensus = Consensus::default();
+    let storage = Storage::with_store(MemoryStore::new()).unwrap();
+    storage.init_genesis_block(consensus.genesis_block().data());
+    let lock_script = Script::new_builder()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(b"lock_script".to_vec()).pack())
+        .build();
+    storage.update_filter_scripts(vec![(lock_script.clone(), 0)].into_iter().collect());
+
+    let tx = TransactionBuilder::default()
+        .output(
+            CellOutputBuilder::default()
+                .capacity(capacity_bytes!(1000).pack())
+                .lock(lock_script.clone())
+                .build(),
+        )
+        .output_data(Default::default())
+        .build();
+    let block = BlockBuilder::default()
+        .transaction(tx.clone())
+        .header(HeaderBuilder::default().number(10.pack()).build())
+        .build();
+    assert!(storage.filter_block(block.data()));
+    storage.update_block_number(20);
+
+    let report = storage.check(false).unwrap();
+    assert!(report.is_ok());
+
+    // the transaction is lost, the cell and the tx history refer to it are dangling
+    let mut batch = WriteBatch::default();
+    batch.delete(Key::TxHash(&tx.hash()).into_vec());
+    storage.store.write(&batch).unwrap();
+
+    let report = storage.check(false).unwrap();
+    assert_eq!(report.dangling.len(), 2);
+    assert!(report.rewound_scripts.is_empty());
+
+    let report = storage.check(true).unwrap();
+    assert_eq!(report.dangling.len(), 2);
+    assert_eq!(report.rewound_scripts, vec![(lock_script.clone(), 9)]);
+    assert_eq!(storage.get_filter_scripts().get(&lock_script), Some(&9));
+
+    assert!(storage.check(false).unwrap().is_ok());
+}
+
+#[test]
+fn prune_tx_history() {
+    let consensus = Consensus::default();
+    let storage = Storage::with_store(MemoryStore::new()).unwrap();
+    storage.init_genesis_block(consensus.genesis_block().data());
+    let lock_script = Script::new_builder()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(b"lock_script".to_vec()).pack())
+        .build();
+    storage.update_filter_scripts(vec![(lock_script.clone(), 0)].into_iter().collect());
+
+    let output = CellOutputBuilder::default()
+        .capacity(capacity_bytes!(1000).pack())
+        .lock(lock_script.clone())
+        .build();
+    // the cell of tx1 is spent by tx2, tx3 owns a live cell
+    let tx1 = TransactionBuilder::default()
+        .output(output.clone())
+        .output_data(Default::default())
+        .build();
+    let tx2 = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::new(tx1.hash(), 0), 0))
+        .output(CellOutputBuilder::default().build())
+        .output_data(Default::default())
+        .build();
+    let tx3 = TransactionBuilder::default()
+        .output(output)
+        .output_data(Default::default())
+        .build();
+    for (block_number, tx) in [(10u64, &tx1), (20, &tx2), (30, &tx3)] {
+        let block = BlockBuilder::default()
+            .transaction(tx.clone())
+            .header(HeaderBuilder::default().number(block_number.pack()).build())
+            .build();
+        assert!(storage.filter_block(block.data()));
+    }
+    storage.update_block_number(100);
+
+    // the last `LAST_N_BLOCKS` blocks are always kept
+    let report = storage
+        .prune(RetentionPolicy::KeepBlocks { blocks: 10 })
+        .unwrap();
+    assert_eq!((report.transactions, report.tx_history), (0, 0));
+
+    storage.update_block_number(1000);
+    let report = storage.prune(RetentionPolicy::LiveCells).unwrap();
+    assert_eq!(report.transactions, 2);
+    assert_eq!(report.tx_history, 3);
+    assert!(storage.get_transaction_with_header(&tx1.hash()).is_none());
+    assert!(storage.get_transaction_with_header(&tx2.hash()).is_none());
+    assert!(storage.get_transaction_with_header(&tx3.hash()).is_some());
+    assert!(storage.check(false).unwrap().is_ok());
+}
+
+#[test]
+fn migrate_legacy_cell_values() {
+    let tmp_dir = tempfile::Builder::new()
+        .prefix("migrate_legacy_cell_values")
+        .tempdir()
+        .unwrap();
+    let consensus = Consensus::default();
+    let lock_script = Script::new_builder()
+        .code_hash(H256(rand::random()).pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(b"lock_script".to_vec()).pack())
+        .build();
+    let output = CellOutputBuilder::default()
+        .capacity(capacity_bytes!(1000).pack())
+        .lock(lock_script.clone())
+        .build();
+    let tx = TransactionBuilder::default()
+        .output(output.clone())
+        .output_data(Bytes::from(b"data".to_vec()).pack())
+        .build();
+    let block = BlockBuilder::default()
+        .transaction(tx.clone())
+        .header(HeaderBuilder::default().number(10.pack()).build())
+        .build();
+    let key = Key::CellLockScript(&lock_script, 10, 0, 0).into_vec();
+    let dangling_key = Key::CellLockScript(&lock_script, 10, 1, 0).into_vec();
+
+    // the cell values of the previous versions only carry the tx hash
+    {
+        let storage = Storage::new(tmp_dir.path());
+        storage.init_genesis_block(consensus.genesis_block().data());
+        storage.update_filter_scripts(vec![(lock_script.clone(), 0)].into_iter().collect());
+        assert!(storage.filter_block(block.data()));
+        storage.update_block_number(20);
+        let mut batch = WriteBatch::default();
+        batch.put(&key, tx.hash().as_slice());
+        // the transaction of the dangling cell is missing
+        batch.put(&dangling_key, H256(rand::random()).as_bytes());
+        // the stores of the previous versions have no schema version
+        batch.delete(Key::Meta("SCHEMA_VERSION").into_vec());
+        storage.store.write(&batch).unwrap();
+        assert!(!storage.check(false).unwrap().is_ok());
+    }
+
+    {
+        let storage = Storage::new(tmp_dir.path());
+        let value = storage.store.get(&key).unwrap().unwrap();
+        let cell = CellValue::from_slice(&value).unwrap();
+        assert_eq!(cell.tx_hash, tx.hash());
+        assert_eq!(cell.block_hash, block.hash());
+        assert_eq!(cell.output.as_slice(), output.as_slice());
+        assert_eq!(cell.data_len, 4);
+        assert_eq!(cell.data_hash, packed::CellOutput::calc_data_hash(b"data"));
+        assert!(storage.store.get(&dangling_key).unwrap().is_none());
+        // the script is rewound to index the deleted cell again
+        assert_eq!(storage.get_filter_scripts().get(&lock_script), Some(&9));
+        assert!(storage.check(false).unwrap().is_ok());
+
+        let mut batch = WriteBatch::default();
+        batch.put(&key, tx.hash().as_slice());
+        storage.store.write(&batch).unwrap();
+    }
+
+    // the migration only runs once
+    let storage = Storage::new(tmp_dir.path());
+    let value = storage.store.get(&key).unwrap().unwrap();
+    assert_eq!(value, tx.hash().as_slice());
+}
+
+#[test]
+fn migrate_type_script_index() {
+    let tmp_dir = tempfile::Builder::new()
+        .prefix("migrate_type_script_index")
+        .tempdir()
+        .unwrap();
+    let consensus = Consensus::default();
+    let new_script = |args: &[u8]| {
+        Script::new_builder()
+            .code_hash(H256(rand::random()).pack())
+            .hash_type(ScriptHashType::Type.into())
+            .args(Bytes::from(args.to_vec()).pack())
+            .build()
+    };
+    let lock_script = new_script(b"lock_script");
+    let type_script = new_script(b"type_script");
+    let tx1 = TransactionBuilder::default()
+        .output(
+            CellOutputBuilder::default()
+                .capacity(capacity_bytes!(1000).pack())
+                .lock(lock_script.clone())
+                .type_(Some(type_script.clone()).pack())
+                .build(),
+        )
+        .output(
+            CellOutputBuilder::default()
+                .capacity(capacity_bytes!(2000).pack())
+                .lock(lock_script.clone())
+                .type_(Some(type_script.clone()).pack())
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .output_data(Bytes::new().pack())
+        .build();
+    let tx2 = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::new(tx1.hash(), 0), 0))
+        .output(
+            CellOutputBuilder::default()
+                .capacity(capacity_bytes!(1000).pack())
+                .lock(lock_script.clone())
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .build();
+    let type_script_keys = |storage: &Storage| {
+        [KeyPrefix::CellTypeScript, KeyPrefix::TxTypeScript]
+            .into_iter()
+            .flat_map(|prefix| keys(storage.store.prefix_iter(&[prefix as u8])))
+            .collect::<Vec<_>>()
+    };
+
+    // the stores of the previous versions only index the cells and the tx history by lock
+    // scripts
+    let indexed_keys = {
+        let storage = Storage::new(tmp_dir.path());
+        storage.init_genesis_block(consensus.genesis_block().data());
+        storage.update_filter_scripts(vec![(lock_script.clone(), 0)].into_iter().collect());
+        for (number, tx) in [(1u64, &tx1), (2, &tx2)] {
+            let block = BlockBuilder::default()
+                .transaction(tx.clone())
+                .header(HeaderBuilder::default().number(number.pack()).build())
+                .build();
+            assert!(storage.filter_block(block.data()));
+        }
+        let indexed_keys = type_script_keys(&storage);
+        // the live cell tx1#1, the outputs of tx1 and the input of tx2
+        assert_eq!(indexed_keys.len(), 4);
+        let mut batch = WriteBatch::default();
+        for key in &indexed_keys {
+            batch.delete(key);
+        }
+        batch.delete(Key::Meta("SCHEMA_VERSION").into_vec());
+        storage.store.write(&batch).unwrap();
+        assert!(type_script_keys(&storage).is_empty());
+        indexed_keys
+    };
+
+    let storage = Storage::new(tmp_dir.path());
+    assert_eq!(type_script_keys(&storage), indexed_keys);
+    assert!(storage.check(false).unwrap().is_ok());
+}
+
+#[test]
+fn compact_rollback_logs() {
+    let consensus = Consensus::default();
+    let storage = Storage::with_store(MemoryStore::new()).unwrap();
+    storage.init_genesis_block(consensus.genesis_block().data());
+    let prefix = Key::Meta("ROLLBACKS").into_vec();
+    let logs_count = || {
+        storage
+            .store
+            .iter(&prefix, Direction::Forward)
+            .take_while(|(key, _value)| key.starts_with(&prefix))
+            .count()
+    };
+
+    // the rollbacks which are not lower than a later one are redundant
+    storage.rollback_to_block(10);
+    storage.rollback_to_block(20);
+    storage.rollback_to_block(15);
+    assert_eq!(logs_count(), 2);
+    let snapshot = storage.snapshot();
+    assert_eq!(snapshot.get_rollback_count(), 3);
+    assert_eq!(snapshot.get_rolled_back_to(0), Some(10));
+    assert_eq!(snapshot.get_rolled_back_to(1), Some(15));
+    assert_eq!(snapshot.get_rolled_back_to(2), Some(15));
+    assert_eq!(snapshot.get_rolled_back_to(3), None);
+
+    // the oldest rollbacks are merged, the lowest block number is kept
+    for i in 0..100 {
+        storage.rollback_to_block(100 + i);
+    }
+    assert_eq!(logs_count(), 64);
+    let snapshot = storage.snapshot();
+    assert_eq!(snapshot.get_rollback_count(), 103);
+    assert_eq!(snapshot.get_rolled_back_to(0), Some(10));
+    assert_eq!(snapshot.get_rolled_back_to(90), Some(187));
+    assert_eq!(snapshot.get_rolled_back_to(102), Some(199));
+}
+
+#[test]
+fn store_fetched_headers_by_hash() {
+    let consensus = Consensus::default();
+    let storage = Storage::with_store(MemoryStore::new()).unwrap();
+    storage.init_genesis_block(consensus.genesis_block().data());
+
+    // the fetched header could be on a stale fork, it's not served by number
+    let header = HeaderBuilder::default().number(10u64.pack()).build();
+    storage.add_fetched_header(&header.data());
+    assert_eq!(
+        storage
+            .get_header(&header.hash())
+            .map(|header| header.hash()),
+        Some(header.hash())
+    );
+    assert!(storage.get_header_by_number(10).is_none());
+}
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use ckb_types::{
+    core::DepType,
+    h256,
+    packed::{CellDep, OutPoint},
+    prelude::*,
+    H256,
+};
+
+use crate::{
+    service::{ChainRpc, ChainRpcImpl},
+    storage::StorageWithLastHeaders,
+    system_scripts::SystemScripts,
+    tests::verify::setup,
+};
+
+const CELLBASE_HASH: H256 =
+    h256!("0x8f8c79eb6671709633fe6a46de93c0fedc9c1b8a6527a18d3983879542635c9f");
+const DEP_GROUP_TX_HASH: H256 =
+    h256!("0xf8de3bb47d055cdf460d93a2a6e1b05f7432f9777c8c474abf4eec1d4aee5d37");
+
+fn cell_dep(tx_hash: &H256, index: u32, dep_type: DepType) -> CellDep {
+    CellDep::new_builder()
+        .out_point(OutPoint::new(tx_hash.pack(), index))
+        .dep_type(dep_type.into())
+        .build()
+}
+
+// the system scripts of the testnet
+#[test]
+fn system_scripts_of_genesis_block() {
+    let (storage, consensus) = setup("system_scripts_of_genesis_block");
+    let system_scripts = SystemScripts::new(&storage.get_genesis_block(), &consensus);
+
+    let sighash = system_scripts
+        .secp256k1_blake160_sighash_all
+        .clone()
+        .unwrap();
+    assert_eq!(
+        sighash.type_hash,
+        h256!("0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8").pack()
+    );
+    assert_eq!(
+        sighash.cell_dep,
+        cell_dep(&DEP_GROUP_TX_HASH, 0, DepType::DepGroup)
+    );
+    let multisig = system_scripts
+        .secp256k1_blake160_multisig_all
+        .clone()
+        .unwrap();
+    assert_eq!(
+        multisig.type_hash,
+        h256!("0x5c5069eb0857efc65e1bca0c07df34c31663b3622fd3876c876320fc9634e2a8").pack()
+    );
+    assert_eq!(
+        multisig.cell_dep,
+        cell_dep(&DEP_GROUP_TX_HASH, 1, DepType::DepGroup)
+    );
+    let dao = system_scripts.dao.clone().unwrap();
+    assert_eq!(Some(dao.type_hash), consensus.dao_type_hash());
+    assert_eq!(dao.cell_dep, cell_dep(&CELLBASE_HASH, 2, DepType::Code));
+
+    // the dep groups, the code cells and the secp256k1 data are kept
+    for out_point in [
+        OutPoint::new(DEP_GROUP_TX_HASH.pack(), 0),
+        OutPoint::new(DEP_GROUP_TX_HASH.pack(), 1),
+        OutPoint::new(CELLBASE_HASH.pack(), 1),
+        OutPoint::new(CELLBASE_HASH.pack(), 2),
+        OutPoint::new(CELLBASE_HASH.pack(), 3),
+        OutPoint::new(CELLBASE_HASH.pack(), 4),
+    ] {
+        let cell = system_scripts.cell(&out_point).unwrap();
+        assert!(cell.mem_cell_data.is_some());
+        assert_eq!(0, cell.transaction_info.as_ref().unwrap().block_number);
+    }
+    assert!(system_scripts
+        .cell(&OutPoint::new(CELLBASE_HASH.pack(), 5))
+        .is_none());
+
+    let rpc = ChainRpcImpl {
+        swl: StorageWithLastHeaders::new(storage, Default::default()),
+        consensus,
+        system_scripts: Arc::new(system_scripts),
+    };
+    let names: Vec<_> = rpc
+        .get_system_scripts()
+        .unwrap()
+        .into_iter()
+        .map(|system_script| system_script.name)
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            "secp256k1_blake160_sighash_all",
+            "secp256k1_blake160_multisig_all",
+            "dao"
+        ]
+    );
+}
@@ -1,12 +1,24 @@
 use std::collections::HashMap;
 
 use ckb_chain_spec::{consensus::Consensus, ChainSpec};
+use ckb_crypto::secp::Privkey;
+use ckb_hash::{blake2b_256, new_blake2b};
 use ckb_jsonrpc_types::{Block, Script, Transaction};
 use ckb_resource::Resource;
-use ckb_types::packed;
+use ckb_types::{
+    bytes::Bytes,
+    core::{
+        capacity_bytes, BlockBuilder, Capacity, EpochNumberWithFraction, HeaderBuilder,
+        ScriptHashType, TransactionBuilder,
+    },
+    packed::{self, CellInput, CellOutputBuilder, OutPoint},
+    prelude::*,
+    H256, U256,
+};
 
 use crate::{
     storage::{Storage, StorageWithLastHeaders},
+    system_scripts::SystemScripts,
     verify::verify_tx,
 };
 
@@ -38,23 +50,155 @@ fn verify_valid_transaction() {
     // https://pudge.explorer.nervos.org/transaction/0xf34f4eaac4a662927fb52d4cb608e603150b9e0678a0f5ed941e3cfd5b68fb30
     let transaction: packed::Transaction = serde_json::from_str::<Transaction>(r#"{"cell_deps":[{"dep_type":"dep_group","out_point":{"index":"0x0","tx_hash":"0xf8de3bb47d055cdf460d93a2a6e1b05f7432f9777c8c474abf4eec1d4aee5d37"}}],"header_deps":[],"inputs":[{"previous_output":{"index":"0x7","tx_hash":"0x8f8c79eb6671709633fe6a46de93c0fedc9c1b8a6527a18d3983879542635c9f"},"since":"0x0"}],"outputs":[{"capacity":"0x470de4df820000","lock":{"args":"0xff5094c2c5f476fc38510018609a3fd921dd28ad","code_hash":"0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8","hash_type":"type"},"type":null},{"capacity":"0xb61134e5a35e800","lock":{"args":"0x64257f00b6b63e987609fa9be2d0c86d351020fb","code_hash":"0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8","hash_type":"type"},"type":null}],"outputs_data":["0x","0x"],"version":"0x0","witnesses":["0x5500000010000000550000005500000041000000af34b54bebf8c5971da6a880f2df5a186c3f8d0b5c9a1fe1a90c95b8a4fb89ef3bab1ccec13797dcb3fee80400f953227dd7741227e08032e3598e16ccdaa49c00"]}"#).unwrap().into();
 
+    let system_scripts = SystemScripts::new(&storage.get_genesis_block(), &consensus);
     let swl = StorageWithLastHeaders::new(storage, Default::default());
-    let result = verify_tx(transaction.into_view(), &swl, &consensus).unwrap();
-    assert_eq!(1682789, result);
+    let (cycles, _fee) =
+        verify_tx(transaction.into_view(), &swl, &consensus, &system_scripts).unwrap();
+    assert_eq!(1682789, cycles);
 }
 
 #[test]
 fn non_contextual_transaction_verifier() {
     let (storage, consensus) = setup("non_contextual_transaction_verifier");
+    let system_scripts = SystemScripts::new(&storage.get_genesis_block(), &consensus);
     let swl = StorageWithLastHeaders::new(storage, Default::default());
     // duplicate cell deps base on a valid transaction
     // https://pudge.explorer.nervos.org/transaction/0xf34f4eaac4a662927fb52d4cb608e603150b9e0678a0f5ed941e3cfd5b68fb30
     let transaction: packed::Transaction = serde_json::from_str::<Transaction>(r#"{"cell_deps":[{"dep_type":"dep_group","out_point":{"index":"0x0","tx_hash":"0xf8de3bb47d055cdf460d93a2a6e1b05f7432f9777c8c474abf4eec1d4aee5d37"}}, {"dep_type":"dep_group","out_point":{"index":"0x0","tx_hash":"0xf8de3bb47d055cdf460d93a2a6e1b05f7432f9777c8c474abf4eec1d4aee5d37"}}],"header_deps":[],"inputs":[{"previous_output":{"index":"0x7","tx_hash":"0x8f8c79eb6671709633fe6a46de93c0fedc9c1b8a6527a18d3983879542635c9f"},"since":"0x0"}],"outputs":[{"capacity":"0x470de4df820000","lock":{"args":"0xff5094c2c5f476fc38510018609a3fd921dd28ad","code_hash":"0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8","hash_type":"type"},"type":null},{"capacity":"0xb61134e5a35e800","lock":{"args":"0x64257f00b6b63e987609fa9be2d0c86d351020fb","code_hash":"0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8","hash_type":"type"},"type":null}],"outputs_data":["0x","0x"],"version":"0x0","witnesses":["0x5500000010000000550000005500000041000000af34b54bebf8c5971da6a880f2df5a186c3f8d0b5c9a1fe1a90c95b8a4fb89ef3bab1ccec13797dcb3fee80400f953227dd7741227e08032e3598e16ccdaa49c00"]}"#).unwrap().into();
-    let error = verify_tx(transaction.into_view(), &swl, &consensus).unwrap_err();
+    let error = verify_tx(transaction.into_view(), &swl, &consensus, &system_scripts).unwrap_err();
     assert!(error.to_string().contains("DuplicateCellDeps"));
 
     // insufficient cell capacity
     let transaction: packed::Transaction = serde_json::from_str::<Transaction>(r#"{"cell_deps":[{"dep_type":"dep_group","out_point":{"index":"0x0","tx_hash":"0xf8de3bb47d055cdf460d93a2a6e1b05f7432f9777c8c474abf4eec1d4aee5d37"}}],"header_deps":[],"inputs":[{"previous_output":{"index":"0x7","tx_hash":"0x8f8c79eb6671709633fe6a46de93c0fedc9c1b8a6527a18d3983879542635c9f"},"since":"0x0"}],"outputs":[{"capacity":"0x470de4df820000","lock":{"args":"0xff5094c2c5f476fc38510018609a3fd921dd28ad","code_hash":"0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8","hash_type":"type"},"type":null},{"capacity":"0xb6113","lock":{"args":"0x64257f00b6b63e987609fa9be2d0c86d351020fb","code_hash":"0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8","hash_type":"type"},"type":null}],"outputs_data":["0x","0x"],"version":"0x0","witnesses":["0x5500000010000000550000005500000041000000af34b54bebf8c5971da6a880f2df5a186c3f8d0b5c9a1fe1a90c95b8a4fb89ef3bab1ccec13797dcb3fee80400f953227dd7741227e08032e3598e16ccdaa49c00"]}"#).unwrap().into();
-    let error = verify_tx(transaction.into_view(), &swl, &consensus).unwrap_err();
+    let error = verify_tx(transaction.into_view(), &swl, &consensus, &system_scripts).unwrap_err();
     assert!(error.to_string().contains("InsufficientCellCapacity"));
 }
+
+#[test]
+fn verify_dao_withdrawing_transaction() {
+    let (storage, consensus) = setup("verify_dao_withdrawing_transaction");
+    let system_scripts = SystemScripts::new(&storage.get_genesis_block(), &consensus);
+    let sighash = system_scripts
+        .secp256k1_blake160_sighash_all
+        .clone()
+        .unwrap();
+    let dao = system_scripts.dao.clone().unwrap();
+
+    let privkey = Privkey::from_slice(&[1u8; 32]);
+    let pubkey = privkey.pubkey().unwrap();
+    let lock_script = packed::Script::new_builder()
+        .code_hash(sighash.type_hash.clone())
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(blake2b_256(pubkey.serialize())[..20].to_vec()).pack())
+        .build();
+    storage.update_filter_scripts(HashMap::from([(lock_script.clone(), 0)]));
+
+    let dao_type_script = packed::Script::new_builder()
+        .code_hash(dao.type_hash.clone())
+        .hash_type(ScriptHashType::Type.into())
+        .build();
+    let dao_output = CellOutputBuilder::default()
+        .capacity(capacity_bytes!(1000).pack())
+        .lock(lock_script.clone())
+        .type_(Some(dao_type_script).pack())
+        .build();
+    // only the accumulated rate is used to calculate the interest
+    let dao_field = |ar: u64| {
+        let mut dao = [0u8; 32];
+        dao[8..16].copy_from_slice(&ar.to_le_bytes());
+        H256(dao).pack()
+    };
+    let ar = 10_000_000_000_000_000u64;
+
+    // deposit in epoch 5, and withdraw in epoch 100
+    let deposit_tx = TransactionBuilder::default()
+        .output(dao_output.clone())
+        .output_data(Bytes::from(vec![0u8; 8]).pack())
+        .build();
+    let deposit_block = BlockBuilder::default()
+        .transaction(deposit_tx.clone())
+        .header(
+            HeaderBuilder::default()
+                .number(1.pack())
+                .epoch(EpochNumberWithFraction::new(5, 0, 1000).pack())
+                .dao(dao_field(ar))
+                .build(),
+        )
+        .build();
+    storage.filter_block(deposit_block.data());
+    let withdrawing_tx = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::new(deposit_tx.hash(), 0), 0))
+        .header_dep(deposit_block.hash())
+        .output(dao_output.clone())
+        .output_data(Bytes::from(1u64.to_le_bytes().to_vec()).pack())
+        .build();
+    let withdrawing_block = BlockBuilder::default()
+        .transaction(withdrawing_tx.clone())
+        .header(
+            HeaderBuilder::default()
+                .number(2.pack())
+                .epoch(EpochNumberWithFraction::new(100, 0, 1000).pack())
+                .dao(dao_field(ar / 10 * 11))
+                .build(),
+        )
+        .build();
+    storage.filter_block(withdrawing_block.data());
+    let tip_header = HeaderBuilder::default()
+        .number(3.pack())
+        .epoch(EpochNumberWithFraction::new(200, 0, 1000).pack())
+        .dao(dao_field(ar * 2))
+        .build();
+    storage.update_last_state(&U256::one(), &tip_header.data());
+
+    // the cell is locked for 180 epochs since the deposit
+    let since = 0x2000_0000_0000_0000 | EpochNumberWithFraction::new(185, 0, 1000).full_value();
+    let occupied_capacity = dao_output
+        .occupied_capacity(Capacity::bytes(8).unwrap())
+        .unwrap()
+        .as_u64();
+    let maximum_withdraw =
+        occupied_capacity + (capacity_bytes!(1000).as_u64() - occupied_capacity) / 10 * 11;
+    let fee = 100_000;
+    let witness = |lock: Bytes| {
+        packed::WitnessArgs::new_builder()
+            .lock(Some(lock.pack()).pack())
+            .input_type(Some(Bytes::from(0u64.to_le_bytes().to_vec()).pack()).pack())
+            .build()
+            .as_bytes()
+    };
+    let tx = TransactionBuilder::default()
+        .cell_dep(sighash.cell_dep)
+        .cell_dep(dao.cell_dep)
+        .header_dep(deposit_block.hash())
+        .header_dep(withdrawing_block.hash())
+        .input(CellInput::new(
+            OutPoint::new(withdrawing_tx.hash(), 0),
+            since,
+        ))
+        .output(
+            CellOutputBuilder::default()
+                .capacity((maximum_withdraw - fee).pack())
+                .lock(lock_script)
+                .build(),
+        )
+        .output_data(Default::default())
+        .witness(witness(Bytes::from(vec![0u8; 65])).pack())
+        .build();
+    let placeholder = tx.witnesses().get(0).unwrap().raw_data();
+    let mut hasher = new_blake2b();
+    hasher.update(tx.hash().as_slice());
+    hasher.update(&(placeholder.len() as u64).to_le_bytes());
+    hasher.update(&placeholder);
+    let mut message = [0u8; 32];
+    hasher.finalize(&mut message);
+    let signature = privkey.sign_recoverable(&message.into()).unwrap();
+    let tx = tx
+        .as_advanced_builder()
+        .set_witnesses(vec![witness(Bytes::from(signature.serialize())).pack()])
+        .build();
+
+    // the outputs are more than the inputs without the interest
+    let swl = StorageWithLastHeaders::new(storage, Default::default());
+    let (_cycles, actual_fee) = verify_tx(tx, &swl, &consensus, &system_scripts).unwrap();
+    assert_eq!(fee, actual_fee.as_u64());
+}
@@ -0,0 +1,93 @@
+use std::str::FromStr;
+
+use crate::wallet::{DerivationPath, ExtendedPubKey, WatchWallet};
+
+// the master key of the test vector 2 of BIP 32
+const MASTER_XPUB: &str = "xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB";
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+// the fingerprints of the child keys are not the HASH160 of BIP 32, so only the chain codes and
+// the public keys are compared
+fn assert_same_key(actual: &ExtendedPubKey, expected: &str) {
+    let expected = ExtendedPubKey::from_str(expected).unwrap();
+    assert_eq!(actual.serialize()[13..], expected.serialize()[13..]);
+}
+
+#[test]
+fn derive_child_keys() {
+    let master = ExtendedPubKey::from_str(MASTER_XPUB).unwrap();
+    assert_eq!(master.to_string(), MASTER_XPUB);
+    assert_same_key(
+        &master.derive_child(0).unwrap(),
+        "xpub69H7F5d8KSRgmmdJg2KhpAK8SR3DjMwAdkxj3ZuxV27CprR9LgpeyGmXUbC6wb7ERfvrnKZjXoUmmDznezpbZb7ap6r1D3tgFxHmwMkQTPH",
+    );
+
+    // the m/0H/1/2H/2 key of the test vector 1
+    let parent = ExtendedPubKey::from_str("xpub6FHa3pjLCk84BayeJxFW2SP4XRrFd1JYnxeLeU8EqN3vDfZmbqBqaGJAyiLjTAwm6ZLRQUMv1ZACTj37sR62cfN7fe5JnJ7dh8zL4fiyLHV").unwrap();
+    assert_same_key(
+        &parent.derive_child(1000000000).unwrap(),
+        "xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy",
+    );
+
+    assert!(master.derive_child(0x8000_0000).is_err());
+}
+
+#[test]
+fn parse_extended_pub_key() {
+    // the extended private key of the test vector 2
+    assert!(ExtendedPubKey::from_str("xprv9s21ZrQH143K31xYSDQpPDxsXRTUcvj2iNHm5NUtrGiGG5e2DtALGdso3pGz6ssrdK4PFmM8NSpSBHNqPqm55Qn3LqFtT2emdEXVYsCzC2U").is_err());
+    // the last character is changed
+    assert!(ExtendedPubKey::from_str("xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduC").is_err());
+    assert!(ExtendedPubKey::from_str("").is_err());
+}
+
+#[test]
+fn parse_derivation_path() {
+    assert_eq!(
+        DerivationPath::from_str("m").unwrap(),
+        DerivationPath::default()
+    );
+    let path = DerivationPath::from_str("m/0/1").unwrap();
+    assert_eq!(path.to_string(), "m/0/1");
+    assert_eq!(DerivationPath::from_slice(&path.serialize()), Some(path));
+    assert!(DerivationPath::from_str("m/0'").is_err());
+    assert!(DerivationPath::from_str("m/2147483648").is_err());
+    assert!(DerivationPath::from_str("m/a").is_err());
+}
+
+#[test]
+fn watch_wallet_lock_scripts() {
+    let wallet = WatchWallet {
+        xpub: ExtendedPubKey::from_str(MASTER_XPUB).unwrap(),
+        path: DerivationPath::from_str("m/0").unwrap(),
+        gap_limit: 20,
+    };
+    assert_eq!(
+        WatchWallet::from_slice(&wallet.serialize()),
+        Some(wallet.clone())
+    );
+
+    let args: Vec<_> = wallet
+        .lock_scripts(0, 3)
+        .into_iter()
+        .map(|(index, script)| (index, script.args().raw_data().to_vec()))
+        .collect();
+    assert_eq!(
+        args,
+        vec![
+            (0, hex("79896f6c1865653cdf5236b41433d640fd9e59a0")),
+            (1, hex("c9a41a3aefbe15ae1ccf965ee6d6a8c45c53ffdc")),
+            (2, hex("443816abc797c944d6fe379a6147e655193c2989")),
+        ]
+    );
+    assert_eq!(
+        wallet.lock_scripts(1, 2)[0].1,
+        wallet.lock_scripts(0, 3)[1].1
+    );
+}
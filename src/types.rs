@@ -3,18 +3,79 @@ use std::{fmt, path::PathBuf, result::Result as StdResult, str::FromStr};
 use ckb_app_config::NetworkConfig;
 use serde::{Deserialize, Serialize};
 
+use crate::storage::{CompressionType, RetentionPolicy, RocksdbOptions};
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct RunEnv {
     pub(crate) chain: String,
     pub(crate) store: StoreConfig,
     pub(crate) network: NetworkConfig,
+    #[serde(default)]
+    pub(crate) tx_pool: TxPoolConfig,
+    #[serde(default)]
+    pub(crate) metrics: Option<MetricsConfig>,
+    #[serde(default)]
+    pub(crate) rpc: RpcConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct StoreConfig {
     pub(crate) path: PathBuf,
+    /// The capacity (bytes) of the block cache shared by all column families.
+    #[serde(default)]
+    pub(crate) cache_size: Option<usize>,
+    #[serde(default)]
+    pub(crate) compression: Option<CompressionType>,
+    #[serde(default)]
+    pub(crate) max_open_files: Option<i32>,
+    /// The size (bytes) of the memtable of each column family.
+    #[serde(default)]
+    pub(crate) write_buffer_size: Option<usize>,
+    #[serde(default)]
+    pub(crate) retention: RetentionPolicy,
+}
+
+impl StoreConfig {
+    pub(crate) fn rocksdb_options(&self) -> RocksdbOptions {
+        RocksdbOptions {
+            cache_size: self.cache_size,
+            compression: self.compression,
+            max_open_files: self.max_open_files,
+            write_buffer_size: self.write_buffer_size,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TxPoolConfig {
+    /// The minimal fee rate (shannons per KB) of the transactions which are sent to peers.
+    pub(crate) min_fee_rate: u64,
+}
+
+impl Default for TxPoolConfig {
+    fn default() -> Self {
+        // Same as the default `min_fee_rate` of the CKB full node.
+        Self { min_fee_rate: 1000 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MetricsConfig {
+    /// The address which the Prometheus metrics endpoint listens on.
+    pub(crate) listen_address: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RpcConfig {
+    /// Serve `get_cells`, `get_transactions` and `get_cells_capacity` in the same shapes as
+    /// ckb-indexer, and `get_indexer_tip`.
+    #[serde(default)]
+    pub(crate) indexer_compatible: bool,
 }
 
 impl FromStr for RunEnv {
@@ -1,13 +1,14 @@
 use std::collections::{hash_map::Entry, HashMap, HashSet};
 
 use ckb_chain_spec::consensus::Consensus;
+use ckb_dao::DaoCalculator;
 use ckb_error::Error;
 use ckb_script::TxVerifyEnv;
 use ckb_types::{
     core::{
         cell::{CellMeta, CellProvider, CellStatus, ResolvedTransaction},
         error::OutPointError,
-        Cycle, DepType, TransactionView,
+        Capacity, Cycle, DepType, TransactionView,
     },
     packed::{OutPoint, OutPointVec},
     prelude::Entity,
@@ -17,7 +18,7 @@ use ckb_verification::{
     TimeRelativeTransactionVerifier,
 };
 
-use crate::storage::StorageWithLastHeaders;
+use crate::{storage::StorageWithLastHeaders, system_scripts::SystemScripts};
 
 /// Light client can only verify non-cellbase transaction,
 /// can not reuse the `ContextualTransactionVerifier` in ckb_verification crate which is used to verify cellbase also.
@@ -49,23 +50,40 @@ impl<'a> ContextualTransactionVerifier<'a> {
     }
 }
 
+/// Verifies the transaction, returns the consumed cycles and the transaction fee.
 pub fn verify_tx(
     transaction: TransactionView,
     swl: &StorageWithLastHeaders,
     consensus: &Consensus,
-) -> Result<Cycle, Error> {
+    system_scripts: &SystemScripts,
+) -> Result<(Cycle, Capacity), Error> {
     NonContextualTransactionVerifier::new(&transaction, consensus).verify()?;
 
-    let rtx = resolve_tx(swl, transaction)?;
+    let rtx = resolve_tx(swl, system_scripts, transaction)?;
     let (_, tip_header) = swl.storage().get_last_state();
     let tx_env = TxVerifyEnv::new_submit(&tip_header.into_view());
-    ContextualTransactionVerifier::new(&rtx, consensus, swl, &tx_env)
-        .verify(consensus.max_block_cycles())
+    let cycles = ContextualTransactionVerifier::new(&rtx, consensus, swl, &tx_env)
+        .verify(consensus.max_block_cycles())?;
+    let fee = calculate_fee(&rtx, swl, consensus)?;
+    Ok((cycles, fee))
+}
+
+/// The fee is the capacity difference between the resolved inputs and the outputs, the inputs of
+/// the NervosDAO withdrawing cells are counted with their interest, same as the full node.
+pub fn calculate_fee(
+    rtx: &ResolvedTransaction,
+    swl: &StorageWithLastHeaders,
+    consensus: &Consensus,
+) -> Result<Capacity, Error> {
+    DaoCalculator::new(consensus, swl)
+        .transaction_fee(rtx)
+        .map_err(Into::into)
 }
 
 #[allow(clippy::mutable_key_type)]
 fn resolve_tx(
     swl: &StorageWithLastHeaders,
+    system_scripts: &SystemScripts,
     transaction: TransactionView,
 ) -> Result<ResolvedTransaction, OutPointError> {
     let (mut resolved_inputs, mut resolved_cell_deps, mut resolved_dep_groups) = (
@@ -81,7 +99,11 @@ fn resolve_tx(
             match resolved_cells.entry((out_point.clone(), eager_load)) {
                 Entry::Occupied(entry) => Ok(entry.get().clone()),
                 Entry::Vacant(entry) => {
-                    let cell_status = swl.cell(out_point, eager_load);
+                    // the well-known deps are resolved without loading the genesis transactions
+                    let cell_status = match system_scripts.cell(out_point) {
+                        Some(cell_meta) => CellStatus::Live(cell_meta.clone()),
+                        None => swl.cell(out_point, eager_load),
+                    };
                     match cell_status {
                         CellStatus::Dead => Err(OutPointError::Dead(out_point.clone())),
                         CellStatus::Unknown => Err(OutPointError::Unknown(out_point.clone())),
@@ -0,0 +1,270 @@
+//! Watch-only HD wallets, which are registered by the extended public keys of
+//! [BIP 32](https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki).
+//!
+//! The secp256k1 blake160 sighash lock scripts are derived at `<path>/<index>` from the extended
+//! public key, only the non-hardened derivation is possible without the private key.
+
+use std::{convert::TryInto, fmt, str::FromStr};
+
+use ckb_hash::blake2b_256;
+use ckb_types::{bytes::Bytes, core::ScriptHashType, packed, prelude::*};
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use secp256k1::{PublicKey, Secp256k1, VerifyOnly};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::address::SIGHASH_TYPE_HASH;
+
+const XPUB_VERSION: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+const TPUB_VERSION: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+const XPUB_LEN: usize = 78;
+const HARDENED_INDEX: u32 = 1 << 31;
+
+static SECP256K1: Lazy<Secp256k1<VerifyOnly>> = Lazy::new(Secp256k1::verification_only);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtendedPubKey {
+    version: [u8; 4],
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: u32,
+    chain_code: [u8; 32],
+    public_key: PublicKey,
+}
+
+impl ExtendedPubKey {
+    /// Derives the non-hardened child key.
+    pub fn derive_child(&self, index: u32) -> Result<Self, String> {
+        if index >= HARDENED_INDEX {
+            return Err(format!("can't derive the hardened child {}", index));
+        }
+        let mut mac = Hmac::<Sha512>::new_from_slice(&self.chain_code)
+            .expect("HMAC accepts the keys of any length");
+        mac.update(&self.public_key.serialize());
+        mac.update(&index.to_be_bytes());
+        let result = mac.finalize().into_bytes();
+        let (tweak, chain_code) = result.split_at(32);
+        let mut public_key = self.public_key;
+        // the key is invalid when the tweak is not less than the curve order, or the result is
+        // the point at infinity
+        public_key
+            .add_exp_assign(&*SECP256K1, tweak)
+            .map_err(|_| format!("the child {} is invalid, skip it", index))?;
+        Ok(Self {
+            version: self.version,
+            depth: self.depth.wrapping_add(1),
+            parent_fingerprint: self.fingerprint(),
+            child_number: index,
+            chain_code: chain_code.try_into().expect("32 bytes chain code"),
+            public_key,
+        })
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, String> {
+        path.0
+            .iter()
+            .try_fold(self.clone(), |key, index| key.derive_child(*index))
+    }
+
+    /// The blake160 of the compressed public key, which is the args of the sighash lock.
+    pub fn blake160(&self) -> [u8; 20] {
+        blake2b_256(&self.public_key.serialize())[..20]
+            .try_into()
+            .expect("20 bytes blake160")
+    }
+
+    // BIP 32 uses the HASH160 of the public key, it's only used to fill the serialization of the
+    // child keys, which are never serialized here, so the blake160 is good enough.
+    fn fingerprint(&self) -> [u8; 4] {
+        self.blake160()[..4]
+            .try_into()
+            .expect("4 bytes fingerprint")
+    }
+
+    pub fn serialize(&self) -> [u8; XPUB_LEN] {
+        let mut data = [0u8; XPUB_LEN];
+        data[0..4].copy_from_slice(&self.version);
+        data[4] = self.depth;
+        data[5..9].copy_from_slice(&self.parent_fingerprint);
+        data[9..13].copy_from_slice(&self.child_number.to_be_bytes());
+        data[13..45].copy_from_slice(&self.chain_code);
+        data[45..78].copy_from_slice(&self.public_key.serialize());
+        data
+    }
+
+    pub fn from_slice(data: &[u8]) -> Result<Self, String> {
+        if data.len() != XPUB_LEN {
+            return Err(format!(
+                "the length of the extended key should be {}",
+                XPUB_LEN
+            ));
+        }
+        let version: [u8; 4] = data[0..4].try_into().expect("checked length");
+        if version != XPUB_VERSION && version != TPUB_VERSION {
+            return Err("only the extended public keys are supported".to_owned());
+        }
+        let public_key = PublicKey::from_slice(&data[45..78])
+            .map_err(|err| format!("invalid public key: {}", err))?;
+        Ok(Self {
+            version,
+            depth: data[4],
+            parent_fingerprint: data[5..9].try_into().expect("checked length"),
+            child_number: u32::from_be_bytes(data[9..13].try_into().expect("checked length")),
+            chain_code: data[13..45].try_into().expect("checked length"),
+            public_key,
+        })
+    }
+}
+
+impl fmt::Display for ExtendedPubKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let data = self.serialize();
+        let mut payload = data.to_vec();
+        payload.extend_from_slice(&checksum(&data));
+        write!(f, "{}", bs58::encode(payload).into_string())
+    }
+}
+
+impl FromStr for ExtendedPubKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let payload = bs58::decode(s)
+            .into_vec()
+            .map_err(|err| format!("invalid base58 string: {}", err))?;
+        if payload.len() != XPUB_LEN + 4 {
+            return Err(format!(
+                "the length of the extended key should be {}",
+                XPUB_LEN
+            ));
+        }
+        let (data, expected) = payload.split_at(XPUB_LEN);
+        if checksum(data) != expected {
+            return Err("invalid checksum".to_owned());
+        }
+        Self::from_slice(data)
+    }
+}
+
+// the first 4 bytes of the double SHA256
+fn checksum(data: &[u8]) -> [u8; 4] {
+    Sha256::digest(&Sha256::digest(data))[..4]
+        .try_into()
+        .expect("4 bytes checksum")
+}
+
+/// A non-hardened derivation path which is relative to the extended public key, e.g. `m/0` for
+/// the receiving addresses of an account key.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    pub fn serialize(&self) -> Vec<u8> {
+        self.0
+            .iter()
+            .flat_map(|index| index.to_be_bytes())
+            .collect()
+    }
+
+    pub fn from_slice(data: &[u8]) -> Option<Self> {
+        if data.len() % 4 != 0 {
+            return None;
+        }
+        Some(Self(
+            data.chunks(4)
+                .map(|index| u32::from_be_bytes(index.try_into().expect("checked length")))
+                .collect(),
+        ))
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            write!(f, "/{}", index)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_prefix('m').unwrap_or(s);
+        s.split('/')
+            .filter(|index| !index.is_empty())
+            .map(|index| {
+                index
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|index| *index < HARDENED_INDEX)
+                    .ok_or_else(|| format!("invalid non-hardened index {}", index))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+/// A watch-only wallet, the lock scripts are derived from index 0 in order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WatchWallet {
+    pub xpub: ExtendedPubKey,
+    pub path: DerivationPath,
+    /// How many unused addresses are kept after the last used one.
+    pub gap_limit: u32,
+}
+
+impl WatchWallet {
+    /// The wallet is identified by the extended public key and the path.
+    pub fn id(&self) -> [u8; 32] {
+        blake2b_256(
+            [
+                self.xpub.serialize().as_ref(),
+                self.path.serialize().as_ref(),
+            ]
+            .concat(),
+        )
+    }
+
+    /// Returns the lock scripts of the indexes in the range, the invalid child keys are skipped.
+    pub fn lock_scripts(&self, start: u32, end: u32) -> Vec<(u32, packed::Script)> {
+        let parent = match self.xpub.derive_path(&self.path) {
+            Ok(parent) => parent,
+            Err(_) => return Vec::new(),
+        };
+        (start..end)
+            .filter_map(|index| {
+                parent.derive_child(index).ok().map(|key| {
+                    let script = packed::Script::new_builder()
+                        .code_hash(SIGHASH_TYPE_HASH.pack())
+                        .hash_type(ScriptHashType::Type.into())
+                        .args(Bytes::from(key.blake160().to_vec()).pack())
+                        .build();
+                    (index, script)
+                })
+            })
+            .collect()
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        [
+            self.xpub.serialize().as_ref(),
+            self.gap_limit.to_be_bytes().as_ref(),
+            self.path.serialize().as_ref(),
+        ]
+        .concat()
+    }
+
+    pub fn from_slice(data: &[u8]) -> Option<Self> {
+        if data.len() < XPUB_LEN + 4 {
+            return None;
+        }
+        Some(Self {
+            xpub: ExtendedPubKey::from_slice(&data[..XPUB_LEN]).ok()?,
+            gap_limit: u32::from_be_bytes(data[XPUB_LEN..XPUB_LEN + 4].try_into().ok()?),
+            path: DerivationPath::from_slice(&data[XPUB_LEN + 4..])?,
+        })
+    }
+}